@@ -1,8 +1,7 @@
 //! Knox CLI: build, run, new, fmt (stub).
 
 use clap::{Parser, Subcommand};
-use knox_compiler::print_diagnostics;
-use knox_syntax::span::FileId;
+use knox_compiler::{print_diagnostics, CompileOptions};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
@@ -59,10 +58,18 @@ enum Commands {
     Build {
         #[arg(long, default_value = "wasm-wasi")]
         target: String,
+        /// Trap on integer overflow (`on`) or let arithmetic wrap (`off`)
+        #[arg(long, default_value = "on", value_parser = ["on", "off"])]
+        overflow_checks: String,
         path: PathBuf,
     },
     /// Compile and run a Knox file (wasm-wasi via Wasmtime)
-    Run { path: PathBuf },
+    Run {
+        /// Trap on integer overflow (`on`) or let arithmetic wrap (`off`)
+        #[arg(long, default_value = "on", value_parser = ["on", "off"])]
+        overflow_checks: String,
+        path: PathBuf,
+    },
     /// Format Knox source (TODO: not implemented)
     Fmt {
         #[arg(default_value = ".")]
@@ -87,13 +94,27 @@ fn main() {
 fn run(cli: Cli) -> Result<(), String> {
     match cli.command {
         Commands::New { name } => cmd_new(&name),
-        Commands::Build { target, path } => cmd_build(&target, &path),
-        Commands::Run { path } => cmd_run(&path),
+        Commands::Build {
+            target,
+            overflow_checks,
+            path,
+        } => cmd_build(&target, &compile_options(&overflow_checks), &path),
+        Commands::Run {
+            overflow_checks,
+            path,
+        } => cmd_run(&compile_options(&overflow_checks), &path),
         Commands::Fmt { path } => cmd_fmt(&path),
         Commands::Add { name, path } => cmd_add(&name, path.as_deref()),
     }
 }
 
+/// Compiler options from the `build`/`run` flags.
+fn compile_options(overflow_checks: &str) -> CompileOptions {
+    CompileOptions {
+        overflow_checks: overflow_checks == "on",
+    }
+}
+
 fn cmd_new(name: &str) -> Result<(), String> {
     let dir = PathBuf::from(name);
     if dir.exists() {
@@ -143,13 +164,14 @@ fn resolve_compile_and_project(path: &Path) -> Result<(PathBuf, PathBuf), String
     }
 }
 
-fn cmd_build(_target: &str, path: &Path) -> Result<(), String> {
+fn cmd_build(_target: &str, options: &CompileOptions, path: &Path) -> Result<(), String> {
     let (compile_path, project_root) = resolve_compile_and_project(path)?;
     let out_path = project_root.join("dist").join("main.wasm");
 
-    let source_for_diags = std::fs::read_to_string(&compile_path).unwrap_or_default();
-    let wasm = knox_compiler::compile_file(&compile_path).map_err(|diags| {
-        print_diagnostics(&source_for_diags, FileId::new(0), &diags);
+    let compiled = knox_compiler::compile_file_with(&compile_path, options);
+    print_diagnostics(&compiled.files, &compiled.warnings);
+    let wasm = compiled.wasm.map_err(|diags| {
+        print_diagnostics(&compiled.files, &diags);
         "Compilation failed".to_string()
     })?;
 
@@ -159,7 +181,7 @@ fn cmd_build(_target: &str, path: &Path) -> Result<(), String> {
     Ok(())
 }
 
-fn cmd_run(path: &Path) -> Result<(), String> {
+fn cmd_run(options: &CompileOptions, path: &Path) -> Result<(), String> {
     let (compile_path, project_root) = resolve_compile_and_project(path)?;
     let wasm_path = project_root.join("dist").join("main.wasm");
 
@@ -186,11 +208,12 @@ fn cmd_run(path: &Path) -> Result<(), String> {
         );
     }
 
-    let source = std::fs::read_to_string(&compile_path).map_err(|e| e.to_string())?;
-    let wasm = match knox_compiler::compile_file(&compile_path) {
+    let compiled = knox_compiler::compile_file_with(&compile_path, options);
+    print_diagnostics(&compiled.files, &compiled.warnings);
+    let wasm = match compiled.wasm {
         Ok(w) => w,
         Err(diags) => {
-            print_diagnostics(&source, FileId::new(0), &diags);
+            print_diagnostics(&compiled.files, &diags);
             return Err("Compilation failed".to_string());
         }
     };
@@ -280,6 +303,95 @@ mod tests {
         has_start && has_memory
     }
 
+    /// Compiles `src` as the `main.kx` of a scratch directory named after the test.
+    fn compile_src_with(
+        name: &str,
+        src: &str,
+        options: &CompileOptions,
+    ) -> knox_compiler::Compilation {
+        let tmp = std::env::temp_dir().join(format!("knox_test_{}", name));
+        let _ = std::fs::create_dir(&tmp);
+        let main_kx = tmp.join("main.kx");
+        std::fs::write(&main_kx, src).expect("write main.kx");
+        let compiled = knox_compiler::compile_file_with(&main_kx, options);
+        let _ = std::fs::remove_dir_all(&tmp);
+        compiled
+    }
+
+    /// Compiles `src` with the default options and checks that the module validates.
+    fn compile_valid(name: &str, src: &str) -> Vec<u8> {
+        let wasm = compile_src_with(name, src, &CompileOptions::default())
+            .wasm
+            .expect("compile");
+        wasmparser::Validator::new()
+            .validate_all(&wasm)
+            .expect("wasm must validate");
+        wasm
+    }
+
+    /// What a module wrote to stdout and stderr, and whether it trapped.
+    #[derive(Default)]
+    struct Run {
+        stdout: String,
+        stderr: String,
+        trapped: bool,
+    }
+
+    /// Runs a module's `_start` with the two WASI calls the compiler imports.
+    fn run_wasm(wasm: &[u8]) -> Run {
+        use wasmi::{Caller, Engine, Linker, Module, Store};
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm).expect("load wasm");
+        let mut store = Store::new(&engine, Run::default());
+        let mut linker = <Linker<Run>>::new(&engine);
+        linker
+            .func_wrap(
+                "wasi_snapshot_preview1",
+                "fd_write",
+                |mut caller: Caller<'_, Run>, fd: i32, iovs: i32, len: i32, written: i32| -> i32 {
+                    let memory = caller
+                        .get_export("memory")
+                        .and_then(|e| e.into_memory())
+                        .expect("memory export");
+                    let data = memory.data(&caller);
+                    let word = |at: usize| {
+                        u32::from_le_bytes(data[at..at + 4].try_into().expect("word")) as usize
+                    };
+                    let mut bytes = Vec::new();
+                    for i in 0..len as usize {
+                        let iov = iovs as usize + i * 8;
+                        let (ptr, n) = (word(iov), word(iov + 4));
+                        bytes.extend_from_slice(&data[ptr..ptr + n]);
+                    }
+                    let count = (bytes.len() as u32).to_le_bytes();
+                    let written = written as usize;
+                    memory.data_mut(&mut caller)[written..written + 4].copy_from_slice(&count);
+                    let text = String::from_utf8_lossy(&bytes);
+                    match fd {
+                        2 => caller.data_mut().stderr.push_str(&text),
+                        _ => caller.data_mut().stdout.push_str(&text),
+                    }
+                    0
+                },
+            )
+            .expect("define fd_write");
+        linker
+            .func_wrap(
+                "wasi_snapshot_preview1",
+                "proc_exit",
+                |code: i32| -> Result<(), wasmi::Error> { Err(wasmi::Error::i32_exit(code)) },
+            )
+            .expect("define proc_exit");
+        let result = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store));
+        let trapped = result.is_err_and(|e| e.i32_exit_status() != Some(0));
+        Run {
+            trapped,
+            ..store.into_data()
+        }
+    }
+
     #[test]
     fn accessors_generic_wasm_has_start_and_memory_exports() {
         let ws = workspace_root();
@@ -297,15 +409,95 @@ mod tests {
         );
     }
 
+    /// A documentation example as a whole `main.kx`: statements that follow the items are the
+    /// body of `main`, and a file of items only gets an empty `main`.
+    fn example_program(block: &str) -> String {
+        const ITEM_STARTS: [&str; 14] = [
+            "fn ",
+            "pub ",
+            "export ",
+            "struct ",
+            "enum ",
+            "impl",
+            "trait ",
+            "const ",
+            "type ",
+            "import ",
+            "resource ",
+            "@",
+            "//",
+            "}",
+        ];
+        let lines: Vec<&str> = block.lines().collect();
+        let first_stmt = lines.iter().position(|l| {
+            !l.is_empty()
+                && !l.starts_with(char::is_whitespace)
+                && !ITEM_STARTS.iter().any(|s| l.starts_with(s))
+        });
+        match first_stmt {
+            Some(k) => format!(
+                "{}\nfn main() -> () {{\n{}\n}}\n",
+                lines[..k].join("\n"),
+                lines[k..].join("\n")
+            ),
+            None if block.contains("fn main(") => block.to_string(),
+            None => format!("{}\nfn main() -> () {{}}\n", block),
+        }
+    }
+
+    /// Every ` ```kx ` block of the README and docs compiles, or fails with the error its
+    /// `// error: ..` comment announces. Blocks fenced ` ```kx,ignore ` use names defined
+    /// elsewhere and are skipped.
     #[test]
-    fn print_one_compiles_and_has_wasi_exports() {
-        let tmp = std::env::temp_dir().join("knox_test_print_one");
+    fn documented_examples_compile() {
+        let ws = workspace_root();
+        let mut pages = vec![ws.join("README.md")];
+        let mut docs: Vec<PathBuf> = std::fs::read_dir(ws.join("docs"))
+            .expect("read docs")
+            .map(|e| e.expect("docs entry").path())
+            .filter(|p| p.extension().is_some_and(|e| e == "md"))
+            .collect();
+        docs.sort();
+        pages.extend(docs);
+        let tmp = std::env::temp_dir().join("knox_test_documented_examples");
         let _ = std::fs::create_dir(&tmp);
         let main_kx = tmp.join("main.kx");
-        std::fs::write(&main_kx, "fn main() -> () { print(1); }").expect("write main.kx");
-        let wasm = knox_compiler::compile_file(&main_kx).expect("compile");
+        let mut compiled = 0;
+        for page in &pages {
+            let text = std::fs::read_to_string(page).expect("read page");
+            for (i, block) in text.split("```kx\n").skip(1).enumerate() {
+                let block = block.split("```").next().unwrap_or_default();
+                let at = format!("{} (example {})", page.display(), i + 1);
+                std::fs::write(&main_kx, example_program(block)).expect("write main.kx");
+                let result = knox_compiler::compile_file(&main_kx);
+                match block.split_once("// error: ") {
+                    Some((_, rest)) => {
+                        let expected = rest.lines().next().unwrap_or_default();
+                        let diags = result.expect_err(&format!("{} should not compile", at));
+                        assert!(
+                            diags.iter().any(|d| d.message == expected),
+                            "{}: expected error `{}`, got {:?}",
+                            at,
+                            expected,
+                            diags
+                        );
+                    }
+                    None => {
+                        if let Err(diags) = result {
+                            panic!("{} does not compile: {:?}", at, diags);
+                        }
+                    }
+                }
+                compiled += 1;
+            }
+        }
         let _ = std::fs::remove_dir_all(&tmp);
-        assert!(!wasm.is_empty());
+        assert!(compiled > 20, "only {} examples found", compiled);
+    }
+
+    #[test]
+    fn print_one_compiles_and_has_wasi_exports() {
+        let wasm = compile_valid("print_one", "fn main() -> () { print(1); }");
         assert!(
             wasm_has_start_and_memory(&wasm),
             "print(1) wasm must export _start and memory"
@@ -314,20 +506,886 @@ mod tests {
 
     #[test]
     fn hello_world_compiles_and_has_wasi_exports() {
-        let tmp = std::env::temp_dir().join("knox_test_hello");
-        let _ = std::fs::create_dir(&tmp);
-        let main_kx = tmp.join("main.kx");
-        std::fs::write(&main_kx, r#"fn main() -> () { print("Hello, World!"); }"#)
-            .expect("write main.kx");
-        let wasm = knox_compiler::compile_file(&main_kx).expect("compile");
-        let _ = std::fs::remove_dir_all(&tmp);
-        assert!(!wasm.is_empty());
+        let wasm = compile_valid("hello", r#"fn main() -> () { print("Hello, World!"); }"#);
         assert!(
             wasm_has_start_and_memory(&wasm),
             "Hello World wasm must export _start and memory"
         );
     }
 
+    #[test]
+    fn import_demo_compiles_to_valid_wasm() {
+        let ws = workspace_root();
+        let main_kx = ws.join("examples/import_demo/src/main.kx");
+        let wasm = knox_compiler::compile_file(&main_kx).expect("compile");
+        wasmparser::Validator::new()
+            .validate_all(&wasm)
+            .expect("wasm must validate");
+        assert!(wasm_has_start_and_memory(&wasm));
+    }
+
+    /// An error in an imported module is printed with that module's path and source line.
+    #[test]
+    fn module_errors_point_into_the_module() {
+        let tmp = std::env::temp_dir().join("knox_test_module_errors");
+        let _ = std::fs::remove_dir_all(&tmp);
+        std::fs::create_dir_all(tmp.join("src")).expect("create src");
+        std::fs::write(tmp.join("knox.toml"), "[package]\nname = \"m\"\n")
+            .expect("write knox.toml");
+        std::fs::write(
+            tmp.join("src/main.kx"),
+            "import util;\nfn main() -> () { print(util::one()); }\n",
+        )
+        .expect("write main.kx");
+        std::fs::write(
+            tmp.join("src/util.kx"),
+            "export fn one() -> int {\n  let s: string = 1;\n  1\n}\n",
+        )
+        .expect("write util.kx");
+        let compiled =
+            knox_compiler::compile_file_with(&tmp.join("src/main.kx"), &CompileOptions::default());
+        let _ = std::fs::remove_dir_all(&tmp);
+        let diags = compiled.wasm.expect_err("should not compile");
+        let shown = knox_syntax::diagnostics::format_diagnostic(&compiled.files, &diags[0]);
+        assert_eq!(
+            shown,
+            "src/util.kx:2:19: error: mismatched types: expected `string`, found `int`\n  \
+             2 |   let s: string = 1;\n  2 |                   ^"
+        );
+    }
+
+    /// Warnings are returned with the Wasm for the CLI to print, not printed by the compiler.
+    #[test]
+    fn warnings_are_returned_to_the_caller() {
+        let compiled = compile_src_with(
+            "warnings",
+            "fn main() -> () {\n  match 3 { _ => print(1), 3 => print(2) }\n}\n",
+            &CompileOptions::default(),
+        );
+        assert!(compiled.wasm.is_ok());
+        let shown: Vec<String> = compiled
+            .warnings
+            .iter()
+            .map(|d| knox_syntax::diagnostics::format_diagnostic(&compiled.files, d))
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                "main.kx:2:28: warning: unreachable pattern\n  \
+                 2 |   match 3 { _ => print(1), 3 => print(2) }\n  \
+                 2 |                            ^"
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_functions_compile_to_valid_wasm() {
+        compile_valid(
+            "multi_fn",
+            r#"
+fn seven() -> int { return 7; }
+fn echo(s: string, n: int) -> string { print(n); return s; }
+fn main() -> () { print(echo("hi", seven())); }
+"#,
+        );
+    }
+
+    #[test]
+    fn if_else_compiles_to_valid_wasm() {
+        compile_valid(
+            "if_else",
+            r#"
+fn pick(a: bool, b: bool) -> string {
+  if a { return "a"; } else if b { "b" } else { "c" }
+}
+fn main() -> () {
+  let flag = false;
+  let n = if flag { 1 } else { 2 };
+  if true { print(n); } else { print(pick(true, false)); }
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn vars_ops_runs() {
+        let ws = workspace_root();
+        let main_kx = ws.join("examples/vars_ops/src/main.kx");
+        let wasm = knox_compiler::compile_file(&main_kx).expect("compile");
+        wasmparser::Validator::new()
+            .validate_all(&wasm)
+            .expect("wasm must validate");
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        assert_eq!(run.stdout, "3\n6\ntrue\nvars_ops ok\n");
+    }
+
+    /// Binary operators bind by precedence and associate to the left; `&&` and `||` skip
+    /// their right side once the left decides the result.
+    #[test]
+    fn operators_follow_precedence_and_short_circuit() {
+        let wasm = compile_valid(
+            "operators",
+            r#"
+fn t(s: string, b: bool) -> bool { print(s); b }
+fn main() -> () {
+  print(2 + 3 * 4);
+  print((2 + 3) * 4);
+  print(10 - 4 - 3);
+  print(-2 * 3 + 1);
+  print(7 % 3 * 2);
+  print(1 + 2 < 4 && 3 != 2 + 1 || 5 >= 5);
+  print(t("a", false) && t("b", true));
+  print(t("c", true) || t("d", true));
+  print(!t("e", false) && t("f", true));
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        let lines: Vec<&str> = run.stdout.lines().collect();
+        assert_eq!(
+            lines,
+            ["14", "20", "3", "-5", "2", "true", "a", "false", "c", "true", "e", "f", "true"]
+        );
+    }
+
+    #[test]
+    fn loops_compile_to_valid_wasm() {
+        compile_valid(
+            "loops",
+            r#"
+fn main() -> () {
+  let mut total = 0;
+  'outer: for i in 0..10 {
+    let mut j = 0;
+    while j < i {
+      j = j + 1;
+      if j == 3 { continue 'outer; }
+    }
+    total = total + i;
+  }
+  let first = loop { if total > 0 { break total; } };
+  print(first);
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn option_result_compile_to_valid_wasm() {
+        compile_valid(
+            "option_result",
+            r#"
+fn half(n: int) -> Option<int> {
+  if n % 2 == 0 { Some(n / 2) } else { None }
+}
+fn digit(n: int) -> Result<string, string> {
+  if n > 9 { return Err("too big"); }
+  Ok("digit")
+}
+fn check(n: int) -> Result<int, string> {
+  let s = digit(n)?;
+  print(s);
+  Ok(n)
+}
+fn main() -> () {
+  match half(4) { Some(h) => print(h), None => print("odd") };
+  match check(12) { Ok(v) => print(v), Err(e) => print(e) };
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn enums_compile_to_valid_wasm() {
+        compile_valid(
+            "enums",
+            r#"
+enum Shape { Empty, Circle(int), Rect { w: int, h: int, label: string } }
+fn area(s: Shape) -> int {
+  match s {
+    Shape::Empty => 0,
+    Shape::Circle(r) => r * r * 3,
+    Shape::Rect { w, h, .. } => w * h,
+  }
+}
+fn main() -> () {
+  print(area(Shape::Rect { w: 3, h: 4, label: "r" }));
+  print(area(Shape::Circle(2)));
+  print(area(Shape::Empty));
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn rich_patterns_compile_to_valid_wasm() {
+        compile_valid(
+            "rich_patterns",
+            r#"
+struct Point { x: int, y: int }
+enum Shape { Empty, Circle(int), Rect { w: int, h: int } }
+fn size(s: Shape) -> int {
+  match s {
+    Shape::Empty => 0,
+    Shape::Circle(r) if r > 10 => 10,
+    Shape::Circle(r) | Shape::Rect { w: r, h: 0 } => r,
+    Shape::Rect { w, h } => w * h,
+  }
+}
+fn main() -> () {
+  print(size(Shape::Rect { w: 3, h: 4 }));
+  match 7 { 0 => print(0), 1 | 2 => print(1), 3..=9 => print(2), _ => print(3) };
+  let p = main::Point { x: 0, y: 5 };
+  match p { Point { x: 0, y } => print(y), Point { x, .. } => print(x) };
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn methods_compile_to_valid_wasm() {
+        compile_valid(
+            "methods",
+            r#"
+@derive(Copy)
+struct Counter { n: int, label: string @pub(get, set) }
+impl Counter {
+  fn new(label: string) -> Counter { main::Counter { n: 0, label: label } }
+  fn get(&self) -> int { self.n }
+  fn bump(&mut self, by: int) -> () { self.n = self.n + by; }
+  fn twice(self) -> int { self.get() * 2 }
+}
+fn main() -> () {
+  let mut c = Counter::new("clicks");
+  c.bump(3);
+  print(c.twice());
+  c.set_label("taps");
+  print(c.label());
+  print(Counter::twice(c));
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn generics_compile_to_valid_wasm() {
+        compile_valid(
+            "generics",
+            r#"
+struct Pair<A, B> { first: A, second: B @pub(get) }
+impl<A, B> Pair<A, B> {
+  fn swap(self) -> Pair<B, A> { main::Pair { first: self.second, second: self.first } }
+}
+fn id<T>(x: T) -> T { x }
+fn max<T: Ord>(a: T, b: T) -> T { if a < b { b } else { a } }
+fn main() -> () {
+  let p = main::Pair { first: 1, second: "one" }.swap();
+  print(p.first);
+  print(p.second());
+  print(id(true));
+  print(max(3, 9));
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn lists_run() {
+        let wasm = compile_valid(
+            "lists",
+            r#"
+fn fill(xs: &mut List<string>, n: int) -> () {
+  for i in 0..n { xs.push("x"); }
+}
+fn main() -> () {
+  let mut xs = [1, 2, 3];
+  xs.push(4);
+  xs[0] = xs[3];
+  for x in xs { print(x); }
+  match xs.pop() { Some(v) => print(v), None => print("empty"), };
+  let mut names: List<string> = [];
+  fill(&mut names, 10);
+  print(names.len());
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        assert_eq!(run.stdout, "4\n2\n3\n4\n4\n10\n");
+    }
+
+    /// Reading past the end and writing below zero both panic with the index's location.
+    #[test]
+    fn list_index_out_of_bounds_traps() {
+        let cases = [
+            (
+                "fn main() -> () {\n  let xs = [1, 2, 3];\n  print(xs[2]);\n  print(xs[xs.len()]);\n}\n",
+                "3\n",
+                "panic at main.kx:4:9: index out of bounds\n",
+            ),
+            (
+                "fn main() -> () {\n  let mut xs = [1, 2, 3];\n  let i = 0 - 1;\n  xs[i] = 0;\n}\n",
+                "",
+                "panic at main.kx:4:3: index out of bounds\n",
+            ),
+        ];
+        for (src, stdout, stderr) in cases {
+            let run = run_wasm(&compile_valid("list_bounds", src));
+            assert!(run.trapped);
+            assert_eq!(run.stdout, stdout);
+            assert_eq!(run.stderr, stderr);
+        }
+    }
+
+    #[test]
+    fn tuples_compile_to_valid_wasm() {
+        compile_valid(
+            "tuples",
+            r#"
+fn min_max(xs: &List<int>) -> (int, int) {
+  let mut lo = xs[0];
+  let mut hi = xs[0];
+  for x in xs {
+    if x < lo { lo = x; }
+    if x > hi { hi = x; }
+  }
+  (lo, hi)
+}
+fn label(p: (string, int)) -> (int, string) { (p.1, p.0) }
+fn main() -> () {
+  let (lo, hi) = min_max(&[3, 9, 1]);
+  print(hi - lo);
+  let mut named = label(("answer", 42));
+  named.0 = named.0 + 1;
+  match named {
+    (43, s) => print(s),
+    (_, _) => print("other"),
+  }
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn closures_run() {
+        let wasm = compile_valid(
+            "closures",
+            r#"
+fn compose(f: fn(int) -> int, g: fn(int) -> int) -> fn(int) -> int {
+  |x| g(f(x))
+}
+fn double(x: int) -> int { x * 2 }
+fn main() -> () {
+  let offset = 3;
+  let name = "knox";
+  let shift = |x: int| x + offset;
+  let both = compose(shift, double);
+  print(both(1));
+  let greet = || -> string { name };
+  print(greet());
+  let fs = [double, shift];
+  for f in fs { print(f(10)); }
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        let lines: Vec<&str> = run.stdout.lines().collect();
+        assert_eq!(lines, ["8", "knox", "20", "13"]);
+    }
+
+    #[test]
+    fn strings_run() {
+        let wasm = compile_valid(
+            "strings",
+            r#"
+fn greet(name: string) -> string { "Hello, " + name + "!" }
+fn main() -> () {
+  let name = "knox";
+  print(greet(name));
+  print("{name} has {name.len()} bytes");
+  print("abc" < "abd");
+  for part in " a, b ".split(",") { print(part.trim()); }
+  match "12".parse_int() { Some(n) => print(n + 1), None => print(0) };
+  match name { "knox" => print(name.slice(0, 2)), _ => print("?") };
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        let lines: Vec<&str> = run.stdout.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Hello, knox!",
+                "knox has 4 bytes",
+                "true",
+                "a",
+                "b",
+                "13",
+                "kn"
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_compile_to_valid_wasm() {
+        compile_valid(
+            "numbers",
+            r#"
+struct Sample { id: u8, at: i64, value: f64 }
+fn scale(x: f64, by: i64) -> f64 { x * by as f64 }
+fn main() -> () {
+  let s = main::Sample { id: 7, at: 1700000000000, value: 0.25 };
+  let xs: List<u64> = [18446744073709551615, 2];
+  print(xs[0] / xs[1]);
+  print(scale(s.value, s.at));
+  print("{s.id} {s.at + 1} {1.5 / 3.0}");
+  match s.at { 0 => print("epoch"), _ => print(300 as u8) };
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn constants_compile_to_valid_wasm() {
+        compile_valid(
+            "constants",
+            r#"
+const SIZE: int = 4 * 2;
+const LIMIT: u64 = 18446744073709551615;
+const RATIO: f64 = SIZE as f64 / 3.0;
+const NAME: string = "kn" + "ox";
+fn label(n: int) -> string {
+  match n { SIZE => NAME, _ => "other" }
+}
+fn main() -> () {
+  let mut grid = [0u8; SIZE * SIZE];
+  grid[SIZE] = 1;
+  print(grid.len());
+  print(label(8) + label(1) + NAME);
+  print(LIMIT);
+  print(RATIO);
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn type_aliases_and_newtypes_compile_to_valid_wasm() {
+        compile_valid(
+            "newtypes",
+            r#"
+type UserId = i64;
+@derive(Eq, Hash, Clone, Copy)
+struct Meters(int);
+struct Account { flag: u8, owner: UserId, walked: Meters }
+enum Event { Moved(Meters, u8), Idle }
+impl Meters {
+  fn add(&self, other: Meters) -> Meters { Meters(self.0 + other.0) }
+  fn double(&mut self) -> () { self.0 = self.0 * 2; }
+}
+fn total(xs: &List<Meters>) -> Meters {
+  let mut t = Meters(0);
+  for x in xs {
+    let next = t.add(x);
+    t = next;
+  }
+  t
+}
+fn main() -> () {
+  let mut m = Meters(3);
+  m.double();
+  let r = &mut m;
+  r.0 = 10;
+  let a = main::Account { flag: 1, owner: 7, walked: m };
+  print(a.owner + 1);
+  print(total(&[m, Meters(2)]).0);
+  match Event::Moved(a.walked, 2) {
+    Event::Moved(Meters(n), _) => print(n),
+    Event::Idle => print(0),
+  };
+  let mut seen: Map<Meters, string> = Map::new();
+  seen.insert(m.clone(), "ten");
+  let Meters(inner) = m;
+  print(inner);
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn borrowing_compiles_to_valid_wasm() {
+        let ws = workspace_root();
+        let main_kx = ws.join("examples/borrowing/src/main.kx");
+        let wasm = knox_compiler::compile_file(&main_kx).expect("compile");
+        wasmparser::Validator::new()
+            .validate_all(&wasm)
+            .expect("wasm must validate");
+    }
+
+    #[test]
+    fn references_run() {
+        let wasm = compile_valid(
+            "references",
+            r#"
+struct P { x: int, name: string }
+fn inc(x: &mut int) -> () { *x = *x + 1; }
+fn rename(s: &mut string) -> () { *s = "there"; }
+fn bump(p: &mut P) -> () {
+  p.x = p.x + 10;
+  inc(&mut p.x);
+}
+fn main() -> () {
+  let mut a = 1;
+  let mut t = (1, 2.5);
+  let mut xs = [1, 2];
+  let mut p = main::P { x: 1, name: "p" };
+  inc(&mut a);
+  inc(&mut t.0);
+  inc(&mut xs[1]);
+  inc(&mut p.x);
+  rename(&mut p.name);
+  let q = &p;
+  print(a + t.0 + xs[1] + q.x + *&5);
+  print(a);
+  print(t.0);
+  print(xs[1]);
+  print(p.name);
+  bump(&mut p);
+  print(p.x);
+  let r = &mut a;
+  *r = *r * 10;
+  print(a);
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        let lines: Vec<&str> = run.stdout.lines().collect();
+        assert_eq!(lines, ["14", "2", "2", "3", "there", "13", "20"]);
+    }
+
+    #[test]
+    fn resources_compile_to_valid_wasm() {
+        compile_valid(
+            "resources",
+            r#"
+resource struct Coin { value: int }
+
+resource struct Vault { coins: List<Coin> }
+
+fn mint(v: int) -> Coin { main::Coin { value: v } }
+
+fn burn(c: Coin) -> int {
+  let Coin { value } = c;
+  value
+}
+
+fn merge(a: Coin, b: Coin) -> Coin {
+  let Coin { value: x } = a;
+  let Coin { value: y } = b;
+  mint(x + y)
+}
+
+fn peek(c: &Coin) -> int { c.value }
+
+fn main() -> () {
+  let c = mint(5);
+  print(peek(&c));
+  print(c.value);
+  let d = merge(c, mint(2));
+  let mut purse = [mint(1)];
+  purse.push(d);
+  let total = match purse.pop() {
+    Some(k) => burn(k),
+    None => 0,
+  };
+  let rest = match purse.pop() { Some(k) => burn(k), None => 0 };
+  print(total + rest);
+  for k in purse { print(burn(k)); }
+  let o: Option<Coin> = None;
+  match o { Some(k) => { burn(k); }, None => {} };
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn moves_and_copies_run() {
+        let wasm = compile_valid(
+            "moves",
+            r#"
+@derive(Clone, Copy)
+struct V { x: int, y: int }
+
+@derive(Clone, Copy)
+struct Seg { a: V, b: V }
+
+@derive(Clone)
+struct Bag { name: string, v: V }
+
+struct Owner { name: string }
+
+struct Title { owner: Owner @pub(get) }
+
+fn take(o: Owner) -> string { o.name }
+
+fn len(s: Seg) -> int { s.b.x - s.a.x }
+
+fn main() -> () {
+  let mut v = main::V { x: 1, y: 2 };
+  let mut w = v;
+  w.x = 10;
+  print(v.x);
+  print(w.x);
+  let s = main::Seg { a: v, b: w };
+  let mut t = s;
+  t.a.x = 5;
+  print(s.a.x);
+  print(len(t));
+  print(len(s));
+  let segs = [s, t];
+  let mut u = segs[1];
+  u.b.y = 99;
+  print(segs[1].b.y);
+  let bag = main::Bag { name: "b", v: v };
+  let mut bag2 = bag.clone();
+  bag2.v.x = 7;
+  print(bag.v.x);
+  print(bag2.v.x);
+  let o = main::Owner { name: "ann" };
+  let r = &o;
+  print(r.name);
+  let p = o;
+  let t = main::Title { owner: p };
+  print(t.owner().name);
+  let tup = (v, 3);
+  let mut tup2 = tup;
+  tup2.0.y = 50;
+  print(tup.0.y);
+  let mut n = 1;
+  let at = v;
+  let grab = || -> int { n + at.x };
+  n = 100;
+  v.x = 100;
+  print(grab());
+  print(n + v.x);
+  let bag3 = bag2;
+  let show = || -> string { bag3.name };
+  print(show());
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        let lines: Vec<&str> = run.stdout.lines().collect();
+        assert_eq!(
+            lines,
+            ["1", "10", "1", "5", "9", "2", "1", "7", "ann", "ann", "2", "2", "200", "b"]
+        );
+    }
+
+    /// `get` and `for` read structs in place, so changing a clone leaves the collection as it was.
+    #[test]
+    fn collection_items_are_not_shared() {
+        let wasm = compile_valid(
+            "collection_items",
+            r#"
+@derive(Clone, Eq, Hash)
+struct P { x: int }
+enum Slot { Full(P), Empty }
+fn main() -> () {
+  let xs = [main::P { x: 1 }, main::P { x: 2 }];
+  match xs.get(0) {
+    Some(q) => {
+      let mut c = q.clone();
+      c.x = 9;
+      print(c.x);
+    },
+    None => {},
+  };
+  for p in xs {
+    let mut c = p.clone();
+    c.x = c.x * 10;
+    print(c.x);
+  }
+  print(xs[0].x + xs[1].x);
+  let mut m: Map<P, int> = Map::new();
+  m.insert(main::P { x: 1 }, 1);
+  m.insert(main::P { x: 2 }, 2);
+  let mut next: List<P> = [];
+  for k in m {
+    let mut c = k.clone();
+    c.x = c.x + 10;
+    next.push(c);
+  }
+  for k in next { m.insert(k.clone(), 3); }
+  print(m.len());
+  print(m.contains(main::P { x: 1 }));
+  print(m.contains(main::P { x: 12 }));
+  let s = Slot::Full(main::P { x: 5 });
+  let t = s;
+  match t { Slot::Full(p) => print(p.x), Slot::Empty => print(0) };
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        let lines: Vec<&str> = run.stdout.lines().collect();
+        assert_eq!(lines, ["9", "10", "20", "3", "4", "true", "true", "5"]);
+    }
+
+    #[test]
+    fn overflow_checks_compile_to_valid_wasm() {
+        let src = r#"
+fn mean(xs: List<i64>) -> i64 {
+  let mut total: i64 = 0;
+  for x in xs { total = total + x; }
+  total / xs.len() as i64
+}
+fn main() -> () {
+  let b: u8 = 250;
+  print(b.wrapping_add(10));
+  print(2147483647.saturating_mul(-3) - 1 * 7 % 4);
+  match (9 as u64).checked_sub(10) { Some(v) => print(v), None => print(-mean([1, 2])) };
+}
+"#;
+        for overflow_checks in [true, false] {
+            let options = CompileOptions { overflow_checks };
+            let wasm = compile_src_with("overflow", src, &options)
+                .wasm
+                .expect("compile");
+            wasmparser::Validator::new()
+                .validate_all(&wasm)
+                .expect("wasm must validate");
+        }
+    }
+
+    /// Overflow traps with the location of the operator, and wraps when checks are off.
+    #[test]
+    fn overflow_traps_unless_checks_are_off() {
+        let src = "fn main() -> () {\n  let big: i32 = 2147483647;\n  print(big + 1);\n}\n";
+        let wasm = compile_src_with("overflow_trap", src, &CompileOptions::default())
+            .wasm
+            .expect("compile");
+        let run = run_wasm(&wasm);
+        assert!(run.trapped);
+        assert_eq!(run.stdout, "");
+        assert_eq!(
+            run.stderr,
+            "panic at main.kx:3:9: attempt to add with overflow\n"
+        );
+        let options = CompileOptions {
+            overflow_checks: false,
+        };
+        let wasm = compile_src_with("overflow_wrap", src, &options)
+            .wasm
+            .expect("compile");
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped);
+        assert_eq!(run.stdout, "-2147483648\n");
+    }
+
+    /// Checked operators evaluate each operand once, left to right, before dividing by zero.
+    #[test]
+    fn checked_arithmetic_runs_in_order() {
+        let wasm = compile_valid(
+            "arith_order",
+            r#"
+fn p(n: int) -> int { print(n); n }
+fn main() -> () {
+  let big: i32 = 2147483647;
+  print(big.wrapping_add(1));
+  print(big.saturating_add(1));
+  match big.checked_add(1) { Some(v) => print(v), None => print("none") };
+  print(p(1) + p(2) * p(3) - p(4) / p(2));
+  print(7 / p(0));
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(run.trapped);
+        assert_eq!(
+            run.stdout,
+            "-2147483648\n2147483647\nnone\n1\n2\n3\n4\n2\n5\n0\n"
+        );
+        assert_eq!(
+            run.stderr,
+            "panic at main.kx:9:9: attempt to divide by zero\n"
+        );
+    }
+
+    #[test]
+    fn maps_run() {
+        let wasm = compile_valid(
+            "maps",
+            r#"
+@derive(Eq, Hash)
+struct Point { x: int, y: int }
+fn main() -> () {
+  let mut ages: Map<string, int> = Map::new();
+  ages.insert("ada", 36);
+  ages.insert("alan", 41);
+  for name in ages { print(name); }
+  match ages.remove("ada") { Some(v) => print(v), None => print("missing"), };
+  ages.insert("alan", 42);
+  ages.insert("ada", 37);
+  for name in ages { print(name); }
+  match ages.get("alan") { Some(v) => print(v), None => print("missing"), };
+  print(ages.contains("bob"));
+  let mut grid: Set<Point> = Set::new();
+  for i in 0..20 { grid.insert(main::Point { x: i, y: i }); }
+  print(grid.contains(main::Point { x: 3, y: 3 }));
+  print(grid.len());
+}
+"#,
+        );
+        let run = run_wasm(&wasm);
+        assert!(!run.trapped, "{}", run.stderr);
+        let lines: Vec<&str> = run.stdout.lines().collect();
+        assert_eq!(
+            lines,
+            ["ada", "alan", "36", "alan", "ada", "42", "false", "true", "20"]
+        );
+    }
+
+    #[test]
+    fn traits_compile_to_valid_wasm() {
+        compile_valid(
+            "traits",
+            r#"
+trait Shape {
+  fn area(&self) -> int;
+  fn double_area(&self) -> int { self.area() * 2 }
+}
+struct P { x: int, y: int }
+impl Shape for P {
+  fn area(&self) -> int { self.x * self.y }
+}
+impl Display for P {
+  fn show(&self) -> string { "P" }
+}
+impl Eq for P {
+  fn eq(&self, other: &Self) -> bool { self.x == other.x && self.y == other.y }
+}
+impl Ord for P {
+  fn cmp(&self, other: &Self) -> int { self.x - other.x }
+}
+fn main() -> () {
+  let a = main::P { x: 2, y: 3 };
+  let b = main::P { x: 4, y: 1 };
+  print(a.double_area());
+  print(a);
+  print(a != b);
+  print(a < b);
+}
+"#,
+        );
+    }
+
     /// Runs the exact command the user runs: `knox run examples/accessors_generic/src/main.kx` from monorepo root.
     #[test]
     #[ignore = "requires wasmtime on PATH; run with: cargo test -p knox_cli -- --ignored accessors_generic_run"]
@@ -1,7 +1,8 @@
 //! Wasm emitter for Knox. Emits WebAssembly (wasm-wasi) from typed AST or from IR.
 
-use knox_syntax::ast::Root;
-use knox_syntax::ir::{IrFunction, IrInstr, Program};
+use knox_syntax::ast::{Root, Type};
+use knox_syntax::ir::{IrBinOp, IrFunction, IrInstr, Program};
+use knox_syntax::{LIST_CAP_OFFSET, LIST_DATA_OFFSET, LIST_LEN_OFFSET};
 use wasm_encoder::{BlockType, *};
 
 fn memarg(align: u32, offset: u64) -> MemArg {
@@ -12,6 +13,26 @@ fn memarg(align: u32, offset: u64) -> MemArg {
     }
 }
 
+/// Parameter and result types of a Wasm function type.
+type FuncSig = (Vec<ValType>, Vec<ValType>);
+
+/// Runtime function indices: the two WASI imports, then the helpers emitted before user functions.
+const PRINT_INT: u32 = 2;
+const PRINT_STR: u32 = 3;
+const ALLOC: u32 = 4;
+const LIST_PUSH: u32 = 5;
+const PANIC: u32 = 6;
+const STR_EQ: u32 = 7;
+const STR_HASH: u32 = 8;
+const LIST_REMOVE: u32 = 9;
+const STR_CONCAT: u32 = 10;
+const STR_CMP: u32 = 11;
+const STR_FIND: u32 = 12;
+const STR_TRIM: u32 = 13;
+const INT_TO_STR: u32 = 14;
+const STR_PARSE_INT: u32 = 15;
+const F64_TO_STR: u32 = 16;
+
 /// Emit Wasm from IR. Single path: no pattern matching; works for any valid Program.
 /// Uses fd_write for print (itoa for int, no NUL bytes). _start calls Knox main.
 pub fn emit_from_ir(program: &Program, debug: bool) -> Vec<u8> {
@@ -43,7 +64,8 @@ pub fn emit_from_ir(program: &Program, debug: bool) -> Vec<u8> {
     const IOV_OFF: u32 = ITOA_OFF + 12;
     const NEWLINE_OFF: u32 = IOV_OFF + 16;
     const NWRITTEN_OFF: u32 = NEWLINE_OFF + 4;
-    const BUMP_INITIAL: u32 = NWRITTEN_OFF + 4;
+    // The heap starts 8-byte aligned; `alloc` keeps it that way.
+    const BUMP_INITIAL: u32 = (NWRITTEN_OFF + 4).next_multiple_of(8);
 
     let mut string_offsets: Vec<u32> = Vec::with_capacity(program.string_data.len());
     let mut off = 0u32;
@@ -55,18 +77,42 @@ pub fn emit_from_ir(program: &Program, debug: bool) -> Vec<u8> {
 
     let mut module = Module::new();
 
+    // Fixed types first; one interned type per distinct IR signature after them.
+    let mut type_sigs: Vec<FuncSig> = vec![
+        (vec![ValType::I32; 4], vec![ValType::I32]), // fd_write
+        (vec![ValType::I32], vec![]),                // proc_exit
+        (vec![ValType::I32], vec![]),                // print_int
+        (vec![ValType::I32, ValType::I32], vec![]),  // print_str
+        (vec![], vec![]),                            // () -> ()
+        (vec![ValType::I32], vec![ValType::I32]),    // alloc
+        (vec![ValType::I32, ValType::I32], vec![ValType::I32]), // list_push
+        (vec![ValType::I32; 3], vec![]),             // list_remove
+        (vec![ValType::I32; 4], vec![ValType::I32; 2]), // str_concat
+        (vec![ValType::I32; 5], vec![ValType::I32]), // str_find
+        (vec![ValType::I32; 2], vec![ValType::I32; 2]), // str_trim, str_parse_int
+        (vec![ValType::I64, ValType::I32], vec![ValType::I32; 2]), // int_to_str
+        (vec![ValType::F64], vec![ValType::I32; 2]), // f64_to_str
+    ];
+    let func_types: Vec<u32> = program
+        .functions
+        .iter()
+        .map(|f| intern_sig(&mut type_sigs, &f.params, &f.results))
+        .collect();
+    // Signatures of indirect calls, looked up again when their bodies are emitted.
+    for f in &program.functions {
+        for instr in &f.body {
+            if let IrInstr::CallIndirect {
+                params, results, ..
+            } = instr
+            {
+                intern_sig(&mut type_sigs, params, results);
+            }
+        }
+    }
     let mut types = TypeSection::new();
-    types.function(
-        [ValType::I32, ValType::I32, ValType::I32, ValType::I32],
-        [ValType::I32],
-    );
-    types.function([ValType::I32], []); // proc_exit
-    types.function([ValType::I32], []); // print_int
-    types.function([ValType::I32, ValType::I32], []); // print_str
-    types.function([], []); // () -> ()
-    types.function([ValType::I32], [ValType::I32]); // getter int
-    types.function([ValType::I32, ValType::I32], []); // setter
-    types.function([ValType::I32], [ValType::I32, ValType::I32]); // getter string
+    for (params, results) in &type_sigs {
+        types.function(params.iter().copied(), results.iter().copied());
+    }
     module.section(&types);
 
     let mut imports = ImportSection::new();
@@ -85,13 +131,34 @@ pub fn emit_from_ir(program: &Program, debug: bool) -> Vec<u8> {
     let mut functions = FunctionSection::new();
     functions.function(2); // print_int
     functions.function(3); // print_str
-    for f in &program.functions {
-        let ty = ir_func_type_index(f);
-        functions.function(ty);
+    functions.function(5); // alloc
+    functions.function(6); // list_push
+    functions.function(3); // panic
+    functions.function(0); // str_eq
+    functions.function(6); // str_hash
+    functions.function(7); // list_remove
+    functions.function(8); // str_concat
+    functions.function(0); // str_cmp
+    functions.function(9); // str_find
+    functions.function(10); // str_trim
+    functions.function(11); // int_to_str
+    functions.function(10); // str_parse_int
+    functions.function(12); // f64_to_str
+    for ty in &func_types {
+        functions.function(*ty);
     }
     functions.function(4); // _start
     module.section(&functions);
 
+    // Every IR function is in the table at its own index; closures call through it.
+    let mut tables = TableSection::new();
+    tables.table(TableType {
+        element_type: ValType::FuncRef,
+        minimum: program.functions.len() as u32,
+        maximum: Some(program.functions.len() as u32),
+    });
+    module.section(&tables);
+
     let mut memories = MemorySection::new();
     memories.memory(MemoryType {
         minimum: 1,
@@ -111,8 +178,8 @@ pub fn emit_from_ir(program: &Program, debug: bool) -> Vec<u8> {
     );
     module.section(&globals);
 
-    let main_idx = 4u32;
-    let start_idx = 4 + program.functions.len() as u32;
+    let main_idx = F64_TO_STR + 1;
+    let start_idx = main_idx + program.functions.len() as u32;
 
     let mut exports = ExportSection::new();
     exports.export("memory", ExportKind::Memory, 0);
@@ -124,9 +191,19 @@ pub fn emit_from_ir(program: &Program, debug: bool) -> Vec<u8> {
         function_index: start_idx,
     });
 
+    let table_funcs: Vec<u32> = (main_idx..start_idx).collect();
+    let mut elements = ElementSection::new();
+    elements.active(
+        None,
+        &ConstExpr::i32_const(0),
+        ValType::FuncRef,
+        Elements::Functions(&table_funcs),
+    );
+    module.section(&elements);
+
     let mut codes = CodeSection::new();
 
-    let mut print_int_fn = Function::new([(1, ValType::I32)]);
+    let mut print_int_fn = Function::new([(3, ValType::I32)]);
     emit_print_int_body(
         &mut print_int_fn,
         ITOA_OFF,
@@ -137,21 +214,78 @@ pub fn emit_from_ir(program: &Program, debug: bool) -> Vec<u8> {
     codes.function(&print_int_fn);
 
     let mut print_str_fn = Function::new(vec![]);
-    emit_print_str_body(&mut print_str_fn, IOV_OFF, NEWLINE_OFF, NWRITTEN_OFF);
+    emit_write_line(&mut print_str_fn, 1, IOV_OFF, NEWLINE_OFF, NWRITTEN_OFF);
+    print_str_fn.instruction(&Instruction::End);
     codes.function(&print_str_fn);
 
-    for (ir_idx, f) in program.functions.iter().enumerate() {
+    let mut alloc_fn = Function::new([(2, ValType::I32)]);
+    emit_alloc_body(&mut alloc_fn);
+    codes.function(&alloc_fn);
+
+    let mut list_push_fn = Function::new([(3, ValType::I32)]);
+    emit_list_push_body(&mut list_push_fn);
+    codes.function(&list_push_fn);
+
+    let mut panic_fn = Function::new(vec![]);
+    emit_write_line(&mut panic_fn, 2, IOV_OFF, NEWLINE_OFF, NWRITTEN_OFF);
+    panic_fn.instruction(&Instruction::Unreachable);
+    panic_fn.instruction(&Instruction::End);
+    codes.function(&panic_fn);
+
+    let mut str_eq_fn = Function::new([(1, ValType::I32)]);
+    emit_str_eq_body(&mut str_eq_fn);
+    codes.function(&str_eq_fn);
+
+    let mut str_hash_fn = Function::new([(1, ValType::I32)]);
+    emit_str_hash_body(&mut str_hash_fn);
+    codes.function(&str_hash_fn);
+
+    let mut list_remove_fn = Function::new([(2, ValType::I32)]);
+    emit_list_remove_body(&mut list_remove_fn);
+    codes.function(&list_remove_fn);
+
+    let mut str_concat_fn = Function::new([(1, ValType::I32)]);
+    emit_str_concat_body(&mut str_concat_fn);
+    codes.function(&str_concat_fn);
+
+    let mut str_cmp_fn = Function::new([(3, ValType::I32)]);
+    emit_str_cmp_body(&mut str_cmp_fn);
+    codes.function(&str_cmp_fn);
+
+    let mut str_find_fn = Function::new([(1, ValType::I32)]);
+    emit_str_find_body(&mut str_find_fn);
+    codes.function(&str_find_fn);
+
+    let mut str_trim_fn = Function::new([(1, ValType::I32)]);
+    emit_str_trim_body(&mut str_trim_fn);
+    codes.function(&str_trim_fn);
+
+    let mut int_to_str_fn = Function::new([(3, ValType::I32), (1, ValType::I64)]);
+    emit_int_to_str_body(&mut int_to_str_fn);
+    codes.function(&int_to_str_fn);
+
+    let mut str_parse_int_fn = Function::new([(4, ValType::I32)]);
+    emit_str_parse_int_body(&mut str_parse_int_fn);
+    codes.function(&str_parse_int_fn);
+
+    let mut f64_to_str_fn =
+        Function::new([(3, ValType::I32), (3, ValType::I64), (3, ValType::I32)]);
+    emit_f64_to_str_body(&mut f64_to_str_fn);
+    codes.function(&f64_to_str_fn);
+
+    for f in &program.functions {
         let mut wf = Function::new(
             f.locals
                 .iter()
-                .map(|_| (1u32, ValType::I32))
+                .map(|t| (1u32, val_type(t)))
                 .collect::<Vec<_>>(),
         );
         emit_ir_function(
             f,
             program,
             &string_offsets,
-            main_idx + ir_idx as u32,
+            &type_sigs,
+            main_idx,
             &mut wf,
             debug,
         );
@@ -183,23 +317,135 @@ pub fn emit_from_ir(program: &Program, debug: bool) -> Vec<u8> {
     module.finish()
 }
 
-fn ir_func_type_index(f: &IrFunction) -> u32 {
-    let p = &f.params;
-    let has_return_int = f.body.iter().any(|i| matches!(i, IrInstr::ReturnInt(_)));
-    let has_return_str = f.body.iter().any(|i| matches!(i, IrInstr::ReturnStr(_, _)));
-    if p.is_empty() {
-        4
-    } else if p.len() == 1 && has_return_str {
-        7
-    } else if p.len() == 1 && has_return_int {
-        5
-    } else if p.len() == 2 {
-        6
-    } else {
-        4
+fn bin_instruction(op: IrBinOp) -> Instruction<'static> {
+    match op {
+        IrBinOp::Add => Instruction::I32Add,
+        IrBinOp::Sub => Instruction::I32Sub,
+        IrBinOp::Mul => Instruction::I32Mul,
+        IrBinOp::DivS => Instruction::I32DivS,
+        IrBinOp::DivU => Instruction::I32DivU,
+        IrBinOp::RemS => Instruction::I32RemS,
+        IrBinOp::RemU => Instruction::I32RemU,
+        IrBinOp::Eq => Instruction::I32Eq,
+        IrBinOp::Ne => Instruction::I32Ne,
+        IrBinOp::LtS => Instruction::I32LtS,
+        IrBinOp::LtU => Instruction::I32LtU,
+        IrBinOp::LeS => Instruction::I32LeS,
+        IrBinOp::LeU => Instruction::I32LeU,
+        IrBinOp::GtS => Instruction::I32GtS,
+        IrBinOp::GtU => Instruction::I32GtU,
+        IrBinOp::GeS => Instruction::I32GeS,
+        IrBinOp::GeU => Instruction::I32GeU,
+        IrBinOp::And => Instruction::I32And,
+        IrBinOp::Xor => Instruction::I32Xor,
+        IrBinOp::ShrU => Instruction::I32ShrU,
     }
 }
 
+fn bin_i64_instruction(op: IrBinOp) -> Instruction<'static> {
+    match op {
+        IrBinOp::Add => Instruction::I64Add,
+        IrBinOp::Sub => Instruction::I64Sub,
+        IrBinOp::Mul => Instruction::I64Mul,
+        IrBinOp::DivS => Instruction::I64DivS,
+        IrBinOp::DivU => Instruction::I64DivU,
+        IrBinOp::RemS => Instruction::I64RemS,
+        IrBinOp::RemU => Instruction::I64RemU,
+        IrBinOp::Eq => Instruction::I64Eq,
+        IrBinOp::Ne => Instruction::I64Ne,
+        IrBinOp::LtS => Instruction::I64LtS,
+        IrBinOp::LtU => Instruction::I64LtU,
+        IrBinOp::LeS => Instruction::I64LeS,
+        IrBinOp::LeU => Instruction::I64LeU,
+        IrBinOp::GtS => Instruction::I64GtS,
+        IrBinOp::GtU => Instruction::I64GtU,
+        IrBinOp::GeS => Instruction::I64GeS,
+        IrBinOp::GeU => Instruction::I64GeU,
+        IrBinOp::And => Instruction::I64And,
+        IrBinOp::Xor => Instruction::I64Xor,
+        IrBinOp::ShrU => Instruction::I64ShrU,
+    }
+}
+
+fn bin_f64_instruction(op: IrBinOp) -> Instruction<'static> {
+    match op {
+        IrBinOp::Add => Instruction::F64Add,
+        IrBinOp::Sub => Instruction::F64Sub,
+        IrBinOp::Mul => Instruction::F64Mul,
+        IrBinOp::DivS => Instruction::F64Div,
+        IrBinOp::Eq => Instruction::F64Eq,
+        IrBinOp::Ne => Instruction::F64Ne,
+        IrBinOp::LtS => Instruction::F64Lt,
+        IrBinOp::LeS => Instruction::F64Le,
+        IrBinOp::GtS => Instruction::F64Gt,
+        IrBinOp::GeS => Instruction::F64Ge,
+        _ => unreachable!("no f64 instruction for {:?}", op),
+    }
+}
+
+/// Instructions converting a number of type `from` on the stack to `to` (see `IrInstr::Cast`).
+fn cast_instructions(from: &Type, to: &Type) -> Vec<Instruction<'static>> {
+    let wide = |t: &Type| matches!(t, Type::I64 | Type::U64);
+    match (from, to) {
+        (Type::F64, Type::F64) => vec![],
+        // Clamp first so that anything past 255 saturates like the other conversions.
+        (Type::F64, Type::U8) => vec![
+            Instruction::F64Const(255.0),
+            Instruction::F64Min,
+            Instruction::I32TruncSatF64U,
+        ],
+        (Type::F64, Type::I64) => vec![Instruction::I64TruncSatF64S],
+        (Type::F64, Type::U64) => vec![Instruction::I64TruncSatF64U],
+        (Type::F64, _) => vec![Instruction::I32TruncSatF64S],
+        (Type::Int, Type::F64) => vec![Instruction::F64ConvertI32S],
+        (Type::I64, Type::F64) => vec![Instruction::F64ConvertI64S],
+        (Type::U64, Type::F64) => vec![Instruction::F64ConvertI64U],
+        (_, Type::F64) => vec![Instruction::F64ConvertI32U],
+        (f, t) if wide(f) && wide(t) => vec![],
+        (f, Type::U8) if wide(f) => vec![
+            Instruction::I32WrapI64,
+            Instruction::I32Const(0xff),
+            Instruction::I32And,
+        ],
+        (f, _) if wide(f) => vec![Instruction::I32WrapI64],
+        (Type::Int, t) if wide(t) => vec![Instruction::I64ExtendI32S],
+        (_, t) if wide(t) => vec![Instruction::I64ExtendI32U],
+        (_, Type::U8) => vec![Instruction::I32Const(0xff), Instruction::I32And],
+        _ => vec![],
+    }
+}
+
+/// Load of a value of slot type `ty` from the address on the stack.
+fn load_instruction(ty: &Type) -> Instruction<'static> {
+    match ty {
+        Type::I64 | Type::U64 => Instruction::I64Load(memarg(3, 0)),
+        Type::F64 => Instruction::F64Load(memarg(3, 0)),
+        Type::U8 => Instruction::I32Load8U(memarg(0, 0)),
+        _ => Instruction::I32Load(memarg(2, 0)),
+    }
+}
+
+/// Store of a value of slot type `ty` (address, then value, on the stack).
+fn store_instruction(ty: &Type) -> Instruction<'static> {
+    match ty {
+        Type::I64 | Type::U64 => Instruction::I64Store(memarg(3, 0)),
+        Type::F64 => Instruction::F64Store(memarg(3, 0)),
+        Type::U8 => Instruction::I32Store8(memarg(0, 0)),
+        _ => Instruction::I32Store(memarg(2, 0)),
+    }
+}
+
+/// Wasm value type of one IR slot.
+fn val_type(ty: &Type) -> ValType {
+    match ty {
+        Type::I64 | Type::U64 => ValType::I64,
+        Type::F64 => ValType::F64,
+        _ => ValType::I32,
+    }
+}
+
+/// print_int(n): itoa into the 12-byte scratch buffer (digits written backwards, then the sign), then fd_write.
+/// Locals: 1 = write position, 2 = is negative, 3 = remaining magnitude (treated as unsigned).
 fn emit_print_int_body(
     f: &mut wasm_encoder::Function,
     itoa_off: u32,
@@ -207,50 +453,56 @@ fn emit_print_int_body(
     newline_off: u32,
     nwritten_off: u32,
 ) {
-    let d0 = 48i32;
-    // Store order: address then value (Wasm spec pops value then address).
-    f.instruction(&Instruction::LocalGet(0));
-    f.instruction(&Instruction::I32Const(10));
-    f.instruction(&Instruction::I32RemS);
-    f.instruction(&Instruction::I32Const(d0));
-    f.instruction(&Instruction::I32Add);
+    let end = itoa_off as i32 + 12;
+    f.instruction(&Instruction::I32Const(end));
     f.instruction(&Instruction::LocalSet(1));
-    f.instruction(&Instruction::I32Const(itoa_off as i32 + 1));
-    f.instruction(&Instruction::LocalGet(1));
-    f.instruction(&Instruction::I32Store8(memarg(0, 0)));
     f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::I32LtS);
+    f.instruction(&Instruction::LocalSet(2));
+    // |n| as unsigned: 0 - n wraps i32::MIN onto its own magnitude.
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::Select);
+    f.instruction(&Instruction::LocalSet(3));
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalTee(1));
+    // Store order: address then value (Wasm spec pops value then address).
+    f.instruction(&Instruction::LocalGet(3));
     f.instruction(&Instruction::I32Const(10));
-    f.instruction(&Instruction::I32DivS);
-    f.instruction(&Instruction::I32Const(d0));
+    f.instruction(&Instruction::I32RemU);
+    f.instruction(&Instruction::I32Const(48));
     f.instruction(&Instruction::I32Add);
-    f.instruction(&Instruction::LocalSet(1));
-    f.instruction(&Instruction::I32Const(itoa_off as i32));
-    f.instruction(&Instruction::LocalGet(1));
     f.instruction(&Instruction::I32Store8(memarg(0, 0)));
-    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(3));
     f.instruction(&Instruction::I32Const(10));
-    f.instruction(&Instruction::I32LtS);
+    f.instruction(&Instruction::I32DivU);
+    f.instruction(&Instruction::LocalTee(3));
+    f.instruction(&Instruction::BrIf(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(2));
     f.instruction(&Instruction::If(BlockType::Empty));
-    f.instruction(&Instruction::I32Const((itoa_off + 1) as i32));
-    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
-    f.instruction(&Instruction::LocalSet(1));
-    f.instruction(&Instruction::I32Const(itoa_off as i32));
     f.instruction(&Instruction::LocalGet(1));
-    f.instruction(&Instruction::I32Store8(memarg(0, 0)));
-    f.instruction(&Instruction::End);
     f.instruction(&Instruction::I32Const(1));
-    f.instruction(&Instruction::LocalGet(0));
-    f.instruction(&Instruction::I32Const(10));
-    f.instruction(&Instruction::I32GeS);
-    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Sub);
     f.instruction(&Instruction::LocalTee(1));
-    f.instruction(&Instruction::Drop);
+    f.instruction(&Instruction::I32Const(b'-' as i32));
+    f.instruction(&Instruction::I32Store8(memarg(0, 0)));
+    f.instruction(&Instruction::End);
     // First fd_write: digits only (1 iov)
     f.instruction(&Instruction::I32Const(iov_off as i32));
-    f.instruction(&Instruction::I32Const(itoa_off as i32));
+    f.instruction(&Instruction::LocalGet(1));
     f.instruction(&Instruction::I32Store(memarg(2, 0)));
     f.instruction(&Instruction::I32Const(iov_off as i32 + 4));
+    f.instruction(&Instruction::I32Const(end));
     f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Sub);
     f.instruction(&Instruction::I32Store(memarg(2, 0)));
     f.instruction(&Instruction::I32Const(1)); // fd 1 (stdout)
     f.instruction(&Instruction::I32Const(iov_off as i32));
@@ -274,8 +526,11 @@ fn emit_print_int_body(
     f.instruction(&Instruction::End);
 }
 
-fn emit_print_str_body(
+/// Write the string (ptr, len) in locals 0 and 1 followed by a newline to `fd`; used by
+/// print_str (stdout) and panic (stderr). Leaves the function body open.
+fn emit_write_line(
     f: &mut wasm_encoder::Function,
+    fd: i32,
     iov_off: u32,
     newline_off: u32,
     nwritten_off: u32,
@@ -287,7 +542,7 @@ fn emit_print_str_body(
     f.instruction(&Instruction::I32Const(iov_off as i32 + 4));
     f.instruction(&Instruction::LocalGet(1));
     f.instruction(&Instruction::I32Store(memarg(2, 0)));
-    f.instruction(&Instruction::I32Const(1)); // fd 1 (stdout)
+    f.instruction(&Instruction::I32Const(fd));
     f.instruction(&Instruction::I32Const(iov_off as i32));
     f.instruction(&Instruction::I32Const(1));
     f.instruction(&Instruction::I32Const(nwritten_off as i32));
@@ -300,19 +555,748 @@ fn emit_print_str_body(
     f.instruction(&Instruction::I32Const(iov_off as i32 + 4));
     f.instruction(&Instruction::I32Const(1));
     f.instruction(&Instruction::I32Store(memarg(2, 0)));
-    f.instruction(&Instruction::I32Const(1)); // fd 1 (stdout)
+    f.instruction(&Instruction::I32Const(fd));
     f.instruction(&Instruction::I32Const(iov_off as i32));
     f.instruction(&Instruction::I32Const(1));
     f.instruction(&Instruction::I32Const(nwritten_off as i32));
     f.instruction(&Instruction::Call(0));
     f.instruction(&Instruction::Drop);
+}
+
+/// alloc(size) -> ptr: bump-allocate `size` bytes rounded up to 8, growing memory when the heap
+/// runs past its end. Memory is never reused, so the bytes are still zero.
+/// Locals: 1 = result, 2 = new heap end.
+fn emit_alloc_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::GlobalGet(0));
+    f.instruction(&Instruction::LocalTee(1));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Const(7));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Const(!7));
+    f.instruction(&Instruction::I32And);
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalTee(2));
+    f.instruction(&Instruction::GlobalSet(0));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::MemorySize(0));
+    f.instruction(&Instruction::I32Const(16));
+    f.instruction(&Instruction::I32Shl);
+    f.instruction(&Instruction::I32GtU);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    // Grow by enough 64 KiB pages to cover the new end; trap if the host refuses.
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::MemorySize(0));
+    f.instruction(&Instruction::I32Const(16));
+    f.instruction(&Instruction::I32Shl);
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::I32Const(0xffff));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Const(16));
+    f.instruction(&Instruction::I32ShrU);
+    f.instruction(&Instruction::MemoryGrow(0));
+    f.instruction(&Instruction::I32Const(-1));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::Unreachable);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::End);
+}
+
+/// list_push(list, stride) -> addr: append an element slot to the list header at `list` and return
+/// its address. A full list moves its elements to a buffer of twice the capacity (at least 4).
+/// Locals: 2 = length, 3 = capacity, 4 = new buffer.
+fn emit_list_push_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(2, LIST_LEN_OFFSET as u64)));
+    f.instruction(&Instruction::LocalSet(2));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(2, LIST_CAP_OFFSET as u64)));
+    f.instruction(&Instruction::LocalTee(3));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(4));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Shl);
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::Select);
+    f.instruction(&Instruction::LocalTee(3));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::Call(ALLOC));
+    f.instruction(&Instruction::LocalTee(4));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(2, LIST_DATA_OFFSET as u64)));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    });
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Store(memarg(2, LIST_CAP_OFFSET as u64)));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Store(memarg(2, LIST_DATA_OFFSET as u64)));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Store(memarg(2, LIST_LEN_OFFSET as u64)));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(2, LIST_DATA_OFFSET as u64)));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::End);
+}
+
+/// str_eq(p1, l1, p2, l2) -> 0/1: equal lengths, then a byte-by-byte comparison.
+/// Locals: 4 = byte index.
+fn emit_str_eq_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Ne);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32GeU);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::I32Ne);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(4));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::Unreachable);
+    f.instruction(&Instruction::End);
+}
+
+/// str_hash(ptr, len) -> hash: 32-bit FNV-1a over the bytes, so equal strings hash alike.
+/// Locals: 2 = hash.
+fn emit_str_hash_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::I32Const(0x811c9dc5_u32 as i32));
+    f.instruction(&Instruction::LocalSet(2));
+    f.instruction(&Instruction::Block(BlockType::Empty));
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::BrIf(1));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::I32Xor);
+    f.instruction(&Instruction::I32Const(0x0100_0193));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::LocalSet(2));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(0));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalSet(1));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::End);
+}
+
+/// list_remove(list, index, stride): move the elements after `index` down one slot and shorten
+/// the list. Locals: 3 = length, 4 = address of the removed element.
+fn emit_list_remove_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(2, LIST_LEN_OFFSET as u64)));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalSet(3));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load(memarg(2, LIST_DATA_OFFSET as u64)));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalTee(4));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    });
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Store(memarg(2, LIST_LEN_OFFSET as u64)));
+    f.instruction(&Instruction::End);
+}
+
+/// str_concat(p1, l1, p2, l2) -> (ptr, len): copy both strings into a fresh heap buffer.
+/// Locals: 4 = new buffer.
+fn emit_str_concat_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::Call(ALLOC));
+    f.instruction(&Instruction::LocalTee(4));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    });
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    });
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::End);
+}
+
+/// Push `(a > b) - (a < b)` for the unsigned values in locals `a` and `b`.
+fn emit_sign_of_difference(f: &mut wasm_encoder::Function, a: u32, b: u32) {
+    f.instruction(&Instruction::LocalGet(a));
+    f.instruction(&Instruction::LocalGet(b));
+    f.instruction(&Instruction::I32GtU);
+    f.instruction(&Instruction::LocalGet(a));
+    f.instruction(&Instruction::LocalGet(b));
+    f.instruction(&Instruction::I32LtU);
+    f.instruction(&Instruction::I32Sub);
+}
+
+/// str_cmp(p1, l1, p2, l2) -> -1/0/1: the first differing byte decides; otherwise the shorter
+/// string sorts first. Locals: 4 = byte index, 5 and 6 = the bytes at that index.
+fn emit_str_cmp_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32GeU);
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32GeU);
+    f.instruction(&Instruction::I32Or);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_sign_of_difference(f, 1, 3);
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::LocalSet(5));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::LocalSet(6));
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::LocalGet(6));
+    f.instruction(&Instruction::I32Ne);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_sign_of_difference(f, 5, 6);
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(4));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::Unreachable);
+    f.instruction(&Instruction::End);
+}
+
+/// str_find(hp, hl, np, nl, start) -> index: first offset at or after `start` where the needle
+/// occurs in the haystack, or -1. Locals: 5 = index within the needle.
+fn emit_str_find_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32GtU);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(-1));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::LocalSet(5));
+    f.instruction(&Instruction::Block(BlockType::Empty));
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32GeU);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::I32Ne);
+    f.instruction(&Instruction::BrIf(1));
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(5));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(4));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::Unreachable);
+    f.instruction(&Instruction::End);
+}
+
+/// Push 1 if the byte at `addr` (already on the stack) is ASCII whitespace: a space or one of
+/// `\t \n \v \f \r`. Uses local `byte`.
+fn emit_is_space(f: &mut wasm_encoder::Function, byte: u32) {
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::LocalTee(byte));
+    f.instruction(&Instruction::I32Const(b' ' as i32));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::LocalGet(byte));
+    f.instruction(&Instruction::I32Const(b'\t' as i32));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::I32Const(5));
+    f.instruction(&Instruction::I32LtU);
+    f.instruction(&Instruction::I32Or);
+}
+
+/// str_trim(ptr, len) -> (ptr, len): the same bytes without leading and trailing whitespace.
+/// Locals: 2 = current byte.
+fn emit_str_trim_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::Block(BlockType::Empty));
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::BrIf(1));
+    f.instruction(&Instruction::LocalGet(0));
+    emit_is_space(f, 2);
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::BrIf(1));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalSet(0));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalSet(1));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::Block(BlockType::Empty));
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::BrIf(1));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    emit_is_space(f, 2);
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::BrIf(1));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalSet(1));
+    f.instruction(&Instruction::Br(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::End);
+}
+
+/// Write one byte just before `pos`, moving `pos` back.
+fn emit_push_byte(f: &mut wasm_encoder::Function, pos: u32, byte: u8) {
+    f.instruction(&Instruction::LocalGet(pos));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalTee(pos));
+    f.instruction(&Instruction::I32Const(byte as i32));
+    f.instruction(&Instruction::I32Store8(memarg(0, 0)));
+}
+
+/// Write the decimal digits of the i64 local `value` (treated as unsigned, at least one digit)
+/// backwards before `pos`. Leaves `value` at zero.
+fn emit_digits_backward(f: &mut wasm_encoder::Function, pos: u32, value: u32) {
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(pos));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalTee(pos));
+    f.instruction(&Instruction::LocalGet(value));
+    f.instruction(&Instruction::I64Const(10));
+    f.instruction(&Instruction::I64RemU);
+    f.instruction(&Instruction::I32WrapI64);
+    f.instruction(&Instruction::I32Const(b'0' as i32));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Store8(memarg(0, 0)));
+    f.instruction(&Instruction::LocalGet(value));
+    f.instruction(&Instruction::I64Const(10));
+    f.instruction(&Instruction::I64DivU);
+    f.instruction(&Instruction::LocalTee(value));
+    f.instruction(&Instruction::I64Const(0));
+    f.instruction(&Instruction::I64Ne);
+    f.instruction(&Instruction::BrIf(0));
+    f.instruction(&Instruction::End);
+}
+
+/// int_to_str(n: i64, unsigned) -> (ptr, len): decimal digits written backwards into a fresh
+/// 20-byte buffer (enough for `u64::MAX` and `-9223372036854775808`), then the sign. `int`
+/// values are sign-extended first. Locals: 2 = end of the buffer, 3 = write position,
+/// 4 = is negative, 5 = remaining magnitude (treated as unsigned).
+fn emit_int_to_str_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::I32Const(20));
+    f.instruction(&Instruction::Call(ALLOC));
+    f.instruction(&Instruction::I32Const(20));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalTee(2));
+    f.instruction(&Instruction::LocalSet(3));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I64Const(0));
+    f.instruction(&Instruction::I64LtS);
+    f.instruction(&Instruction::I32And);
+    f.instruction(&Instruction::LocalSet(4));
+    f.instruction(&Instruction::I64Const(0));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I64Sub);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::Select);
+    f.instruction(&Instruction::LocalSet(5));
+    emit_digits_backward(f, 3, 5);
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_push_byte(f, 3, b'-');
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::End);
+}
+
+/// f64_to_str(x) -> (ptr, len): `NaN`, `inf`, or the integer part, a point and up to six
+/// decimals (at least one, trailing zeros dropped), with `-` in front when negative. Values from
+/// 1e16 up are scaled to one integer digit and get an exponent: `1.25e20`. Written backwards
+/// into a fresh 40-byte buffer. Locals: 1 = end of the buffer, 2 = write position,
+/// 3 = is negative, 4 = exponent, 5 = integer part, 6 = decimals as an integer,
+/// 7 = decimals left to write, 8 = a decimal was written, 9 = digit.
+fn emit_f64_to_str_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::I32Const(40));
+    f.instruction(&Instruction::Call(ALLOC));
+    f.instruction(&Instruction::I32Const(40));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalTee(1));
+    f.instruction(&Instruction::LocalSet(2));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::F64Ne);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    for b in b"NaN".iter().rev() {
+        emit_push_byte(f, 2, *b);
+    }
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Const(3));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::F64Const(0.0));
+    f.instruction(&Instruction::F64Lt);
+    f.instruction(&Instruction::LocalSet(3));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::F64Abs);
+    f.instruction(&Instruction::LocalTee(0));
+    f.instruction(&Instruction::F64Const(f64::INFINITY));
+    f.instruction(&Instruction::F64Eq);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    for b in b"inf".iter().rev() {
+        emit_push_byte(f, 2, *b);
+    }
+    f.instruction(&Instruction::Else);
+    // Scale large values down to a single integer digit.
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::F64Const(1e16));
+    f.instruction(&Instruction::F64Ge);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::F64Const(10.0));
+    f.instruction(&Instruction::F64Div);
+    f.instruction(&Instruction::LocalSet(0));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I64Const(1));
+    f.instruction(&Instruction::I64Add);
+    f.instruction(&Instruction::LocalSet(4));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::F64Const(10.0));
+    f.instruction(&Instruction::F64Ge);
+    f.instruction(&Instruction::BrIf(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::End);
+    // Integer part, then the decimals rounded to six places; rounding may carry into the
+    // integer part, and past 9 when scaled.
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I64TruncSatF64U);
+    f.instruction(&Instruction::LocalTee(5));
+    f.instruction(&Instruction::F64ConvertI64U);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::F64Sub);
+    f.instruction(&Instruction::F64Neg);
+    f.instruction(&Instruction::F64Const(1e6));
+    f.instruction(&Instruction::F64Mul);
+    f.instruction(&Instruction::F64Nearest);
+    f.instruction(&Instruction::I64TruncSatF64U);
+    f.instruction(&Instruction::LocalTee(6));
+    f.instruction(&Instruction::I64Const(1_000_000));
+    f.instruction(&Instruction::I64Eq);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I64Const(0));
+    f.instruction(&Instruction::LocalSet(6));
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::I64Const(1));
+    f.instruction(&Instruction::I64Add);
+    f.instruction(&Instruction::LocalSet(5));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I64Const(0));
+    f.instruction(&Instruction::I64Ne);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::I64Const(10));
+    f.instruction(&Instruction::I64Eq);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I64Const(1));
+    f.instruction(&Instruction::LocalSet(5));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I64Const(1));
+    f.instruction(&Instruction::I64Add);
+    f.instruction(&Instruction::LocalSet(4));
+    f.instruction(&Instruction::End);
+    emit_digits_backward(f, 2, 4);
+    emit_push_byte(f, 2, b'e');
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::I32Const(6));
+    f.instruction(&Instruction::LocalSet(7));
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(6));
+    f.instruction(&Instruction::I64Const(10));
+    f.instruction(&Instruction::I64RemU);
+    f.instruction(&Instruction::I32WrapI64);
+    f.instruction(&Instruction::LocalSet(9));
+    f.instruction(&Instruction::LocalGet(6));
+    f.instruction(&Instruction::I64Const(10));
+    f.instruction(&Instruction::I64DivU);
+    f.instruction(&Instruction::LocalSet(6));
+    f.instruction(&Instruction::LocalGet(8));
+    f.instruction(&Instruction::LocalGet(9));
+    f.instruction(&Instruction::I32Or);
+    f.instruction(&Instruction::LocalGet(7));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::I32Or);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalTee(2));
+    f.instruction(&Instruction::LocalGet(9));
+    f.instruction(&Instruction::I32Const(b'0' as i32));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Store8(memarg(0, 0)));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::LocalSet(8));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(7));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalTee(7));
+    f.instruction(&Instruction::BrIf(0));
+    f.instruction(&Instruction::End);
+    emit_push_byte(f, 2, b'.');
+    emit_digits_backward(f, 2, 5);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_push_byte(f, 2, b'-');
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::End);
+}
+
+/// Return (0, 0) from str_parse_int: the string is not a number in range.
+fn emit_parse_fail(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::Return);
+}
+
+/// str_parse_int(ptr, len) -> (ok, value): an optional sign then at least one decimal digit.
+/// The value is accumulated as a negative number so that `-2147483648` fits; anything out of
+/// range fails. Locals: 2 = index, 3 = is negative, 4 = accumulated negative value, 5 = digit.
+fn emit_str_parse_int_body(f: &mut wasm_encoder::Function) {
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32Eqz);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_parse_fail(f);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::LocalTee(5));
+    f.instruction(&Instruction::I32Const(b'-' as i32));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::LocalTee(3));
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::I32Const(b'+' as i32));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::I32Or);
+    f.instruction(&Instruction::LocalSet(2));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32GeU);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_parse_fail(f);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::Loop(BlockType::Empty));
+    f.instruction(&Instruction::LocalGet(0));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Load8U(memarg(0, 0)));
+    f.instruction(&Instruction::I32Const(b'0' as i32));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalTee(5));
+    f.instruction(&Instruction::I32Const(9));
+    f.instruction(&Instruction::I32GtU);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_parse_fail(f);
+    f.instruction(&Instruction::End);
+    // acc * 10 - digit >= i32::MIN  <=>  acc >= (i32::MIN + digit) / 10 (rounded towards zero)
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Const(i32::MIN));
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::I32Const(10));
+    f.instruction(&Instruction::I32DivS);
+    f.instruction(&Instruction::I32LtS);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_parse_fail(f);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Const(10));
+    f.instruction(&Instruction::I32Mul);
+    f.instruction(&Instruction::LocalGet(5));
+    f.instruction(&Instruction::I32Sub);
+    f.instruction(&Instruction::LocalSet(4));
+    f.instruction(&Instruction::LocalGet(2));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Add);
+    f.instruction(&Instruction::LocalTee(2));
+    f.instruction(&Instruction::LocalGet(1));
+    f.instruction(&Instruction::I32LtU);
+    f.instruction(&Instruction::BrIf(0));
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(3));
+    f.instruction(&Instruction::If(BlockType::Empty));
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::Return);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Const(i32::MIN));
+    f.instruction(&Instruction::I32Eq);
+    f.instruction(&Instruction::If(BlockType::Empty));
+    emit_parse_fail(f);
+    f.instruction(&Instruction::End);
+    f.instruction(&Instruction::I32Const(1));
+    f.instruction(&Instruction::I32Const(0));
+    f.instruction(&Instruction::LocalGet(4));
+    f.instruction(&Instruction::I32Sub);
     f.instruction(&Instruction::End);
 }
 
+/// Index of the type with these IR slots in `type_sigs`, adding it if it is new.
+fn intern_sig(type_sigs: &mut Vec<FuncSig>, params: &[Type], results: &[Type]) -> u32 {
+    let sig = (
+        params.iter().map(val_type).collect::<Vec<_>>(),
+        results.iter().map(val_type).collect::<Vec<_>>(),
+    );
+    match type_sigs.iter().position(|s| s == &sig) {
+        Some(i) => i as u32,
+        None => {
+            type_sigs.push(sig);
+            type_sigs.len() as u32 - 1
+        }
+    }
+}
+
 fn emit_ir_function(
     f: &IrFunction,
     program: &Program,
     string_offsets: &[u32],
+    type_sigs: &[FuncSig],
     func_base: u32,
     wf: &mut wasm_encoder::Function,
     debug: bool,
@@ -325,15 +1309,19 @@ fn emit_ir_function(
             f.locals.len()
         );
     }
-    let mut done = false;
+    // Slot types by local index: loads and stores take the width of the local.
+    let local_types: Vec<&Type> = f.params.iter().chain(&f.locals).collect();
     for instr in &f.body {
-        if done {
-            break;
-        }
         match instr {
             IrInstr::ConstInt(v) => {
                 wf.instruction(&Instruction::I32Const(*v as i32));
             }
+            IrInstr::ConstI64(v) => {
+                wf.instruction(&Instruction::I64Const(*v));
+            }
+            IrInstr::ConstF64(v) => {
+                wf.instruction(&Instruction::F64Const(*v));
+            }
             IrInstr::ConstString {
                 ptr_local,
                 len_local,
@@ -362,19 +1350,66 @@ fn emit_ir_function(
                     .get(*layout_id as usize)
                     .map(|l| l.total_size)
                     .unwrap_or(0);
-                let size_aligned = (size + 3) & !3;
-                wf.instruction(&Instruction::GlobalGet(0));
-                wf.instruction(&Instruction::GlobalGet(0));
-                wf.instruction(&Instruction::I32Const(size_aligned as i32));
-                wf.instruction(&Instruction::I32Add);
-                wf.instruction(&Instruction::GlobalSet(0));
+                wf.instruction(&Instruction::I32Const(size as i32));
+                wf.instruction(&Instruction::Call(ALLOC));
+            }
+            IrInstr::Alloc => {
+                wf.instruction(&Instruction::Call(ALLOC));
+            }
+            IrInstr::ListPush { list, stride } => {
+                wf.instruction(&Instruction::LocalGet(*list));
+                wf.instruction(&Instruction::I32Const(*stride as i32));
+                wf.instruction(&Instruction::Call(LIST_PUSH));
+            }
+            IrInstr::ListRemove {
+                list,
+                index,
+                stride,
+            } => {
+                wf.instruction(&Instruction::LocalGet(*list));
+                wf.instruction(&Instruction::LocalGet(*index));
+                wf.instruction(&Instruction::I32Const(*stride as i32));
+                wf.instruction(&Instruction::Call(LIST_REMOVE));
+            }
+            IrInstr::StrEq => {
+                wf.instruction(&Instruction::Call(STR_EQ));
+            }
+            IrInstr::StrHash => {
+                wf.instruction(&Instruction::Call(STR_HASH));
+            }
+            IrInstr::StrConcat => {
+                wf.instruction(&Instruction::Call(STR_CONCAT));
+            }
+            IrInstr::StrCmp => {
+                wf.instruction(&Instruction::Call(STR_CMP));
+            }
+            IrInstr::StrFind => {
+                wf.instruction(&Instruction::Call(STR_FIND));
+            }
+            IrInstr::StrTrim => {
+                wf.instruction(&Instruction::Call(STR_TRIM));
+            }
+            IrInstr::IntToStr => {
+                wf.instruction(&Instruction::I64ExtendI32S);
+                wf.instruction(&Instruction::I32Const(0));
+                wf.instruction(&Instruction::Call(INT_TO_STR));
+            }
+            IrInstr::I64ToStr { unsigned } => {
+                wf.instruction(&Instruction::I32Const(*unsigned as i32));
+                wf.instruction(&Instruction::Call(INT_TO_STR));
+            }
+            IrInstr::F64ToStr => {
+                wf.instruction(&Instruction::Call(F64_TO_STR));
+            }
+            IrInstr::StrParseInt => {
+                wf.instruction(&Instruction::Call(STR_PARSE_INT));
             }
             IrInstr::StructSet(ptr_local, field_offset, value_local) => {
                 wf.instruction(&Instruction::LocalGet(*ptr_local));
                 wf.instruction(&Instruction::I32Const(*field_offset as i32));
                 wf.instruction(&Instruction::I32Add);
                 wf.instruction(&Instruction::LocalGet(*value_local));
-                wf.instruction(&Instruction::I32Store(memarg(2, 0)));
+                wf.instruction(&store_instruction(local_types[*value_local as usize]));
             }
             IrInstr::StructSetStr(ptr_local, field_offset, ptr_val_local, len_val_local) => {
                 wf.instruction(&Instruction::LocalGet(*ptr_local));
@@ -392,7 +1427,7 @@ fn emit_ir_function(
                 wf.instruction(&Instruction::LocalGet(*ptr_local));
                 wf.instruction(&Instruction::I32Const(*field_offset as i32));
                 wf.instruction(&Instruction::I32Add);
-                wf.instruction(&Instruction::I32Load(memarg(2, 0)));
+                wf.instruction(&load_instruction(local_types[*dest_local as usize]));
                 wf.instruction(&Instruction::LocalSet(*dest_local));
             }
             IrInstr::StructGetStr(ptr_local, field_offset, ptr_dest, len_dest) => {
@@ -410,40 +1445,112 @@ fn emit_ir_function(
             IrInstr::Call(ir_idx) => {
                 wf.instruction(&Instruction::Call(func_base + ir_idx));
             }
-            IrInstr::CallStr(ir_idx, ptr_dest, len_dest) => {
-                wf.instruction(&Instruction::Call(func_base + ir_idx));
-                wf.instruction(&Instruction::LocalSet(*len_dest));
-                wf.instruction(&Instruction::LocalSet(*ptr_dest));
+            IrInstr::CallMulti { func, dest, count } => {
+                wf.instruction(&Instruction::Call(func_base + func));
+                for i in (0..*count).rev() {
+                    wf.instruction(&Instruction::LocalSet(dest + i));
+                }
+            }
+            IrInstr::CallIndirect {
+                params,
+                results,
+                dest,
+            } => {
+                let sig = (
+                    params.iter().map(val_type).collect::<Vec<_>>(),
+                    results.iter().map(val_type).collect::<Vec<_>>(),
+                );
+                let ty = type_sigs.iter().position(|s| s == &sig).unwrap_or(0) as u32;
+                wf.instruction(&Instruction::CallIndirect { ty, table: 0 });
+                for i in (0..results.len() as u32).rev() {
+                    wf.instruction(&Instruction::LocalSet(dest + i));
+                }
             }
             IrInstr::PrintInt(local) => {
                 wf.instruction(&Instruction::LocalGet(*local));
-                wf.instruction(&Instruction::Call(2));
+                wf.instruction(&Instruction::Call(PRINT_INT));
             }
             IrInstr::PrintStr(ptr_local, len_local) => {
                 wf.instruction(&Instruction::LocalGet(*ptr_local));
                 wf.instruction(&Instruction::LocalGet(*len_local));
-                wf.instruction(&Instruction::Call(3));
+                wf.instruction(&Instruction::Call(PRINT_STR));
             }
             IrInstr::Return => {
-                wf.instruction(&Instruction::End);
-                done = true;
+                wf.instruction(&Instruction::Return);
             }
             IrInstr::ReturnInt(local) => {
                 wf.instruction(&Instruction::LocalGet(*local));
-                wf.instruction(&Instruction::End);
-                done = true;
+                wf.instruction(&Instruction::Return);
             }
-            IrInstr::ReturnStr(ptr_local, len_local) => {
-                wf.instruction(&Instruction::LocalGet(*ptr_local));
-                wf.instruction(&Instruction::LocalGet(*len_local));
+            IrInstr::ReturnMulti { base, count } => {
+                for i in 0..*count {
+                    wf.instruction(&Instruction::LocalGet(base + i));
+                }
+                wf.instruction(&Instruction::Return);
+            }
+            IrInstr::Bin(op) => {
+                wf.instruction(&bin_instruction(*op));
+            }
+            IrInstr::BinI64(op) => {
+                wf.instruction(&bin_i64_instruction(*op));
+            }
+            IrInstr::BinF64(op) => {
+                wf.instruction(&bin_f64_instruction(*op));
+            }
+            IrInstr::Cast { from, to } => {
+                for instr in cast_instructions(from, to) {
+                    wf.instruction(&instr);
+                }
+            }
+            IrInstr::Eqz => {
+                wf.instruction(&Instruction::I32Eqz);
+            }
+            IrInstr::If => {
+                wf.instruction(&Instruction::If(BlockType::Empty));
+            }
+            IrInstr::Else => {
+                wf.instruction(&Instruction::Else);
+            }
+            IrInstr::Block => {
+                wf.instruction(&Instruction::Block(BlockType::Empty));
+            }
+            IrInstr::Loop => {
+                wf.instruction(&Instruction::Loop(BlockType::Empty));
+            }
+            IrInstr::End => {
                 wf.instruction(&Instruction::End);
-                done = true;
+            }
+            IrInstr::Br(depth) => {
+                wf.instruction(&Instruction::Br(*depth));
+            }
+            IrInstr::BrIf(depth) => {
+                wf.instruction(&Instruction::BrIf(*depth));
+            }
+            IrInstr::BrTable { targets, default } => {
+                wf.instruction(&Instruction::BrTable(targets.into(), *default));
+            }
+            IrInstr::Unreachable => {
+                wf.instruction(&Instruction::Unreachable);
+            }
+            IrInstr::Panic(data_id) => {
+                let ptr = string_offsets.get(*data_id as usize).copied().unwrap_or(0);
+                let len = program
+                    .string_data
+                    .get(*data_id as usize)
+                    .map(|s| s.len() as i32)
+                    .unwrap_or(0);
+                wf.instruction(&Instruction::I32Const(ptr as i32));
+                wf.instruction(&Instruction::I32Const(len));
+                wf.instruction(&Instruction::Call(PANIC));
+                wf.instruction(&Instruction::Unreachable);
             }
         }
     }
-    if !done {
-        wf.instruction(&Instruction::End);
+    // Falling off the end is only valid for functions without results; the checker guarantees the rest return.
+    if !f.results.is_empty() {
+        wf.instruction(&Instruction::Unreachable);
     }
+    wf.instruction(&Instruction::End);
 }
 
 /// (Legacy) Emit a single module's AST to Wasm bytes (wasm-wasi). Only supports main() with a single print(string).
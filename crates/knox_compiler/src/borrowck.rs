@@ -0,0 +1,1289 @@
+//! Borrow checking, on the typed program. A value can have many `&` references or one `&mut`
+//! reference at a time, and cannot be used while a `&mut` reference to it is in use, nor
+//! changed while any reference to it is. A reference is in use until the last use of a local
+//! holding it (or the end of a loop using it, if it was made before the loop), also inside a
+//! struct, collection or other value of the local, or stored in it by a call that gets it by
+//! `&mut`; a reference that no local holds lasts until the end of its statement. A reference cannot outlive the
+//! local it points into, so it cannot be returned or stored through a `&mut` reference from the
+//! caller. Changing a local, by assignment or through a `&mut` borrow (explicit
+//! or by calling a `&mut self` method), needs a `mut` binding.
+
+use crate::typed::{
+    carries_ref, Callee, ListMethod, LocalId, MapMethod, TBlock, TExpr, TExprKind, TFunction,
+    TPattern, TStmt, TypedProgram,
+};
+use knox_syntax::ast::Type;
+use knox_syntax::span::{Location, Span};
+use knox_syntax::Diagnostic;
+use std::collections::HashSet;
+
+/// Check every function of the program; all violations are reported.
+pub fn check_borrows(program: &TypedProgram) -> Result<(), Vec<Diagnostic>> {
+    let mut diags = Vec::new();
+    for f in &program.functions {
+        let mut checker = BorrowChecker {
+            program,
+            f,
+            last_use: last_uses(f),
+            loans: Vec::new(),
+            scope: Vec::new(),
+            depth: 0,
+            diags: Vec::new(),
+            reported: HashSet::new(),
+        };
+        checker.function();
+        diags.extend(checker.diags);
+    }
+    if diags.is_empty() {
+        Ok(())
+    } else {
+        Err(diags)
+    }
+}
+
+/// One step from a value to a part of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Proj {
+    /// What a reference points to.
+    Deref,
+    /// The struct field at this byte offset.
+    Field(u32),
+    /// A tuple element.
+    Elem(usize),
+    /// Any element of a list.
+    Index,
+}
+
+/// A local or a part of one.
+#[derive(Clone, Debug)]
+struct Place {
+    root: LocalId,
+    proj: Vec<Proj>,
+    /// Reached through a `&` reference: it cannot be changed.
+    behind_shared: bool,
+}
+
+impl Place {
+    fn local(root: LocalId) -> Self {
+        Self {
+            root,
+            proj: Vec::new(),
+            behind_shared: false,
+        }
+    }
+
+    /// True if the place is not owned by its root local but reached through a reference.
+    fn through_ref(&self) -> bool {
+        self.proj.contains(&Proj::Deref)
+    }
+
+    /// True if the places can share memory: one of them contains the other.
+    fn overlaps(&self, other: &Place) -> bool {
+        self.root == other.root && self.proj.iter().zip(&other.proj).all(|(a, b)| a == b)
+    }
+}
+
+type LoanId = usize;
+
+/// A reference to a place, or to a temporary value (`place` None).
+struct Loan {
+    place: Option<Place>,
+    mutable: bool,
+    span: Span,
+    /// Locals holding the reference: it is in use until the last use of one of them.
+    holders: Vec<LocalId>,
+    /// Depth of the statement the reference is a temporary of, until the statement ends.
+    temp: Option<usize>,
+}
+
+struct BorrowChecker<'a> {
+    program: &'a TypedProgram,
+    f: &'a TFunction,
+    /// For each local: the position up to which it is used, and the span of that use.
+    last_use: Vec<Option<(u32, Span)>>,
+    loans: Vec<Loan>,
+    /// Locals in scope, innermost last.
+    scope: Vec<LocalId>,
+    /// Number of statements (and conditions) being checked.
+    depth: usize,
+    diags: Vec<Diagnostic>,
+    /// (span, message) of each error, so a loop checked twice reports it once.
+    reported: HashSet<(u32, u32, String)>,
+}
+
+/// The locals a pattern binds.
+fn bindings(pat: &TPattern, out: &mut Vec<LocalId>) {
+    match pat {
+        TPattern::Binding(id) => out.push(*id),
+        TPattern::Variant { fields, .. } | TPattern::Struct { fields } => {
+            fields.iter().for_each(|(_, _, p)| bindings(p, out))
+        }
+        TPattern::Or(pats) | TPattern::Tuple(pats) => pats.iter().for_each(|p| bindings(p, out)),
+        _ => {}
+    }
+}
+
+/// Type of the items a `for` loop visits in a list, or the keys of a map or set, or of a
+/// reference to one.
+fn item_type(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::List(t) | Type::Set(t) | Type::Map(t, _) => Some(t),
+        Type::Ref(_, inner) => item_type(inner),
+        _ => None,
+    }
+}
+
+/// End of the last statement or value of a block.
+fn block_end(block: &TBlock) -> u32 {
+    let mut end = 0;
+    block.for_each_expr(&mut |e| end = end.max(e.span.end));
+    end
+}
+
+/// Where each local is last used. A local passed to a call or assigned through is in use until
+/// the call or assignment completes; one made before a loop and used in it, until the loop ends.
+fn last_uses(f: &TFunction) -> Vec<Option<(u32, Span)>> {
+    fn note(uses: &mut [Option<(u32, Span)>], id: usize, pos: u32, span: Span) {
+        if uses[id].is_none_or(|(p, _)| pos >= p) {
+            uses[id] = Some((pos, span));
+        }
+    }
+    fn visit(e: &TExpr, until: u32, f: &TFunction, uses: &mut Vec<Option<(u32, Span)>>) {
+        match &e.kind {
+            TExprKind::Local(id) => note(uses, *id as usize, until.max(e.span.start), e.span),
+            TExprKind::Call { args, .. } => {
+                for a in args {
+                    visit(a, e.span.end, f, uses);
+                }
+            }
+            TExprKind::Assign { target, value } => {
+                visit(value, until, f, uses);
+                visit(target, e.span.end, f, uses);
+            }
+            TExprKind::While { .. }
+            | TExprKind::Loop { .. }
+            | TExprKind::ForRange { .. }
+            | TExprKind::ForEach { .. } => {
+                let mut inner = vec![None; uses.len()];
+                e.for_each_child(&mut |c| visit(c, until, f, &mut inner));
+                for (id, used) in inner.into_iter().enumerate() {
+                    if let Some((pos, span)) = used {
+                        let outside = f.locals[id].span.start < e.span.start;
+                        let pos = if outside { pos.max(e.span.end) } else { pos };
+                        note(uses, id, pos, span);
+                    }
+                }
+            }
+            _ => e.for_each_child(&mut |c| visit(c, until, f, uses)),
+        }
+    }
+    let mut uses = vec![None; f.locals.len()];
+    f.body.for_each_expr(&mut |e| visit(e, 0, f, &mut uses));
+    uses
+}
+
+impl BorrowChecker<'_> {
+    fn function(&mut self) {
+        self.scope.extend(0..self.f.param_count);
+        if let Some(captures) = &self.f.captures {
+            self.scope.extend(captures);
+        }
+        for stmt in &self.f.body.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(value) = &self.f.body.value {
+            let loans = self.expr(value);
+            self.returned(&loans, value.span);
+        }
+    }
+
+    fn report(&mut self, msg: String, span: Span, note: Option<(String, Span)>) {
+        if !self.reported.insert((span.start, span.end, msg.clone())) {
+            return;
+        }
+        let mut diag = Diagnostic::error(msg, Some(Location::new(self.f.file, span)));
+        if let Some((note, at)) = note {
+            diag = diag.with_note(note, Location::new(self.f.file, at));
+        }
+        self.diags.push(diag);
+    }
+
+    fn carries_ref(&self, ty: &Type) -> bool {
+        carries_ref(&self.program.ref_structs, ty)
+    }
+
+    /// How a place is named in messages: its local, with `*` if it is reached through a
+    /// reference.
+    fn name(&self, place: &Place) -> String {
+        let name = &self.f.locals[place.root as usize].name;
+        match place.proj.first() {
+            Some(Proj::Deref) => format!("*{}", name),
+            _ => name.clone(),
+        }
+    }
+
+    /// True if the loan may still be used at `pos`.
+    fn live(&self, loan: &Loan, pos: u32) -> bool {
+        loan.temp.is_some()
+            || loan
+                .holders
+                .iter()
+                .any(|h| self.last_use[*h as usize].is_some_and(|(until, _)| until >= pos))
+    }
+
+    /// The first loan in use at `pos` on a place overlapping `place` (only `&mut` ones if
+    /// `mutable_only`).
+    fn conflict(&self, place: &Place, pos: u32, mutable_only: bool) -> Option<&Loan> {
+        self.loans.iter().find(|l| {
+            (l.mutable || !mutable_only)
+                && l.place.as_ref().is_some_and(|p| p.overlaps(place))
+                && self.live(l, pos)
+        })
+    }
+
+    /// Loans whose references a local holds.
+    fn held_by(&self, id: LocalId) -> Vec<LoanId> {
+        (0..self.loans.len())
+            .filter(|l| self.loans[*l].holders.contains(&id))
+            .collect()
+    }
+
+    fn hold(&mut self, loans: &[LoanId], holder: LocalId) {
+        for l in loans {
+            if !self.loans[*l].holders.contains(&holder) {
+                self.loans[*l].holders.push(holder);
+            }
+        }
+    }
+
+    /// Bring the locals of a pattern into scope, holding the references in the matched value.
+    fn bind(&mut self, pattern: &TPattern, loans: &[LoanId]) {
+        let mut ids = Vec::new();
+        bindings(pattern, &mut ids);
+        for id in ids {
+            self.scope.push(id);
+            self.hold(loans, id);
+        }
+    }
+
+    /// End the temporaries of the statements deeper than the current one.
+    fn end_temps(&mut self) {
+        for loan in &mut self.loans {
+            if loan.temp.is_some_and(|d| d > self.depth) {
+                loan.temp = None;
+            }
+        }
+    }
+
+    /// The locals bound since `mark` go out of scope at `end`. A reference to one of them must
+    /// not be used later, nor be part of `value`, the value of the scope; the other references
+    /// in `value` stay in use until the end of the enclosing statement.
+    fn end_scope(&mut self, mark: usize, end: u32, value: &[LoanId]) {
+        let dead = self.scope.split_off(mark);
+        for id in 0..self.loans.len() {
+            let loan = &self.loans[id];
+            let owned = match &loan.place {
+                Some(p) if !p.through_ref() && dead.contains(&p.root) => p.clone(),
+                _ => continue,
+            };
+            let later = loan
+                .holders
+                .iter()
+                .filter(|h| !dead.contains(h))
+                .filter_map(|h| self.last_use[*h as usize])
+                .find(|(until, _)| *until > end);
+            if later.is_some() || value.contains(&id) {
+                let name = self.name(&owned);
+                let note = later.map(|(_, at)| {
+                    (
+                        format!(
+                            "the reference is used here, after `{}` goes out of scope",
+                            name
+                        ),
+                        at,
+                    )
+                });
+                self.report(
+                    format!("`{}` does not live long enough", name),
+                    loan.span,
+                    note,
+                );
+            }
+            let loan = &mut self.loans[id];
+            loan.holders.clear();
+            loan.temp = None;
+        }
+        for loan in &mut self.loans {
+            loan.holders.retain(|h| !dead.contains(h));
+        }
+        for l in value {
+            self.loans[*l].temp.get_or_insert(self.depth);
+        }
+    }
+
+    /// References returned from the function must not point into its locals or temporaries.
+    fn returned(&mut self, loans: &[LoanId], span: Span) {
+        for l in loans {
+            let loan = &self.loans[*l];
+            let loan_span = loan.span;
+            match &loan.place {
+                None => self.report(
+                    "cannot return a reference to a temporary value".to_string(),
+                    loan_span,
+                    None,
+                ),
+                Some(p) if !p.through_ref() => {
+                    let name = self.name(p);
+                    let note = (span != loan_span)
+                        .then(|| (format!("`{}` is borrowed here", name), loan_span));
+                    self.report(
+                        format!("cannot return a reference to local variable `{}`", name),
+                        span,
+                        note,
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Store the references in `loans` into `place` at `span`. Its local holds them, and so
+    /// does each local it points into through a `&mut` borrow. A place reached through a
+    /// reference the function did not borrow itself belongs to the caller, and outlives the
+    /// function like a returned value.
+    fn store(&mut self, loans: &[LoanId], place: &Place, span: Span) {
+        self.hold(loans, place.root);
+        if !place.through_ref() {
+            return;
+        }
+        let targets: Vec<LocalId> = self
+            .held_by(place.root)
+            .into_iter()
+            .filter_map(|l| match &self.loans[l] {
+                Loan {
+                    mutable: true,
+                    place: Some(p),
+                    ..
+                } if !p.through_ref() => Some(p.root),
+                _ => None,
+            })
+            .collect();
+        if targets.is_empty() {
+            self.escapes(loans, place, span);
+        }
+        for target in targets {
+            self.hold(loans, target);
+        }
+    }
+
+    /// References stored through `place`, which the caller can still use after the function
+    /// returns, must not point into its locals or temporaries.
+    fn escapes(&mut self, loans: &[LoanId], place: &Place, span: Span) {
+        let through = self.name(place);
+        for l in loans {
+            let loan = &self.loans[*l];
+            let loan_span = loan.span;
+            let note = (
+                format!(
+                    "the reference is stored here, through `{}`, which outlives the function",
+                    through
+                ),
+                span,
+            );
+            match &loan.place {
+                None => self.report(
+                    "cannot store a reference to a temporary value where it outlives the function"
+                        .to_string(),
+                    loan_span,
+                    Some(note),
+                ),
+                Some(p) if !p.through_ref() => {
+                    let name = self.name(p);
+                    self.report(
+                        format!("`{}` does not live long enough", name),
+                        loan_span,
+                        Some(note),
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &TStmt) {
+        self.depth += 1;
+        match stmt {
+            TStmt::Let { pattern, init } => {
+                let loans = self.expr(init);
+                self.bind(pattern, &loans);
+            }
+            TStmt::Expr(e) => {
+                self.expr(e);
+            }
+            TStmt::Return { value: Some(e), .. } => {
+                let loans = self.expr(e);
+                self.returned(&loans, e.span);
+            }
+            TStmt::Return { value: None, .. } => {}
+        }
+        self.depth -= 1;
+        self.end_temps();
+    }
+
+    /// A condition or guard: its temporaries end before the code it controls runs.
+    fn condition(&mut self, cond: &TExpr) {
+        self.depth += 1;
+        self.expr(cond);
+        self.depth -= 1;
+        self.end_temps();
+    }
+
+    fn block(&mut self, block: &TBlock) -> Vec<LoanId> {
+        let mark = self.scope.len();
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        let value = match &block.value {
+            Some(v) => self.expr(v),
+            None => Vec::new(),
+        };
+        self.end_scope(mark, block_end(block), &value);
+        value
+    }
+
+    /// The place `e` denotes, if it is a local or part of one.
+    fn place(&self, e: &TExpr) -> Option<Place> {
+        let (base, proj) = match &e.kind {
+            TExprKind::Local(id) => return Some(Place::local(*id)),
+            TExprKind::Field { base, offset } => (base, Proj::Field(*offset)),
+            TExprKind::TupleField { base, index } => (base, Proj::Elem(*index)),
+            TExprKind::Index { base, .. } => (base, Proj::Index),
+            TExprKind::Deref(base) => (base, Proj::Deref),
+            _ => return None,
+        };
+        let mut place = self.place(base)?;
+        // A field or element of a reference's target is reached by dereferencing it.
+        if let Type::Ref(mutable, _) = &base.ty {
+            place.behind_shared |= !mutable;
+            if proj != Proj::Deref {
+                place.proj.push(Proj::Deref);
+            }
+        }
+        place.proj.push(proj);
+        Some(place)
+    }
+
+    /// Check the list indices in a place expression.
+    fn place_operands(&mut self, e: &TExpr) {
+        match &e.kind {
+            TExprKind::Field { base, .. }
+            | TExprKind::TupleField { base, .. }
+            | TExprKind::Deref(base) => self.place_operands(base),
+            TExprKind::Index { base, index } => {
+                self.place_operands(base);
+                self.expr(index);
+            }
+            _ => {}
+        }
+    }
+
+    fn read(&mut self, place: &Place, span: Span) {
+        if let Some(loan) = self.conflict(place, span.start, true) {
+            let name = self.name(place);
+            let note = (format!("`{}` is borrowed as mutable here", name), loan.span);
+            self.report(
+                format!("cannot use `{}` because it is borrowed as mutable", name),
+                span,
+                Some(note),
+            );
+        }
+    }
+
+    fn write(&mut self, place: &Place, span: Span) {
+        let name = self.name(place);
+        if place.behind_shared {
+            self.report(
+                format!(
+                    "cannot assign to `{}`, which is behind a `&` reference",
+                    name
+                ),
+                span,
+                None,
+            );
+        } else if let Some(note) = self.missing_mut(place) {
+            let msg = match place.proj.last() {
+                None => format!(
+                    "cannot assign to `{}`, as it is not declared as mutable",
+                    name
+                ),
+                Some(proj) => format!(
+                    "cannot assign to {} `{}`, as `{}` is not declared as mutable",
+                    if matches!(proj, Proj::Field(_)) {
+                        "a field of"
+                    } else {
+                        "an element of"
+                    },
+                    name,
+                    name
+                ),
+            };
+            self.report(msg, span, Some(note));
+        } else if let Some(loan) = self.conflict(place, span.start, false) {
+            let note = (format!("`{}` is borrowed here", name), loan.span);
+            self.report(
+                format!("cannot assign to `{}` because it is borrowed", name),
+                span,
+                Some(note),
+            );
+        }
+    }
+
+    /// Borrow `place` as a temporary of the current statement, after checking that no
+    /// reference in use conflicts with it.
+    fn borrow(&mut self, place: Place, mutable: bool, span: Span) -> LoanId {
+        if let Some(loan) = self.conflict(&place, span.start, !mutable) {
+            let name = self.name(&place);
+            let (msg, note) = match (mutable, loan.mutable) {
+                (true, true) => (
+                    format!(
+                        "cannot borrow `{}` as mutable more than once at a time",
+                        name
+                    ),
+                    format!("first mutable borrow of `{}` occurs here", name),
+                ),
+                (true, false) => (
+                    format!(
+                        "cannot borrow `{}` as mutable because it is also borrowed as immutable",
+                        name
+                    ),
+                    format!("immutable borrow of `{}` occurs here", name),
+                ),
+                _ => (
+                    format!(
+                        "cannot borrow `{}` as immutable because it is also borrowed as mutable",
+                        name
+                    ),
+                    format!("mutable borrow of `{}` occurs here", name),
+                ),
+            };
+            let at = loan.span;
+            self.report(msg, span, Some((note, at)));
+        }
+        self.loans.push(Loan {
+            place: Some(place),
+            mutable,
+            span,
+            holders: Vec::new(),
+            temp: Some(self.depth),
+        });
+        self.loans.len() - 1
+    }
+
+    /// If `place` is owned by a local not declared `mut`: a note at the binding, suggesting
+    /// `mut` where it can be written.
+    fn missing_mut(&self, place: &Place) -> Option<(String, Span)> {
+        let local = &self.f.locals[place.root as usize];
+        if place.through_ref() || local.mutable {
+            return None;
+        }
+        let note = if local.can_be_mut {
+            format!("consider changing this to `mut {}`", local.name)
+        } else {
+            format!("`{}` is bound here and cannot be changed", local.name)
+        };
+        Some((note, local.span))
+    }
+
+    /// Report why `place` cannot be borrowed as `&mut`, if it cannot.
+    fn borrow_mut_allowed(&mut self, place: &Place, span: Span) {
+        let name = self.name(place);
+        let captured = !place.through_ref()
+            && self
+                .f
+                .captures
+                .as_ref()
+                .is_some_and(|c| c.contains(&place.root));
+        if place.behind_shared {
+            self.report(
+                format!(
+                    "cannot borrow `{}` as mutable, as it is behind a `&` reference",
+                    name
+                ),
+                span,
+                None,
+            );
+        } else if captured {
+            self.report(
+                format!(
+                    "cannot borrow `{}` as mutable: it is captured by value in a closure",
+                    name
+                ),
+                span,
+                None,
+            );
+        } else if let Some(note) = self.missing_mut(place) {
+            self.report(
+                format!(
+                    "cannot borrow `{}` as mutable, as it is not declared as mutable",
+                    name
+                ),
+                span,
+                Some(note),
+            );
+        }
+    }
+
+    /// `&inner` or `&mut inner`; returns the loans the reference carries.
+    fn reference(&mut self, inner: &TExpr, mutable: bool, span: Span) -> Vec<LoanId> {
+        let Some(place) = self.place(inner) else {
+            let mut loans = self.expr(inner);
+            self.loans.push(Loan {
+                place: None,
+                mutable,
+                span,
+                holders: Vec::new(),
+                temp: Some(self.depth),
+            });
+            loans.push(self.loans.len() - 1);
+            return loans;
+        };
+        self.place_operands(inner);
+        if mutable {
+            self.borrow_mut_allowed(&place, span);
+        }
+        // A reference made through another one is only valid while that one is.
+        let mut loans = if place.through_ref() {
+            self.held_by(place.root)
+        } else {
+            Vec::new()
+        };
+        loans.push(self.borrow(place, mutable, span));
+        loans
+    }
+
+    /// Types of the parameters of a called function.
+    fn param_types(&self, callee: &Callee) -> Vec<Type> {
+        match callee {
+            Callee::Fn(id) => self
+                .program
+                .functions
+                .get(*id as usize)
+                .map(|f| {
+                    f.locals[..f.param_count as usize]
+                        .iter()
+                        .map(|l| l.ty.clone())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// A call. A receiver passed by reference without a `&` is borrowed after the other
+    /// arguments are evaluated, as the call is made. The call may store the references its
+    /// arguments carry in a value it gets by `&mut` (`xs.push(&a)`), whose local then holds them.
+    fn call(&mut self, e: &TExpr, callee: &Callee, args: &[TExpr]) -> Vec<LoanId> {
+        let params = self.param_types(callee);
+        let changes_receiver = matches!(
+            callee,
+            Callee::List(ListMethod::Push | ListMethod::Pop)
+                | Callee::Map(MapMethod::Insert | MapMethod::Remove, _)
+        );
+        // `get` gives a `&` reference into the collection for items that move.
+        let reads_receiver = matches!(
+            callee,
+            Callee::List(ListMethod::Get) | Callee::Map(MapMethod::Get, _)
+        ) && self.carries_ref(&e.ty);
+        let mut loans = Vec::new();
+        let mut implicit = Vec::new();
+        // (argument index, place written) for each `&mut` argument that can hold references,
+        // and the references each argument carries.
+        let mut stores = Vec::new();
+        let mut carried = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let is_ref = matches!(arg.ty, Type::Ref(_, _));
+            let by_ref = match params.get(i) {
+                Some(Type::Ref(mutable, _)) if !is_ref => Some(*mutable),
+                _ if i == 0 && changes_receiver => Some(true),
+                _ if i == 0 && reads_receiver => Some(false),
+                _ => None,
+            };
+            match (by_ref, self.place(arg)) {
+                (Some(mutable), Some(mut place)) => {
+                    self.place_operands(arg);
+                    let target = match &arg.ty {
+                        Type::Ref(_, target) => target,
+                        ty => ty,
+                    };
+                    let stored = mutable && self.carries_ref(target);
+                    if let Type::Ref(shared, _) = &arg.ty {
+                        place.behind_shared |= !shared;
+                        place.proj.push(Proj::Deref);
+                    }
+                    if stored {
+                        stores.push((i, place.clone()));
+                    }
+                    loans.extend(self.held_by(place.root));
+                    implicit.push((place, mutable, arg.span));
+                    carried.push(Vec::new());
+                }
+                _ => {
+                    if let TExprKind::Ref { mut_: true, expr } = &arg.kind {
+                        if let Some(place) = self.place(expr).filter(|_| self.carries_ref(&expr.ty))
+                        {
+                            stores.push((i, place));
+                        }
+                    } else if let Type::Ref(true, target) = &arg.ty {
+                        // A `&mut` reference passed on: the call writes where it points.
+                        if let Some(mut place) =
+                            self.place(arg).filter(|_| self.carries_ref(target))
+                        {
+                            place.proj.push(Proj::Deref);
+                            stores.push((i, place));
+                        }
+                    }
+                    let arg_loans = self.expr(arg);
+                    loans.extend(arg_loans.iter().copied());
+                    carried.push(arg_loans);
+                }
+            }
+        }
+        for (place, mutable, span) in implicit {
+            if mutable {
+                self.borrow_mut_allowed(&place, span);
+            }
+            loans.push(self.borrow(place, mutable, span));
+        }
+        for (i, place) in stores {
+            let stored: Vec<LoanId> = carried
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .flat_map(|(_, arg_loans)| arg_loans.iter().copied())
+                .collect();
+            self.store(&stored, &place, e.span);
+        }
+        if self.carries_ref(&e.ty) {
+            loans
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Check an expression; returns the loans whose references its value may carry.
+    fn expr(&mut self, e: &TExpr) -> Vec<LoanId> {
+        let loans = match &e.kind {
+            TExprKind::Local(_)
+            | TExprKind::Field { .. }
+            | TExprKind::TupleField { .. }
+            | TExprKind::Index { .. }
+            | TExprKind::Deref(_)
+                if self.place(e).is_some() =>
+            {
+                let place = self.place(e).expect("place");
+                self.place_operands(e);
+                self.read(&place, e.span);
+                self.held_by(place.root)
+            }
+            TExprKind::Ref { mut_, expr } => self.reference(expr, *mut_, e.span),
+            TExprKind::Assign { target, value } => {
+                let loans = self.expr(value);
+                match self.place(target) {
+                    Some(place) => {
+                        self.place_operands(target);
+                        self.write(&place, target.span);
+                        self.store(&loans, &place, e.span);
+                    }
+                    None => {
+                        self.expr(target);
+                    }
+                }
+                Vec::new()
+            }
+            TExprKind::Call { callee, args } => self.call(e, callee, args),
+            // A captured `&mut` reference is reborrowed by the closure: what it points to is
+            // used through the closure until the closure's last use.
+            TExprKind::Closure { captures, .. } => {
+                let mut loans = Vec::new();
+                for c in captures {
+                    loans.extend(self.expr(c));
+                    if let (Type::Ref(true, _), Some(mut place)) = (&c.ty, self.place(c)) {
+                        place.proj.push(Proj::Deref);
+                        loans.push(self.borrow(place, true, c.span));
+                    }
+                }
+                loans
+            }
+            TExprKind::Block(block) => self.block(block),
+            TExprKind::If {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                self.condition(cond);
+                let mut loans = self.block(then_block);
+                if let Some(block) = else_block {
+                    loans.extend(self.block(block));
+                }
+                loans
+            }
+            TExprKind::Match { value, arms } => {
+                let scrutinee = self.expr(value);
+                let mut loans = Vec::new();
+                for arm in arms {
+                    let mark = self.scope.len();
+                    self.bind(&arm.pattern, &scrutinee);
+                    if let Some(guard) = &arm.guard {
+                        self.condition(guard);
+                    }
+                    let value = self.expr(&arm.body);
+                    self.end_scope(mark, arm.body.span.end, &value);
+                    loans.extend(value);
+                }
+                loans
+            }
+            // A loop body is checked twice, so that references made in one iteration are
+            // seen by the next.
+            TExprKind::While { cond, body } => {
+                for _ in 0..2 {
+                    self.condition(cond);
+                    self.block(body);
+                }
+                Vec::new()
+            }
+            TExprKind::Loop { body, .. } => {
+                for _ in 0..2 {
+                    self.block(body);
+                }
+                Vec::new()
+            }
+            TExprKind::ForRange {
+                var,
+                start,
+                end,
+                body,
+            } => {
+                self.expr(start);
+                self.expr(end);
+                for _ in 0..2 {
+                    let mark = self.scope.len();
+                    self.scope.push(*var);
+                    self.block(body);
+                    self.end_scope(mark, e.span.end, &[]);
+                }
+                Vec::new()
+            }
+            TExprKind::ForEach { var, list, body } => {
+                let mut items = self.expr(list);
+                // A variable that refers to the items in place keeps the collection borrowed.
+                let in_place = matches!(self.f.locals[*var as usize].ty, Type::Ref(_, _))
+                    && item_type(&list.ty).is_some_and(|t| !matches!(t, Type::Ref(_, _)));
+                if let Some(mut place) = self.place(list).filter(|_| in_place) {
+                    if let Type::Ref(mutable, _) = &list.ty {
+                        place.behind_shared |= !mutable;
+                        place.proj.push(Proj::Deref);
+                    }
+                    items.push(self.borrow(place, false, list.span));
+                }
+                for _ in 0..2 {
+                    let mark = self.scope.len();
+                    self.scope.push(*var);
+                    self.hold(&items, *var);
+                    self.block(body);
+                    self.end_scope(mark, e.span.end, &[]);
+                }
+                Vec::new()
+            }
+            _ => {
+                let mut loans = Vec::new();
+                e.for_each_child(&mut |c| loans.push(c));
+                loans.into_iter().flat_map(|c| self.expr(c)).collect()
+            }
+        };
+        if self.carries_ref(&e.ty) {
+            loans
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::parse;
+    use knox_syntax::span::FileId;
+
+    fn borrow_errors(src: &str) -> Vec<String> {
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let mut modules = vec![(
+            "main".to_string(),
+            parse(tokens, FileId::new(0)).expect("parse should succeed"),
+        )];
+        crate::desugar::add_accessor_methods(&mut modules);
+        let typed = crate::check::check_program(&modules).expect("program should type-check");
+        match check_borrows(&typed) {
+            Ok(()) => Vec::new(),
+            Err(diags) => diags.into_iter().map(|d| d.message).collect(),
+        }
+    }
+
+    #[test]
+    fn references_in_turn_are_accepted() {
+        let errors = borrow_errors(
+            r#"
+struct P { x: int, y: int }
+fn inc(x: &mut int) -> () { *x = *x + 1; }
+fn first(xs: &List<int>) -> &int { &xs[0] }
+fn main() -> () {
+  let mut a = 1;
+  let r = &mut a;
+  *r = 2;
+  print(a);
+  inc(&mut a);
+  let s = &a;
+  let t = &a;
+  print(*s + *t);
+  a = 3;
+  let mut p = main::P { x: 1, y: 2 };
+  let px = &mut p.x;
+  let py = &mut p.y;
+  *px = *py;
+  let mut xs = [1];
+  print(*first(&xs));
+  xs.push(2);
+}
+"#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn conflicting_borrows_are_rejected() {
+        let errors = borrow_errors(
+            r#"
+fn both(x: &mut int, y: &int) -> () { *x = *y; }
+fn main() -> () {
+  let mut a = 1;
+  let m = &mut a;
+  let n = &mut a;
+  *m = 1;
+  both(&mut a, &a);
+  let s = &a;
+  a = 2;
+  print(*s);
+  let r = &mut a;
+  print(a);
+  *r = 3;
+  let mut xs = [1];
+  let e = &xs[0];
+  xs.push(2);
+  print(*e);
+}
+"#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "cannot borrow `a` as mutable more than once at a time",
+                "cannot borrow `a` as immutable because it is also borrowed as mutable",
+                "cannot assign to `a` because it is borrowed",
+                "cannot use `a` because it is borrowed as mutable",
+                "cannot borrow `xs` as mutable because it is also borrowed as immutable",
+            ]
+        );
+    }
+
+    #[test]
+    fn borrow_in_loop_lasts_until_loop_ends() {
+        let errors = borrow_errors(
+            r#"
+fn main() -> () {
+  let mut z = 0;
+  let r = &mut z;
+  for i in 0..3 {
+    *r = i;
+    print(z);
+  }
+}
+"#,
+        );
+        assert_eq!(errors, ["cannot use `z` because it is borrowed as mutable"]);
+    }
+
+    #[test]
+    fn escaping_references_are_rejected() {
+        let errors = borrow_errors(
+            r#"
+fn local() -> &int {
+  let a = 1;
+  &a
+}
+fn temporary() -> &int { return &3; }
+fn param(x: &int) -> &int { x }
+struct H { r: &int }
+fn push(xs: &mut List<&int>) -> () { let a = 1; xs.push(&a); }
+fn field(h: &mut H) -> () { let a = 2; h.r = &a; }
+fn deref(out: &mut &int) -> () { let a = 3; *out = &a; }
+fn passed(xs: &mut List<&int>) -> () { let a = 4; push_on(xs, &a); }
+fn push_on(xs: &mut List<&int>, x: &int) -> () { xs.push(x); }
+fn kept(out: &mut &int, x: &int) -> () { *out = x; }
+fn main() -> () {
+  let a = 1;
+  let mut out = &a;
+  {
+    let c = 2;
+    out = &c;
+  }
+  print(*out);
+  let mut held = &a;
+  {
+    let d = 3;
+    let r = &mut held;
+    *r = &d;
+  }
+  print(*held);
+}
+"#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "`c` does not live long enough",
+                "`d` does not live long enough",
+                "cannot return a reference to local variable `a`",
+                "cannot return a reference to a temporary value",
+                "`a` does not live long enough",
+                "`a` does not live long enough",
+                "`a` does not live long enough",
+                "`a` does not live long enough",
+            ]
+        );
+    }
+
+    #[test]
+    fn references_stored_in_values_are_tracked() {
+        let ok = borrow_errors(
+            r#"
+struct H { r: &int }
+impl H { fn get(&self) -> int { *self.r } }
+fn main() -> () {
+  let a = 41;
+  let h = main::H { r: &a };
+  print(h.get());
+  let mut xs: List<&int> = [];
+  xs.push(&a);
+  print(*xs[0]);
+}
+"#,
+        );
+        assert!(ok.is_empty(), "{:?}", ok);
+
+        let errors = borrow_errors(
+            r#"
+struct H { r: &int }
+struct M { r: &mut int }
+enum E { Held(main::H), Empty }
+struct W<T> { v: T }
+impl H { fn set(&mut self, r: &int) -> () { self.r = r; } }
+fn mk() -> H {
+  let a = 41;
+  main::H { r: &a }
+}
+fn wrapped() -> E {
+  let b = 1;
+  E::Held(main::H { r: &b })
+}
+fn main() -> () {
+  let mut a = 1;
+  let m = main::M { r: &mut a };
+  let s = &a;
+  *m.r = 5;
+  print(*s);
+  let mut xs: List<&int> = [];
+  {
+    let c = 41;
+    xs.push(&c);
+  }
+  print(*xs[0]);
+  let mut h = main::H { r: &a };
+  {
+    let d = 2;
+    h.set(&d);
+  }
+  print(*h.r);
+  let mut ws: Map<int, W<&int>> = Map::new();
+  {
+    let e = 3;
+    ws.insert(1, main::W { v: &e });
+  }
+  print(ws.len());
+}
+"#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "cannot borrow `a` as immutable because it is also borrowed as mutable",
+                "`c` does not live long enough",
+                "`d` does not live long enough",
+                "`e` does not live long enough",
+                "cannot return a reference to local variable `a`",
+                "cannot return a reference to local variable `b`",
+            ]
+        );
+    }
+
+    #[test]
+    fn closures_hold_captured_mut_references() {
+        let errors = borrow_errors(
+            r#"
+fn main() -> () {
+  let mut a = 1;
+  let r = &mut a;
+  let f = || *r + 1;
+  *r = 5;
+  print(*r);
+  print(f());
+  let mut b = 1;
+  let s = &mut b;
+  let g = || *s;
+  print(g() + g());
+  *s = 2;
+  print(b);
+}
+"#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "cannot assign to `*r` because it is borrowed",
+                "cannot use `*r` because it is borrowed as mutable",
+            ]
+        );
+    }
+
+    #[test]
+    fn items_read_in_place_borrow_the_collection() {
+        let errors = borrow_errors(
+            r#"
+struct P { x: int }
+fn main() -> () {
+  let mut ps = [main::P { x: 1 }];
+  let first = ps.get(0);
+  ps.push(main::P { x: 2 });
+  match first { Some(p) => print(p.x), None => {} };
+  for p in ps {
+    ps.push(main::P { x: p.x });
+  }
+  match ps.get(0) { Some(p) => print(p.x), None => {} };
+  ps.push(main::P { x: 3 });
+  for p in ps { print(p.x); }
+  ps.push(main::P { x: 4 });
+}
+"#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "cannot borrow `ps` as mutable because it is also borrowed as immutable",
+                "cannot borrow `ps` as mutable because it is also borrowed as immutable",
+            ]
+        );
+    }
+
+    #[test]
+    fn mutable_borrow_needs_mut() {
+        let errors = borrow_errors(
+            r#"
+fn set(x: &mut int) -> () { *x = 0; }
+fn main() -> () {
+  let a = 1;
+  set(&mut a);
+  let r = &a;
+  *r = 2;
+  let s = &mut *r;
+}
+"#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "cannot borrow `a` as mutable, as it is not declared as mutable",
+                "cannot assign to `*r`, which is behind a `&` reference",
+                "cannot borrow `*r` as mutable, as it is behind a `&` reference",
+            ]
+        );
+    }
+
+    #[test]
+    fn immutable_bindings_cannot_change() {
+        let src = r#"
+struct P { x: int @pub(set) }
+fn bump(mut n: int) -> int {
+  n = n + 1;
+  n
+}
+fn main() -> () {
+  let a = 1;
+  a = 2;
+  let p = main::P { x: 1 };
+  p.set_x(3);
+  p.x = 4;
+  let xs = [1];
+  xs.push(2);
+  let mut ok = main::P { x: 1 };
+  ok.set_x(bump(a));
+  for i in 0..3 { i = 2; }
+}
+"#;
+        assert_eq!(
+            borrow_errors(src),
+            [
+                "cannot assign to `a`, as it is not declared as mutable",
+                "cannot borrow `p` as mutable, as it is not declared as mutable",
+                "cannot assign to a field of `p`, as `p` is not declared as mutable",
+                "cannot borrow `xs` as mutable, as it is not declared as mutable",
+                "cannot assign to `i`, as it is not declared as mutable",
+            ]
+        );
+    }
+
+    #[test]
+    fn binding_notes_point_at_the_binding() {
+        let src = r#"
+fn reset(n: int, xs: List<int>) -> () {
+  n = 0;
+  for x in xs { x = 1; }
+  for i in 0..3 { i = 2; }
+}
+fn main() -> () {}
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let modules = vec![(
+            "main".to_string(),
+            parse(tokens, FileId::new(0)).expect("parse should succeed"),
+        )];
+        let typed = crate::check::check_program(&modules).expect("program should type-check");
+        let diags = check_borrows(&typed).expect_err("assignments should be rejected");
+        let notes: Vec<(&str, &str)> = diags
+            .iter()
+            .flat_map(|d| &d.notes)
+            .map(|(m, loc)| {
+                (
+                    m.as_str(),
+                    &src[loc.span.start as usize..loc.span.end as usize],
+                )
+            })
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                ("consider changing this to `mut n`", "n: int"),
+                ("`x` is bound here and cannot be changed", "x"),
+                ("`i` is bound here and cannot be changed", "i"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Type aliases: `type UserId = int;`. An alias is another name for a type, not a new type: it
+//! is resolved in its own module and stands for the resulting type wherever it is written.
+
+use super::env::AliasState;
+use super::Checker;
+use knox_syntax::ast::{Type, Visibility};
+use knox_syntax::span::Span;
+
+impl Checker {
+    /// Resolve every type alias in module and source order, so that unused ones are checked too.
+    pub(super) fn resolve_aliases(&mut self) {
+        let mut aliases: Vec<(usize, u32, String)> = self
+            .modules
+            .iter()
+            .enumerate()
+            .flat_map(|(m, env)| {
+                env.aliases
+                    .iter()
+                    .map(move |(name, a)| (m, a.span.start, name.clone()))
+            })
+            .collect();
+        aliases.sort();
+        for (module, _, name) in aliases {
+            let span = self.modules[module].aliases[&name].span;
+            self.alias_type(module, &name, span);
+        }
+    }
+
+    /// Module of the type alias `Name` or `module::Name`, if `path` names one.
+    pub(super) fn alias_module(&self, path: &[String]) -> Option<usize> {
+        let (module, name) = match path {
+            [name] => (self.module, name),
+            [module, name] => (self.resolve_module(module)?, name),
+            _ => return None,
+        };
+        self.modules[module]
+            .aliases
+            .contains_key(name)
+            .then_some(module)
+    }
+
+    /// The type that alias `name` of `module` stands for, used at `span`; the alias must be
+    /// visible from the current module.
+    pub(super) fn use_alias(&mut self, module: usize, name: &str, span: Span) -> Option<Type> {
+        let vis = self.modules[module].aliases[name].vis;
+        if module != self.module && vis != Visibility::Exported {
+            let owner = self.modules[module].name.clone();
+            self.error(
+                format!("type alias `{}` is private to module `{}`", name, owner),
+                span,
+            );
+            return None;
+        }
+        self.alias_type(module, name, span)
+    }
+
+    /// Resolve alias `name` of `module` the first time it is needed. An alias that refers to
+    /// itself is reported at `span`.
+    fn alias_type(&mut self, module: usize, name: &str, span: Span) -> Option<Type> {
+        let info = self.modules[module].aliases.get_mut(name)?;
+        match &info.state {
+            AliasState::Done(ty) => return ty.clone(),
+            AliasState::Resolving => {
+                self.error(
+                    format!("cycle detected when expanding type alias `{}`", name),
+                    span,
+                );
+                return None;
+            }
+            AliasState::Pending => info.state = AliasState::Resolving,
+        }
+        let (written, at) = (info.ty.clone(), info.span);
+        // The aliased type is resolved in the alias's module, outside of any impl or generic item.
+        let outer = (
+            std::mem::replace(&mut self.module, module),
+            self.self_ty.take(),
+            std::mem::take(&mut self.type_params),
+        );
+        let ty = self.resolve_type(&written, at);
+        (self.module, self.self_ty, self.type_params) = outer;
+        if let Some(info) = self.modules[module].aliases.get_mut(name) {
+            info.state = AliasState::Done(ty.clone());
+        }
+        ty
+    }
+}
@@ -0,0 +1,332 @@
+//! Closures and functions used as values. Each closure is checked in place, with the locals it
+//! uses from enclosing functions captured by value, and lifted into a function of its own whose
+//! first local is its environment.
+
+use super::env::{is_concrete, FnSig};
+use super::{Checker, Local, LoopCtx};
+use crate::typed::{Callee, FnId, LocalId, TBlock, TExpr, TExprKind, TFunction, TLocal};
+use knox_syntax::ast::{Expr, Type};
+use knox_syntax::span::Span;
+use std::collections::HashMap;
+
+/// A closure whose body is being checked. The state of the enclosing function is saved here.
+pub(super) struct ClosureFrame {
+    /// Index in `Checker::scopes` of the closure's parameter scope; captured names go there too.
+    boundary: usize,
+    locals: Vec<TLocal>,
+    loops: Vec<LoopCtx>,
+    ret_ty: Type,
+    /// True if the return type is inferred from the body, which rules out `return`.
+    pub(super) infer_ret: bool,
+    /// (local of the enclosing function, local of the closure) for each captured value.
+    captures: Vec<(LocalId, LocalId)>,
+}
+
+impl Checker {
+    /// True while a generic body is checked with its type parameters abstract; nothing it
+    /// uses is instantiated or lifted then.
+    fn checking_abstractly(&self) -> bool {
+        self.type_params.values().any(|t| !is_concrete(t))
+    }
+
+    /// Find a local by name. A local of an enclosing function is captured by every closure
+    /// between its scope and the current one, outermost first.
+    pub(super) fn lookup_local(&mut self, name: &str) -> Option<Local> {
+        let depth = self.scopes.iter().rposition(|s| s.contains_key(name))?;
+        let mut local = self.scopes[depth][name].clone();
+        let first = self.closures.iter().position(|c| c.boundary > depth);
+        for frame in first.unwrap_or(self.closures.len())..self.closures.len() {
+            // The enclosing function's locals are saved in the closure's frame, and the
+            // closure's own in the frame of the closure nested in it.
+            let span = self.closures[frame].locals[local.id as usize].span;
+            let locals = match self.closures.get_mut(frame + 1) {
+                Some(inner) => &mut inner.locals,
+                None => &mut self.locals,
+            };
+            let id = locals.len() as LocalId;
+            // A captured value is a copy: it cannot be changed, whatever the original's `mut`.
+            locals.push(TLocal {
+                name: name.to_string(),
+                ty: local.ty.clone().unwrap_or(Type::Unit),
+                mutable: false,
+                can_be_mut: false,
+                span,
+            });
+            let boundary = self.closures[frame].boundary;
+            self.closures[frame].captures.push((local.id, id));
+            local = Local { id, ty: local.ty };
+            self.scopes[boundary].insert(name.to_string(), local.clone());
+        }
+        Some(local)
+    }
+
+    /// Name of `place`'s local if it is a value captured by the current closure (or an element
+    /// of one): assigning to it would only change the closure's copy.
+    pub(super) fn captured_place(&self, place: &TExpr) -> Option<String> {
+        match &place.kind {
+            TExprKind::Local(id) => {
+                let frame = self.closures.last()?;
+                frame
+                    .captures
+                    .iter()
+                    .any(|(_, inner)| inner == id)
+                    .then(|| self.locals[*id as usize].name.clone())
+            }
+            TExprKind::TupleField { base, .. } => self.captured_place(base),
+            _ => None,
+        }
+    }
+
+    /// `|x, y: int| body` or `|x| -> T { .. }`. Parameter types that are not written come from
+    /// the `fn` type the context expects; the return type, when not written, from the body.
+    pub(super) fn check_closure(
+        &mut self,
+        span: Span,
+        params: &[(String, Option<Type>)],
+        ret: Option<&Type>,
+        body: &Expr,
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let (hint_params, hint_ret) = match expected {
+            Some(Type::Fn(p, r)) if p.len() == params.len() => (Some(p), Some(&**r)),
+            _ => (None, None),
+        };
+        let mut param_tys = Vec::with_capacity(params.len());
+        for (i, (name, ty)) in params.iter().enumerate() {
+            let ty = match (ty, hint_params) {
+                (Some(ty), _) => self.resolve_type(ty, span),
+                (None, Some(hints)) => Some(hints[i].clone()),
+                (None, None) => {
+                    self.error(
+                        format!(
+                            "type annotations needed: cannot infer the type of closure parameter `{}`",
+                            name
+                        ),
+                        span,
+                    );
+                    None
+                }
+            };
+            param_tys.push(ty);
+        }
+        let ret = match ret {
+            Some(ty) => Some(self.resolve_type(ty, span)?),
+            None => hint_ret.cloned(),
+        };
+
+        self.scopes.push(HashMap::new());
+        let frame = ClosureFrame {
+            boundary: self.scopes.len() - 1,
+            locals: std::mem::take(&mut self.locals),
+            loops: std::mem::take(&mut self.loops),
+            ret_ty: std::mem::replace(&mut self.ret_ty, ret.clone().unwrap_or(Type::Unit)),
+            infer_ret: ret.is_none(),
+            captures: Vec::new(),
+        };
+        self.closures.push(frame);
+        self.locals.push(TLocal {
+            name: "env".to_string(),
+            ty: Type::Int,
+            mutable: false,
+            can_be_mut: false,
+            span,
+        });
+        for ((name, _), ty) in params.iter().zip(&param_tys) {
+            self.declare(name, ty.clone(), span);
+        }
+        let checked = self.check_value(body, ret.as_ref());
+        let frame = self.closures.pop()?;
+        self.scopes.truncate(frame.boundary);
+        let locals = std::mem::replace(&mut self.locals, frame.locals);
+        self.loops = frame.loops;
+        self.ret_ty = frame.ret_ty;
+
+        let checked = checked?;
+        let ret = match ret {
+            Some(ret) if checked.ty != ret && !checked.diverges() => {
+                self.error(
+                    format!(
+                        "mismatched types: expected `{}`, found `{}`",
+                        ret, checked.ty
+                    ),
+                    body.span(),
+                );
+                return None;
+            }
+            Some(ret) => ret,
+            None => checked.ty.clone(),
+        };
+        let param_tys = param_tys.into_iter().collect::<Option<Vec<_>>>()?;
+        let (outer, inner): (Vec<LocalId>, Vec<LocalId>) = frame.captures.into_iter().unzip();
+        let captures = outer
+            .into_iter()
+            .map(|id| {
+                let ty = self.locals[id as usize].ty.clone();
+                TExpr {
+                    span,
+                    ty,
+                    kind: TExprKind::Local(id),
+                }
+            })
+            .collect();
+        let func = self.lift(
+            "closure",
+            TFunction {
+                module: String::new(),
+                name: String::new(),
+                file: self.current().file,
+                param_count: 1 + params.len() as u32,
+                locals,
+                ret: ret.clone(),
+                body: TBlock {
+                    stmts: Vec::new(),
+                    value: Some(Box::new(checked)),
+                },
+                captures: Some(inner),
+            },
+        );
+        Some(TExpr {
+            span,
+            ty: Type::Fn(param_tys, Box::new(ret)),
+            kind: TExprKind::Closure { func, captures },
+        })
+    }
+
+    /// Give a closure's function an id and keep it for the program. It is named after the
+    /// current module and `what` with its id.
+    fn lift(&mut self, what: &str, mut f: TFunction) -> FnId {
+        if self.checking_abstractly() {
+            return 0;
+        }
+        let id = self.next_fn_id();
+        f.module = self.current().name.clone();
+        f.name = format!("{}#{}", what, id);
+        self.lifted.push((id, f));
+        id
+    }
+
+    /// A named function used as a value: a closure with no captures around a function that
+    /// forwards its arguments. Generic functions have no single function to refer to.
+    pub(super) fn check_fn_value(
+        &mut self,
+        span: Span,
+        qualifier: &[String],
+        name: &str,
+    ) -> Option<TExpr> {
+        let sig = self.resolve_fn(span, qualifier, name)?;
+        if sig.is_generic() {
+            self.error(
+                format!("generic function `{}` cannot be used as a value", name),
+                span,
+            );
+            return None;
+        }
+        let func = match self.thunks.get(&sig.id) {
+            Some(id) => *id,
+            None => {
+                let id = self.thunk(&sig);
+                self.thunks.insert(sig.id, id);
+                id
+            }
+        };
+        Some(TExpr {
+            span,
+            ty: Type::Fn(sig.params.clone(), Box::new(sig.ret.clone())),
+            kind: TExprKind::Closure {
+                func,
+                captures: Vec::new(),
+            },
+        })
+    }
+
+    /// The function behind a named function value: it ignores its environment and calls `sig`.
+    fn thunk(&mut self, sig: &FnSig) -> FnId {
+        let mut locals = vec![TLocal {
+            name: "env".to_string(),
+            ty: Type::Int,
+            mutable: false,
+            can_be_mut: false,
+            span: sig.span,
+        }];
+        let mut args = Vec::with_capacity(sig.params.len());
+        for ty in &sig.params {
+            args.push(TExpr {
+                span: sig.span,
+                ty: ty.clone(),
+                kind: TExprKind::Local(locals.len() as LocalId),
+            });
+            locals.push(TLocal {
+                name: format!("arg{}", args.len()),
+                ty: ty.clone(),
+                mutable: false,
+                can_be_mut: false,
+                span: sig.span,
+            });
+        }
+        let call = TExpr {
+            span: sig.span,
+            ty: sig.ret.clone(),
+            kind: TExprKind::Call {
+                callee: Callee::Fn(sig.id),
+                args,
+            },
+        };
+        let id = self.next_fn_id();
+        self.lifted.push((
+            id,
+            TFunction {
+                module: self.current().name.clone(),
+                name: format!("fn#{}", id),
+                file: self.current().file,
+                param_count: locals.len() as u32,
+                locals,
+                ret: sig.ret.clone(),
+                body: TBlock {
+                    stmts: Vec::new(),
+                    value: Some(Box::new(call)),
+                },
+                captures: Some(Vec::new()),
+            },
+        ));
+        id
+    }
+
+    /// `f(args)` where `f` is a local: a call through a value of type `fn(..) -> R`.
+    pub(super) fn check_indirect_call(
+        &mut self,
+        span: Span,
+        name: &str,
+        local: Local,
+        args: &[Expr],
+    ) -> Option<TExpr> {
+        let Some(ty) = local.ty else {
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        };
+        let Type::Fn(params, ret) = &ty else {
+            self.error(format!("expected function, found `{}`", ty), span);
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        };
+        let rest = self.check_args(span, name, params, args)?;
+        let callee = TExpr {
+            span,
+            ty: ty.clone(),
+            kind: TExprKind::Local(local.id),
+        };
+        let mut all_args = vec![callee];
+        all_args.extend(rest);
+        Some(TExpr {
+            span,
+            ty: (**ret).clone(),
+            kind: TExprKind::Call {
+                callee: Callee::Indirect,
+                args: all_args,
+            },
+        })
+    }
+}
@@ -0,0 +1,319 @@
+//! Constants: `const NAME: T = value;`. Each initializer is checked like any expression, then
+//! evaluated over the typed AST; uses of the constant (in expressions, patterns, array sizes and
+//! other constants) become the resulting literal.
+
+use super::env::ConstState;
+use super::Checker;
+use crate::typed::{TBlock, TExpr, TExprKind, TPattern};
+use knox_syntax::ast::{BinaryOp, Type, UnaryOp, Visibility};
+use knox_syntax::span::Span;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Why a constant expression could not be evaluated, and where.
+type EvalError = (String, Span);
+
+/// True if a constant can have type `ty`: a number, `bool` or `string`.
+pub(super) fn const_type(ty: &Type) -> bool {
+    ty.is_numeric() || matches!(ty, Type::Bool | Type::String)
+}
+
+impl Checker {
+    /// Evaluate every constant in module and source order, so that unused ones are checked too.
+    pub(super) fn eval_consts(&mut self) {
+        let mut consts: Vec<(usize, u32, String)> = self
+            .modules
+            .iter()
+            .enumerate()
+            .flat_map(|(m, env)| {
+                env.consts
+                    .iter()
+                    .map(move |(name, c)| (m, c.span.start, name.clone()))
+            })
+            .collect();
+        consts.sort();
+        for (module, _, name) in consts {
+            self.module = module;
+            let span = self.modules[module].consts[&name].span;
+            self.const_value(module, &name, span);
+        }
+    }
+
+    /// Module of the constant `name`, or of `module::name` when `qualifier` names a module, if
+    /// there is one.
+    pub(super) fn const_module(&self, qualifier: &[String], name: &str) -> Option<usize> {
+        let module = match qualifier {
+            [] => self.module,
+            [module] => self.resolve_module(module)?,
+            _ => return None,
+        };
+        self.modules[module]
+            .consts
+            .contains_key(name)
+            .then_some(module)
+    }
+
+    /// Constant `name` of `module` used at `span`, which must be visible from the current module.
+    pub(super) fn use_const(&mut self, module: usize, name: &str, span: Span) -> Option<TExpr> {
+        let vis = self.modules[module].consts[name].vis;
+        if module != self.module && vis != Visibility::Exported {
+            let owner = self.modules[module].name.clone();
+            self.error(
+                format!("constant `{}` is private to module `{}`", name, owner),
+                span,
+            );
+            return None;
+        }
+        self.const_value(module, name, span)
+    }
+
+    /// The value of constant `name` of `module` as a literal at `span`, evaluated the first time
+    /// it is needed. A constant that depends on itself is reported at `span`.
+    fn const_value(&mut self, module: usize, name: &str, span: Span) -> Option<TExpr> {
+        let info = self.modules[module].consts.get_mut(name)?;
+        let ty = info.ty.clone();
+        match &info.state {
+            ConstState::Done(value) => {
+                return value.clone().map(|kind| TExpr { span, ty, kind });
+            }
+            ConstState::Evaluating => {
+                self.error(
+                    format!("cycle detected when evaluating constant `{}`", name),
+                    span,
+                );
+                return None;
+            }
+            ConstState::Pending => info.state = ConstState::Evaluating,
+        }
+        let init = info.init.clone();
+        // The initializer is checked in its own module, outside of any function.
+        let outer = (
+            std::mem::replace(&mut self.module, module),
+            std::mem::replace(&mut self.scopes, vec![HashMap::new()]),
+            std::mem::take(&mut self.locals),
+            std::mem::take(&mut self.loops),
+            std::mem::take(&mut self.closures),
+            self.self_ty.take(),
+            std::mem::take(&mut self.type_params),
+        );
+        let value = self
+            .check_expr_as(&init, &ty)
+            .and_then(|e| self.eval_const(&e));
+        (
+            self.module,
+            self.scopes,
+            self.locals,
+            self.loops,
+            self.closures,
+            self.self_ty,
+            self.type_params,
+        ) = outer;
+        if let Some(info) = self.modules[module].consts.get_mut(name) {
+            info.state = ConstState::Done(value.clone());
+        }
+        value.map(|kind| TExpr { span, ty, kind })
+    }
+
+    /// Evaluate a checked expression to a literal, reporting what is not constant.
+    pub(super) fn eval_const(&mut self, e: &TExpr) -> Option<TExprKind> {
+        match fold(e) {
+            Ok(value) => Some(value),
+            Err((msg, span)) => {
+                self.error(msg, span);
+                None
+            }
+        }
+    }
+
+    /// A constant used as a pattern: `MAX` or `limits::MAX` matches the constant's value.
+    pub(super) fn check_const_pattern(
+        &mut self,
+        module: usize,
+        name: &str,
+        ty: &Type,
+        span: Span,
+    ) -> Option<TPattern> {
+        let value = self.use_const(module, name, span)?;
+        self.expect_pattern_type(&value.ty, ty, span)?;
+        match value.kind {
+            TExprKind::Int(n) => Some(TPattern::Int(n)),
+            TExprKind::Bool(b) => Some(TPattern::Bool(b)),
+            TExprKind::Str(s) => Some(TPattern::Str(s)),
+            _ => {
+                self.error(
+                    format!(
+                        "constants of type `{}` cannot be used in patterns",
+                        value.ty
+                    ),
+                    span,
+                );
+                None
+            }
+        }
+    }
+}
+
+/// The literal a constant expression evaluates to: literals, other constants (already inlined),
+/// operators, casts, and `if`/blocks without statements.
+fn fold(e: &TExpr) -> Result<TExprKind, EvalError> {
+    Ok(match &e.kind {
+        TExprKind::Int(_) | TExprKind::Float(_) | TExprKind::Str(_) | TExprKind::Bool(_) => {
+            e.kind.clone()
+        }
+        TExprKind::Unary { op, expr } => match (op, fold(expr)?) {
+            (UnaryOp::Not, TExprKind::Bool(b)) => TExprKind::Bool(!b),
+            (UnaryOp::Neg, TExprKind::Float(f)) => TExprKind::Float(-f),
+            (UnaryOp::Neg, TExprKind::Int(n)) => int(&e.ty, -wide(&e.ty, n))
+                .ok_or_else(|| ("attempt to negate with overflow".to_string(), e.span))?,
+            _ => return Err(not_constant(e)),
+        },
+        TExprKind::Binary { op, lhs, rhs } => binary(*op, fold(lhs)?, fold(rhs)?, &lhs.ty, e)?,
+        TExprKind::Cast(inner) => cast(fold(inner)?, &inner.ty, &e.ty),
+        TExprKind::Block(block) => fold_block(block, e)?,
+        TExprKind::If {
+            cond,
+            then_block,
+            else_block: Some(else_block),
+        } => match fold(cond)? {
+            TExprKind::Bool(true) => fold_block(then_block, e)?,
+            _ => fold_block(else_block, e)?,
+        },
+        _ => return Err(not_constant(e)),
+    })
+}
+
+/// The value of a block without statements.
+fn fold_block(block: &TBlock, e: &TExpr) -> Result<TExprKind, EvalError> {
+    match (&block.stmts[..], &block.value) {
+        ([], Some(value)) => fold(value),
+        _ => Err(not_constant(e)),
+    }
+}
+
+fn not_constant(e: &TExpr) -> EvalError {
+    (
+        "this expression cannot be evaluated at compile time".to_string(),
+        e.span,
+    )
+}
+
+/// `l op r` on two literals of the same type `ty`; integer arithmetic that would trap at run
+/// time is an error.
+fn binary(
+    op: BinaryOp,
+    l: TExprKind,
+    r: TExprKind,
+    ty: &Type,
+    e: &TExpr,
+) -> Result<TExprKind, EvalError> {
+    let fail = |msg: &str| Err((msg.to_string(), e.span));
+    Ok(match (l, r) {
+        (TExprKind::Bool(a), TExprKind::Bool(b)) => TExprKind::Bool(match op {
+            BinaryOp::And => a && b,
+            BinaryOp::Or => a || b,
+            _ => compare(op, Some(a.cmp(&b))),
+        }),
+        (TExprKind::Str(a), TExprKind::Str(b)) => match op {
+            BinaryOp::Add => TExprKind::Str(a + &b),
+            _ => TExprKind::Bool(compare(op, Some(a.cmp(&b)))),
+        },
+        (TExprKind::Float(a), TExprKind::Float(b)) => match op {
+            BinaryOp::Add => TExprKind::Float(a + b),
+            BinaryOp::Sub => TExprKind::Float(a - b),
+            BinaryOp::Mul => TExprKind::Float(a * b),
+            BinaryOp::Div => TExprKind::Float(a / b),
+            _ => TExprKind::Bool(compare(op, a.partial_cmp(&b))),
+        },
+        (TExprKind::Int(a), TExprKind::Int(b)) => {
+            let (a, b) = (wide(ty, a), wide(ty, b));
+            let exact = match op {
+                BinaryOp::Add => a.checked_add(b),
+                BinaryOp::Sub => a.checked_sub(b),
+                BinaryOp::Mul => a.checked_mul(b),
+                BinaryOp::Div if b == 0 => return fail("attempt to divide by zero"),
+                BinaryOp::Rem if b == 0 => {
+                    return fail("attempt to calculate the remainder with a divisor of zero")
+                }
+                // `MIN % -1` traps at run time like `MIN / -1`, whose quotient does not fit.
+                BinaryOp::Div | BinaryOp::Rem if int(ty, a / b).is_none() => None,
+                BinaryOp::Div => Some(a / b),
+                BinaryOp::Rem => Some(a % b),
+                _ => return Ok(TExprKind::Bool(compare(op, Some(a.cmp(&b))))),
+            };
+            match exact.and_then(|v| int(ty, v)) {
+                Some(value) => value,
+                None => return fail(overflow_message(op)),
+            }
+        }
+        _ => return Err(not_constant(e)),
+    })
+}
+
+fn overflow_message(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "attempt to add with overflow",
+        BinaryOp::Sub => "attempt to subtract with overflow",
+        BinaryOp::Mul => "attempt to multiply with overflow",
+        BinaryOp::Div => "attempt to divide with overflow",
+        _ => "attempt to calculate the remainder with overflow",
+    }
+}
+
+/// The result of comparison `op` given how the operands are ordered (None for a NaN).
+fn compare(op: BinaryOp, ord: Option<Ordering>) -> bool {
+    match op {
+        BinaryOp::Eq => ord == Some(Ordering::Equal),
+        BinaryOp::Ne => ord != Some(Ordering::Equal),
+        BinaryOp::Lt => ord == Some(Ordering::Less),
+        BinaryOp::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+        BinaryOp::Gt => ord == Some(Ordering::Greater),
+        _ => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+    }
+}
+
+/// The integer an `Int` literal of type `ty` stands for (a `u64` is stored as its bit pattern).
+fn wide(ty: &Type, n: i64) -> i128 {
+    if *ty == Type::U64 {
+        n as u64 as i128
+    } else {
+        n as i128
+    }
+}
+
+/// `value` as an `Int` literal of type `ty`, or None if it is out of range.
+fn int(ty: &Type, value: i128) -> Option<TExprKind> {
+    let (min, max) = match ty {
+        Type::I64 => (i64::MIN as i128, i64::MAX as i128),
+        Type::U64 => (0, u64::MAX as i128),
+        Type::U8 => (0, u8::MAX as i128),
+        _ => (i32::MIN as i128, i32::MAX as i128),
+    };
+    (min..=max)
+        .contains(&value)
+        .then_some(TExprKind::Int(value as i64))
+}
+
+/// `value as to`, with the run-time semantics of `as`: integers wrap, floats saturate (NaN
+/// becomes 0), `bool` is 0 or 1.
+fn cast(value: TExprKind, from: &Type, to: &Type) -> TExprKind {
+    let n = match value {
+        TExprKind::Float(f) => {
+            return match to {
+                Type::F64 => TExprKind::Float(f),
+                Type::I64 => TExprKind::Int(f as i64),
+                Type::U64 => TExprKind::Int(f as u64 as i64),
+                Type::U8 => TExprKind::Int(f as u8 as i64),
+                _ => TExprKind::Int(f as i32 as i64),
+            }
+        }
+        TExprKind::Bool(b) => b as i128,
+        TExprKind::Int(n) => wide(from, n),
+        other => return other,
+    };
+    match to {
+        Type::F64 => TExprKind::Float(n as f64),
+        Type::I64 | Type::U64 => TExprKind::Int(n as i64),
+        Type::U8 => TExprKind::Int(n as u8 as i64),
+        _ => TExprKind::Int(n as i32 as i64),
+    }
+}
@@ -0,0 +1,227 @@
+//! `Copy` structs: which types may implement it, and copying their values where other structs
+//! would be moved.
+
+use super::Checker;
+use crate::typed::{
+    holds_resource, is_moved, LocalId, TBlock, TExpr, TExprKind, TLocal, TPattern, TStmt,
+};
+use knox_syntax::ast::{Expr, Type};
+use std::collections::HashSet;
+
+impl Checker {
+    /// True if values of type `ty` are copied rather than moved: numbers, `bool`, `string`, `()`,
+    /// structs that implement `Copy`, and tuples of these.
+    pub(super) fn is_copy(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Bool | Type::String | Type::Unit => true,
+            Type::Tuple(ts) => ts.iter().all(|t| self.is_copy(t)),
+            Type::Path(_) => self
+                .struct_info(ty)
+                .is_some_and(|s| s.impls.contains("Copy")),
+            ty => ty.is_numeric(),
+        }
+    }
+
+    /// After all impls are collected: a struct that implements `Copy` must have `Copy` fields.
+    pub(super) fn check_copy_impls(&mut self) {
+        let mut errors = Vec::new();
+        for env in &self.modules {
+            for (name, s) in &env.structs {
+                if !s.impls.contains("Copy") {
+                    continue;
+                }
+                if let Some(f) = s.fields.iter().find(|f| !self.is_copy(&f.ty)) {
+                    errors.push((
+                        format!(
+                            "`{}` cannot implement `Copy`: field `{}` has type `{}`, which is not `Copy`",
+                            name, f.name, f.ty
+                        ),
+                        s.span,
+                    ));
+                }
+            }
+        }
+        errors.sort_by_key(|(_, span)| span.start);
+        for (msg, span) in errors {
+            self.error(msg, span);
+        }
+    }
+
+    /// Paths of the structs and enums whose values are moved (see `TypedProgram::moved_types`),
+    /// once every impl is collected.
+    pub(super) fn collect_moved_types(&mut self) {
+        let mut moved: HashSet<Vec<String>> = self
+            .modules
+            .iter()
+            .flat_map(|env| {
+                env.structs
+                    .iter()
+                    .filter(|(_, s)| !s.impls.contains("Copy"))
+                    .map(|(name, _)| vec![env.name.clone(), name.clone()])
+            })
+            .collect();
+        loop {
+            let more: Vec<Vec<String>> = self
+                .modules
+                .iter()
+                .flat_map(|env| {
+                    env.enums.iter().filter_map(|(name, e)| {
+                        let path = vec![env.name.clone(), name.clone()];
+                        let holds = e.variants.iter().any(|v| {
+                            v.fields
+                                .iter()
+                                .any(|(_, ty, _)| is_moved(&moved, &self.resources, ty))
+                        });
+                        (holds && !moved.contains(&path)).then_some(path)
+                    })
+                })
+                .collect();
+            if more.is_empty() {
+                break;
+            }
+            moved.extend(more);
+        }
+        self.moved_types = moved;
+    }
+
+    /// True if using a value of type `ty` moves it (see `is_moved`).
+    pub(super) fn is_moved(&self, ty: &Type) -> bool {
+        is_moved(&self.moved_types, &self.resources, ty)
+    }
+
+    /// The type of an item of a collection read without taking it out, by `get` or as the
+    /// variable of a `for` loop: a `&` reference to a struct, enum, `Option` or `Result` that
+    /// moves, so that the item is not shared by two owners. Resources are left to the move
+    /// checker, which rejects `get` and makes `for` consume the collection.
+    pub(super) fn read_type(&self, ty: &Type) -> Type {
+        let pointer = matches!(
+            ty,
+            Type::Path(_) | Type::Generic(_, _) | Type::Option(_) | Type::Result(_, _)
+        );
+        if pointer && self.is_moved(ty) && !holds_resource(&self.resources, ty) {
+            Type::Ref(false, Box::new(ty.clone()))
+        } else {
+            ty.clone()
+        }
+    }
+
+    /// `expr` where its value is moved: into a local, a call, a struct, a collection or out of a
+    /// block (see `copied`).
+    pub(super) fn check_value(&mut self, expr: &Expr, expected: Option<&Type>) -> Option<TExpr> {
+        let value = self.check_expr_with(expr, expected)?;
+        Some(self.copied(value))
+    }
+
+    /// A value about to be moved. A `Copy` struct read from a variable, field, element or
+    /// reference is copied field by field, so that the copy and the original can change apart.
+    pub(super) fn copied(&mut self, value: TExpr) -> TExpr {
+        let place = matches!(
+            value.kind,
+            TExprKind::Local(_)
+                | TExprKind::Field { .. }
+                | TExprKind::TupleField { .. }
+                | TExprKind::Index { .. }
+                | TExprKind::Deref(_)
+        );
+        if !place || !self.has_copy_struct(&value.ty) {
+            return value;
+        }
+        if is_path(&value) {
+            return self.copy_of(value);
+        }
+        // `xs[f()]` and the like are evaluated once, into a hidden local.
+        let id = self.locals.len() as LocalId;
+        self.locals.push(TLocal {
+            name: "copy".to_string(),
+            ty: value.ty.clone(),
+            mutable: false,
+            can_be_mut: false,
+            span: value.span,
+        });
+        let (span, ty) = (value.span, value.ty.clone());
+        let local = TExpr {
+            span,
+            ty: ty.clone(),
+            kind: TExprKind::Local(id),
+        };
+        let copy = self.copy_of(local);
+        TExpr {
+            span,
+            ty,
+            kind: TExprKind::Block(TBlock {
+                stmts: vec![TStmt::Let {
+                    pattern: TPattern::Binding(id),
+                    init: value,
+                }],
+                value: Some(Box::new(copy)),
+            }),
+        }
+    }
+
+    /// True if `ty` is a `Copy` struct or a tuple holding one: a value that shares memory with
+    /// the place it is read from.
+    fn has_copy_struct(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Tuple(ts) => ts.iter().any(|t| self.has_copy_struct(t)),
+            Type::Path(_) => self.is_copy(ty),
+            _ => false,
+        }
+    }
+
+    /// A fresh copy of the value at `place`, which is cheap to evaluate again.
+    fn copy_of(&self, place: TExpr) -> TExpr {
+        let (span, ty) = (place.span, place.ty.clone());
+        let kind = match &ty {
+            Type::Tuple(ts) if self.has_copy_struct(&ty) => TExprKind::Tuple(
+                ts.iter()
+                    .enumerate()
+                    .map(|(index, t)| {
+                        self.copy_of(TExpr {
+                            span,
+                            ty: t.clone(),
+                            kind: TExprKind::TupleField {
+                                base: Box::new(place.clone()),
+                                index,
+                            },
+                        })
+                    })
+                    .collect(),
+            ),
+            Type::Path(_) if self.has_copy_struct(&ty) => {
+                let fields = self
+                    .struct_info(&ty)
+                    .map(|s| s.fields_with(&[], |t| self.repr(t)))
+                    .unwrap_or_default();
+                TExprKind::StructLit(
+                    fields
+                        .into_iter()
+                        .map(|(name, fty, offset)| {
+                            let field = TExpr {
+                                span,
+                                ty: fty,
+                                kind: TExprKind::Field {
+                                    base: Box::new(place.clone()),
+                                    offset,
+                                },
+                            };
+                            (name, self.copy_of(field))
+                        })
+                        .collect(),
+                )
+            }
+            _ => return place,
+        };
+        TExpr { span, ty, kind }
+    }
+}
+
+/// True if `e` is a variable, or a field, tuple element or dereference of one.
+fn is_path(e: &TExpr) -> bool {
+    match &e.kind {
+        TExprKind::Local(_) => true,
+        TExprKind::Field { base, .. }
+        | TExprKind::TupleField { base, .. }
+        | TExprKind::Deref(base) => is_path(base),
+        _ => false,
+    }
+}
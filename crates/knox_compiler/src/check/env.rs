@@ -0,0 +1,314 @@
+//! Symbol tables for the checker: one entry per module with its imports, structs (and their methods), enums, traits, type aliases and function signatures.
+//! All types stored here are resolved: struct and enum types are always `Type::Path([module, Name])`.
+
+use crate::typed::{FnId, TExprKind};
+use knox_syntax::ast::{Expr, Receiver, TraitMethod, Type, Visibility};
+use knox_syntax::span::{FileId, Span};
+use knox_syntax::struct_field_offsets;
+use std::collections::{HashMap, HashSet};
+
+/// Struct field with its resolved type.
+#[derive(Clone, Debug)]
+pub struct FieldInfo {
+    pub name: String,
+    pub ty: Type,
+}
+
+/// Struct declaration as seen by the checker. Field types of a generic struct refer to its
+/// parameters as `Type::Param`.
+#[derive(Clone, Debug)]
+pub struct StructInfo {
+    pub span: Span,
+    pub vis: Visibility,
+    pub type_params: Vec<String>,
+    pub fields: Vec<FieldInfo>,
+    /// Methods and associated functions from the module's `impl` blocks (generated accessors and
+    /// trait methods included).
+    pub methods: HashMap<String, FnSig>,
+    /// Traits implemented by the struct, by key (see `TraitInfo`).
+    pub impls: HashSet<String>,
+    /// `struct Meters(int);`: its only field is `0`, and it is represented as that field alone.
+    pub newtype: bool,
+}
+
+impl StructInfo {
+    /// Fields (name, type, byte offset) of the struct instantiated with `args` (empty for a
+    /// non-generic struct); fields are packed in declaration order. `repr` gives the type a
+    /// field is laid out as (see `Checker::repr`).
+    pub fn fields_with(
+        &self,
+        args: &[Type],
+        repr: impl Fn(&Type) -> Type,
+    ) -> Vec<(String, Type, u32)> {
+        let map = bind(&self.type_params, args);
+        let tys: Vec<Type> = self
+            .fields
+            .iter()
+            .map(|f| substitute(&f.ty, &map))
+            .collect();
+        let offsets = struct_field_offsets(&tys.iter().map(repr).collect::<Vec<_>>());
+        self.fields
+            .iter()
+            .zip(tys)
+            .zip(offsets)
+            .map(|((f, ty), offset)| (f.name.clone(), ty, offset))
+            .collect()
+    }
+
+    /// Type and byte offset of a field of the struct instantiated with `args`.
+    pub fn field_layout(
+        &self,
+        name: &str,
+        args: &[Type],
+        repr: impl Fn(&Type) -> Type,
+    ) -> Option<(Type, u32)> {
+        self.fields_with(args, repr)
+            .into_iter()
+            .find(|(n, _, _)| n == name)
+            .map(|(_, ty, offset)| (ty, offset))
+    }
+}
+
+/// Enum variant with its runtime tag and payload fields (name, resolved type, byte offset).
+/// Tuple variant fields are named `0`, `1`, ...
+#[derive(Clone, Debug)]
+pub struct VariantInfo {
+    pub name: String,
+    pub tag: u32,
+    pub kind: VariantKind,
+    pub fields: Vec<(String, Type, u32)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VariantKind {
+    Unit,
+    Tuple,
+    Struct,
+}
+
+/// Enum declaration as seen by the checker. Variants are in declaration (tag) order.
+#[derive(Clone, Debug)]
+pub struct EnumInfo {
+    pub span: Span,
+    pub vis: Visibility,
+    pub variants: Vec<VariantInfo>,
+}
+
+impl EnumInfo {
+    pub fn variant(&self, name: &str) -> Option<&VariantInfo> {
+        self.variants.iter().find(|v| v.name == name)
+    }
+}
+
+/// Trait declaration as seen by the checker. Method signatures are kept as written: they are
+/// resolved for each implementing type, with `Self` standing for that type.
+/// A trait's key is `module::Name`, or just the name for a built-in trait.
+#[derive(Clone, Debug)]
+pub struct TraitInfo {
+    pub key: String,
+    /// Declaring module; None for a built-in trait.
+    pub module: Option<usize>,
+    pub vis: Visibility,
+    pub methods: Vec<TraitMethod>,
+}
+
+impl TraitInfo {
+    pub fn method(&self, name: &str) -> Option<&TraitMethod> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+}
+
+/// Module-level constant. Its value is computed once, the first time it is needed (see
+/// `Checker::const_value`), and inlined at each use.
+#[derive(Clone, Debug)]
+pub struct ConstInfo {
+    pub span: Span,
+    pub vis: Visibility,
+    /// Resolved once the module's items are collected.
+    pub ty: Type,
+    pub init: Expr,
+    pub state: ConstState,
+}
+
+#[derive(Clone, Debug)]
+pub enum ConstState {
+    Pending,
+    /// Being evaluated: reaching it again means the constant depends on itself.
+    Evaluating,
+    /// A literal (`Int`, `Float`, `Str` or `Bool`), or None after an error.
+    Done(Option<TExprKind>),
+}
+
+/// Type alias declaration. The type it stands for is resolved in its module the first time the
+/// alias is used (see `Checker::use_alias`).
+#[derive(Clone, Debug)]
+pub struct AliasInfo {
+    pub span: Span,
+    pub vis: Visibility,
+    /// As written.
+    pub ty: Type,
+    pub state: AliasState,
+}
+
+#[derive(Clone, Debug)]
+pub enum AliasState {
+    Pending,
+    /// Being resolved: reaching it again means the alias refers to itself.
+    Resolving,
+    /// The resolved type, or None after an error.
+    Done(Option<Type>),
+}
+
+/// Type parameter of a generic function with the keys of the traits it must implement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeParamInfo {
+    pub name: String,
+    pub bounds: Vec<String>,
+}
+
+/// Resolved function signature. `params` do not include a method's receiver.
+/// A generic function has `type_params` (for a method of a generic struct, the struct's come
+/// first); its types refer to them as `Type::Param` and `id` indexes `Checker::templates`
+/// instead: each instantiation gets its own `FnId`.
+#[derive(Clone, Debug)]
+pub struct FnSig {
+    pub id: FnId,
+    pub span: Span,
+    pub vis: Visibility,
+    pub receiver: Option<Receiver>,
+    pub type_params: Vec<TypeParamInfo>,
+    pub params: Vec<Type>,
+    pub ret: Type,
+}
+
+impl FnSig {
+    pub fn is_generic(&self) -> bool {
+        !self.type_params.is_empty()
+    }
+}
+
+/// Everything the checker knows about one module.
+#[derive(Debug)]
+pub struct ModuleEnv {
+    pub name: String,
+    pub file: FileId,
+    /// Name used in this module (module name or alias) -> imported module name.
+    pub imports: HashMap<String, String>,
+    pub structs: HashMap<String, StructInfo>,
+    pub enums: HashMap<String, EnumInfo>,
+    pub traits: HashMap<String, TraitInfo>,
+    pub fns: HashMap<String, FnSig>,
+    pub consts: HashMap<String, ConstInfo>,
+    pub aliases: HashMap<String, AliasInfo>,
+}
+
+impl ModuleEnv {
+    pub fn new(name: &str, file: FileId) -> Self {
+        Self {
+            name: name.to_string(),
+            file,
+            imports: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            fns: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+/// Canonical struct or enum type for `module::name`.
+pub fn struct_type(module: &str, name: &str) -> Type {
+    Type::Path(vec![module.to_string(), name.to_string()])
+}
+
+/// Canonical type of struct `module::name` with type arguments (none for a non-generic struct).
+pub fn struct_type_with(module: &str, name: &str, args: Vec<Type>) -> Type {
+    if args.is_empty() {
+        struct_type(module, name)
+    } else {
+        Type::Generic(vec![module.to_string(), name.to_string()], args)
+    }
+}
+
+/// Type arguments of a struct type; empty unless it is a generic instance.
+pub fn type_args(ty: &Type) -> &[Type] {
+    match ty {
+        Type::Generic(_, args) => args,
+        _ => &[],
+    }
+}
+
+/// Map type parameter names to arguments, in order.
+pub fn bind(params: &[String], args: &[Type]) -> HashMap<String, Type> {
+    params.iter().cloned().zip(args.iter().cloned()).collect()
+}
+
+/// Replace the type parameters of `ty` that `map` binds.
+pub fn substitute(ty: &Type, map: &HashMap<String, Type>) -> Type {
+    match ty {
+        Type::Param(name) => map.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Ref(mut_, inner) => Type::Ref(*mut_, Box::new(substitute(inner, map))),
+        Type::Option(inner) => Type::Option(Box::new(substitute(inner, map))),
+        Type::List(elem) => Type::List(Box::new(substitute(elem, map))),
+        Type::Set(elem) => Type::Set(Box::new(substitute(elem, map))),
+        Type::Map(key, value) => Type::Map(
+            Box::new(substitute(key, map)),
+            Box::new(substitute(value, map)),
+        ),
+        Type::Result(ok, err) => Type::Result(
+            Box::new(substitute(ok, map)),
+            Box::new(substitute(err, map)),
+        ),
+        Type::Generic(path, args) => Type::Generic(
+            path.clone(),
+            args.iter().map(|a| substitute(a, map)).collect(),
+        ),
+        Type::Tuple(elems) => Type::Tuple(elems.iter().map(|e| substitute(e, map)).collect()),
+        Type::Fn(params, ret) => Type::Fn(
+            params.iter().map(|p| substitute(p, map)).collect(),
+            Box::new(substitute(ret, map)),
+        ),
+        Type::Int
+        | Type::I64
+        | Type::U64
+        | Type::U8
+        | Type::F64
+        | Type::String
+        | Type::Bool
+        | Type::Unit
+        | Type::Path(_) => ty.clone(),
+    }
+}
+
+/// True if `ty` mentions no type parameter, i.e. it can be laid out.
+pub fn is_concrete(ty: &Type) -> bool {
+    match ty {
+        Type::Param(_) => false,
+        Type::Ref(_, inner) | Type::Option(inner) | Type::List(inner) | Type::Set(inner) => {
+            is_concrete(inner)
+        }
+        Type::Result(ok, err) | Type::Map(ok, err) => is_concrete(ok) && is_concrete(err),
+        Type::Generic(_, args) | Type::Tuple(args) => args.iter().all(is_concrete),
+        Type::Fn(params, ret) => params.iter().all(is_concrete) && is_concrete(ret),
+        Type::Int
+        | Type::I64
+        | Type::U64
+        | Type::U8
+        | Type::F64
+        | Type::String
+        | Type::Bool
+        | Type::Unit
+        | Type::Path(_) => true,
+    }
+}
+
+/// Type of `self` in a method of `ty`.
+pub fn self_type(receiver: Receiver, ty: Type) -> Type {
+    match receiver {
+        Receiver::Value => ty,
+        Receiver::Ref => Type::Ref(false, Box::new(ty)),
+        Receiver::RefMut => Type::Ref(true, Box::new(ty)),
+    }
+}
@@ -0,0 +1,1145 @@
+//! Expression checking: infers the type of each expression, reports mismatches and builds typed expressions.
+
+use super::env::{bind, struct_type, struct_type_with, substitute, FnSig};
+use super::generic::{closed, unify};
+use super::{Checker, LoopCtx};
+use crate::typed::{Callee, LoopId, TBlock, TExpr, TExprKind};
+use knox_syntax::ast::{BinaryOp, Block, BuiltinVariant, Expr, Type, UnaryOp, Visibility};
+use knox_syntax::payload_offset;
+use knox_syntax::span::Span;
+use std::collections::HashMap;
+
+fn texpr(span: Span, ty: Type, kind: TExprKind) -> TExpr {
+    TExpr { span, ty, kind }
+}
+
+/// Type of a checked block: its value's type, or `()`. None if the value failed to check.
+pub(super) fn block_type(checked: &TBlock, block: &Block) -> Option<Type> {
+    match (&checked.value, &block.value) {
+        (Some(v), _) => Some(v.ty.clone()),
+        (None, Some(_)) => None,
+        (None, None) => Some(Type::Unit),
+    }
+}
+
+/// True if `expr` is an integer literal without a suffix (or arithmetic on such literals), whose
+/// type comes from the other operand of a binary operator.
+fn takes_context_type(expr: &Expr) -> bool {
+    match expr {
+        Expr::IntLiteral { suffix, .. } => suffix.is_none(),
+        Expr::Unary {
+            op: UnaryOp::Neg,
+            expr,
+            ..
+        } => takes_context_type(expr),
+        Expr::Binary { op, lhs, rhs, .. } => {
+            op.is_arithmetic() && takes_context_type(lhs) && takes_context_type(rhs)
+        }
+        _ => false,
+    }
+}
+
+/// True if a field or list element place is reached through a `&` (not `&mut`) reference.
+fn behind_shared_ref(place: &TExpr) -> bool {
+    match &place.kind {
+        TExprKind::Field { base, .. }
+        | TExprKind::TupleField { base, .. }
+        | TExprKind::Index { base, .. } => {
+            matches!(base.ty, Type::Ref(false, _)) || behind_shared_ref(base)
+        }
+        _ => false,
+    }
+}
+
+impl Checker {
+    /// Infer the type of `expr`. Returns None after reporting an error (or when a subexpression already failed).
+    pub(super) fn check_expr(&mut self, expr: &Expr) -> Option<TExpr> {
+        self.check_expr_with(expr, None)
+    }
+
+    /// Like `check_expr`, with the type the context expects as a hint for constructors
+    /// that cannot be inferred on their own (`None`, `Ok(..)`, `Err(..)`). Mismatches are not reported here.
+    pub(super) fn check_expr_with(
+        &mut self,
+        expr: &Expr,
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        match expr {
+            Expr::UnitLiteral { span } => Some(texpr(*span, Type::Unit, TExprKind::Unit)),
+            Expr::IntLiteral {
+                span,
+                value,
+                suffix,
+            } => self.check_int_literal(*span, *value, suffix.as_ref(), expected, false),
+            Expr::FloatLiteral { span, value } => {
+                Some(texpr(*span, Type::F64, TExprKind::Float(*value)))
+            }
+            Expr::Cast { span, expr, ty } => self.check_cast(*span, expr, ty),
+            Expr::StringLiteral { span, value } => {
+                Some(texpr(*span, Type::String, TExprKind::Str(value.clone())))
+            }
+            Expr::Interpolation { span, parts } => self.check_interpolation(*span, parts),
+            Expr::BoolLiteral { span, value } => {
+                Some(texpr(*span, Type::Bool, TExprKind::Bool(*value)))
+            }
+            Expr::Ident { span, name } => {
+                if let Some(local) = self.lookup_local(name) {
+                    let ty = local.ty?;
+                    return Some(texpr(*span, ty, TExprKind::Local(local.id)));
+                }
+                if let Some(module) = self.const_module(&[], name) {
+                    return self.use_const(module, name, *span);
+                }
+                if self.current().fns.contains_key(name) {
+                    return self.check_fn_value(*span, &[], name);
+                }
+                self.error(format!("cannot find value `{}` in this scope", name), *span);
+                None
+            }
+            Expr::Path { span, segments } => {
+                if let Some((variant, prefix)) = segments.split_last() {
+                    if let Some(enum_) = self.enum_prefix(prefix) {
+                        return self.check_unit_variant(*span, enum_, segments, variant);
+                    }
+                }
+                if let [qualifier @ .., name] = segments.as_slice() {
+                    if let Some(module) = self.const_module(qualifier, name) {
+                        return self.use_const(module, name, *span);
+                    }
+                }
+                if let [module, name] = segments.as_slice() {
+                    if self.resolve_module(module).is_some() {
+                        return self.check_fn_value(*span, std::slice::from_ref(module), name);
+                    }
+                }
+                self.error(
+                    format!("expected a value, found path `{}`", segments.join("::")),
+                    *span,
+                );
+                None
+            }
+            Expr::StructLiteral { span, path, fields } => {
+                self.check_struct_literal(*span, path, fields, expected)
+            }
+            Expr::Field {
+                span,
+                receiver,
+                name,
+            } => self.check_field(*span, receiver, name),
+            Expr::Call {
+                span,
+                receiver: Some(receiver),
+                name,
+                args,
+                ..
+            } => self.check_method_call(*span, receiver, name, args),
+            Expr::Call {
+                span,
+                receiver: None,
+                qualifier,
+                name,
+                args,
+            } => self.check_fn_call(*span, qualifier, name, args, expected),
+            Expr::Assign {
+                span,
+                target,
+                value,
+            } => {
+                let target = self.check_place(target);
+                // A function value's environment has the size of its captures, so the one a
+                // reference points to cannot be overwritten in place.
+                if let Some(TExpr {
+                    ty: ty @ Type::Fn(_, _),
+                    kind: TExprKind::Deref(_),
+                    span: target_span,
+                }) = &target
+                {
+                    self.error(
+                        format!(
+                            "cannot assign a function value (`{}`) through a reference; store it in a struct field instead",
+                            ty
+                        ),
+                        *target_span,
+                    );
+                }
+                let value = match &target {
+                    Some(t) => {
+                        let ty = t.ty.clone();
+                        self.check_expr_as(value, &ty)
+                    }
+                    None => self.check_expr(value),
+                };
+                Some(texpr(
+                    *span,
+                    Type::Unit,
+                    TExprKind::Assign {
+                        target: Box::new(target?),
+                        value: Box::new(value?),
+                    },
+                ))
+            }
+            Expr::Match { span, value, arms } => self.check_match(*span, value, arms, expected),
+            Expr::Deref { span, expr } => {
+                let inner = self.check_expr(expr)?;
+                match inner.ty.clone() {
+                    Type::Ref(_, ty) => Some(texpr(*span, *ty, TExprKind::Deref(Box::new(inner)))),
+                    other => {
+                        self.error(format!("type `{}` cannot be dereferenced", other), *span);
+                        None
+                    }
+                }
+            }
+            Expr::Ref { span, mut_, expr } => {
+                let inner = self.check_expr(expr)?;
+                Some(texpr(
+                    *span,
+                    Type::Ref(*mut_, Box::new(inner.ty.clone())),
+                    TExprKind::Ref {
+                        mut_: *mut_,
+                        expr: Box::new(inner),
+                    },
+                ))
+            }
+            Expr::If {
+                span,
+                cond,
+                then_block,
+                else_block,
+            } => self.check_if(*span, cond, then_block, else_block.as_ref(), expected),
+            Expr::Block { span, block } => {
+                let checked = self.check_block_with(block, expected);
+                let ty = block_type(&checked, block)?;
+                Some(texpr(*span, ty, TExprKind::Block(checked)))
+            }
+            Expr::Variant {
+                span,
+                variant,
+                value,
+            } => self.check_variant(*span, *variant, value.as_deref(), expected),
+            Expr::Try { span, expr } => self.check_try(*span, expr),
+            Expr::While {
+                span,
+                label,
+                cond,
+                body,
+            } => {
+                let c = self.check_cond(cond, "while");
+                let (body, _) = self.check_loop_body(label, false, body);
+                Some(texpr(
+                    *span,
+                    Type::Unit,
+                    TExprKind::While {
+                        cond: Box::new(c?),
+                        body,
+                    },
+                ))
+            }
+            Expr::Loop { span, label, body } => {
+                let (body, ctx) = self.check_loop_body(label, true, body);
+                Some(texpr(
+                    *span,
+                    ctx.break_ty.unwrap_or(Type::Unit),
+                    TExprKind::Loop {
+                        body,
+                        has_break: ctx.has_break,
+                    },
+                ))
+            }
+            Expr::For {
+                span,
+                label,
+                var,
+                var_span,
+                iter,
+                body,
+            } => self.check_for(*span, label, (var, *var_span), iter, body),
+            Expr::ArrayLiteral { span, elements } => {
+                self.check_array_literal(*span, elements, expected)
+            }
+            Expr::ArrayRepeat { span, value, count } => {
+                self.check_array_repeat(*span, value, count, expected)
+            }
+            Expr::Tuple { span, elements } => {
+                let hints = match expected {
+                    Some(Type::Tuple(hints)) if hints.len() == elements.len() => Some(hints),
+                    _ => None,
+                };
+                let checked: Vec<Option<TExpr>> = elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| self.check_value(e, hints.map(|h| &h[i])))
+                    .collect();
+                let checked = checked.into_iter().collect::<Option<Vec<_>>>()?;
+                let ty = Type::Tuple(checked.iter().map(|e| e.ty.clone()).collect());
+                Some(texpr(*span, ty, TExprKind::Tuple(checked)))
+            }
+            Expr::Index { span, base, index } => self.check_index(*span, base, index),
+            Expr::Closure {
+                span,
+                params,
+                ret,
+                body,
+            } => self.check_closure(*span, params, ret.as_ref(), body, expected),
+            Expr::Range { span, .. } => {
+                self.error(
+                    "ranges are only supported as the iterator of a `for` loop",
+                    *span,
+                );
+                None
+            }
+            Expr::Break { span, label, value } => {
+                let value = match value {
+                    Some(v) => Some(Box::new(self.check_expr(v)?)),
+                    None => None,
+                };
+                let target = self.loop_target(*span, label.as_deref(), "break")?;
+                let ty = value.as_ref().map(|v| v.ty.clone()).unwrap_or(Type::Unit);
+                let ctx = &mut self.loops[target];
+                ctx.has_break = true;
+                if value.is_some() && !ctx.is_loop {
+                    self.error("`break` with a value is only allowed inside `loop`", *span);
+                    return None;
+                }
+                match &ctx.break_ty {
+                    Some(expected) if *expected != ty => {
+                        let msg =
+                            format!("mismatched types: expected `{}`, found `{}`", expected, ty);
+                        self.error(msg, *span);
+                        return None;
+                    }
+                    Some(_) => {}
+                    None => ctx.break_ty = Some(ty),
+                }
+                Some(texpr(
+                    *span,
+                    Type::Unit,
+                    TExprKind::Break {
+                        target: target as LoopId,
+                        value,
+                    },
+                ))
+            }
+            Expr::Continue { span, label } => {
+                let target = self.loop_target(*span, label.as_deref(), "continue")?;
+                Some(texpr(
+                    *span,
+                    Type::Unit,
+                    TExprKind::Continue(target as LoopId),
+                ))
+            }
+            Expr::Binary { span, op, lhs, rhs } => {
+                self.check_binary(*span, *op, lhs, rhs, expected)
+            }
+            Expr::Unary { span, op, expr } => {
+                if let (UnaryOp::Neg, Expr::IntLiteral { value, suffix, .. }) = (op, &**expr) {
+                    return self.check_int_literal(*span, *value, suffix.as_ref(), expected, true);
+                }
+                let hint = expected.filter(|t| *op == UnaryOp::Neg && t.is_numeric());
+                let inner = self.check_expr_with(expr, hint)?;
+                let ok = match op {
+                    UnaryOp::Neg => inner.ty.is_numeric() && !inner.ty.is_unsigned(),
+                    UnaryOp::Not => inner.ty == Type::Bool,
+                };
+                if !ok {
+                    let what = match op {
+                        UnaryOp::Neg => "negate",
+                        UnaryOp::Not => "apply `!` to",
+                    };
+                    self.error(format!("cannot {} `{}`", what, inner.ty), *span);
+                    return None;
+                }
+                Some(texpr(
+                    *span,
+                    inner.ty.clone(),
+                    TExprKind::Unary {
+                        op: *op,
+                        expr: Box::new(inner),
+                    },
+                ))
+            }
+        }
+    }
+
+    /// An integer literal, negated when written as `-literal` so that a signed type's minimum
+    /// is in range. The type is the suffix, else the expected integer type, else `int`.
+    fn check_int_literal(
+        &mut self,
+        span: Span,
+        value: u64,
+        suffix: Option<&Type>,
+        expected: Option<&Type>,
+        negated: bool,
+    ) -> Option<TExpr> {
+        let ty = match (suffix, expected) {
+            (Some(ty), _) => ty.clone(),
+            (None, Some(ty)) if ty.is_integer() => ty.clone(),
+            (None, _) => Type::Int,
+        };
+        if negated && ty.is_unsigned() {
+            self.error(format!("cannot negate `{}`", ty), span);
+            return None;
+        }
+        let max = ty.max_literal() + negated as u64;
+        if value > max {
+            self.error(format!("literal out of range for `{}`", ty), span);
+            return None;
+        }
+        let n = if negated {
+            (value as i64).wrapping_neg()
+        } else {
+            value as i64
+        };
+        Some(texpr(span, ty, TExprKind::Int(n)))
+    }
+
+    /// Arithmetic, ordering and equality on two numbers of the same type (no `%` on `f64`), `+`
+    /// and ordering on `string`, equality on `bool`/`string`, logic on `bool`. On two values of
+    /// the same struct, equality calls `Eq::eq` and ordering compares `Ord::cmp` with 0.
+    /// An unsuffixed integer literal takes the type of the other operand (`x + 1` on `i64`).
+    fn check_binary(
+        &mut self,
+        span: Span,
+        op: BinaryOp,
+        lhs: &Expr,
+        rhs: &Expr,
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let hint = expected.filter(|t| op.is_arithmetic() && t.is_numeric());
+        let (l, r) = if takes_context_type(lhs) {
+            let r = self.check_expr_with(rhs, hint);
+            let l = self.check_expr_with(lhs, r.as_ref().map(|r| &r.ty).or(hint));
+            (l, r)
+        } else {
+            let l = self.check_expr_with(lhs, hint);
+            let r = self.check_expr_with(rhs, l.as_ref().map(|l| &l.ty).or(hint));
+            (l, r)
+        };
+        let (l, r) = (l?, r?);
+        if op.is_comparison() && l.ty == r.ty {
+            let eq = matches!(op, BinaryOp::Eq | BinaryOp::Ne);
+            if eq && self.implements_builtin(&l.ty, "Eq") {
+                let call = self.trait_call(span, "eq", vec![l, r])?;
+                if op == BinaryOp::Eq {
+                    return Some(call);
+                }
+                return Some(texpr(
+                    span,
+                    Type::Bool,
+                    TExprKind::Unary {
+                        op: UnaryOp::Not,
+                        expr: Box::new(call),
+                    },
+                ));
+            }
+            if !eq && self.implements_builtin(&l.ty, "Ord") {
+                let cmp = self.trait_call(span, "cmp", vec![l, r])?;
+                let zero = texpr(span, Type::Int, TExprKind::Int(0));
+                return Some(texpr(
+                    span,
+                    Type::Bool,
+                    TExprKind::Binary {
+                        op,
+                        lhs: Box::new(cmp),
+                        rhs: Box::new(zero),
+                    },
+                ));
+            }
+        }
+        let ty = match op {
+            BinaryOp::And | BinaryOp::Or => {
+                for operand in [&l, &r] {
+                    if operand.ty != Type::Bool {
+                        self.error(
+                            format!(
+                                "`{}` operands must be `bool`, found `{}`",
+                                op.as_str(),
+                                operand.ty
+                            ),
+                            operand.span,
+                        );
+                        return None;
+                    }
+                }
+                Type::Bool
+            }
+            BinaryOp::Eq | BinaryOp::Ne => match (&l.ty, &r.ty) {
+                (lt, rt) if lt == rt && lt.is_numeric() => Type::Bool,
+                (Type::Bool, Type::Bool) | (Type::String, Type::String) => Type::Bool,
+                (lt, rt) => {
+                    self.error(format!("cannot compare `{}` with `{}`", lt, rt), span);
+                    return None;
+                }
+            },
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => match (&l.ty, &r.ty) {
+                (lt, rt) if lt == rt && lt.is_numeric() => Type::Bool,
+                (Type::String, Type::String) => Type::Bool,
+                (lt, rt) => {
+                    self.error(format!("cannot compare `{}` with `{}`", lt, rt), span);
+                    return None;
+                }
+            },
+            BinaryOp::Add => match (&l.ty, &r.ty) {
+                (lt, rt) if lt == rt && lt.is_numeric() => lt.clone(),
+                (Type::String, Type::String) => Type::String,
+                (lt, rt) => {
+                    self.error(format!("cannot add `{}` to `{}`", rt, lt), span);
+                    return None;
+                }
+            },
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => match (&l.ty, &r.ty) {
+                (Type::F64, Type::F64) if op == BinaryOp::Rem => {
+                    self.error("`%` is not supported on `f64`", span);
+                    return None;
+                }
+                (lt, rt) if lt == rt && lt.is_numeric() => lt.clone(),
+                (lt, rt) => {
+                    self.error(
+                        format!("cannot apply `{}` to `{}` and `{}`", op.as_str(), lt, rt),
+                        span,
+                    );
+                    return None;
+                }
+            },
+        };
+        Some(texpr(
+            span,
+            ty,
+            TExprKind::Binary {
+                op,
+                lhs: Box::new(l),
+                rhs: Box::new(r),
+            },
+        ))
+    }
+
+    /// `expr as T`: a conversion between numbers (truncating, wrapping or saturating like
+    /// Rust's `as`), or from `bool` to a number.
+    fn check_cast(&mut self, span: Span, expr: &Expr, ty: &Type) -> Option<TExpr> {
+        let target = self.resolve_type(ty, span);
+        let inner = self.check_expr(expr)?;
+        let target = target?;
+        if !(inner.ty.is_numeric() || inner.ty == Type::Bool) || !target.is_numeric() {
+            self.error(format!("cannot cast `{}` as `{}`", inner.ty, target), span);
+            return None;
+        }
+        Some(texpr(span, target, TExprKind::Cast(Box::new(inner))))
+    }
+
+    /// `if`/`while` conditions must be `bool`.
+    fn check_cond(&mut self, cond: &Expr, what: &str) -> Option<TExpr> {
+        let c = self.check_expr(cond)?;
+        if c.ty != Type::Bool {
+            self.error(
+                format!("`{}` condition must be `bool`, found `{}`", what, c.ty),
+                cond.span(),
+            );
+            return None;
+        }
+        Some(c)
+    }
+
+    /// Check a loop body with the loop pushed as the innermost `break`/`continue` target.
+    pub(super) fn check_loop_body(
+        &mut self,
+        label: &Option<String>,
+        is_loop: bool,
+        body: &Block,
+    ) -> (TBlock, LoopCtx) {
+        self.loops.push(LoopCtx {
+            label: label.clone(),
+            is_loop,
+            break_ty: None,
+            has_break: false,
+        });
+        let checked = self.check_block(body);
+        let ctx = self.loops.pop().expect("loop context pushed above");
+        if let Some(v) = &checked.value {
+            if v.ty != Type::Unit && !v.diverges() {
+                self.error(
+                    format!("loop body must have type `()`, found `{}`", v.ty),
+                    v.span,
+                );
+            }
+        }
+        (checked, ctx)
+    }
+
+    /// Resolve the loop a `break`/`continue` refers to (innermost, or by label).
+    fn loop_target(&mut self, span: Span, label: Option<&str>, what: &str) -> Option<usize> {
+        let found = match label {
+            None => self.loops.len().checked_sub(1),
+            Some(l) => self
+                .loops
+                .iter()
+                .rposition(|ctx| ctx.label.as_deref() == Some(l)),
+        };
+        if found.is_none() {
+            match label {
+                None => self.error(format!("`{}` outside of a loop", what), span),
+                Some(l) => self.error(format!("use of undeclared label `'{}`", l), span),
+            }
+        }
+        found
+    }
+
+    /// `for var in start..end`: both bounds are `int`; `var` is scoped to the body.
+    /// Any other iterator must be a list (see `check_for_each`).
+    fn check_for(
+        &mut self,
+        span: Span,
+        label: &Option<String>,
+        var: (&str, Span),
+        iter: &Expr,
+        body: &Block,
+    ) -> Option<TExpr> {
+        let Expr::Range { start, end, .. } = iter else {
+            return self.check_for_each(span, label, var, iter, body);
+        };
+        let start = self.check_expr(start);
+        let end = self.check_expr(end);
+        let mut ok = true;
+        for bound in [&start, &end].into_iter().flatten() {
+            if bound.ty != Type::Int {
+                self.error(
+                    format!("range bounds must be `int`, found `{}`", bound.ty),
+                    bound.span,
+                );
+                ok = false;
+            }
+        }
+        self.scopes.push(HashMap::new());
+        let var = self.declare(var.0, Some(Type::Int), var.1);
+        let (body, _) = self.check_loop_body(label, false, body);
+        self.scopes.pop();
+        if !ok {
+            return None;
+        }
+        Some(texpr(
+            span,
+            Type::Unit,
+            TExprKind::ForRange {
+                var,
+                start: Box::new(start?),
+                end: Box::new(end?),
+                body,
+            },
+        ))
+    }
+
+    /// The condition must be `bool`. Branches must agree unless one of them never completes;
+    /// without `else` the `then` branch must be `()`.
+    fn check_if(
+        &mut self,
+        span: Span,
+        cond: &Expr,
+        then_block: &Block,
+        else_block: Option<&Block>,
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let c = self.check_cond(cond, "if");
+        let then_t = self.check_block_with(then_block, expected);
+        let else_t = else_block.map(|b| self.check_block_with(b, expected));
+        let then_ty = block_type(&then_t, then_block)?;
+        let ty = match (&else_t, else_block) {
+            (Some(e), Some(b)) => {
+                let else_ty = block_type(e, b)?;
+                if then_t.diverges() {
+                    else_ty
+                } else if e.diverges() || then_ty == else_ty {
+                    then_ty
+                } else {
+                    self.error(
+                        format!(
+                            "`if` and `else` have incompatible types: `{}` and `{}`",
+                            then_ty, else_ty
+                        ),
+                        span,
+                    );
+                    return None;
+                }
+            }
+            _ => {
+                if then_ty != Type::Unit && !then_t.diverges() {
+                    self.error(
+                        format!(
+                            "`if` without `else` must have type `()`, found `{}`",
+                            then_ty
+                        ),
+                        span,
+                    );
+                    return None;
+                }
+                Type::Unit
+            }
+        };
+        let c = c?;
+        Some(texpr(
+            span,
+            ty,
+            TExprKind::If {
+                cond: Box::new(c),
+                then_block: then_t,
+                else_block: else_t,
+            },
+        ))
+    }
+
+    /// Check `expr` against an expected type, reporting a mismatch at the expression's span.
+    pub(super) fn check_expr_as(&mut self, expr: &Expr, expected: &Type) -> Option<TExpr> {
+        let found = self.check_expr_with(expr, Some(expected))?;
+        if &found.ty != expected {
+            self.error(
+                format!(
+                    "mismatched types: expected `{}`, found `{}`",
+                    expected, found.ty
+                ),
+                expr.span(),
+            );
+            return None;
+        }
+        Some(self.copied(found))
+    }
+
+    /// `Some(v)`, `None`, `Ok(v)`, `Err(e)`. The union type comes from `expected` when available;
+    /// only `Some(v)` can be inferred from its payload alone.
+    fn check_variant(
+        &mut self,
+        span: Span,
+        variant: BuiltinVariant,
+        value: Option<&Expr>,
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let (ty, payload_ty) = match (variant, expected) {
+            (BuiltinVariant::Some | BuiltinVariant::None, Some(Type::Option(inner))) => {
+                (expected.cloned(), Some((**inner).clone()))
+            }
+            (BuiltinVariant::Ok, Some(Type::Result(ok, _))) => {
+                (expected.cloned(), Some((**ok).clone()))
+            }
+            (BuiltinVariant::Err, Some(Type::Result(_, err))) => {
+                (expected.cloned(), Some((**err).clone()))
+            }
+            _ => (None, None),
+        };
+        let payload = match (value, &payload_ty) {
+            (Some(v), Some(p)) => Some(self.check_expr_as(v, p)?),
+            (Some(v), None) => Some(self.check_value(v, None)?),
+            (None, _) => None,
+        };
+        let ty = match (ty, variant, &payload) {
+            (Some(ty), _, _) => ty,
+            (None, BuiltinVariant::Some, Some(p)) => Type::Option(Box::new(p.ty.clone())),
+            (None, BuiltinVariant::None, _) => {
+                self.error(
+                    "type annotations needed: cannot infer the type of `None`",
+                    span,
+                );
+                return None;
+            }
+            _ => {
+                self.error(
+                    format!(
+                        "type annotations needed: cannot infer the `Result` type of `{}(..)`",
+                        variant.name()
+                    ),
+                    span,
+                );
+                return None;
+            }
+        };
+        let fields = payload
+            .map(|p| vec![(payload_offset(&self.repr(&p.ty)), p)])
+            .unwrap_or_default();
+        Some(texpr(
+            span,
+            ty,
+            TExprKind::Variant {
+                tag: variant.tag(),
+                fields,
+            },
+        ))
+    }
+
+    /// `expr?` unwraps `Ok`/`Some` and returns `Err`/`None` early, so the enclosing
+    /// function must return an `Option`, or a `Result` with the same error type.
+    fn check_try(&mut self, span: Span, expr: &Expr) -> Option<TExpr> {
+        let inner = self.check_expr(expr)?;
+        let (ty, fail) = match (&inner.ty, &self.ret_ty) {
+            (Type::Option(t), Type::Option(_)) => ((**t).clone(), BuiltinVariant::None),
+            (Type::Result(t, e), Type::Result(_, ret_e)) if e == ret_e => {
+                ((**t).clone(), BuiltinVariant::Err)
+            }
+            (Type::Result(_, e), Type::Result(_, ret_e)) => {
+                self.error(
+                    format!("`?` cannot convert error type `{}` into `{}`", e, ret_e),
+                    span,
+                );
+                return None;
+            }
+            (Type::Option(_) | Type::Result(_, _), ret) => {
+                let ret = ret.clone();
+                self.error(
+                    format!(
+                        "the `?` operator can only be used in a function that returns `Option` or `Result`, not `{}`",
+                        ret
+                    ),
+                    span,
+                );
+                return None;
+            }
+            (other, _) => {
+                let other = other.clone();
+                self.error(
+                    format!(
+                        "the `?` operator can only be applied to `Option` or `Result`, not `{}`",
+                        other
+                    ),
+                    span,
+                );
+                return None;
+            }
+        };
+        Some(texpr(
+            span,
+            ty,
+            TExprKind::Try {
+                expr: Box::new(inner),
+                fail_tag: fail.tag(),
+            },
+        ))
+    }
+
+    /// Type of an assignment target: a local, a dereferenced reference, a field or a list element.
+    /// A field or element cannot be assigned through a `&` reference.
+    fn check_place(&mut self, target: &Expr) -> Option<TExpr> {
+        if !matches!(
+            target,
+            Expr::Ident { .. } | Expr::Deref { .. } | Expr::Field { .. } | Expr::Index { .. }
+        ) {
+            self.error("invalid left-hand side of assignment", target.span());
+            return None;
+        }
+        let place = self.check_expr(target)?;
+        if let Some(name) = self.captured_place(&place) {
+            self.error(
+                format!(
+                    "cannot assign to `{}`: it is captured by value in a closure",
+                    name
+                ),
+                target.span(),
+            );
+            return None;
+        }
+        if behind_shared_ref(&place) {
+            let msg = match target {
+                Expr::Field { name, .. } => {
+                    format!("cannot assign to field `{}` behind a `&` reference", name)
+                }
+                _ => "cannot assign to a list element behind a `&` reference".to_string(),
+            };
+            self.error(msg, target.span());
+            return None;
+        }
+        Some(place)
+    }
+
+    /// Check call arguments against parameter types. Returns None if any argument failed.
+    pub(super) fn check_args(
+        &mut self,
+        span: Span,
+        callee: &str,
+        params: &[Type],
+        args: &[Expr],
+    ) -> Option<Vec<TExpr>> {
+        if params.len() != args.len() {
+            self.error(
+                format!(
+                    "`{}` takes {} arguments but {} were supplied",
+                    callee,
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            );
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        }
+        let checked: Vec<Option<TExpr>> = args
+            .iter()
+            .zip(params)
+            .map(|(a, p)| self.check_expr_as(a, p))
+            .collect();
+        checked.into_iter().collect()
+    }
+
+    /// Resolve `name` or `module::name` to a function signature, enforcing `export` across modules.
+    pub(super) fn resolve_fn(
+        &mut self,
+        span: Span,
+        qualifier: &[String],
+        name: &str,
+    ) -> Option<FnSig> {
+        let module = match qualifier {
+            [] => self.module,
+            [module] => match self.resolve_module(module) {
+                Some(m) => m,
+                None => {
+                    self.error(format!("unresolved module `{}`", module), span);
+                    return None;
+                }
+            },
+            _ => {
+                self.error(
+                    format!("unresolved path `{}::{}`", qualifier.join("::"), name),
+                    span,
+                );
+                return None;
+            }
+        };
+        let Some(sig) = self.modules[module].fns.get(name).cloned() else {
+            let path = qualifier
+                .iter()
+                .chain(std::iter::once(&name.to_string()))
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("::");
+            self.error(format!("cannot find function `{}`", path), span);
+            return None;
+        };
+        if module != self.module && sig.vis != Visibility::Exported {
+            let owner = self.modules[module].name.clone();
+            self.error(
+                format!("function `{}` is private to module `{}`", name, owner),
+                span,
+            );
+            return None;
+        }
+        Some(sig)
+    }
+
+    fn check_fn_call(
+        &mut self,
+        span: Span,
+        qualifier: &[String],
+        name: &str,
+        args: &[Expr],
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        if let Some(enum_) = self.enum_prefix(qualifier) {
+            return self.check_tuple_variant(span, enum_, qualifier, name, args);
+        }
+        if qualifier.is_empty() && name == "print" {
+            if args.len() != 1 {
+                self.error(
+                    format!("`print` takes 1 argument but {} were supplied", args.len()),
+                    span,
+                );
+                return None;
+            }
+            let mut arg = self.check_expr(&args[0])?;
+            if self.implements_builtin(&arg.ty, "Display") {
+                arg = self.trait_call(arg.span, "show", vec![arg])?;
+            }
+            if !(arg.ty.is_numeric() || matches!(arg.ty, Type::Bool | Type::String)) {
+                self.error(
+                    format!(
+                        "`print` expects a number, `bool` or `string` argument or a type that implements `Display`, found `{}`",
+                        arg.ty
+                    ),
+                    args[0].span(),
+                );
+                return None;
+            }
+            return Some(texpr(span, Type::Unit, TExprKind::Print(Box::new(arg))));
+        }
+        if self.struct_prefix(qualifier).is_some() {
+            return self.check_assoc_call(span, qualifier, name, args, expected);
+        }
+        if let [owner] = qualifier {
+            if owner == "Map" || owner == "Set" {
+                return self.check_collection_new(span, owner, name, args, expected);
+            }
+        }
+        if qualifier.is_empty() {
+            if let Some(local) = self.lookup_local(name) {
+                return self.check_indirect_call(span, name, local, args);
+            }
+        }
+        if let Some(built) = self.check_newtype_constructor(span, qualifier, name, args) {
+            return built;
+        }
+        let Some(sig) = self.resolve_fn(span, qualifier, name) else {
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        };
+        if sig.is_generic() {
+            let map = HashMap::new();
+            return self.check_generic_call(
+                span,
+                name,
+                &sig,
+                &sig.params,
+                map,
+                None,
+                args,
+                expected,
+            );
+        }
+        let args = self.check_args(span, name, &sig.params, args)?;
+        Some(texpr(
+            span,
+            sig.ret,
+            TExprKind::Call {
+                callee: Callee::Fn(sig.id),
+                args,
+            },
+        ))
+    }
+
+    fn check_struct_literal(
+        &mut self,
+        span: Span,
+        path: &[String],
+        fields: &[(String, Expr)],
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        if let Some((prefix, variant)) = path.split_last().map(|(v, p)| (p, v)) {
+            if let Some(enum_) = self.enum_prefix(prefix) {
+                return self.check_struct_variant(span, enum_, path, variant, fields);
+            }
+        }
+        let Some((module, name)) = self.resolve_type_name(path, span) else {
+            for (_, e) in fields {
+                self.check_expr(e);
+            }
+            return None;
+        };
+        let ty = struct_type(&self.modules[module].name, &name);
+        let Some(info) = self.modules[module].structs.get(&name).cloned() else {
+            self.error(format!("expected struct, found enum `{}`", ty), span);
+            return None;
+        };
+        if info.newtype {
+            self.error(
+                format!(
+                    "`{}` is a newtype; build it with `{}(..)`",
+                    ty,
+                    path.join("::")
+                ),
+                span,
+            );
+            for (_, e) in fields {
+                self.check_expr(e);
+            }
+            return None;
+        }
+        let mut visible = true;
+        for (i, (fname, fexpr)) in fields.iter().enumerate() {
+            visible &= self.field_visible(&ty, fname, fexpr.span(), i == 0);
+        }
+        if !visible {
+            return None;
+        }
+        let declared: Vec<(String, Type)> = info
+            .fields
+            .iter()
+            .map(|f| (f.name.clone(), f.ty.clone()))
+            .collect();
+        // A generic struct's type arguments come from the expected type, then from the fields.
+        let mut map = match expected {
+            Some(Type::Generic(p, args)) if ty == Type::Path(p.clone()) => {
+                bind(&info.type_params, args)
+            }
+            _ => HashMap::new(),
+        };
+        let checked =
+            self.check_named_fields(span, "struct", &ty.to_string(), &declared, &mut map, fields)?;
+        let mut args = Vec::with_capacity(info.type_params.len());
+        for p in &info.type_params {
+            let Some(arg) = map.get(p) else {
+                self.error(
+                    format!(
+                        "type annotations needed: cannot infer type parameter `{}` of `{}`",
+                        p, ty
+                    ),
+                    span,
+                );
+                return None;
+            };
+            args.push(arg.clone());
+        }
+        let ty = struct_type_with(&self.modules[module].name, &name, args);
+        self.note_struct_instance(&ty);
+        Some(texpr(span, ty, TExprKind::StructLit(checked)))
+    }
+
+    /// Check `name: value` initializers against the declared fields: no duplicates, no unknown
+    /// fields, none missing. `kind` and `name` describe the type in messages (e.g. "struct", "m::S").
+    /// Field types may mention type parameters, which are bound in `map` from the values.
+    pub(super) fn check_named_fields(
+        &mut self,
+        span: Span,
+        kind: &str,
+        name: &str,
+        expected: &[(String, Type)],
+        map: &mut HashMap<String, Type>,
+        fields: &[(String, Expr)],
+    ) -> Option<Vec<(String, TExpr)>> {
+        let mut seen: Vec<&str> = Vec::new();
+        let mut checked = Vec::with_capacity(fields.len());
+        let mut ok = true;
+        for (fname, fexpr) in fields {
+            if seen.contains(&fname.as_str()) {
+                self.error(
+                    format!("field `{}` specified more than once", fname),
+                    fexpr.span(),
+                );
+                ok = false;
+                continue;
+            }
+            seen.push(fname);
+            match expected.iter().find(|(n, _)| n == fname) {
+                Some((_, fty)) => {
+                    let hint = closed(fty, map);
+                    match self.check_value(fexpr, hint.as_ref()) {
+                        Some(e) if unify(fty, &e.ty, map) => checked.push((fname.clone(), e)),
+                        Some(e) => {
+                            self.error(
+                                format!(
+                                    "mismatched types: expected `{}`, found `{}`",
+                                    substitute(fty, map),
+                                    e.ty
+                                ),
+                                fexpr.span(),
+                            );
+                            ok = false;
+                        }
+                        None => ok = false,
+                    }
+                }
+                None => {
+                    self.error(
+                        format!("{} `{}` has no field named `{}`", kind, name, fname),
+                        fexpr.span(),
+                    );
+                    self.check_expr(fexpr);
+                    ok = false;
+                }
+            }
+        }
+        for (field, _) in expected {
+            if !seen.contains(&field.as_str()) {
+                self.error(
+                    format!("missing field `{}` in initializer of `{}`", field, name),
+                    span,
+                );
+                ok = false;
+            }
+        }
+        ok.then_some(checked)
+    }
+}
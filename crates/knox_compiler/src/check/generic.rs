@@ -0,0 +1,456 @@
+//! Generics: type parameters in scope, type-argument inference at call sites, and monomorphization.
+//! A generic function is checked once with its parameters abstract (`Type::Param`, usable only
+//! through their trait bounds), then once more for each list of type arguments it is called with.
+//! Every instantiation is an ordinary function with its own `FnId` and a mangled name.
+
+use super::env::{
+    bind, is_concrete, self_type, struct_type_with, substitute, type_args, FnSig, TypeParamInfo,
+};
+use super::Checker;
+use crate::typed::{Callee, FnId, TExpr, TExprKind, TFunction};
+use knox_syntax::ast::{Expr, FnDecl, ImplBlock, Type, TypeParam};
+use knox_syntax::span::Span;
+use knox_syntax::{mangle, StructLayout};
+use std::collections::{HashMap, HashSet};
+
+/// Instantiations whose type arguments have more nodes than this are reported as unbounded
+/// (e.g. `fn f<T>(x: T) -> () { f(Some(x)); }`, or `f((x, x))`, whose size doubles each time).
+const MAX_TYPE_SIZE: usize = 64;
+
+/// A generic function or method, kept as written so that each instantiation can be checked.
+pub(super) struct Template {
+    /// Module whose names the body uses (the trait's module for a default method).
+    module: usize,
+    /// Module and name of the struct, for a method.
+    owner: Option<(usize, String)>,
+    decl: FnDecl,
+    sig: FnSig,
+}
+
+/// An instantiation waiting to be checked: template index, type arguments and its `FnId`.
+pub(super) struct Instance {
+    template: usize,
+    args: Vec<Type>,
+    id: FnId,
+}
+
+/// Bind the parameters of `pattern` (a generic signature's type) so that it equals `actual`.
+/// Parameters already bound must agree.
+pub(super) fn unify(pattern: &Type, actual: &Type, map: &mut HashMap<String, Type>) -> bool {
+    match (pattern, actual) {
+        (Type::Param(name), _) => match map.get(name) {
+            Some(bound) => bound == actual,
+            None => {
+                map.insert(name.clone(), actual.clone());
+                true
+            }
+        },
+        (Type::Ref(pm, p), Type::Ref(am, a)) => pm == am && unify(p, a, map),
+        (Type::Option(p), Type::Option(a))
+        | (Type::List(p), Type::List(a))
+        | (Type::Set(p), Type::Set(a)) => unify(p, a, map),
+        (Type::Result(pok, perr), Type::Result(aok, aerr))
+        | (Type::Map(pok, perr), Type::Map(aok, aerr)) => {
+            unify(pok, aok, map) && unify(perr, aerr, map)
+        }
+        (Type::Generic(pp, pargs), Type::Generic(ap, aargs)) => {
+            pp == ap
+                && pargs.len() == aargs.len()
+                && pargs.iter().zip(aargs).all(|(p, a)| unify(p, a, map))
+        }
+        (Type::Tuple(pelems), Type::Tuple(aelems)) => {
+            pelems.len() == aelems.len() && pelems.iter().zip(aelems).all(|(p, a)| unify(p, a, map))
+        }
+        (Type::Fn(pparams, pret), Type::Fn(aparams, aret)) => {
+            pparams.len() == aparams.len()
+                && pparams.iter().zip(aparams).all(|(p, a)| unify(p, a, map))
+                && unify(pret, aret, map)
+        }
+        _ => pattern == actual,
+    }
+}
+
+/// `pattern` with its parameters replaced, if `map` binds all of them.
+pub(super) fn closed(pattern: &Type, map: &HashMap<String, Type>) -> Option<Type> {
+    let mut open = false;
+    visit_params(pattern, &mut |name| open |= !map.contains_key(name));
+    (!open).then(|| substitute(pattern, map))
+}
+
+fn visit_params(ty: &Type, f: &mut impl FnMut(&str)) {
+    match ty {
+        Type::Param(name) => f(name),
+        Type::Ref(_, inner) | Type::Option(inner) | Type::List(inner) | Type::Set(inner) => {
+            visit_params(inner, f)
+        }
+        Type::Result(ok, err) | Type::Map(ok, err) => {
+            visit_params(ok, f);
+            visit_params(err, f);
+        }
+        Type::Generic(_, args) | Type::Tuple(args) => args.iter().for_each(|a| visit_params(a, f)),
+        Type::Fn(params, ret) => {
+            params.iter().for_each(|p| visit_params(p, f));
+            visit_params(ret, f);
+        }
+        Type::Int
+        | Type::I64
+        | Type::U64
+        | Type::U8
+        | Type::F64
+        | Type::String
+        | Type::Bool
+        | Type::Unit
+        | Type::Path(_) => {}
+    }
+}
+
+/// Number of nodes in `ty`.
+fn size(ty: &Type) -> usize {
+    match ty {
+        Type::Ref(_, inner) | Type::Option(inner) | Type::List(inner) | Type::Set(inner) => {
+            1 + size(inner)
+        }
+        Type::Result(ok, err) | Type::Map(ok, err) => 1 + size(ok) + size(err),
+        Type::Generic(_, args) | Type::Tuple(args) => 1 + args.iter().map(size).sum::<usize>(),
+        Type::Fn(params, ret) => 1 + params.iter().map(size).sum::<usize>() + size(ret),
+        _ => 1,
+    }
+}
+
+/// Built-in traits implemented by the primitive types (through `print`, `==` and `<`).
+fn primitive_implements(ty: &Type, key: &str) -> bool {
+    match ty {
+        Type::Int | Type::I64 | Type::U64 | Type::U8 => {
+            matches!(key, "Display" | "Eq" | "Ord" | "Clone" | "Hash")
+        }
+        Type::F64 => matches!(key, "Display" | "Eq" | "Ord" | "Clone"),
+        Type::String => matches!(key, "Display" | "Eq" | "Ord" | "Clone" | "Hash"),
+        Type::Bool => matches!(key, "Display" | "Eq" | "Clone" | "Hash"),
+        _ => false,
+    }
+}
+
+impl Checker {
+    /// Bring the type parameters of a function into scope as `Type::Param`, resolving their
+    /// bounds to trait keys. Undo with `leave_type_params`.
+    pub(super) fn enter_type_params(
+        &mut self,
+        params: &[TypeParam],
+        span: Span,
+    ) -> Vec<TypeParamInfo> {
+        let mut infos = Vec::with_capacity(params.len());
+        for p in params {
+            if self.type_params.contains_key(&p.name) {
+                self.error(
+                    format!("type parameter `{}` is declared more than once", p.name),
+                    span,
+                );
+                continue;
+            }
+            let bounds = p
+                .bounds
+                .iter()
+                .filter_map(|path| self.resolve_trait(path, span))
+                .map(|info| info.key)
+                .collect();
+            self.type_params
+                .insert(p.name.clone(), Type::Param(p.name.clone()));
+            infos.push(TypeParamInfo {
+                name: p.name.clone(),
+                bounds,
+            });
+        }
+        infos
+    }
+
+    pub(super) fn leave_type_params(&mut self, params: &[TypeParamInfo]) {
+        for p in params {
+            self.type_params.remove(&p.name);
+        }
+    }
+
+    /// Bring the parameters of a generic struct (or impl block) into scope, without bounds.
+    pub(super) fn enter_struct_params(
+        &mut self,
+        names: &[String],
+        span: Span,
+    ) -> Vec<TypeParamInfo> {
+        let params: Vec<TypeParam> = names
+            .iter()
+            .map(|name| TypeParam {
+                name: name.clone(),
+                bounds: Vec::new(),
+            })
+            .collect();
+        self.enter_type_params(&params, span)
+    }
+
+    /// The type parameters of a generic impl block, which come first in its methods' signatures.
+    pub(super) fn impl_type_params(&self, imp: &ImplBlock) -> Vec<TypeParamInfo> {
+        imp.type_params
+            .iter()
+            .map(|name| TypeParamInfo {
+                name: name.clone(),
+                bounds: Vec::new(),
+            })
+            .collect()
+    }
+
+    /// Register a generic function; its signature's `id` becomes the template index.
+    pub(super) fn add_template(
+        &mut self,
+        module: usize,
+        owner: Option<(usize, String)>,
+        decl: &FnDecl,
+        sig: &mut FnSig,
+    ) {
+        sig.id = self.templates.len() as FnId;
+        self.templates.push(Template {
+            module,
+            owner,
+            decl: decl.clone(),
+            sig: sig.clone(),
+        });
+    }
+
+    /// The `FnId` of `template` instantiated with `args`, queueing the instantiation for checking
+    /// the first time. Arguments that still mention type parameters (in a generic body being
+    /// checked abstractly) get a placeholder: that body is never lowered.
+    fn instantiate(&mut self, template: FnId, args: Vec<Type>, span: Span) -> Option<FnId> {
+        if !args.iter().all(is_concrete) {
+            return Some(0);
+        }
+        if let Some(id) = self.instances.get(&(template, args.clone())) {
+            return Some(*id);
+        }
+        let t = &self.templates[template as usize];
+        if args.iter().map(size).sum::<usize>() > MAX_TYPE_SIZE {
+            let name = mangle(&t.decl.name, &args);
+            self.error(
+                format!("reached the recursion limit while instantiating `{}`", name),
+                span,
+            );
+            return None;
+        }
+        let id = self.next_fn_id();
+        self.instances.insert((template, args.clone()), id);
+        self.pending.push(Instance {
+            template: template as usize,
+            args,
+            id,
+        });
+        Some(id)
+    }
+
+    /// True if `ty` satisfies the bound `key`: a struct implementing the trait, a type parameter
+    /// bounded by it, or a primitive for the built-in traits it supports.
+    pub(super) fn implements(&self, ty: &Type, key: &str) -> bool {
+        primitive_implements(ty, key) || self.implements_builtin(ty, key)
+    }
+
+    /// Call of a generic function: infer its type arguments from the arguments (then from the
+    /// type the context expects), check their bounds, and instantiate it. `params` are the
+    /// parameter types in terms of the signature's type parameters; `map` holds those already
+    /// known (a generic struct's, from the receiver); `recv` is a receiver checked by the caller.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn check_generic_call(
+        &mut self,
+        span: Span,
+        callee: &str,
+        sig: &FnSig,
+        params: &[Type],
+        mut map: HashMap<String, Type>,
+        recv: Option<TExpr>,
+        args: &[Expr],
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        if params.len() != args.len() {
+            self.error(
+                format!(
+                    "`{}` takes {} arguments but {} were supplied",
+                    callee,
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            );
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        }
+        let mut ok = true;
+        let mut checked = Vec::with_capacity(args.len() + 1);
+        checked.extend(recv);
+        for (a, p) in args.iter().zip(params) {
+            let hint = closed(p, &map);
+            let Some(e) = self.check_value(a, hint.as_ref()) else {
+                ok = false;
+                continue;
+            };
+            if !unify(p, &e.ty, &mut map) {
+                self.error(
+                    format!(
+                        "mismatched types: expected `{}`, found `{}`",
+                        substitute(p, &map),
+                        e.ty
+                    ),
+                    a.span(),
+                );
+                ok = false;
+            }
+            checked.push(e);
+        }
+        if let Some(expected) = expected {
+            let mut with_ret = map.clone();
+            if unify(&sig.ret, expected, &mut with_ret) {
+                map = with_ret;
+            }
+        }
+        if !ok {
+            return None;
+        }
+        let mut type_args = Vec::with_capacity(sig.type_params.len());
+        for p in &sig.type_params {
+            let Some(arg) = map.get(&p.name) else {
+                self.error(
+                    format!(
+                        "type annotations needed: cannot infer type parameter `{}` of `{}`",
+                        p.name, callee
+                    ),
+                    span,
+                );
+                return None;
+            };
+            for key in &p.bounds {
+                if !self.implements(arg, key) {
+                    self.error(
+                        format!("the trait `{}` is not implemented for `{}`", key, arg),
+                        span,
+                    );
+                    ok = false;
+                }
+            }
+            type_args.push(arg.clone());
+        }
+        if !ok {
+            return None;
+        }
+        let id = self.instantiate(sig.id, type_args, span)?;
+        Some(TExpr {
+            span,
+            ty: substitute(&sig.ret, &map),
+            kind: TExprKind::Call {
+                callee: Callee::Fn(id),
+                args: checked,
+            },
+        })
+    }
+
+    /// Parameter types of a method or associated function called by path (`Type::f(..)`),
+    /// with the receiver first.
+    pub(super) fn path_call_params(&self, ty: &Type, sig: &FnSig) -> Vec<Type> {
+        let owner = match (ty, sig.is_generic()) {
+            (Type::Path(p), true) => {
+                let n = self.struct_info(ty).map_or(0, |s| s.type_params.len());
+                let params = sig.type_params[..n]
+                    .iter()
+                    .map(|p| Type::Param(p.name.clone()))
+                    .collect();
+                struct_type_with(&p[0], &p[1], params)
+            }
+            _ => ty.clone(),
+        };
+        let mut params: Vec<Type> = sig
+            .receiver
+            .map(|r| self_type(r, owner))
+            .into_iter()
+            .collect();
+        params.extend(sig.params.iter().cloned());
+        params
+    }
+
+    /// The type arguments a method of generic struct `ty` gets from it.
+    pub(super) fn receiver_bindings(&self, ty: &Type, sig: &FnSig) -> HashMap<String, Type> {
+        let names: Vec<String> = sig.type_params.iter().map(|p| p.name.clone()).collect();
+        bind(&names, type_args(ty))
+    }
+
+    /// Statically resolved call of a trait method of a generic struct instance.
+    pub(super) fn instantiate_method(
+        &mut self,
+        ty: &Type,
+        sig: &FnSig,
+        span: Span,
+    ) -> Option<(FnId, Type)> {
+        if !sig.is_generic() {
+            return Some((sig.id, sig.ret.clone()));
+        }
+        let map = self.receiver_bindings(ty, sig);
+        let args = type_args(ty).to_vec();
+        let id = self.instantiate(sig.id, args, span)?;
+        Some((id, substitute(&sig.ret, &map)))
+    }
+
+    /// Record a generic struct instance built by a struct literal, so that it gets a layout.
+    pub(super) fn note_struct_instance(&mut self, ty: &Type) {
+        if matches!(ty, Type::Generic(..)) && is_concrete(ty) && !self.struct_instances.contains(ty)
+        {
+            self.struct_instances.push(ty.clone());
+        }
+    }
+
+    /// Layouts of the recorded generic struct instances, named `Pair[int, string]`. Instances
+    /// that differ only in newtypes share the layout named after the types they are represented as.
+    pub(super) fn struct_instance_layouts(&self) -> Vec<StructLayout> {
+        let mut named = HashSet::new();
+        self.struct_instances
+            .iter()
+            .filter_map(|ty| {
+                let Type::Generic(p, _) = ty else {
+                    return None;
+                };
+                let args = type_args(&self.repr(ty)).to_vec();
+                let name = mangle(&p[1], &args);
+                if !named.insert(name.clone()) {
+                    return None;
+                }
+                let fields = self.struct_info(ty)?.fields_with(&args, |t| self.repr(t));
+                Some(self.layout(&p[0], name, fields))
+            })
+            .collect()
+    }
+
+    /// Check every queued instantiation (which may queue more) as an ordinary function with its
+    /// type parameters bound to the arguments.
+    pub(super) fn check_instances(&mut self) -> Vec<(FnId, TFunction)> {
+        let mut functions = Vec::new();
+        let mut next = 0;
+        while next < self.pending.len() {
+            let Instance { template, args, id } = &self.pending[next];
+            let (args, id) = (args.clone(), *id);
+            let t = &self.templates[*template];
+            let (module, owner, decl) = (t.module, t.owner.clone(), t.decl.clone());
+            let names: Vec<String> = t.sig.type_params.iter().map(|p| p.name.clone()).collect();
+            let map = bind(&names, &args);
+            let sig = FnSig {
+                id,
+                type_params: Vec::new(),
+                params: t.sig.params.iter().map(|p| substitute(p, &map)).collect(),
+                ret: substitute(&t.sig.ret, &map),
+                ..t.sig.clone()
+            };
+            let self_ty = owner.as_ref().map(|(m, name)| {
+                let n = self.modules[*m].structs[name].type_params.len();
+                struct_type_with(&self.modules[*m].name, name, args[..n].to_vec())
+            });
+            self.type_params = map;
+            let owner = owner.as_ref().map(|(m, name)| (*m, name.as_str()));
+            let tf = self.check_body(module, owner, self_ty, &decl, &sig, &args);
+            self.type_params.clear();
+            functions.push((id, tf));
+            next += 1;
+        }
+        functions
+    }
+}
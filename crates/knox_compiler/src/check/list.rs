@@ -0,0 +1,309 @@
+//! `List<T>`: array literals, indexing, `for` over a list, and the built-in list methods.
+
+use super::map::map_parts;
+use super::Checker;
+use crate::typed::{
+    Callee, ListMethod, LocalId, TBlock, TExpr, TExprKind, TLocal, TPattern, TStmt,
+};
+use knox_syntax::ast::{Block, Expr, Type};
+use knox_syntax::span::Span;
+use std::collections::HashMap;
+
+/// Element type of a list or of a reference to one.
+fn list_elem(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::List(elem) => Some(elem),
+        Type::Ref(_, inner) => match &**inner {
+            Type::List(elem) => Some(elem),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Built-in method `name` of `List<elem>`: parameter types, return type, and whether it takes `&mut self`.
+/// `get` gives an element as `read` (see `Checker::read_type`).
+fn list_method(
+    elem: &Type,
+    read: &Type,
+    name: &str,
+) -> Option<(ListMethod, Vec<Type>, Type, bool)> {
+    let item = |ty: &Type| Type::Option(Box::new(ty.clone()));
+    Some(match name {
+        "push" => (ListMethod::Push, vec![elem.clone()], Type::Unit, true),
+        "pop" => (ListMethod::Pop, Vec::new(), item(elem), true),
+        "len" => (ListMethod::Len, Vec::new(), Type::Int, false),
+        "get" => (ListMethod::Get, vec![Type::Int], item(read), false),
+        _ => return None,
+    })
+}
+
+impl Checker {
+    /// `[a, b, c]`: every element has the first one's type. An empty literal takes its element
+    /// type from the expected type.
+    pub(super) fn check_array_literal(
+        &mut self,
+        span: Span,
+        elements: &[Expr],
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let hint = match expected {
+            Some(Type::List(elem)) => Some((**elem).clone()),
+            _ => None,
+        };
+        let mut elem_ty: Option<Type> = None;
+        let mut checked = Vec::with_capacity(elements.len());
+        let mut ok = true;
+        for e in elements {
+            let Some(te) = self.check_value(e, elem_ty.as_ref().or(hint.as_ref())) else {
+                ok = false;
+                continue;
+            };
+            match &elem_ty {
+                Some(ty) if *ty != te.ty => {
+                    self.error(
+                        format!("mismatched types: expected `{}`, found `{}`", ty, te.ty),
+                        e.span(),
+                    );
+                    ok = false;
+                }
+                Some(_) => {}
+                None => elem_ty = Some(te.ty.clone()),
+            }
+            checked.push(te);
+        }
+        if !ok {
+            return None;
+        }
+        let Some(elem) = elem_ty.or(hint) else {
+            self.error(
+                "type annotations needed: cannot infer the element type of `[]`",
+                span,
+            );
+            return None;
+        };
+        Some(TExpr {
+            span,
+            ty: Type::List(Box::new(elem)),
+            kind: TExprKind::List(checked),
+        })
+    }
+
+    /// `[value; count]`: a list of `count` copies of a `Copy` value, where `count` is a constant
+    /// `int`. It is built like `{ let v = value; let mut xs = []; for _ in 0..count { xs.push(v) }; xs }`.
+    pub(super) fn check_array_repeat(
+        &mut self,
+        span: Span,
+        value: &Expr,
+        count: &Expr,
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let hint = match expected {
+            Some(Type::List(elem)) => Some(&**elem),
+            _ => None,
+        };
+        let value = self.check_expr_with(value, hint);
+        let count = self.check_expr_as(count, &Type::Int);
+        let (value, count) = (value?, count?);
+        let count = match self.eval_const(&count)? {
+            TExprKind::Int(n) if n >= 0 => n,
+            _ => {
+                self.error("array size must not be negative", count.span);
+                return None;
+            }
+        };
+        if !self.is_copy(&value.ty) {
+            self.error(
+                format!(
+                    "the value of `[value; count]` is copied, but `{}` is not `Copy`",
+                    value.ty
+                ),
+                value.span,
+            );
+            return None;
+        }
+        let ty = Type::List(Box::new(value.ty.clone()));
+        let hidden = |checker: &mut Self, name: &str, ty: &Type, mutable: bool| {
+            checker.locals.push(TLocal {
+                name: name.to_string(),
+                ty: ty.clone(),
+                mutable,
+                can_be_mut: false,
+                span,
+            });
+            (checker.locals.len() - 1) as LocalId
+        };
+        let local = |id: LocalId, ty: &Type| TExpr {
+            span,
+            ty: ty.clone(),
+            kind: TExprKind::Local(id),
+        };
+        let elem = hidden(self, "value", &value.ty, false);
+        let list = hidden(self, "list", &ty, true);
+        let index = hidden(self, "index", &Type::Int, false);
+        let push = TExpr {
+            span,
+            ty: Type::Unit,
+            kind: TExprKind::Call {
+                callee: Callee::List(ListMethod::Push),
+                args: vec![local(list, &ty), self.copied(local(elem, &value.ty))],
+            },
+        };
+        let int = |n: i64| TExpr {
+            span,
+            ty: Type::Int,
+            kind: TExprKind::Int(n),
+        };
+        let fill = TExpr {
+            span,
+            ty: Type::Unit,
+            kind: TExprKind::ForRange {
+                var: index,
+                start: Box::new(int(0)),
+                end: Box::new(int(count)),
+                body: TBlock {
+                    stmts: vec![TStmt::Expr(push)],
+                    value: None,
+                },
+            },
+        };
+        let stmts = vec![
+            TStmt::Let {
+                pattern: TPattern::Binding(elem),
+                init: value,
+            },
+            TStmt::Let {
+                pattern: TPattern::Binding(list),
+                init: TExpr {
+                    span,
+                    ty: ty.clone(),
+                    kind: TExprKind::List(Vec::new()),
+                },
+            },
+            TStmt::Expr(fill),
+        ];
+        Some(TExpr {
+            span,
+            ty: ty.clone(),
+            kind: TExprKind::Block(TBlock {
+                stmts,
+                value: Some(Box::new(local(list, &ty))),
+            }),
+        })
+    }
+
+    /// `base[index]` on a list (or a reference to one) with an `int` index.
+    pub(super) fn check_index(&mut self, span: Span, base: &Expr, index: &Expr) -> Option<TExpr> {
+        let base = self.check_expr(base);
+        let index = self.check_expr(index);
+        let (base, index) = (base?, index?);
+        let Some(elem) = list_elem(&base.ty).cloned() else {
+            self.error(
+                format!("cannot index into a value of type `{}`", base.ty),
+                span,
+            );
+            return None;
+        };
+        if index.ty != Type::Int {
+            self.error(
+                format!("list indices must be `int`, found `{}`", index.ty),
+                index.span,
+            );
+            return None;
+        }
+        Some(TExpr {
+            span,
+            ty: elem,
+            kind: TExprKind::Index {
+                base: Box::new(base),
+                index: Box::new(index),
+            },
+        })
+    }
+
+    /// `list.name(args)` on a checked list receiver: `push`, `pop`, `len` or `get`.
+    pub(super) fn check_list_method(
+        &mut self,
+        span: Span,
+        recv: TExpr,
+        name: &str,
+        args: &[Expr],
+    ) -> Option<TExpr> {
+        let elem = list_elem(&recv.ty)?.clone();
+        let read = self.read_type(&elem);
+        let Some((method, params, ret, mutates)) = list_method(&elem, &read, name) else {
+            self.error(
+                format!(
+                    "no method named `{}` found for `{}`",
+                    name,
+                    Type::List(Box::new(elem))
+                ),
+                span,
+            );
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        };
+        if mutates && matches!(recv.ty, Type::Ref(false, _)) {
+            self.error(
+                format!(
+                    "cannot call `{}` through a `&` reference: it takes `&mut self`",
+                    name
+                ),
+                span,
+            );
+        }
+        let rest = self.check_args(span, name, &params, args)?;
+        let mut all_args = vec![recv];
+        all_args.extend(rest);
+        Some(TExpr {
+            span,
+            ty: ret,
+            kind: TExprKind::Call {
+                callee: Callee::List(method),
+                args: all_args,
+            },
+        })
+    }
+
+    /// `for var in list`: `var` takes each element in order and is scoped to the body.
+    /// A map or set is iterated over its keys, in insertion order.
+    pub(super) fn check_for_each(
+        &mut self,
+        span: Span,
+        label: &Option<String>,
+        var: (&str, Span),
+        iter: &Expr,
+        body: &Block,
+    ) -> Option<TExpr> {
+        let list = self.check_expr(iter);
+        let elem = list.as_ref().and_then(|l| {
+            let elem = list_elem(&l.ty).or_else(|| map_parts(&l.ty).map(|(key, _)| key));
+            if elem.is_none() {
+                self.error(
+                    format!(
+                        "`for` expects a range `start..end`, a `List`, a `Map` or a `Set`, found `{}`",
+                        l.ty
+                    ),
+                    iter.span(),
+                );
+            }
+            elem.map(|e| self.read_type(e))
+        });
+        self.scopes.push(HashMap::new());
+        let var = self.declare(var.0, elem.clone(), var.1);
+        let (body, _) = self.check_loop_body(label, false, body);
+        self.scopes.pop();
+        elem?;
+        Some(TExpr {
+            span,
+            ty: Type::Unit,
+            kind: TExprKind::ForEach {
+                var,
+                list: Box::new(list?),
+                body,
+            },
+        })
+    }
+}
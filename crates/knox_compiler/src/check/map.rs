@@ -0,0 +1,228 @@
+//! `Map<K, V>` and `Set<K>`: `Map::new()`/`Set::new()` and the built-in methods.
+
+use super::Checker;
+use crate::typed::{holds_resource, Callee, FnId, KeyOps, MapMethod, TExpr, TExprKind};
+use knox_syntax::ast::{Expr, Type};
+use knox_syntax::span::Span;
+
+/// Key type and value type (None for a set) of a map or set, or of a reference to one.
+pub(super) fn map_parts(ty: &Type) -> Option<(&Type, Option<&Type>)> {
+    match ty {
+        Type::Map(key, value) => Some((key, Some(value))),
+        Type::Set(key) => Some((key, None)),
+        Type::Ref(_, inner) => map_parts(inner),
+        _ => None,
+    }
+}
+
+/// Built-in method `name` of a map (or a set when `value` is None): parameter types, return
+/// type, and whether it takes `&mut self`. `get` gives a value as `read` (see
+/// `Checker::read_type`). `insert` into a map whose values hold a resource gives back the
+/// value it replaces, so the caller has to consume it.
+fn map_method(
+    key: &Type,
+    value: Option<&Type>,
+    read: Option<&Type>,
+    displaces: bool,
+    name: &str,
+) -> Option<(MapMethod, Vec<Type>, Type, bool)> {
+    let key = key.clone();
+    Some(match (name, value) {
+        ("insert", Some(v)) => {
+            let ret = if displaces {
+                Type::Option(Box::new(v.clone()))
+            } else {
+                Type::Unit
+            };
+            (MapMethod::Insert, vec![key, v.clone()], ret, true)
+        }
+        ("insert", None) => (MapMethod::Insert, vec![key], Type::Unit, true),
+        ("get", Some(v)) => (
+            MapMethod::Get,
+            vec![key],
+            Type::Option(Box::new(read.unwrap_or(v).clone())),
+            false,
+        ),
+        ("remove", Some(v)) => (
+            MapMethod::Remove,
+            vec![key],
+            Type::Option(Box::new(v.clone())),
+            true,
+        ),
+        ("remove", None) => (MapMethod::Remove, vec![key], Type::Bool, true),
+        ("contains", _) => (MapMethod::Contains, vec![key], Type::Bool, false),
+        ("len", _) => (MapMethod::Len, Vec::new(), Type::Int, false),
+        _ => return None,
+    })
+}
+
+impl Checker {
+    /// How keys of type `key` are compared and hashed. A key is an integer, a `bool` or a
+    /// `string`, a struct that implements `Eq` and `Hash`, or a type parameter bounded by both.
+    /// A resource cannot be a key: inserting a key equal to one already present would drop it.
+    fn key_ops(&mut self, key: &Type, span: Span) -> Option<KeyOps> {
+        let builtin = KeyOps {
+            eq: None,
+            hash: None,
+        };
+        if holds_resource(&self.resources, key) {
+            self.error(
+                format!(
+                    "`{}` cannot be used as a key: it holds a resource, which an equal key would replace and drop",
+                    key
+                ),
+                span,
+            );
+            return None;
+        }
+        match key {
+            Type::Int | Type::I64 | Type::U64 | Type::U8 | Type::Bool | Type::String => {
+                Some(builtin)
+            }
+            _ if matches!(key, Type::Param(_)) || self.struct_info(key).is_some() => {
+                for trait_ in ["Eq", "Hash"] {
+                    if !self.implements_builtin(key, trait_) {
+                        self.error(
+                            format!("the trait `{}` is not implemented for `{}`", trait_, key),
+                            span,
+                        );
+                        return None;
+                    }
+                }
+                if let Type::Param(_) = key {
+                    return Some(builtin);
+                }
+                Some(KeyOps {
+                    eq: Some(self.method_id(key, "eq", span)?),
+                    hash: Some(self.method_id(key, "hash", span)?),
+                })
+            }
+            _ => {
+                self.error(
+                    format!(
+                        "`{}` cannot be used as a key: keys must be integers, `bool`, `string` or a struct that implements `Eq` and `Hash`",
+                        key
+                    ),
+                    span,
+                );
+                None
+            }
+        }
+    }
+
+    /// Function id of method `name` of struct type `ty`, instantiating it if generic.
+    fn method_id(&mut self, ty: &Type, name: &str, span: Span) -> Option<FnId> {
+        let sig = self.struct_info(ty)?.methods.get(name)?.clone();
+        Some(self.instantiate_method(ty, &sig, span)?.0)
+    }
+
+    /// `Map::new()` or `Set::new()`: the key and value types come from the expected type.
+    pub(super) fn check_collection_new(
+        &mut self,
+        span: Span,
+        owner: &str,
+        name: &str,
+        args: &[Expr],
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let path = format!("{}::{}", owner, name);
+        if name != "new" {
+            self.error(
+                format!(
+                    "no function or associated item named `{}` found for `{}`",
+                    name, owner
+                ),
+                span,
+            );
+            return None;
+        }
+        self.check_args(span, &path, &[], args)?;
+        let ty = match (owner, expected) {
+            ("Map", Some(ty @ Type::Map(_, _))) | ("Set", Some(ty @ Type::Set(_))) => ty.clone(),
+            _ => {
+                let what = if owner == "Map" {
+                    "the key and value types"
+                } else {
+                    "the element type"
+                };
+                self.error(
+                    format!(
+                        "type annotations needed: cannot infer {} of `{}()`",
+                        what, path
+                    ),
+                    span,
+                );
+                return None;
+            }
+        };
+        let key = map_parts(&ty)?.0.clone();
+        let ops = self.key_ops(&key, span)?;
+        Some(TExpr {
+            span,
+            ty,
+            kind: TExprKind::Call {
+                callee: Callee::Map(MapMethod::New, ops),
+                args: Vec::new(),
+            },
+        })
+    }
+
+    /// `map.name(args)` on a checked map or set receiver.
+    pub(super) fn check_map_method(
+        &mut self,
+        span: Span,
+        recv: TExpr,
+        name: &str,
+        args: &[Expr],
+    ) -> Option<TExpr> {
+        let (key, value) = map_parts(&recv.ty)?;
+        let (key, value) = (key.clone(), value.cloned());
+        let read = value.as_ref().map(|v| self.read_type(v));
+        let displaces = value
+            .as_ref()
+            .is_some_and(|v| holds_resource(&self.resources, v));
+        let Some((method, params, ret, mutates)) =
+            map_method(&key, value.as_ref(), read.as_ref(), displaces, name)
+        else {
+            let ty = match &recv.ty {
+                Type::Ref(_, inner) => (**inner).clone(),
+                ty => ty.clone(),
+            };
+            self.error(
+                format!("no method named `{}` found for `{}`", name, ty),
+                span,
+            );
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        };
+        if mutates && matches!(recv.ty, Type::Ref(false, _)) {
+            self.error(
+                format!(
+                    "cannot call `{}` through a `&` reference: it takes `&mut self`",
+                    name
+                ),
+                span,
+            );
+        }
+        let rest = self.check_args(span, name, &params, args)?;
+        let ops = match method {
+            MapMethod::Len => KeyOps {
+                eq: None,
+                hash: None,
+            },
+            _ => self.key_ops(&key, span)?,
+        };
+        let mut all_args = vec![recv];
+        all_args.extend(rest);
+        Some(TExpr {
+            span,
+            ty: ret,
+            kind: TExprKind::Call {
+                callee: Callee::Map(method, ops),
+                args: all_args,
+            },
+        })
+    }
+}
@@ -0,0 +1,397 @@
+//! Impl blocks: collecting methods and associated functions, and resolving field accesses and calls to them.
+
+use super::env::{struct_type, struct_type_with, type_args, FnSig};
+use super::number::number_method;
+use super::string::string_method;
+use super::Checker;
+use crate::typed::{Callee, TExpr, TExprKind};
+use knox_syntax::ast::{Expr, FnDecl, ImplBlock, Receiver, Type, Visibility};
+use knox_syntax::span::{Location, Span};
+use knox_syntax::Diagnostic;
+use std::collections::HashMap;
+
+impl Checker {
+    /// Register the functions of an `impl` block in the method table of its struct.
+    pub(super) fn collect_methods(&mut self, module: usize, imp: &ImplBlock) {
+        let Some(ty) = self.impl_owner(module, imp) else {
+            return;
+        };
+        let params = self.enter_struct_params(&imp.type_params, imp.span);
+        self.self_ty = Some(ty);
+        for f in &imp.methods {
+            self.add_method(module, imp, f, f.vis);
+        }
+        self.self_ty = None;
+        self.leave_type_params(&params);
+    }
+
+    /// The struct an `impl` block is for; it must be declared in the same module. A generic
+    /// struct is instantiated with the block's type parameters.
+    pub(super) fn impl_owner(&mut self, module: usize, imp: &ImplBlock) -> Option<Type> {
+        let env = &self.modules[module];
+        let Some(info) = env.structs.get(&imp.name) else {
+            let msg = if env.enums.contains_key(&imp.name) {
+                format!("expected struct, found enum `{}`", imp.name)
+            } else {
+                format!("cannot find type `{}`", imp.name)
+            };
+            self.error(msg, imp.span);
+            return None;
+        };
+        let expected = info.type_params.len();
+        let args = imp
+            .type_params
+            .iter()
+            .map(|p| Type::Param(p.clone()))
+            .collect();
+        let ty = struct_type_with(&env.name, &imp.name, args);
+        self.check_type_arg_count(&imp.name, expected, imp.type_params.len(), imp.span)
+            .then_some(ty)
+    }
+
+    /// Resolve `f`'s signature (with `Self` and the impl's type parameters in scope) and add it
+    /// to the method table of the impl's struct. A method of a generic struct, or one with type
+    /// parameters of its own, is a generic function.
+    pub(super) fn add_method(
+        &mut self,
+        module: usize,
+        imp: &ImplBlock,
+        f: &FnDecl,
+        vis: Visibility,
+    ) -> Option<FnSig> {
+        let owner = &imp.name;
+        if self.modules[module].structs[owner]
+            .methods
+            .contains_key(&f.name)
+        {
+            self.error(
+                format!(
+                    "method `{}` is defined more than once for `{}`",
+                    f.name, owner
+                ),
+                f.span,
+            );
+            return None;
+        }
+        let generic = !imp.type_params.is_empty() || !f.type_params.is_empty();
+        let id = if generic { 0 } else { self.next_fn_id() };
+        let mut sig = self.fn_sig(id, f);
+        sig.vis = vis;
+        if generic {
+            let mut type_params = self.impl_type_params(imp);
+            type_params.append(&mut sig.type_params);
+            sig.type_params = type_params;
+            self.add_template(module, Some((module, owner.clone())), f, &mut sig);
+        }
+        if let Some(info) = self.modules[module].structs.get_mut(owner) {
+            info.methods.insert(f.name.clone(), sig.clone());
+        }
+        Some(sig)
+    }
+
+    /// If `prefix` (`Name` or `module::Name`) names a struct, its module index and name.
+    /// Reports nothing: a path that is not a struct is left to the caller.
+    pub(super) fn struct_prefix(&self, prefix: &[String]) -> Option<(usize, String)> {
+        let (module, name) = match prefix {
+            [name] => (self.module, name),
+            [module, name] => (self.resolve_module(module)?, name),
+            _ => return None,
+        };
+        self.modules[module]
+            .structs
+            .contains_key(name)
+            .then(|| (module, name.clone()))
+    }
+
+    /// Method or associated function `name` of a canonical struct type, or the trait method
+    /// `name` of a type parameter's bounds.
+    fn method_sig(&mut self, ty: &Type, name: &str) -> Option<FnSig> {
+        if let Type::Param(param) = ty {
+            return self.param_method(param, name);
+        }
+        self.struct_info(ty)?.methods.get(name).cloned()
+    }
+
+    /// Methods are private to the struct's module unless declared `pub`.
+    fn method_visible(&mut self, ty: &Type, name: &str, sig: &FnSig, span: Span) -> bool {
+        let (Type::Path(p) | Type::Generic(p, _)) = ty else {
+            return true;
+        };
+        if p[0] != self.current().name && sig.vis != Visibility::Exported {
+            self.error(
+                format!("method `{}` is private to module `{}`", name, p[0]),
+                span,
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Fields are private to the struct's module: elsewhere a struct is built, read and changed
+    /// through the functions and methods its module exports. With `hint_constructor`, the error
+    /// points to an exported function that builds the struct, if there is one.
+    pub(super) fn field_visible(
+        &mut self,
+        ty: &Type,
+        name: &str,
+        span: Span,
+        hint_constructor: bool,
+    ) -> bool {
+        let (Type::Path(p) | Type::Generic(p, _)) = ty else {
+            return true;
+        };
+        if p[0] == self.current().name {
+            return true;
+        }
+        let msg = format!(
+            "field `{}` of `{}` is private to module `{}`",
+            name, p[1], p[0]
+        );
+        let mut diag = Diagnostic::error(msg, Some(Location::new(self.current().file, span)));
+        if let Some((path, at)) = hint_constructor.then(|| self.constructor(p)).flatten() {
+            diag = diag.with_note(
+                format!("build it with `{}`, which module `{}` exports", path, p[0]),
+                at,
+            );
+        }
+        self.diags.push(diag);
+        false
+    }
+
+    /// An exported function of the struct `p`'s module, or a `pub` associated function of the
+    /// struct, that returns one: its path and where it is declared. The first declared wins.
+    fn constructor(&self, p: &[String]) -> Option<(String, Location)> {
+        let env = &self.modules[*self.by_name.get(&p[0])?];
+        let builds = |sig: &FnSig| {
+            sig.vis == Visibility::Exported
+                && matches!(&sig.ret, Type::Path(r) | Type::Generic(r, _) if r == p)
+        };
+        let free = env
+            .fns
+            .iter()
+            .filter(|(_, sig)| builds(sig))
+            .map(|(name, sig)| (format!("{}::{}", p[0], name), sig.span));
+        let associated = env
+            .structs
+            .get(&p[1])?
+            .methods
+            .iter()
+            .filter(|(_, sig)| sig.receiver.is_none() && builds(sig))
+            .map(|(name, sig)| (format!("{}::{}::{}", p[0], p[1], name), sig.span));
+        let (path, span) = free.chain(associated).min_by_key(|(_, span)| span.start)?;
+        Some((path, Location::new(env.file, span)))
+    }
+
+    /// `value.name(args)`: the receiver is passed as the first argument, by pointer whatever the
+    /// method's receiver kind; calling a `&mut self` method through a `&` reference is an error.
+    pub(super) fn check_method_call(
+        &mut self,
+        span: Span,
+        receiver: &Expr,
+        name: &str,
+        args: &[Expr],
+    ) -> Option<TExpr> {
+        let check_args_only = |this: &mut Self| {
+            for a in args {
+                this.check_expr(a);
+            }
+        };
+        let Some(recv) = self.check_expr(receiver) else {
+            check_args_only(self);
+            return None;
+        };
+        let (shared_ref, ty) = match &recv.ty {
+            Type::Ref(mut_, inner) => (!mut_, (**inner).clone()),
+            t => (false, t.clone()),
+        };
+        match ty {
+            Type::List(_) => return self.check_list_method(span, recv, name, args),
+            Type::Map(_, _) | Type::Set(_) => return self.check_map_method(span, recv, name, args),
+            _ => {}
+        }
+        if string_method(&recv.ty, name).is_some() {
+            return self.check_string_method(span, recv, name, args);
+        }
+        if number_method(&recv.ty, name).is_some() {
+            return self.check_number_method(span, recv, name, args);
+        }
+        // `clone()` on a primitive (e.g. in an instantiation with `T = int`) is the value itself.
+        if name == "clone"
+            && args.is_empty()
+            && (recv.ty.is_numeric() || matches!(recv.ty, Type::Bool | Type::String))
+        {
+            return Some(recv);
+        }
+        if name == "hash"
+            && args.is_empty()
+            && matches!(
+                recv.ty,
+                Type::Int | Type::I64 | Type::U64 | Type::U8 | Type::Bool | Type::String
+            )
+        {
+            return Some(TExpr {
+                span,
+                ty: Type::Int,
+                kind: TExprKind::Call {
+                    callee: Callee::Hash,
+                    args: vec![recv],
+                },
+            });
+        }
+        let Some(sig) = self.method_sig(&ty, name) else {
+            self.error(
+                format!("no method named `{}` found for `{}`", name, ty),
+                span,
+            );
+            check_args_only(self);
+            return None;
+        };
+        if !self.method_visible(&ty, name, &sig, span) {
+            check_args_only(self);
+            return None;
+        }
+        match sig.receiver {
+            None => {
+                self.error(
+                    format!(
+                        "`{}` is an associated function of `{}`, not a method; call it as `{}::{}(..)`",
+                        name, ty, ty, name
+                    ),
+                    span,
+                );
+                check_args_only(self);
+                return None;
+            }
+            Some(Receiver::RefMut) if shared_ref => {
+                self.error(
+                    format!(
+                        "cannot call `{}` through a `&` reference: it takes `&mut self`",
+                        name
+                    ),
+                    span,
+                );
+            }
+            Some(_) => {}
+        }
+        if sig.is_generic() {
+            let map = self.receiver_bindings(&ty, &sig);
+            let params = sig.params.clone();
+            return self.check_generic_call(span, name, &sig, &params, map, Some(recv), args, None);
+        }
+        let rest = self.check_args(span, name, &sig.params, args)?;
+        let mut all_args = vec![recv];
+        all_args.extend(rest);
+        Some(TExpr {
+            span,
+            ty: sig.ret,
+            kind: TExprKind::Call {
+                callee: Callee::Fn(sig.id),
+                args: all_args,
+            },
+        })
+    }
+
+    /// `Type::name(args)` or `module::Type::name(args)`. A method called this way takes its
+    /// receiver as an explicit first argument. The type arguments of a generic struct are
+    /// inferred like those of a generic function.
+    pub(super) fn check_assoc_call(
+        &mut self,
+        span: Span,
+        qualifier: &[String],
+        name: &str,
+        args: &[Expr],
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let path = format!("{}::{}", qualifier.join("::"), name);
+        let sig = self
+            .resolve_type_name(qualifier, span)
+            .and_then(|(module, owner)| {
+                let ty = struct_type(&self.modules[module].name, &owner);
+                let Some(sig) = self.method_sig(&ty, name) else {
+                    self.error(
+                        format!(
+                            "no function or associated item named `{}` found for struct `{}`",
+                            name, ty
+                        ),
+                        span,
+                    );
+                    return None;
+                };
+                self.method_visible(&ty, name, &sig, span)
+                    .then_some((ty, sig))
+            });
+        let Some((ty, sig)) = sig else {
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        };
+        let params = self.path_call_params(&ty, &sig);
+        if sig.is_generic() {
+            let map = HashMap::new();
+            return self.check_generic_call(span, &path, &sig, &params, map, None, args, expected);
+        }
+        let args = self.check_args(span, &path, &params, args)?;
+        Some(TExpr {
+            span,
+            ty: sig.ret,
+            kind: TExprKind::Call {
+                callee: Callee::Fn(sig.id),
+                args,
+            },
+        })
+    }
+
+    /// `value.name` on a struct or a reference to one, or `value.0` on a tuple.
+    pub(super) fn check_field(&mut self, span: Span, receiver: &Expr, name: &str) -> Option<TExpr> {
+        let base = self.check_expr(receiver)?;
+        let ty = match &base.ty {
+            Type::Ref(_, inner) => (**inner).clone(),
+            t => t.clone(),
+        };
+        if let Type::Tuple(elems) = &ty {
+            let Some((index, elem)) = name
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| Some((i, elems.get(i)?.clone())))
+            else {
+                self.error(format!("no field `{}` on type `{}`", name, ty), span);
+                return None;
+            };
+            return Some(TExpr {
+                span,
+                ty: elem,
+                kind: TExprKind::TupleField {
+                    base: Box::new(base),
+                    index,
+                },
+            });
+        }
+        let info = self.struct_info(&ty);
+        let Some((field_ty, offset)) =
+            info.and_then(|s| s.field_layout(name, type_args(&ty), |t| self.repr(t)))
+        else {
+            let msg = if info.is_some_and(|s| s.methods.contains_key(name)) {
+                format!(
+                    "attempted to take value of method `{}` on type `{}`; call it with `{}(..)`",
+                    name, ty, name
+                )
+            } else {
+                format!("no field `{}` on type `{}`", name, ty)
+            };
+            self.error(msg, span);
+            return None;
+        };
+        if !self.field_visible(&ty, name, span, false) {
+            return None;
+        }
+        Some(TExpr {
+            span,
+            ty: field_ty,
+            kind: TExprKind::Field {
+                base: Box::new(base),
+                offset,
+            },
+        })
+    }
+}
@@ -0,0 +1,2408 @@
+//! Semantic analysis: resolves names and type-checks every function before lowering.
+//! Module 0 is the entry module; module i is parsed with FileId(i). The output is a typed AST.
+
+mod alias;
+mod closure;
+mod consts;
+mod copy;
+mod env;
+mod expr;
+mod generic;
+mod list;
+mod map;
+mod method;
+mod newtype;
+mod number;
+mod pattern;
+mod string;
+mod traits;
+mod variant;
+
+use crate::typed::{
+    carries_ref, holds_resource, FnId, LocalId, TBlock, TFunction, TLocal, TPattern, TStmt,
+    TypedProgram,
+};
+use closure::ClosureFrame;
+use env::{
+    self_type, struct_type, struct_type_with, AliasInfo, AliasState, ConstInfo, ConstState,
+    EnumInfo, FieldInfo, FnSig, ModuleEnv, StructInfo, TraitInfo, VariantInfo, VariantKind,
+};
+use generic::{Instance, Template};
+use knox_syntax::ast::{
+    Block, EnumDecl, FnDecl, Item, MatchPattern, Root, Stmt, Type, VariantPayload, Visibility,
+};
+use knox_syntax::span::{FileId, Location, Span};
+use knox_syntax::{field_byte_size, mangle, Diagnostic, Level, StructLayout};
+use std::collections::{HashMap, HashSet};
+use traits::DefaultMethod;
+
+/// Check the entry module (first) and every loaded dependency. Returns all diagnostics on failure;
+/// on success, warnings are kept in `TypedProgram::warnings`.
+pub fn check_program(modules: &[(String, Root)]) -> Result<TypedProgram, Vec<Diagnostic>> {
+    let mut checker = Checker::new(modules);
+    checker.collect_items(modules);
+    checker.eval_consts();
+    if let Some((_, main_root)) = modules.first() {
+        checker.check_entry(main_root);
+    }
+    let mut checked = Vec::new();
+    for (module, (_, root)) in modules.iter().enumerate() {
+        for item in &root.items {
+            let (owner, fns) = match item {
+                Item::Fn(f) => (None, std::slice::from_ref(f)),
+                Item::Impl(imp) => (Some((module, imp.name.as_str())), imp.methods.as_slice()),
+                _ => continue,
+            };
+            for f in fns {
+                checked.extend(checker.check_fn(module, owner, f));
+            }
+        }
+    }
+    checked.extend(checker.check_default_methods());
+    let has_errors = |c: &Checker| c.diags.iter().any(|d| d.level == Level::Error);
+    // Instantiations of generic bodies that failed to check would only repeat their errors.
+    if has_errors(&checker) {
+        return Err(checker.diags);
+    }
+    checked.extend(checker.check_instances());
+    if has_errors(&checker) {
+        return Err(checker.diags);
+    }
+    checked.append(&mut checker.lifted);
+    let mut functions: Vec<Option<TFunction>> = vec![None; checker.fn_count as usize];
+    for (id, tf) in checked {
+        functions[id as usize] = Some(tf);
+    }
+    let ref_structs = checker.ref_structs();
+    Ok(TypedProgram {
+        functions: functions.into_iter().flatten().collect(),
+        struct_layouts: checker.struct_layouts(),
+        newtypes: checker.newtypes(),
+        warnings: checker.diags,
+        resources: checker.resources,
+        moved_types: checker.moved_types,
+        ref_structs,
+    })
+}
+
+/// A local binding (parameter or `let`). `ty` is None when its initializer failed to check.
+#[derive(Clone, Debug)]
+struct Local {
+    id: LocalId,
+    ty: Option<Type>,
+}
+
+/// An enclosing loop of the expression being checked.
+struct LoopCtx {
+    label: Option<String>,
+    /// Only `loop` can produce a value with `break value`.
+    is_loop: bool,
+    /// Type carried by the `break`s seen so far.
+    break_ty: Option<Type>,
+    has_break: bool,
+}
+
+struct Checker {
+    modules: Vec<ModuleEnv>,
+    by_name: HashMap<String, usize>,
+    diags: Vec<Diagnostic>,
+    fn_count: FnId,
+    /// Module of the function being checked.
+    module: usize,
+    scopes: Vec<HashMap<String, Local>>,
+    /// Locals of the function being checked; LocalId indexes here.
+    locals: Vec<TLocal>,
+    ret_ty: Type,
+    loops: Vec<LoopCtx>,
+    /// What `Self` stands for: the struct of the impl block (or trait impl) being collected or checked.
+    self_ty: Option<Type>,
+    /// Default trait methods to check once per implementing struct.
+    defaults: Vec<DefaultMethod>,
+    /// Type parameters in scope and what they stand for: themselves (`Type::Param`) while a
+    /// generic item is checked abstractly, their arguments while an instantiation is checked.
+    type_params: HashMap<String, Type>,
+    /// Trait bounds (keys) of the abstract type parameters in scope.
+    bounds: HashMap<String, Vec<String>>,
+    /// Generic functions and methods; a generic `FnSig::id` indexes here.
+    templates: Vec<Template>,
+    /// `FnId` of each instantiation, by template index and type arguments.
+    instances: HashMap<(FnId, Vec<Type>), FnId>,
+    /// Instantiations in the order they were first used; checked after everything else.
+    pending: Vec<Instance>,
+    /// Generic struct instances built by struct literals, in first-use order.
+    struct_instances: Vec<Type>,
+    /// Closures whose bodies are being checked, innermost last.
+    closures: Vec<ClosureFrame>,
+    /// Functions behind closures and function values, added to the program after the rest.
+    lifted: Vec<(FnId, TFunction)>,
+    /// Function behind the value of each named function used as one.
+    thunks: HashMap<FnId, FnId>,
+    /// Paths of the `resource struct`s (see `TypedProgram::resources`).
+    resources: HashSet<Vec<String>>,
+    /// Paths of the structs and enums whose values are moved (see `TypedProgram::moved_types`).
+    moved_types: HashSet<Vec<String>>,
+}
+
+impl Checker {
+    fn new(roots: &[(String, Root)]) -> Self {
+        let modules: Vec<ModuleEnv> = roots
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| ModuleEnv::new(name, FileId::new(i as u32)))
+            .collect();
+        let by_name = modules
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.name.clone(), i))
+            .collect();
+        Self {
+            modules,
+            by_name,
+            diags: Vec::new(),
+            fn_count: 0,
+            module: 0,
+            scopes: Vec::new(),
+            locals: Vec::new(),
+            ret_ty: Type::Unit,
+            loops: Vec::new(),
+            self_ty: None,
+            defaults: Vec::new(),
+            type_params: HashMap::new(),
+            bounds: HashMap::new(),
+            templates: Vec::new(),
+            instances: HashMap::new(),
+            pending: Vec::new(),
+            struct_instances: Vec::new(),
+            closures: Vec::new(),
+            lifted: Vec::new(),
+            thunks: HashMap::new(),
+            resources: HashSet::new(),
+            moved_types: HashSet::new(),
+        }
+    }
+
+    fn error(&mut self, msg: impl Into<String>, span: Span) {
+        let loc = Location::new(self.modules[self.module].file, span);
+        self.diags.push(Diagnostic::error(msg, Some(loc)));
+    }
+
+    fn warn(&mut self, msg: impl Into<String>, span: Span) {
+        let loc = Location::new(self.modules[self.module].file, span);
+        self.diags.push(Diagnostic::warning(msg, Some(loc)));
+    }
+
+    fn current(&self) -> &ModuleEnv {
+        &self.modules[self.module]
+    }
+
+    /// Register imports, struct and enum names, then resolve field types, variants and function signatures.
+    /// Function ids follow module and source order, except that the entry `main` is always 0.
+    fn collect_items(&mut self, roots: &[(String, Root)]) {
+        self.fn_count = 1;
+        for (module, (_, root)) in roots.iter().enumerate() {
+            self.module = module;
+            for item in &root.items {
+                match item {
+                    Item::Import(imp) => {
+                        let target = imp.path.join("::");
+                        if !self.by_name.contains_key(&target) {
+                            self.error(format!("module not found: {}", target), imp.span);
+                            continue;
+                        }
+                        let local = imp
+                            .alias
+                            .clone()
+                            .unwrap_or_else(|| imp.path.last().cloned().unwrap_or_default());
+                        self.modules[module].imports.insert(local, target);
+                    }
+                    Item::Struct(s) => {
+                        if self.modules[module].structs.contains_key(&s.name) {
+                            self.error(
+                                format!("struct `{}` is defined more than once", s.name),
+                                s.span,
+                            );
+                            continue;
+                        }
+                        let env = &self.modules[module];
+                        if env.enums.contains_key(&s.name) || env.aliases.contains_key(&s.name) {
+                            self.error(
+                                format!("the name `{}` is defined more than once", s.name),
+                                s.span,
+                            );
+                            continue;
+                        }
+                        if s.resource {
+                            let path = vec![self.modules[module].name.clone(), s.name.clone()];
+                            self.resources.insert(path);
+                        }
+                        let info = StructInfo {
+                            span: s.span,
+                            vis: s.vis,
+                            type_params: s.type_params.clone(),
+                            fields: Vec::new(),
+                            methods: HashMap::new(),
+                            impls: HashSet::new(),
+                            newtype: s.newtype,
+                        };
+                        self.modules[module].structs.insert(s.name.clone(), info);
+                    }
+                    Item::Enum(e) => {
+                        if self.name_taken(module, &e.name) {
+                            self.error(
+                                format!("the name `{}` is defined more than once", e.name),
+                                e.span,
+                            );
+                            continue;
+                        }
+                        let info = EnumInfo {
+                            span: e.span,
+                            vis: e.vis,
+                            variants: Vec::new(),
+                        };
+                        self.modules[module].enums.insert(e.name.clone(), info);
+                    }
+                    Item::Trait(t) => {
+                        if self.name_taken(module, &t.name) {
+                            self.error(
+                                format!("the name `{}` is defined more than once", t.name),
+                                t.span,
+                            );
+                            continue;
+                        }
+                        let info = TraitInfo {
+                            key: format!("{}::{}", self.modules[module].name, t.name),
+                            module: Some(module),
+                            vis: t.vis,
+                            methods: t.methods.clone(),
+                        };
+                        self.modules[module].traits.insert(t.name.clone(), info);
+                    }
+                    Item::Const(c) => {
+                        if self.modules[module].consts.contains_key(&c.name) {
+                            self.error(
+                                format!("constant `{}` is defined more than once", c.name),
+                                c.span,
+                            );
+                            continue;
+                        }
+                        let info = ConstInfo {
+                            span: c.span,
+                            vis: c.vis,
+                            ty: c.ty.clone(),
+                            init: c.value.clone(),
+                            state: ConstState::Pending,
+                        };
+                        self.modules[module].consts.insert(c.name.clone(), info);
+                    }
+                    Item::TypeAlias(a) => {
+                        if self.name_taken(module, &a.name) {
+                            self.error(
+                                format!("the name `{}` is defined more than once", a.name),
+                                a.span,
+                            );
+                            continue;
+                        }
+                        let info = AliasInfo {
+                            span: a.span,
+                            vis: a.vis,
+                            ty: a.ty.clone(),
+                            state: AliasState::Pending,
+                        };
+                        self.modules[module].aliases.insert(a.name.clone(), info);
+                    }
+                    Item::Fn(_) | Item::Impl(_) => {}
+                }
+            }
+        }
+
+        self.resolve_aliases();
+        for (module, (_, root)) in roots.iter().enumerate() {
+            self.module = module;
+            for item in &root.items {
+                match item {
+                    Item::Struct(s) => {
+                        let params = self.enter_struct_params(&s.type_params, s.span);
+                        let mut fields: Vec<FieldInfo> = Vec::with_capacity(s.fields.len());
+                        for f in &s.fields {
+                            if fields.iter().any(|other| other.name == f.name) {
+                                self.error(
+                                    format!("field `{}` is declared more than once", f.name),
+                                    f.span,
+                                );
+                                continue;
+                            }
+                            let ty = self.resolve_type(&f.ty, f.span).unwrap_or(Type::Unit);
+                            if !s.resource && holds_resource(&self.resources, &ty) {
+                                self.error(
+                                    format!(
+                                        "field `{}` holds a resource (`{}`), so `{}` must be declared `resource struct`",
+                                        f.name, ty, s.name
+                                    ),
+                                    f.span,
+                                );
+                            }
+                            fields.push(FieldInfo {
+                                name: f.name.clone(),
+                                ty,
+                            });
+                        }
+                        self.leave_type_params(&params);
+                        if let Some(info) = self.modules[module].structs.get_mut(&s.name) {
+                            if info.span == s.span {
+                                info.fields = fields;
+                            }
+                        }
+                    }
+                    Item::Fn(f) => {
+                        if self.modules[module].fns.contains_key(&f.name) {
+                            self.error(
+                                format!("function `{}` is defined more than once", f.name),
+                                f.span,
+                            );
+                            continue;
+                        }
+                        if self.modules[module].consts.contains_key(&f.name) {
+                            self.error(
+                                format!("the name `{}` is defined more than once", f.name),
+                                f.span,
+                            );
+                            continue;
+                        }
+                        let generic = !f.type_params.is_empty();
+                        let id = if generic || (module == 0 && f.name == "main") {
+                            0
+                        } else {
+                            self.next_fn_id()
+                        };
+                        let mut sig = self.fn_sig(id, f);
+                        if generic {
+                            self.add_template(module, None, f, &mut sig);
+                        }
+                        self.modules[module].fns.insert(f.name.clone(), sig);
+                    }
+                    Item::Impl(imp) => match &imp.trait_ {
+                        Some(path) => self.collect_trait_impl(module, imp, path),
+                        None => self.collect_methods(module, imp),
+                    },
+                    Item::Trait(t) => self.check_trait_decl(t),
+                    Item::Enum(e) => self.collect_variants(module, e),
+                    Item::Const(c) => self.collect_const_type(module, &c.name, c.span),
+                    Item::Import(_) | Item::TypeAlias(_) => {}
+                }
+            }
+        }
+        self.check_newtype_cycles();
+        self.lay_out_variants();
+        self.check_copy_impls();
+        self.collect_moved_types();
+    }
+
+    /// Resolve the declared type of constant `name`, which must be a number, `bool` or `string`.
+    fn collect_const_type(&mut self, module: usize, name: &str, span: Span) {
+        let Some(info) = self.modules[module].consts.get(name) else {
+            return;
+        };
+        if info.span != span {
+            return;
+        }
+        let written = info.ty.clone();
+        let ty = self.resolve_type(&written, span);
+        let ty = match ty {
+            Some(ty) if consts::const_type(&ty) => Some(ty),
+            Some(ty) => {
+                self.error(
+                    format!(
+                        "constants must have a number, `bool` or `string` type, found `{}`",
+                        ty
+                    ),
+                    span,
+                );
+                None
+            }
+            None => None,
+        };
+        if let Some(info) = self.modules[module].consts.get_mut(name) {
+            match ty {
+                Some(ty) => info.ty = ty,
+                None => info.state = ConstState::Done(None),
+            }
+        }
+    }
+
+    /// True if a struct, enum, trait or type alias of this name is already declared in the module.
+    fn name_taken(&self, module: usize, name: &str) -> bool {
+        let env = &self.modules[module];
+        env.structs.contains_key(name)
+            || env.enums.contains_key(name)
+            || env.traits.contains_key(name)
+            || env.aliases.contains_key(name)
+    }
+
+    fn next_fn_id(&mut self) -> FnId {
+        self.fn_count += 1;
+        self.fn_count - 1
+    }
+
+    /// Resolve the parameter and return types of a function in the current module, with its own
+    /// type parameters in scope (those of an enclosing generic impl are added by the caller).
+    fn fn_sig(&mut self, id: FnId, f: &FnDecl) -> FnSig {
+        let type_params = self.enter_type_params(&f.type_params, f.span);
+        let params = f
+            .params
+            .iter()
+            .map(|p| self.resolve_type(&p.ty, f.span).unwrap_or(Type::Unit))
+            .collect();
+        let ret = self
+            .resolve_type(&f.return_ty, f.span)
+            .unwrap_or(Type::Unit);
+        self.leave_type_params(&type_params);
+        FnSig {
+            id,
+            span: f.span,
+            vis: f.vis,
+            receiver: f.receiver,
+            type_params,
+            params,
+            ret,
+        }
+    }
+
+    /// Resolve the payload types of an enum's variants and assign tags in declaration order.
+    fn collect_variants(&mut self, module: usize, e: &EnumDecl) {
+        let mut variants: Vec<VariantInfo> = Vec::with_capacity(e.variants.len());
+        for v in &e.variants {
+            if variants.iter().any(|other| other.name == v.name) {
+                self.error(
+                    format!("variant `{}` is declared more than once", v.name),
+                    v.span,
+                );
+                continue;
+            }
+            let kind = match v.payload {
+                VariantPayload::Unit => VariantKind::Unit,
+                VariantPayload::Tuple(_) => VariantKind::Tuple,
+                VariantPayload::Struct(_) => VariantKind::Struct,
+            };
+            let payload = v.payload.fields();
+            let mut fields: Vec<(String, Type, u32)> = Vec::with_capacity(payload.len());
+            for (name, ty) in payload {
+                if fields.iter().any(|(other, _, _)| *other == name) {
+                    self.error(
+                        format!("field `{}` is declared more than once", name),
+                        v.span,
+                    );
+                    continue;
+                }
+                let ty = self.resolve_type(&ty, v.span).unwrap_or(Type::Unit);
+                if holds_resource(&self.resources, &ty) {
+                    self.error(
+                        format!(
+                            "variant `{}` of enum `{}` cannot hold a resource (`{}`)",
+                            v.name, e.name, ty
+                        ),
+                        v.span,
+                    );
+                }
+                // Offsets are assigned once every newtype is known (see `lay_out_variants`).
+                fields.push((name, ty, 0));
+            }
+            variants.push(VariantInfo {
+                name: v.name.clone(),
+                tag: variants.len() as u32,
+                kind,
+                fields,
+            });
+        }
+        if let Some(info) = self.modules[module].enums.get_mut(&e.name) {
+            if info.span == e.span {
+                info.variants = variants;
+            }
+        }
+    }
+
+    /// The entry module must define `fn main() -> ()`.
+    fn check_entry(&mut self, main_root: &Root) {
+        self.module = 0;
+        let main = main_root.items.iter().find_map(|i| match i {
+            Item::Fn(f) if f.name == "main" => Some(f),
+            _ => None,
+        });
+        match main {
+            None => self
+                .diags
+                .push(Diagnostic::error("main function not found", None)),
+            Some(f) => {
+                if !f.params.is_empty() || !f.type_params.is_empty() || f.return_ty != Type::Unit {
+                    self.error("`main` must take no parameters and return `()`", f.span);
+                }
+            }
+        }
+    }
+
+    /// Resolve a module name as written in the current module (itself or an import) to its index.
+    fn resolve_module(&self, name: &str) -> Option<usize> {
+        if self.current().name == name {
+            return Some(self.module);
+        }
+        let target = self.current().imports.get(name)?;
+        self.by_name.get(target).copied()
+    }
+
+    /// Resolve a struct or enum path (`Name` or `module::Name`) to its module index, reporting unknown or private types.
+    fn resolve_type_name(&mut self, path: &[String], span: Span) -> Option<(usize, String)> {
+        let (module, name) = match path {
+            [name] => (self.module, name.clone()),
+            [module, name] => match self.resolve_module(module) {
+                Some(m) => (m, name.clone()),
+                None => {
+                    self.error(format!("unresolved module `{}`", module), span);
+                    return None;
+                }
+            },
+            _ => {
+                self.error(format!("unresolved path `{}`", path.join("::")), span);
+                return None;
+            }
+        };
+        let env = &self.modules[module];
+        let (kind, vis) = match (env.structs.get(&name), env.enums.get(&name)) {
+            (Some(s), _) => ("struct", s.vis),
+            (None, Some(e)) => ("enum", e.vis),
+            (None, None) => {
+                self.error(format!("cannot find type `{}`", path.join("::")), span);
+                return None;
+            }
+        };
+        if module != self.module && vis != Visibility::Exported {
+            let owner = self.modules[module].name.clone();
+            self.error(
+                format!("{} `{}` is private to module `{}`", kind, name, owner),
+                span,
+            );
+            return None;
+        }
+        Some((module, name))
+    }
+
+    /// Number of type parameters of the struct `module::name` (0 for an enum).
+    fn type_param_count(&self, module: usize, name: &str) -> usize {
+        self.modules[module]
+            .structs
+            .get(name)
+            .map_or(0, |s| s.type_params.len())
+    }
+
+    /// Report a wrong number of type arguments for `path`.
+    fn check_type_arg_count(
+        &mut self,
+        path: &str,
+        expected: usize,
+        found: usize,
+        span: Span,
+    ) -> bool {
+        if expected == found {
+            return true;
+        }
+        if found == 0 {
+            self.error(format!("missing generics for struct `{}`", path), span);
+        } else {
+            self.error(
+                format!(
+                    "`{}` takes {} type arguments but {} were supplied",
+                    path, expected, found
+                ),
+                span,
+            );
+        }
+        false
+    }
+
+    /// Resolve a type as written in source to its canonical form.
+    fn resolve_type(&mut self, ty: &Type, span: Span) -> Option<Type> {
+        match ty {
+            Type::Int
+            | Type::I64
+            | Type::U64
+            | Type::U8
+            | Type::F64
+            | Type::String
+            | Type::Bool
+            | Type::Unit
+            | Type::Param(_) => Some(ty.clone()),
+            Type::Ref(mut_, inner) => {
+                let inner = self.resolve_type(inner, span)?;
+                Some(Type::Ref(*mut_, Box::new(inner)))
+            }
+            Type::Option(inner) => {
+                let inner = self.resolve_type(inner, span)?;
+                Some(Type::Option(Box::new(inner)))
+            }
+            Type::List(elem) => {
+                let elem = self.resolve_type(elem, span)?;
+                Some(Type::List(Box::new(elem)))
+            }
+            Type::Set(elem) => {
+                let elem = self.resolve_type(elem, span)?;
+                Some(Type::Set(Box::new(elem)))
+            }
+            Type::Map(key, value) => {
+                let key = self.resolve_type(key, span);
+                let value = self.resolve_type(value, span);
+                Some(Type::Map(Box::new(key?), Box::new(value?)))
+            }
+            Type::Result(ok, err) => {
+                let ok = self.resolve_type(ok, span);
+                let err = self.resolve_type(err, span);
+                Some(Type::Result(Box::new(ok?), Box::new(err?)))
+            }
+            Type::Tuple(elems) => {
+                let elems: Vec<Option<Type>> =
+                    elems.iter().map(|e| self.resolve_type(e, span)).collect();
+                Some(Type::Tuple(elems.into_iter().collect::<Option<Vec<_>>>()?))
+            }
+            Type::Fn(params, ret) => {
+                let params: Vec<Option<Type>> =
+                    params.iter().map(|p| self.resolve_type(p, span)).collect();
+                let ret = self.resolve_type(ret, span);
+                let params = params.into_iter().collect::<Option<Vec<_>>>()?;
+                Some(Type::Fn(params, Box::new(ret?)))
+            }
+            Type::Path(path) if path.len() == 1 && path[0] == "Self" => {
+                if self.self_ty.is_none() {
+                    self.error("cannot find type `Self` in this scope", span);
+                }
+                self.self_ty.clone()
+            }
+            Type::Path(path) if path.len() == 1 && self.type_params.contains_key(&path[0]) => {
+                self.type_params.get(&path[0]).cloned()
+            }
+            Type::Path(path) if self.alias_module(path).is_some() => {
+                let module = self.alias_module(path)?;
+                self.use_alias(module, &path[path.len() - 1], span)
+            }
+            Type::Path(path) => {
+                let (module, name) = self.resolve_type_name(path, span)?;
+                let expected = self.type_param_count(module, &name);
+                if !self.check_type_arg_count(&path.join("::"), expected, 0, span) {
+                    return None;
+                }
+                Some(struct_type(&self.modules[module].name, &name))
+            }
+            Type::Generic(path, args) if self.alias_module(path).is_some() => {
+                for a in args {
+                    self.resolve_type(a, span);
+                }
+                self.check_type_arg_count(&path.join("::"), 0, args.len(), span);
+                None
+            }
+            Type::Generic(path, args) => {
+                let (module, name) = self.resolve_type_name(path, span)?;
+                let expected = self.type_param_count(module, &name);
+                let args: Vec<Option<Type>> =
+                    args.iter().map(|a| self.resolve_type(a, span)).collect();
+                if !self.check_type_arg_count(&path.join("::"), expected, args.len(), span) {
+                    return None;
+                }
+                let args = args.into_iter().collect::<Option<Vec<_>>>()?;
+                Some(struct_type_with(&self.modules[module].name, &name, args))
+            }
+        }
+    }
+
+    /// Layouts of every struct and enum in module and source order, then of the generic struct
+    /// instances the program builds (named `Pair[int, string]`). Field types are the types the
+    /// fields are represented as; a newtype has no layout of its own.
+    pub(super) fn struct_layouts(&self) -> Vec<StructLayout> {
+        let repr = |t: &Type| self.repr(t);
+        let mut declared: Vec<(usize, u32, StructLayout)> = Vec::new();
+        for (m, env) in self.modules.iter().enumerate() {
+            for (name, s) in &env.structs {
+                if s.newtype || !s.type_params.is_empty() {
+                    continue;
+                }
+                let fields = s.fields_with(&[], repr);
+                declared.push((
+                    m,
+                    s.span.start,
+                    self.layout(&env.name, name.clone(), fields),
+                ));
+            }
+            for (name, e) in &env.enums {
+                let mut fields = vec![("tag".to_string(), Type::Int, 0)];
+                for v in &e.variants {
+                    fields.extend(
+                        v.fields.iter().map(|(f, ty, offset)| {
+                            (format!("{}.{}", v.name, f), ty.clone(), *offset)
+                        }),
+                    );
+                }
+                declared.push((
+                    m,
+                    e.span.start,
+                    self.layout(&env.name, name.clone(), fields),
+                ));
+            }
+        }
+        declared.sort_by_key(|(m, start, _)| (*m, *start));
+        let mut layouts: Vec<StructLayout> = declared.into_iter().map(|(_, _, l)| l).collect();
+        layouts.extend(self.struct_instance_layouts());
+        layouts
+    }
+
+    /// Paths of the structs and enums with a field that can hold a reference, directly or
+    /// through another such type (see `TypedProgram::ref_structs`).
+    pub(super) fn ref_structs(&self) -> HashSet<Vec<String>> {
+        let mut fields: Vec<(Vec<String>, Vec<&Type>)> = Vec::new();
+        for env in &self.modules {
+            for (name, s) in &env.structs {
+                let tys = s.fields.iter().map(|f| &f.ty).collect();
+                fields.push((vec![env.name.clone(), name.clone()], tys));
+            }
+            for (name, e) in &env.enums {
+                let tys = e
+                    .variants
+                    .iter()
+                    .flat_map(|v| v.fields.iter().map(|(_, ty, _)| ty));
+                fields.push((vec![env.name.clone(), name.clone()], tys.collect()));
+            }
+        }
+        let mut found = HashSet::new();
+        loop {
+            let more: Vec<Vec<String>> = fields
+                .iter()
+                .filter(|(path, tys)| {
+                    !found.contains(path) && tys.iter().any(|t| carries_ref(&found, t))
+                })
+                .map(|(path, _)| path.clone())
+                .collect();
+            if more.is_empty() {
+                return found;
+            }
+            found.extend(more);
+        }
+    }
+
+    /// Layout of `module::name` with these fields (name, type, offset), each laid out as the
+    /// type it is represented as; the fields of an enum's variants overlap after the tag.
+    fn layout(&self, module: &str, name: String, fields: Vec<(String, Type, u32)>) -> StructLayout {
+        let fields: Vec<(String, Type, u32)> = fields
+            .into_iter()
+            .map(|(f, ty, offset)| (f, self.repr(&ty), offset))
+            .collect();
+        let total_size = fields
+            .iter()
+            .map(|(_, t, offset)| offset + field_byte_size(t))
+            .max()
+            .unwrap_or(0);
+        StructLayout {
+            module: module.to_string(),
+            struct_name: name,
+            fields,
+            total_size,
+        }
+    }
+
+    /// Look up the struct behind a canonical struct type.
+    fn struct_info(&self, ty: &Type) -> Option<&StructInfo> {
+        match ty {
+            Type::Path(p) | Type::Generic(p, _) if p.len() == 2 => {
+                let module = *self.by_name.get(&p[0])?;
+                self.modules[module].structs.get(&p[1])
+            }
+            _ => None,
+        }
+    }
+
+    /// Check a free function, or a method or associated function of struct `owner` (its module and name).
+    /// Names in the body resolve in `module`, which is the trait's module for a default method.
+    /// A generic function is checked with its type parameters abstract and yields no function:
+    /// its instantiations are checked later (see `check_instances`).
+    fn check_fn(
+        &mut self,
+        module: usize,
+        owner: Option<(usize, &str)>,
+        f: &FnDecl,
+    ) -> Option<(FnId, TFunction)> {
+        let sig = match owner {
+            None => self.modules[module].fns.get(&f.name),
+            Some((m, owner)) => self.modules[m]
+                .structs
+                .get(owner)
+                .and_then(|s| s.methods.get(&f.name)),
+        };
+        let sig = match sig {
+            Some(sig) if sig.span == f.span => sig.clone(),
+            _ => return None,
+        };
+        if !sig.is_generic() {
+            let self_ty = owner.map(|(m, owner)| struct_type(&self.modules[m].name, owner));
+            let tf = self.check_body(module, owner, self_ty, f, &sig, &[]);
+            return Some((sig.id, tf));
+        }
+        for p in &sig.type_params {
+            self.type_params
+                .insert(p.name.clone(), Type::Param(p.name.clone()));
+            self.bounds.insert(p.name.clone(), p.bounds.clone());
+        }
+        let self_ty = owner.map(|(m, owner)| {
+            let n = self.type_param_count(m, owner);
+            let params = sig.type_params[..n]
+                .iter()
+                .map(|p| Type::Param(p.name.clone()))
+                .collect();
+            struct_type_with(&self.modules[m].name, owner, params)
+        });
+        self.check_body(module, owner, self_ty, f, &sig, &[]);
+        self.type_params.clear();
+        self.bounds.clear();
+        None
+    }
+
+    /// Check the body of `f` against `sig` and build the typed function. `self_ty` is the type of
+    /// the owner struct; `type_args` are those of an instantiation, which are part of its name.
+    fn check_body(
+        &mut self,
+        module: usize,
+        owner: Option<(usize, &str)>,
+        self_ty: Option<Type>,
+        f: &FnDecl,
+        sig: &FnSig,
+        type_args: &[Type],
+    ) -> TFunction {
+        self.module = module;
+        self.ret_ty = sig.ret.clone();
+        self.scopes = vec![HashMap::new()];
+        self.locals.clear();
+        self.self_ty = self_ty;
+        if let (Some(ty), Some(receiver)) = (self.self_ty.clone(), sig.receiver) {
+            self.declare("self", Some(self_type(receiver, ty)), f.span);
+        }
+        for (p, ty) in f.params.iter().zip(&sig.params) {
+            let id = self.declare(&p.name, Some(ty.clone()), p.span);
+            let local = &mut self.locals[id as usize];
+            local.mutable = p.mut_;
+            local.can_be_mut = true;
+        }
+        let body = self.check_block_with(&f.body, Some(&sig.ret));
+        match &body.value {
+            _ if body.diverges() => {}
+            Some(v) if v.ty != sig.ret => self.error(
+                format!("mismatched types: expected `{}`, found `{}`", sig.ret, v.ty),
+                v.span,
+            ),
+            Some(_) => {}
+            // A failed tail expression was already reported.
+            None if f.body.value.is_some() => {}
+            None if sig.ret != Type::Unit => self.error(
+                format!(
+                    "function `{}` must return a value of type `{}`",
+                    f.name, sig.ret
+                ),
+                f.span,
+            ),
+            None => {}
+        }
+        self.scopes.clear();
+        self.self_ty = None;
+        let (module_name, mut name) = match owner {
+            Some((m, owner)) => (
+                self.modules[m].name.clone(),
+                format!("{}_{}", owner, f.name),
+            ),
+            None => (self.modules[module].name.clone(), f.name.clone()),
+        };
+        if !type_args.is_empty() {
+            name = mangle(&name, type_args);
+        }
+        TFunction {
+            module: module_name,
+            name,
+            file: self.modules[module].file,
+            param_count: (sig.receiver.is_some() as usize + sig.params.len()) as u32,
+            locals: std::mem::take(&mut self.locals),
+            ret: sig.ret.clone(),
+            body,
+            captures: None,
+        }
+    }
+
+    /// Bring a new local into the innermost scope (shadowing any earlier one with the same name).
+    fn declare(&mut self, name: &str, ty: Option<Type>, span: Span) -> LocalId {
+        let id = self.locals.len() as LocalId;
+        self.locals.push(TLocal {
+            name: name.to_string(),
+            ty: ty.clone().unwrap_or(Type::Unit),
+            mutable: false,
+            can_be_mut: false,
+            span,
+        });
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Local { id, ty });
+        }
+        id
+    }
+
+    fn check_block(&mut self, block: &Block) -> TBlock {
+        self.check_block_with(block, None)
+    }
+
+    /// Check a block whose value is expected to have type `expected` (a hint, see `check_expr_with`).
+    fn check_block_with(&mut self, block: &Block, expected: Option<&Type>) -> TBlock {
+        self.scopes.push(HashMap::new());
+        let stmts = block
+            .stmts
+            .iter()
+            .filter_map(|s| self.check_stmt(s))
+            .collect();
+        let value = block
+            .value
+            .as_ref()
+            .and_then(|v| self.check_value(v, expected))
+            .map(Box::new);
+        self.scopes.pop();
+        TBlock { stmts, value }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) -> Option<TStmt> {
+        match stmt {
+            Stmt::Let {
+                span,
+                mut_,
+                pattern,
+                ty,
+                init,
+            } => {
+                let declared = match ty {
+                    Some(t) => match self.resolve_type(t, *span) {
+                        Some(t) => Some(t),
+                        None => {
+                            self.check_expr(init);
+                            self.check_let_pattern(pattern, None, *span);
+                            return None;
+                        }
+                    },
+                    None => None,
+                };
+                let init = match &declared {
+                    Some(t) => self.check_expr_as(init, t),
+                    None => self.check_value(init, None),
+                };
+                // Keep the names in scope even after an error so later uses don't cascade.
+                let ty = init.as_ref().map(|e| e.ty.clone()).or(declared);
+                let first = self.locals.len();
+                let pattern = match pattern {
+                    MatchPattern::Binding(name) => {
+                        Some(TPattern::Binding(self.declare(name, ty, *span)))
+                    }
+                    _ => self.check_let_pattern(pattern, ty.as_ref(), *span),
+                };
+                // `let mut` applies to every name the pattern binds.
+                for local in &mut self.locals[first..] {
+                    local.mutable = *mut_;
+                    local.can_be_mut = true;
+                }
+                Some(TStmt::Let {
+                    pattern: pattern?,
+                    init: init?,
+                })
+            }
+            Stmt::Expr { expr, .. } => self.check_expr(expr).map(TStmt::Expr),
+            Stmt::Return { span, value } => {
+                if self.closures.last().is_some_and(|c| c.infer_ret) {
+                    self.error(
+                        "`return` in a closure needs its return type: write `|..| -> T { .. }`",
+                        *span,
+                    );
+                    if let Some(v) = value {
+                        self.check_expr(v);
+                    }
+                    return None;
+                }
+                let ret = self.ret_ty.clone();
+                match value {
+                    Some(v) => self.check_expr_as(v, &ret).map(|e| TStmt::Return {
+                        span: *span,
+                        value: Some(e),
+                    }),
+                    None if ret != Type::Unit => {
+                        self.error(format!("expected a return value of type `{}`", ret), *span);
+                        None
+                    }
+                    None => Some(TStmt::Return {
+                        span: *span,
+                        value: None,
+                    }),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::parse;
+
+    fn parse_src(src: &str, file: u32) -> Root {
+        let tokens = Lexer::new(src, FileId::new(file)).collect_tokens();
+        parse(tokens, FileId::new(file)).expect("parse should succeed")
+    }
+
+    fn check_src(main: &str, deps: &[(&str, &str)]) -> Result<TypedProgram, Vec<Diagnostic>> {
+        let mut modules = vec![("main".to_string(), parse_src(main, 0))];
+        for (i, (name, src)) in deps.iter().enumerate() {
+            modules.push((name.to_string(), parse_src(src, i as u32 + 1)));
+        }
+        crate::desugar::add_accessor_methods(&mut modules);
+        crate::desugar::add_derived_impls(&mut modules);
+        check_program(&modules)
+    }
+
+    fn errors(main: &str, deps: &[(&str, &str)]) -> Vec<String> {
+        check_src(main, deps)
+            .expect_err("expected type errors")
+            .into_iter()
+            .filter(|d| d.level == Level::Error)
+            .map(|d| d.message)
+            .collect()
+    }
+
+    fn warnings(main: &str) -> Vec<String> {
+        check_src(main, &[])
+            .expect("program should type-check")
+            .warnings
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    const PRODUCT: &str = r#"
+export struct Product { id: int @pub(get), price: int @pub(get, set), }
+impl Product {
+  pub fn new(id: int, price: int) -> Product { product::Product { id: id, price: price } }
+}
+"#;
+
+    #[test]
+    fn accessors_program_checks() {
+        let main = r#"
+import product;
+fn main() -> () {
+  let mut p = product::Product::new(1, 10);
+  print(p.id());
+  p.set_price(99);
+  print(p.price());
+}
+"#;
+        check_src(main, &[("product", PRODUCT)]).expect("program should type-check");
+    }
+
+    #[test]
+    fn struct_layout_follows_field_order() {
+        let main = "import product;\nfn main() -> () {}\n";
+        let program = check_src(main, &[("product", PRODUCT)]).expect("program should type-check");
+        assert_eq!(program.struct_layouts.len(), 1);
+        let layout = &program.struct_layouts[0];
+        assert_eq!(
+            (layout.module.as_str(), layout.struct_name.as_str()),
+            ("product", "Product")
+        );
+        let offsets: Vec<(&str, u32)> = layout
+            .fields
+            .iter()
+            .map(|(n, _, o)| (n.as_str(), *o))
+            .collect();
+        assert_eq!(offsets, vec![("id", 0), ("price", 4)]);
+        assert_eq!(layout.total_size, 8);
+    }
+
+    #[test]
+    fn enum_layout_overlaps_payloads() {
+        let main = r#"
+enum Shape { Empty, Circle(int), Label { text: string, size: int } }
+fn main() -> () {}
+"#;
+        let program = check_src(main, &[]).expect("program should type-check");
+        let layout = &program.struct_layouts[0];
+        assert_eq!(layout.struct_name, "Shape");
+        let offsets: Vec<(&str, u32)> = layout
+            .fields
+            .iter()
+            .map(|(n, _, o)| (n.as_str(), *o))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                ("tag", 0),
+                ("Circle.0", 4),
+                ("Label.text", 4),
+                ("Label.size", 12)
+            ]
+        );
+        assert_eq!(layout.total_size, 16);
+    }
+
+    #[test]
+    fn let_annotation_mismatch_has_span() {
+        let main = r#"fn main() -> () { let x: int = "hi"; }"#;
+        let diags = check_src(main, &[]).unwrap_err();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "mismatched types: expected `int`, found `string`"
+        );
+        let loc = diags[0]
+            .location
+            .expect("diagnostic should carry a location");
+        assert_eq!(
+            &main[loc.span.start as usize..loc.span.end as usize],
+            "\"hi\""
+        );
+    }
+
+    #[test]
+    fn call_arguments_are_checked() {
+        let main = r#"
+fn add(a: int, b: int) -> int { return a + b; }
+fn main() -> () {
+  add(1);
+  add(1, true);
+}
+"#;
+        let errs = errors(main, &[]);
+        assert!(
+            errs.iter().any(|e| e.contains("takes 2 arguments but 1")),
+            "{:?}",
+            errs
+        );
+        assert!(errs
+            .iter()
+            .any(|e| e == "mismatched types: expected `int`, found `bool`"));
+    }
+
+    #[test]
+    fn return_types_are_checked() {
+        let main = r#"
+fn name() -> string { return 1; }
+fn count() -> int { print(1); }
+fn main() -> () {}
+"#;
+        let errs = errors(main, &[]);
+        assert!(errs.contains(&"mismatched types: expected `string`, found `int`".to_string()));
+        assert!(errs
+            .iter()
+            .any(|e| e.contains("`count` must return a value of type `int`")));
+    }
+
+    #[test]
+    fn unknown_names_are_reported() {
+        let main = r#"fn main() -> () { print(y); let a = 1 + "s"; }"#;
+        let errs = errors(main, &[]);
+        assert!(errs.contains(&"cannot find value `y` in this scope".to_string()));
+        assert!(errs.contains(&"cannot add `string` to `int`".to_string()));
+    }
+
+    #[test]
+    fn struct_literal_fields_are_checked() {
+        let main = r#"
+struct Product { id: int, price: int }
+fn main() -> () {
+  let p = main::Product { id: "one", cost: 3 };
+}
+"#;
+        let errs = errors(main, &[]);
+        assert!(errs.contains(&"mismatched types: expected `int`, found `string`".to_string()));
+        assert!(errs.iter().any(|e| e.contains("has no field named `cost`")));
+        assert!(errs.iter().any(|e| e.contains("missing field `price`")));
+    }
+
+    #[test]
+    fn private_struct_and_missing_module() {
+        let main = r#"
+import secret;
+import nowhere;
+fn main() -> () { let s = secret::Hidden { v: 1 }; }
+"#;
+        let errs = errors(main, &[("secret", "struct Hidden { v: int }")]);
+        assert!(errs.contains(&"module not found: nowhere".to_string()));
+        assert!(errs.contains(&"struct `Hidden` is private to module `secret`".to_string()));
+    }
+
+    #[test]
+    fn constants_are_evaluated_at_compile_time() {
+        let main = r#"
+import limits;
+const TOTAL: int = limits::MAX * 2 + 1;
+fn main() -> () {
+  print(TOTAL);
+  print(limits::NAME);
+  match 3 { TOTAL => print(0), limits::MAX => print(1), _ => print(2) };
+  let xs = [0u8; limits::MAX / 4];
+}
+"#;
+        let limits = r#"
+export const MAX: int = 20;
+export const NAME: string = PREFIX + "!";
+const PREFIX: string = if MAX > 10 { "knox" } else { "k" };
+"#;
+        let program = check_src(main, &[("limits", limits)]).expect("program should type-check");
+        let printed: Vec<&crate::typed::TExprKind> = program.functions[0]
+            .body
+            .stmts
+            .iter()
+            .filter_map(|s| match s {
+                TStmt::Expr(e) => match &e.kind {
+                    crate::typed::TExprKind::Print(arg) => Some(&arg.kind),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(
+            printed[..],
+            [crate::typed::TExprKind::Int(41), crate::typed::TExprKind::Str(s)] if s == "knox!"
+        ));
+
+        let main = r#"
+import limits;
+const A: int = B;
+const B: int = A + 1;
+const LIST: List<int> = [1];
+const BIG: u8 = 200 + 100;
+const CALL: int = twice(2);
+const RATIO: f64 = 0.5;
+fn twice(x: int) -> int { x * 2 }
+fn main() -> () {
+  let n = 2;
+  let xs = [0; n];
+  let ys = [[1]; 2];
+  match 0.5 { RATIO => print(1), _ => print(2) };
+  print(limits::HIDDEN);
+}
+"#;
+        let errs = errors(main, &[("limits", "const HIDDEN: int = 1;")]);
+        assert_eq!(
+            errs,
+            vec![
+                "constants must have a number, `bool` or `string` type, found `List<int>`",
+                "cycle detected when evaluating constant `A`",
+                "attempt to add with overflow",
+                "this expression cannot be evaluated at compile time",
+                "this expression cannot be evaluated at compile time",
+                "the value of `[value; count]` is copied, but `List<int>` is not `Copy`",
+                "constants of type `f64` cannot be used in patterns",
+                "constant `HIDDEN` is private to module `limits`",
+            ]
+        );
+    }
+
+    #[test]
+    fn fields_are_private_to_their_module() {
+        let main = r#"
+import product;
+fn main() -> () {
+  let p = product::Product { id: 1, price: 2 };
+  let mut q = product::Product::new(1, 2);
+  print(q.id);
+  q.price = 3;
+  match q { product::Product { id, .. } => print(id) };
+}
+"#;
+        let diags = check_src(main, &[("product", PRODUCT)]).unwrap_err();
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "field `id` of `Product` is private to module `product`",
+                "field `price` of `Product` is private to module `product`",
+                "field `id` of `Product` is private to module `product`",
+                "field `price` of `Product` is private to module `product`",
+                "field `id` of `Product` is private to module `product`",
+            ]
+        );
+        assert_eq!(
+            diags[0].notes[0].0,
+            "build it with `product::Product::new`, which module `product` exports"
+        );
+    }
+
+    #[test]
+    fn method_and_match_types() {
+        let main = r#"
+import product;
+fn main() -> () {
+  let p = product::Product::new(1, 2);
+  p.set_id(3);
+  let x = match p.price() { 0 => "zero", "one" => "one", _ => 2 };
+}
+"#;
+        let errs = errors(main, &[("product", PRODUCT)]);
+        assert!(errs.iter().any(|e| e.contains("no method named `set_id`")));
+        assert!(errs.iter().any(|e| e.contains("pattern of type `string`")));
+        assert!(errs.contains(&"mismatched types: expected `string`, found `int`".to_string()));
+    }
+
+    #[test]
+    fn qualified_calls_resolve_across_modules() {
+        let util = r#"
+pub fn twice(n: int) -> int { return n; }
+fn hidden() -> () { return; }
+"#;
+        let main = r#"
+import util;
+fn helper(s: string) -> string { return s; }
+fn main() -> () {
+  print(util::twice(2));
+  print(helper("x"));
+}
+"#;
+        let typed = check_src(main, &[("util", util)]).expect("program should type-check");
+        let names: Vec<&str> = typed.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["main", "helper", "twice", "hidden"]);
+
+        let bad = "import util;\nfn main() -> () { util::hidden(); util::nope(); other::f(); }";
+        let errs = errors(bad, &[("util", util)]);
+        assert!(errs.contains(&"function `hidden` is private to module `util`".to_string()));
+        assert!(errs.contains(&"cannot find function `util::nope`".to_string()));
+        assert!(errs.contains(&"unresolved module `other`".to_string()));
+    }
+
+    #[test]
+    fn if_conditions_and_branches() {
+        let main = r#"
+fn pick(a: bool, b: bool) -> int {
+  if a { return 1; } else if b { 2 } else { 3 }
+}
+fn main() -> () {
+  let s = if true { "x" } else { "y" };
+  if false { print(s); }
+}
+"#;
+        check_src(main, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn f() -> int { if 1 { 2 } else { 3 } }
+fn g() -> int { if true { 1 } else { "a" } }
+fn main() -> () { if true { 5 } }
+"#;
+        let errs = errors(bad, &[]);
+        assert!(errs.contains(&"`if` condition must be `bool`, found `int`".to_string()));
+        assert!(errs
+            .contains(&"`if` and `else` have incompatible types: `int` and `string`".to_string()));
+        assert!(errs.contains(&"`if` without `else` must have type `()`, found `int`".to_string()));
+    }
+
+    #[test]
+    fn loops_and_break_values() {
+        let main = r#"
+fn find(n: int) -> int {
+  let mut k = 0;
+  loop {
+    k = k + 1;
+    if k * k > n { break k; }
+  }
+}
+fn spin() -> int {
+  loop { return 1; }
+}
+fn main() -> () {
+  'outer: for i in 0..find(10) {
+    while i > 2 { break 'outer; }
+    if i == 1 { continue; }
+  }
+}
+"#;
+        check_src(main, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn main() -> () {
+  break;
+  while true { break 5; }
+  loop { continue 'x; }
+  let v = loop { break 1; break "a"; };
+  for i in true..3 { }
+}
+"#;
+        let errs = errors(bad, &[]);
+        assert!(errs.contains(&"`break` outside of a loop".to_string()));
+        assert!(errs.contains(&"`break` with a value is only allowed inside `loop`".to_string()));
+        assert!(errs.contains(&"use of undeclared label `'x`".to_string()));
+        assert!(errs.contains(&"mismatched types: expected `int`, found `string`".to_string()));
+        assert!(errs.contains(&"range bounds must be `int`, found `bool`".to_string()));
+    }
+
+    #[test]
+    fn operator_types() {
+        let main = r#"
+fn main() -> () {
+  let a = 1 + 2 * 3 - -4 / 2 % 3;
+  let b = a < 3 || a >= 4 && !(a == 5) && true != false;
+  let c = "x" + "y";
+}
+"#;
+        check_src(main, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn main() -> () {
+  let a = 1 && true;
+  let b = "x" - "y";
+  let c = 1 == true;
+  let d = !1;
+  let e = -false;
+}
+"#;
+        let errs = errors(bad, &[]);
+        assert!(errs.contains(&"`&&` operands must be `bool`, found `int`".to_string()));
+        assert!(errs.contains(&"cannot apply `-` to `string` and `string`".to_string()));
+        assert!(errs.contains(&"cannot compare `int` with `bool`".to_string()));
+        assert!(errs.contains(&"cannot apply `!` to `int`".to_string()));
+        assert!(errs.contains(&"cannot negate `bool`".to_string()));
+    }
+
+    #[test]
+    fn option_result_and_try() {
+        let main = r#"
+fn half(n: int) -> Option<int> {
+  if n % 2 == 0 { Some(n / 2) } else { None }
+}
+fn quarter(n: int) -> Option<int> {
+  let h = half(n)?;
+  half(h)
+}
+fn digit(n: int) -> Result<int, string> {
+  if n > 9 { return Err("too big"); }
+  Ok(n)
+}
+fn sum(a: int, b: int) -> Result<int, string> {
+  Ok(digit(a)? + digit(b)?)
+}
+fn main() -> () {
+  let o: Option<bool> = None;
+  match o { Some(true) => print(1), Some(false) => print(2), None => print(3) };
+  let label = match sum(1, 2) {
+    Ok(v) => v,
+    Err(e) => { print(e); return; }
+  };
+}
+"#;
+        check_src(main, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn f() -> Result<int, int> { let x: Result<int, string> = Ok(1); Ok(x?) }
+fn g() -> int { let o: Option<int> = None; o? }
+fn h() -> Option<int> { let v = 3?; None }
+fn main() -> () {
+  let n = None;
+  let r = Ok(1);
+  let o: Option<int> = Some(2);
+  match o { Some(1) => print(1), None => print(0) };
+  match true { true => print(1) };
+  match o { Ok(x) => print(x), _ => print(0) };
+}
+"#;
+        let errs = errors(bad, &[]);
+        assert!(errs.contains(&"`?` cannot convert error type `string` into `int`".to_string()));
+        assert!(errs.contains(
+            &"the `?` operator can only be used in a function that returns `Option` or `Result`, not `int`"
+                .to_string()
+        ));
+        assert!(errs.contains(
+            &"the `?` operator can only be applied to `Option` or `Result`, not `int`".to_string()
+        ));
+        assert!(
+            errs.contains(&"type annotations needed: cannot infer the type of `None`".to_string())
+        );
+        assert!(errs
+            .iter()
+            .any(|e| e.contains("cannot infer the `Result` type of `Ok(..)`")));
+        assert!(errs.contains(&"non-exhaustive patterns: `Some(_)` not covered".to_string()));
+        assert!(errs.contains(&"non-exhaustive patterns: `false` not covered".to_string()));
+        assert!(
+            errs.contains(&"pattern `Ok` cannot match a value of type `Option<int>`".to_string())
+        );
+        assert_eq!(errs.len(), 8, "{:?}", errs);
+    }
+
+    #[test]
+    fn enums_and_exhaustive_match() {
+        let shapes = r#"
+export enum Shape { Empty, Circle(int), Rect { w: int, h: int }, }
+enum Hidden { A }
+"#;
+        let main = r#"
+import shapes;
+enum Level { Low, High(bool) }
+fn area(s: shapes::Shape) -> int {
+  match s {
+    shapes::Shape::Empty => 0,
+    shapes::Shape::Circle(r) => r * r * 3,
+    shapes::Shape::Rect { w, h } => w * h,
+  }
+}
+fn main() -> () {
+  let r = shapes::Shape::Rect { h: 3, w: 4 };
+  print(area(r));
+  print(area(shapes::Shape::Circle(2)));
+  let l = Level::High(true);
+  match l { Level::High(true) => print(1), Level::High(false) => print(2), Level::Low => print(3) };
+  match Some(Level::Low) { Some(Level::Low) => print(1), Some(_) => print(2), None => print(3) };
+}
+"#;
+        check_src(main, &[("shapes", shapes)]).expect("program should type-check");
+
+        let bad = r#"
+import shapes;
+enum E { A, B(int, bool), C { x: string } }
+enum E { Z }
+enum F { X, X }
+fn main() -> () {
+  let e = E::A;
+  match e { E::A => print(1) };
+  match e { E::A => print(1), E::B(_, true) => print(2), E::C { .. } => print(3) };
+  match e { E::B(n) => print(n), E::C { y } => print(0), E::C { x: 1 } => print(2), _ => print(3) };
+  let a = E::B(1);
+  let b = E::D;
+  let c = E::A(1);
+  let d = E::C { x: "s", x: "t" };
+  let h = shapes::Hidden::A;
+}
+"#;
+        let errs = errors(bad, &[("shapes", shapes)]);
+        for expected in [
+            "the name `E` is defined more than once",
+            "variant `X` is declared more than once",
+            "non-exhaustive patterns: `E::B(_, _)` and `E::C { .. }` not covered",
+            "non-exhaustive patterns: `E::B(_, false)` not covered",
+            "this pattern has 1 field, but variant `E::B` has 2 fields",
+            "variant `E::C` has no field named `y`",
+            "pattern of type `int` cannot match a value of type `string`",
+            "`E::B` takes 2 arguments but 1 were supplied",
+            "no variant named `D` in enum `main::E`",
+            "variant `E::A` has no fields; use `E::A`",
+            "field `x` specified more than once",
+            "enum `Hidden` is private to module `shapes`",
+        ] {
+            assert!(
+                errs.contains(&expected.to_string()),
+                "missing {:?} in {:?}",
+                expected,
+                errs
+            );
+        }
+        assert_eq!(errs.len(), 12, "{:?}", errs);
+    }
+
+    #[test]
+    fn guards_or_patterns_and_unreachable_arms() {
+        let main = r#"
+struct Point { x: int, y: int }
+enum Shape { Empty, Circle(int), Rect { w: int, h: int } }
+fn size(s: Shape) -> int {
+  match s {
+    Shape::Empty => 0,
+    Shape::Circle(r) if r > 10 => 10,
+    Shape::Circle(r) | Shape::Rect { w: r, h: 0 } => r,
+    Shape::Rect { w, h } => w * h,
+  }
+}
+fn main() -> () {
+  let p = main::Point { x: 1, y: 2 };
+  match p { Point { x: 0, y } | Point { x: y, y: 0 } => print(y), Point { x, .. } => print(x) };
+  match 4 { 1..=3 => print(1), 3..10 => print(2), _ => print(3) };
+  match true { true | false => print(1) };
+}
+"#;
+        assert!(warnings(main).is_empty(), "{:?}", warnings(main));
+
+        let unreachable = r#"
+enum E { A, B(int) }
+fn main() -> () {
+  let e = E::B(1);
+  match e { E::A | E::B(_) => print(1), E::B(3) => print(2) };
+  match 5 { 1..=5 => print(1), 2 | 4 => print(2), n if n > 0 => print(n), _ => print(0) };
+  match Some(e) { Some(_) => print(1), None => print(2), _ => print(3) };
+}
+"#;
+        assert_eq!(warnings(unreachable), vec!["unreachable pattern"; 3]);
+
+        let bad = r#"
+struct Point { x: int, y: int }
+enum E { A, B(int), C { s: string } }
+fn main() -> () {
+  let e = E::A;
+  let p = main::Point { x: 1, y: 2 };
+  match e { E::B(x) if x > 2 => print(x), E::A => print(0), E::C { .. } => print(1) };
+  match e { E::B(x) | E::C { s: x } => print(1), _ => print(2) };
+  match e { E::B(x) | E::A => print(1), _ => print(2) };
+  match e { E::B(n) if n => print(1), _ => print(2) };
+  match p { Point { x, y: x } => print(x) };
+  match p { E { .. } => print(0), _ => print(1) };
+  match 1 { 5..5 => print(1), 6..=2 => print(2), _ => print(3) };
+}
+"#;
+        let errs = errors(bad, &[]);
+        for expected in [
+            "non-exhaustive patterns: `E::B(_)` not covered",
+            "variable `x` has type `int` in one alternative and `string` in another",
+            "variable `x` is not bound in all patterns",
+            "mismatched types: expected `bool`, found `int`",
+            "identifier `x` is bound more than once in the same pattern",
+            "expected struct, found enum `E`",
+            "lower range bound must be less than upper",
+            "lower range bound must be less than or equal to upper",
+        ] {
+            assert!(
+                errs.contains(&expected.to_string()),
+                "missing {:?} in {:?}",
+                expected,
+                errs
+            );
+        }
+        assert_eq!(errs.len(), 8, "{:?}", errs);
+    }
+
+    #[test]
+    fn methods_and_associated_functions() {
+        let shapes = r#"
+export struct Sq { side: int @pub(get) }
+impl Sq {
+  pub fn new(side: int) -> Sq { shapes::Sq { side: side } }
+  pub fn area(&self) -> int { self.side * self.side }
+  pub fn grow(&mut self, by: int) -> () { self.side = self.side + by; }
+  fn secret(&self) -> int { 0 }
+}
+"#;
+        let main = r#"
+import shapes;
+fn main() -> () {
+  let mut s = shapes::Sq::new(2);
+  s.grow(1);
+  print(s.area());
+  print(s.side());
+  print(shapes::Sq::area(&s));
+}
+"#;
+        let typed = check_src(main, &[("shapes", shapes)]).expect("program should type-check");
+        let names: Vec<&str> = typed.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "main",
+                "Sq_side",
+                "Sq_new",
+                "Sq_area",
+                "Sq_grow",
+                "Sq_secret"
+            ]
+        );
+        assert_eq!(typed.functions[4].param_count, 2);
+
+        let bad = r#"
+import shapes;
+struct P { x: int @pub(get) }
+impl P {
+  fn x(&self) -> int { 1 }
+  fn peek(&self) -> int { self.y }
+  fn poke(&self) -> () { self.x = 2; }
+  fn grow(&mut self) -> () { self.x = self.x + 1; }
+  fn make() -> P { main::P { x: 0 } }
+  fn shared(&self) -> () { self.grow(); }
+}
+impl Q { fn f() -> () {} }
+fn main() -> () {
+  let p = P::make();
+  p.make();
+  P::nope();
+  p.nope();
+  let v = p.grow;
+  shapes::Sq::new(1).secret();
+}
+"#;
+        assert_eq!(
+            errors(bad, &[("shapes", shapes)]),
+            vec![
+                "method `x` is defined more than once for `P`",
+                "cannot find type `Q`",
+                "no field `y` on type `main::P`",
+                "cannot assign to field `x` behind a `&` reference",
+                "cannot call `grow` through a `&` reference: it takes `&mut self`",
+                "`make` is an associated function of `main::P`, not a method; call it as `main::P::make(..)`",
+                "no function or associated item named `nope` found for struct `main::P`",
+                "no method named `nope` found for `main::P`",
+                "attempted to take value of method `grow` on type `main::P`; call it with `grow(..)`",
+                "method `secret` is private to module `shapes`",
+            ]
+        );
+    }
+
+    #[test]
+    fn traits_with_defaults_and_builtins() {
+        let shapes = r#"
+export trait Shape {
+  fn area(&self) -> int;
+  fn describe(&self) -> string { "shape" }
+}
+trait Hidden { fn h(&self) -> int; }
+"#;
+        let main = r#"
+import shapes;
+struct P { x: int }
+impl shapes::Shape for P {
+  fn area(&self) -> int { self.x }
+}
+impl Display for P { fn show(&self) -> string { "P" } }
+impl Eq for P { fn eq(&self, other: &Self) -> bool { self.x == other.x } }
+impl Ord for P { fn cmp(&self, other: &P) -> int { self.x - other.x } }
+fn main() -> () {
+  let p = main::P { x: 1 };
+  print(p.describe());
+  print(p);
+  print(p == p);
+  print(p < p);
+}
+"#;
+        let typed = check_src(main, &[("shapes", shapes)]).expect("program should type-check");
+        let names: Vec<&str> = typed.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["main", "P_area", "P_describe", "P_show", "P_eq", "P_cmp"]
+        );
+        assert_eq!(typed.functions[2].module, "main");
+
+        let bad = r#"
+import shapes;
+struct A { v: int }
+struct B { v: int }
+enum E { X }
+trait T { fn f(&self) -> int; fn f(&self) -> int; }
+impl shapes::Shape for A {
+  fn area(&self) -> bool { true }
+  fn extra(&self) -> int { 1 }
+}
+impl shapes::Shape for B {}
+impl Display for A { fn show(&self) -> string { "a" } }
+impl Display for A { fn show(&self) -> string { "a" } }
+impl Nope for A {}
+impl shapes::Hidden for A {}
+impl Display for E {}
+fn main() -> () {
+  let b = main::B { v: 1 };
+  print(b);
+  print(b == b);
+}
+"#;
+        assert_eq!(
+            errors(bad, &[("shapes", shapes)]),
+            vec![
+                "method `f` is declared more than once in trait `T`",
+                "method `area` has an incompatible signature for trait `shapes::Shape`: expected `fn(&self) -> int`, found `fn(&self) -> bool`",
+                "method `extra` is not a member of trait `shapes::Shape`",
+                "not all trait items implemented, missing: `area`",
+                "conflicting implementations of trait `Display` for `main::A`",
+                "cannot find trait `Nope`",
+                "trait `Hidden` is private to module `shapes`",
+                "expected struct, found enum `E`",
+                "`print` expects a number, `bool` or `string` argument or a type that implements `Display`, found `main::B`",
+                "cannot compare `main::B` with `main::B`",
+            ]
+        );
+    }
+
+    #[test]
+    fn generics_are_monomorphized() {
+        let src = r#"
+struct Pair<A, B> { first: A, second: B }
+impl<A, B> Pair<A, B> {
+  fn first(&self) -> A { self.first }
+}
+struct P { x: int }
+impl Ord for P { fn cmp(&self, other: &P) -> int { self.x - other.x } }
+fn id<T>(x: T) -> T { x }
+fn max<T: Ord>(a: T, b: T) -> T { if a < b { b } else { a } }
+fn none<T>() -> Option<T> { None }
+fn main() -> () {
+  print(id(1));
+  print(id("a"));
+  print(id(2));
+  let p = main::Pair { first: 1, second: "b" };
+  print(p.first());
+  let m = max(main::P { x: 1 }, main::P { x: 2 });
+  print(max(m.x, 3));
+  print(max("a", "b"));
+  let o: Option<bool> = none();
+}
+"#;
+        let typed = check_src(src, &[]).expect("program should type-check");
+        let names: Vec<&str> = typed.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "main",
+                "P_cmp",
+                "id[int]",
+                "id[string]",
+                "Pair_first[int, string]",
+                "max[main::P]",
+                "max[int]",
+                "max[string]",
+                "none[bool]",
+            ]
+        );
+        let layouts: Vec<&str> = typed
+            .struct_layouts
+            .iter()
+            .map(|l| l.struct_name.as_str())
+            .collect();
+        assert_eq!(layouts, vec!["P", "Pair[int, string]"]);
+
+        let bad = r#"
+struct Pair<A, B> { first: A, second: B }
+struct P { x: int }
+fn none<T>() -> Option<T> { None }
+fn max<T: Ord>(a: T, b: T) -> T { a }
+fn add<T>(x: T) -> T { x + 1 }
+fn twice<T, T>(x: T) -> T { x }
+fn takes(p: Pair) -> () {}
+fn wrong(p: Pair<int>) -> () {}
+fn main() -> () {
+  let o = none();
+  max(main::P { x: 1 }, main::P { x: 2 });
+  max(1, true);
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "type parameter `T` is declared more than once",
+                "missing generics for struct `Pair`",
+                "`Pair` takes 2 type arguments but 1 were supplied",
+                "cannot add `int` to `T`",
+                "type annotations needed: cannot infer type parameter `T` of `none`",
+                "the trait `Ord` is not implemented for `main::P`",
+                "mismatched types: expected `int`, found `bool`",
+            ]
+        );
+
+        let unbounded = r#"
+fn deep<T>(x: T) -> () { deep(Some(x)); }
+fn main() -> () { deep(1); }
+"#;
+        let errs = errors(unbounded, &[]);
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].starts_with("reached the recursion limit while instantiating `deep["));
+
+        let doubling = r#"
+fn f<T>(x: T, n: int) -> int { if n == 0 { 0 } else { f((x, x), n - 1) } }
+fn main() -> () { print(f(1, 3)); }
+"#;
+        let errs = errors(doubling, &[]);
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].starts_with("reached the recursion limit while instantiating `f["));
+    }
+
+    #[test]
+    fn lists_check() {
+        let src = r#"
+struct P { x: int }
+fn total(xs: &List<int>) -> int {
+  let mut sum = 0;
+  for x in xs { sum = sum + x; }
+  sum
+}
+fn main() -> () {
+  let ps = [main::P { x: 1 }];
+  let p: Option<&P> = ps.get(0);
+  for q in ps { let r: &P = q; }
+  let mut xs = [1, 2, 3];
+  xs.push(4);
+  xs[0] = xs[1] + xs.len();
+  let first: Option<int> = xs.get(0);
+  let last = xs.pop();
+  let empty: List<string> = [];
+  print(total(&xs));
+}
+"#;
+        check_src(src, &[]).expect("program should type-check");
+
+        let bad = r#"
+struct P { x: int }
+fn f(xs: &List<int>) -> () {
+  xs.push(1);
+  xs[0] = 2;
+}
+fn main() -> () {
+  let ps = [main::P { x: 1 }];
+  for q in ps { q.x = 2; }
+  let a = [1, "x"];
+  let b = [];
+  let c = 5;
+  print(c[0]);
+  let d = [1, 2];
+  print(d[true]);
+  d.frob();
+  for x in c { }
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "cannot call `push` through a `&` reference: it takes `&mut self`",
+                "cannot assign to a list element behind a `&` reference",
+                "cannot assign to field `x` behind a `&` reference",
+                "mismatched types: expected `int`, found `string`",
+                "type annotations needed: cannot infer the element type of `[]`",
+                "cannot index into a value of type `int`",
+                "list indices must be `int`, found `bool`",
+                "no method named `frob` found for `List<int>`",
+                "`for` expects a range `start..end`, a `List`, a `Map` or a `Set`, found `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn tuples_check() {
+        let src = r#"
+fn divmod(a: int, b: int) -> (int, int) {
+  (a / b, a % b)
+}
+fn first<T, U>(p: (T, U)) -> T { p.0 }
+fn main() -> () {
+  let (q, r) = divmod(7, 2);
+  let mut t = (q, "x");
+  t.0 = r;
+  let s: string = t.1;
+  let n: int = first((1, true));
+  let label = match (q, r > 0) {
+    (0, _) => "zero",
+    (_, true) => "rest",
+    (_, false) => "exact",
+  };
+}
+"#;
+        check_src(src, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn main() -> () {
+  let (a, true) = (1, false);
+  let (x, y, z) = (1, 2);
+  let (m, n) = 5;
+  let t = (1, "a");
+  let c = t.2;
+  let u: (int, string) = (1, 2);
+  match t { (1, s) => {}, };
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "refutable pattern in `let`: `(_, false)` not covered",
+                "mismatched types: expected a tuple with 2 elements, found one with 3 elements",
+                "tuple pattern cannot match a value of type `int`",
+                "no field `2` on type `(int, string)`",
+                "mismatched types: expected `(int, string)`, found `(int, int)`",
+                "non-exhaustive patterns: `(_, _)` not covered",
+            ]
+        );
+    }
+
+    #[test]
+    fn maps_check() {
+        let src = r#"
+@derive(Eq, Hash)
+struct Point { x: int, y: int }
+resource struct Coin { v: int }
+fn count(words: &List<string>) -> Map<string, int> {
+  let mut counts: Map<string, int> = Map::new();
+  for w in words {
+    let n = match counts.get(w) { Some(n) => n, None => 0, };
+    counts.insert(w, n + 1);
+  }
+  counts
+}
+fn main() -> () {
+  let mut seen: Set<Point> = Set::new();
+  seen.insert(main::Point { x: 1, y: 2 });
+  for p in seen { let r: &Point = p; }
+  let mut at: Map<int, Point> = Map::new();
+  let found: Option<&Point> = at.get(1);
+  let mut purses: Map<int, Coin> = Map::new();
+  let old: Option<Coin> = purses.insert(1, main::Coin { v: 1 });
+  let fresh: bool = seen.remove(main::Point { x: 1, y: 2 });
+  let counts = count(&["a", "b", "a"]);
+  for w in counts { print(w); }
+  let n: int = counts.len();
+  let same: bool = "a" == "b";
+  let h: int = 5.hash();
+  let mut big: Map<u64, int> = Map::new();
+  big.insert(18446744073709551615, 1);
+  let bytes: Set<u8> = Set::new();
+  let wide: int = 7i64.hash();
+}
+"#;
+        check_src(src, &[]).expect("program should type-check");
+
+        let bad = r#"
+struct Plain { x: int }
+@derive(Eq, Hash)
+resource struct Coin { v: int }
+fn main() -> () {
+  let a = Map::new();
+  let b: Map<Plain, int> = Map::new();
+  let c: Map<List<int>, int> = Map::new();
+  let mut d: Set<int> = Set::new();
+  d.get(1);
+  d.insert("x");
+  let e: Set<Coin> = Set::new();
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "type annotations needed: cannot infer the key and value types of `Map::new()`",
+                "the trait `Eq` is not implemented for `main::Plain`",
+                "`List<int>` cannot be used as a key: keys must be integers, `bool`, `string` or a struct that implements `Eq` and `Hash`",
+                "no method named `get` found for `Set<int>`",
+                "mismatched types: expected `int`, found `string`",
+                "`main::Coin` cannot be used as a key: it holds a resource, which an equal key would replace and drop",
+            ]
+        );
+    }
+
+    #[test]
+    fn closures_check() {
+        let src = r#"
+fn apply(f: fn(int) -> int, x: int) -> int { f(x) }
+fn double(x: int) -> int { x * 2 }
+fn adder(n: int) -> fn(int) -> int { |x| x + n }
+fn twice<T>(x: T, f: fn(T) -> T) -> T { let g = |y: T| f(f(y)); g(x) }
+fn main() -> () {
+  let k = 1;
+  let a: int = apply(|x| x + k, 2);
+  let b: int = apply(double, 3);
+  let c = adder(4);
+  let d: int = c(5);
+  let e: string = twice("s", |s| s);
+  let f = |n: int| -> bool { if n > 0 { return true; } false };
+  let g = main::double;
+}
+"#;
+        check_src(src, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn id<T>(x: T) -> T { x }
+fn set(f: &mut fn(int) -> int) -> () { *f = |x| x + 1; }
+fn main() -> () {
+  let f = |x| x;
+  let g = |x: int| { return x; };
+  let mut n = 0;
+  let h = || { n = n + 1; };
+  let i = id;
+  let j = 3;
+  j(1);
+  let m: fn(int) -> string = |x| x;
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "cannot assign a function value (`fn(int) -> int`) through a reference; store it in a struct field instead",
+                "type annotations needed: cannot infer the type of closure parameter `x`",
+                "`return` in a closure needs its return type: write `|..| -> T { .. }`",
+                "cannot assign to `n`: it is captured by value in a closure",
+                "generic function `id` cannot be used as a value",
+                "expected function, found `int`",
+                "mismatched types: expected `string`, found `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_check() {
+        let src = r#"
+struct P { name: string }
+impl Display for P { fn show(&self) -> string { self.name } }
+fn main() -> () {
+  let name = "knox";
+  let p = main::P { name: name + "!" };
+  let s: string = "{name} is {name.len()} bytes, {true}, {p}";
+  let b: bool = "a" < "b" && s.contains("knox");
+  let parts: List<string> = s.split(" ");
+  let t: string = s.slice(0, 4).trim() + 12.to_string();
+  let n: Option<int> = "42".parse_int();
+  match name { "knox" => print(1), _ => print(0) }
+}
+"#;
+        check_src(src, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn main() -> () {
+  let xs = [1, 2];
+  let s = "xs = {xs}";
+  let t = "abc".slice("a", 2);
+  let u = "abc" < 1;
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "`List<int>` cannot be interpolated into a string: it does not implement `Display`",
+                "mismatched types: expected `int`, found `string`",
+                "cannot compare `string` with `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_check() {
+        let src = r#"
+struct S { small: u8, big: i64, ratio: f64 }
+fn main() -> () {
+  let big: i64 = 9000000000;
+  let s = main::S { small: 255, big: big * 2, ratio: 0.5 };
+  let n: u64 = 18446744073709551615;
+  let x = 1 + big;
+  let y: f64 = s.ratio * 2.0 + big as f64;
+  let z = -(3 as i64) < x && n / 2u64 > 0;
+  let c = 3.7 as u8 + 1;
+  let lo = -2147483648;
+  let hi: i64 = -9223372036854775808;
+  match s.small { 0..=9 => print(s.small), _ => print("{y} {c}") }
+}
+"#;
+        check_src(src, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn main() -> () {
+  let a: u8 = 256;
+  let b: i64 = 1;
+  let c = b + 2 * 3;
+  let d = b + c as int;
+  let e: u64 = 1;
+  let f = -e;
+  let g = "1" as int;
+  let h = 1.5 % 2.0;
+  let i = 2147483648;
+  let j = -2147483649;
+  let k: i64 = 9223372036854775808;
+  let l = -1u8;
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "literal out of range for `u8`",
+                "cannot add `int` to `i64`",
+                "cannot negate `u64`",
+                "cannot cast `string` as `int`",
+                "`%` is not supported on `f64`",
+                "literal out of range for `int`",
+                "literal out of range for `int`",
+                "literal out of range for `i64`",
+                "cannot negate `u8`",
+            ]
+        );
+    }
+
+    #[test]
+    fn overflow_methods_check() {
+        let src = r#"
+fn main() -> () {
+  let b: u8 = 200;
+  let w: u8 = b.wrapping_add(100);
+  let s: i64 = (5 as i64).saturating_mul(3);
+  let c: Option<int> = 7.checked_sub(1);
+  match 2147483647.checked_mul(2) { Some(v) => print(v), None => print(w) }
+}
+"#;
+        check_src(src, &[]).expect("program should type-check");
+
+        let bad = r#"
+fn main() -> () {
+  let a = 1.5.wrapping_add(1.0);
+  let b = 1.checked_div(2);
+  let c: u8 = 1;
+  let d = c.saturating_add(300);
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "no method named `wrapping_add` found for `f64`",
+                "no method named `checked_div` found for `int`",
+                "literal out of range for `u8`",
+            ]
+        );
+    }
+
+    #[test]
+    fn resource_structs_check() {
+        let bad = r#"
+resource struct Coin { value: int }
+struct Bad { c: Coin }
+enum Slot { Full(Coin), Empty }
+resource struct Wallet { coin: Coin, owner: int }
+impl Clone for Wallet { fn clone(&self) -> Wallet { main::Wallet { coin: main::Coin { value: 0 }, owner: 0 } } }
+fn main() -> () {
+  let w = main::Wallet { coin: main::Coin { value: 1 }, owner: 2 };
+  let Wallet { owner, .. } = w;
+  let _ = main::Coin { value: 3 };
+}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "field `c` holds a resource (`main::Coin`), so `Bad` must be declared `resource struct`",
+                "variant `Full` of enum `Slot` cannot hold a resource (`main::Coin`)",
+                "resource `main::Wallet` cannot implement `Clone`",
+                "pattern drops field `coin`, which holds a resource (`main::Coin`)",
+                "the `_` pattern would drop a resource (`main::Coin`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn copy_structs_check() {
+        let bad = r#"
+@derive(Copy)
+struct V { x: int, tag: string, pair: (int, bool) }
+@derive(Copy)
+struct Names { all: List<string> }
+struct P { x: int }
+@derive(Copy)
+struct Holder { p: P }
+resource struct Coin { value: int }
+impl Copy for Coin {}
+fn main() -> () {}
+"#;
+        assert_eq!(
+            errors(bad, &[]),
+            vec![
+                "resource `main::Coin` cannot implement `Copy`",
+                "`Names` cannot implement `Copy`: field `all` has type `List<string>`, which is not `Copy`",
+                "`Holder` cannot implement `Copy`: field `p` has type `main::P`, which is not `Copy`",
+            ]
+        );
+    }
+
+    #[test]
+    fn type_aliases_check() {
+        let main = r#"
+import ids;
+type Ids = List<ids::UserId>;
+type Lookup = fn(ids::UserId) -> bool;
+fn first(xs: &Ids) -> ids::UserId { xs[0] }
+fn main() -> () {
+  let xs: Ids = [1, 2];
+  let id: int = first(&xs);
+  let is_admin: Lookup = |u: int| u == 0;
+  print(is_admin(id));
+}
+"#;
+        let ids = "export type UserId = int;";
+        let program = check_src(main, &[("ids", ids)]).expect("program should type-check");
+        assert_eq!(program.functions[1].ret, Type::Int);
+
+        let bad = r#"
+import ids;
+type Loop = List<Loop>;
+type A = B;
+type B = A;
+type Missing = Nope;
+type Id = int;
+struct Id { x: int }
+fn main() -> () {
+  let s: ids::Secret = 1;
+  let g: Id<int> = 1;
+}
+"#;
+        assert_eq!(
+            errors(bad, &[("ids", "type Secret = int;")]),
+            vec![
+                "the name `Id` is defined more than once",
+                "cycle detected when expanding type alias `Loop`",
+                "cycle detected when expanding type alias `A`",
+                "cannot find type `Nope`",
+                "type alias `Secret` is private to module `ids`",
+                "`Id` takes 0 type arguments but 1 were supplied",
+            ]
+        );
+    }
+
+    #[test]
+    fn newtypes_check() {
+        let main = r#"
+type UserId = int;
+@derive(Eq, Copy)
+struct Meters(i64);
+struct Account { flag: u8, owner: UserId, walked: Meters }
+enum Event { Moved(Meters, u8), Idle }
+impl Meters {
+  fn double(&mut self) -> () { self.0 = self.0 * 2; }
+}
+fn main() -> () {
+  let mut m = Meters(3);
+  m.double();
+  let Meters(inner) = m;
+  let same = m == Meters(6);
+  let e = Event::Moved(m, 1);
+}
+"#;
+        let program = check_src(main, &[]).expect("program should type-check");
+        let sizes: Vec<(&str, u32)> = program
+            .struct_layouts
+            .iter()
+            .map(|l| (l.struct_name.as_str(), l.total_size))
+            .collect();
+        assert_eq!(sizes, vec![("Account", 16), ("Event", 17)]);
+        let offsets: Vec<(&str, u32)> = program
+            .struct_layouts
+            .iter()
+            .flat_map(|l| l.fields.iter().map(|(n, _, o)| (n.as_str(), *o)))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                ("flag", 0),
+                ("owner", 4),
+                ("walked", 8),
+                ("tag", 0),
+                ("Moved.0", 8),
+                ("Moved.1", 16),
+            ]
+        );
+        assert_eq!(
+            program
+                .newtypes
+                .get(&vec!["main".to_string(), "Meters".to_string()]),
+            Some(&Type::I64)
+        );
+
+        let bad = r#"
+import units;
+struct Amount(int);
+struct Loop(List<Loop>);
+fn main() -> () {
+  let a = Amount(5);
+  let n: int = a;
+  let b: Amount = 5;
+  let m = units::Meters(3);
+  let lit = main::Amount { v: 1 };
+  match a { Amount(p, q) => print(p) };
+  match a { main::Amount { v } => print(v) };
+}
+"#;
+        assert_eq!(
+            errors(bad, &[("units", "export struct Meters(int);")]),
+            vec![
+                "newtype `Loop` cannot wrap itself",
+                "mismatched types: expected `int`, found `main::Amount`",
+                "mismatched types: expected `main::Amount`, found `int`",
+                "field `0` of `Meters` is private to module `units`",
+                "`main::Amount` is a newtype; build it with `main::Amount(..)`",
+                "this pattern has 2 fields, but newtype `Amount` has 1 field",
+                "`main::Amount` is a newtype; use `main::Amount(..)`",
+            ]
+        );
+    }
+}
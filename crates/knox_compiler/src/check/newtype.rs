@@ -0,0 +1,209 @@
+//! Newtypes: `struct Meters(int);`. While checking, a newtype is a struct of its own with a
+//! single field `0`, so it does not mix with what it wraps; it is built with `Meters(x)` and
+//! taken apart with `m.0` or a `Meters(p)` pattern. Its values are represented as the wrapped
+//! value alone: layouts see through it (see `repr`), and `erase` removes it before lowering.
+
+use super::env::struct_type;
+use super::Checker;
+use crate::typed::{TExpr, TExprKind};
+use knox_syntax::ast::{Expr, Type};
+use knox_syntax::span::Span;
+use std::collections::{HashMap, HashSet};
+
+impl Checker {
+    /// True if `ty` is a newtype.
+    pub(super) fn is_newtype(&self, ty: &Type) -> bool {
+        self.struct_info(ty).is_some_and(|s| s.newtype)
+    }
+
+    /// The type a value of type `ty` is represented as: `ty` with every newtype replaced by the
+    /// type it wraps. A `&` reference to a newtype is the wrapped value itself, since nothing can
+    /// change it while it is borrowed (see `erase`).
+    pub(super) fn repr(&self, ty: &Type) -> Type {
+        let repr = |t: &Type| self.repr(t);
+        match ty {
+            Type::Path(_) if self.is_newtype(ty) => self
+                .struct_info(ty)
+                .and_then(|s| s.fields.first())
+                .map_or(Type::Unit, |f| self.repr(&f.ty)),
+            Type::Ref(false, inner) if self.is_newtype(inner) => repr(inner),
+            Type::Ref(mut_, inner) => Type::Ref(*mut_, Box::new(repr(inner))),
+            Type::Option(inner) => Type::Option(Box::new(repr(inner))),
+            Type::List(elem) => Type::List(Box::new(repr(elem))),
+            Type::Set(elem) => Type::Set(Box::new(repr(elem))),
+            Type::Map(key, value) => Type::Map(Box::new(repr(key)), Box::new(repr(value))),
+            Type::Result(ok, err) => Type::Result(Box::new(repr(ok)), Box::new(repr(err))),
+            Type::Generic(path, args) => {
+                Type::Generic(path.clone(), args.iter().map(repr).collect())
+            }
+            Type::Tuple(elems) => Type::Tuple(elems.iter().map(repr).collect()),
+            Type::Fn(params, ret) => {
+                Type::Fn(params.iter().map(repr).collect(), Box::new(repr(ret)))
+            }
+            _ => ty.clone(),
+        }
+    }
+
+    /// A newtype cannot wrap itself, even through a collection or another newtype: it would be
+    /// represented as an infinite type. Such a newtype is reported and then wraps `()`.
+    pub(super) fn check_newtype_cycles(&mut self) {
+        let mut newtypes: Vec<(usize, u32, String)> = self
+            .modules
+            .iter()
+            .enumerate()
+            .flat_map(|(m, env)| {
+                env.structs
+                    .iter()
+                    .filter(|(_, s)| s.newtype)
+                    .map(move |(name, s)| (m, s.span.start, name.clone()))
+            })
+            .collect();
+        newtypes.sort();
+        for (module, _, name) in newtypes {
+            let ty = struct_type(&self.modules[module].name, &name);
+            if !self.wraps(&ty, &ty, &mut HashSet::new()) {
+                continue;
+            }
+            let info = self.modules[module].structs.get_mut(&name);
+            let Some(info) = info else { continue };
+            let span = info.span;
+            if let Some(field) = info.fields.first_mut() {
+                field.ty = Type::Unit;
+            }
+            let outer = std::mem::replace(&mut self.module, module);
+            self.error(format!("newtype `{}` cannot wrap itself", name), span);
+            self.module = outer;
+        }
+    }
+
+    /// True if the representation of newtype `ty` mentions `target`.
+    fn wraps(&self, ty: &Type, target: &Type, seen: &mut HashSet<Type>) -> bool {
+        if !seen.insert(ty.clone()) {
+            return false;
+        }
+        let Some(field) = self.struct_info(ty).and_then(|s| s.fields.first()) else {
+            return false;
+        };
+        let mut found = false;
+        visit_paths(&field.ty, &mut |p| {
+            found |= p == target || (self.is_newtype(p) && self.wraps(p, target, seen));
+        });
+        found
+    }
+
+    /// `Meters(value)` or `units::Meters(value)`, if `name` is a newtype: the field `0` must be
+    /// visible from the current module.
+    pub(super) fn check_newtype_constructor(
+        &mut self,
+        span: Span,
+        qualifier: &[String],
+        name: &str,
+        args: &[Expr],
+    ) -> Option<Option<TExpr>> {
+        let module = match qualifier {
+            [] => self.module,
+            [module] => self.resolve_module(module)?,
+            _ => return None,
+        };
+        let inner = self.modules[module]
+            .structs
+            .get(name)
+            .filter(|s| s.newtype)?
+            .fields
+            .first()?
+            .ty
+            .clone();
+        let path: Vec<String> = qualifier
+            .iter()
+            .cloned()
+            .chain([name.to_string()])
+            .collect();
+        let ty = struct_type(&self.modules[module].name, name);
+        let visible = self.resolve_type_name(&path, span).is_some()
+            && self.field_visible(&ty, "0", span, true);
+        if !visible {
+            for a in args {
+                self.check_expr(a);
+            }
+            return Some(None);
+        }
+        let Some(mut args) = self.check_args(span, &path.join("::"), &[inner], args) else {
+            return Some(None);
+        };
+        Some(Some(TExpr {
+            span,
+            ty,
+            kind: TExprKind::StructLit(vec![("0".to_string(), args.remove(0))]),
+        }))
+    }
+
+    /// Paths of the newtypes with the type each is represented as (see
+    /// `TypedProgram::newtypes`).
+    pub(super) fn newtypes(&self) -> HashMap<Vec<String>, Type> {
+        self.modules
+            .iter()
+            .flat_map(|env| {
+                env.structs
+                    .iter()
+                    .filter(|(_, s)| s.newtype)
+                    .map(|(name, _)| struct_type(&env.name, name))
+            })
+            .map(|ty| {
+                let Type::Path(path) = &ty else {
+                    unreachable!("a newtype is not generic")
+                };
+                (path.clone(), self.repr(&ty))
+            })
+            .collect()
+    }
+
+    /// Replace the field offsets of every enum variant with ones computed from the types the
+    /// fields are represented as, once every newtype's field is known.
+    pub(super) fn lay_out_variants(&mut self) {
+        for m in 0..self.modules.len() {
+            let names: Vec<String> = self.modules[m].enums.keys().cloned().collect();
+            for name in names {
+                let variants = self.modules[m].enums[&name].variants.clone();
+                let variants = variants
+                    .into_iter()
+                    .map(|mut v| {
+                        let tys: Vec<Type> =
+                            v.fields.iter().map(|(_, t, _)| self.repr(t)).collect();
+                        let offsets = knox_syntax::variant_field_offsets(&tys);
+                        for (field, offset) in v.fields.iter_mut().zip(offsets) {
+                            field.2 = offset;
+                        }
+                        v
+                    })
+                    .collect();
+                if let Some(e) = self.modules[m].enums.get_mut(&name) {
+                    e.variants = variants;
+                }
+            }
+        }
+    }
+}
+
+/// Call `f` on every struct or enum type `ty` mentions, itself included.
+fn visit_paths(ty: &Type, f: &mut impl FnMut(&Type)) {
+    match ty {
+        Type::Path(_) => f(ty),
+        Type::Generic(_, args) => {
+            f(ty);
+            args.iter().for_each(|a| visit_paths(a, f));
+        }
+        Type::Ref(_, inner) | Type::Option(inner) | Type::List(inner) | Type::Set(inner) => {
+            visit_paths(inner, f)
+        }
+        Type::Result(a, b) | Type::Map(a, b) => {
+            visit_paths(a, f);
+            visit_paths(b, f);
+        }
+        Type::Tuple(elems) => elems.iter().for_each(|e| visit_paths(e, f)),
+        Type::Fn(params, ret) => {
+            params.iter().for_each(|p| visit_paths(p, f));
+            visit_paths(ret, f);
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,53 @@
+//! Built-in integer methods: `+`, `-` and `*` that wrap, report or saturate on overflow instead
+//! of trapping.
+
+use super::Checker;
+use crate::typed::{Callee, NumMethod, TExpr, TExprKind};
+use knox_syntax::ast::{BinaryOp, Expr, Type};
+use knox_syntax::span::Span;
+
+/// Built-in method `name` of an integer of type `ty`: its parameter types and return type.
+pub(super) fn number_method(ty: &Type, name: &str) -> Option<(NumMethod, Vec<Type>, Type)> {
+    if !ty.is_integer() {
+        return None;
+    }
+    let (mode, op) = name.split_once('_')?;
+    let op = match op {
+        "add" => BinaryOp::Add,
+        "sub" => BinaryOp::Sub,
+        "mul" => BinaryOp::Mul,
+        _ => return None,
+    };
+    let (method, ret) = match mode {
+        "wrapping" => (NumMethod::Wrapping(op), ty.clone()),
+        "checked" => (NumMethod::Checked(op), Type::Option(Box::new(ty.clone()))),
+        "saturating" => (NumMethod::Saturating(op), ty.clone()),
+        _ => return None,
+    };
+    Some((method, vec![ty.clone()], ret))
+}
+
+impl Checker {
+    /// `recv.name(args)` where `name` is a built-in method of the receiver's integer type (see
+    /// `number_method`).
+    pub(super) fn check_number_method(
+        &mut self,
+        span: Span,
+        recv: TExpr,
+        name: &str,
+        args: &[Expr],
+    ) -> Option<TExpr> {
+        let (method, params, ret) = number_method(&recv.ty, name)?;
+        let rest = self.check_args(span, name, &params, args)?;
+        let mut all_args = vec![recv];
+        all_args.extend(rest);
+        Some(TExpr {
+            span,
+            ty: ret,
+            kind: TExprKind::Call {
+                callee: Callee::Num(method),
+                args: all_args,
+            },
+        })
+    }
+}
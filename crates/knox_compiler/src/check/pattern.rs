@@ -0,0 +1,965 @@
+//! `match` checking: pattern types, bindings, guards, arm type unification, exhaustiveness and
+//! unreachable arms.
+
+use super::env::{struct_type_with, VariantKind};
+use super::{Checker, Local};
+use crate::typed::{holds_resource, LocalId, TArm, TExpr, TExprKind, TPattern};
+use knox_syntax::ast::{BuiltinVariant, Expr, MatchArm, MatchPattern, PatternFields, Type};
+use knox_syntax::payload_offset;
+use knox_syntax::span::Span;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// A field of a struct or variant: (name, type, byte offset).
+type FieldSlot = (String, Type, u32);
+
+/// One constructor of a type with finitely many shapes (`bool`, `Option`, `Result`, enums, structs).
+struct Ctor {
+    tag: u32,
+    /// As written in a pattern: `true`, `Some`, `Enum::Variant`, `Struct`.
+    name: String,
+    kind: VariantKind,
+    fields: Vec<FieldSlot>,
+}
+
+static WILDCARD: TPattern = TPattern::Wildcard;
+
+/// Type an integer pattern takes when matched against `ty`: `ty` itself for any integer type.
+fn integer_or_int(ty: &Type) -> Type {
+    if ty.is_integer() {
+        ty.clone()
+    } else {
+        Type::Int
+    }
+}
+
+fn is_wild(pat: &TPattern) -> bool {
+    matches!(pat, TPattern::Wildcard | TPattern::Binding(_))
+}
+
+/// The sub-patterns `pat` puts on each field of `ctor`, or None if it matches another constructor.
+fn specialize<'p>(pat: &'p TPattern, ctor: &Ctor) -> Option<Vec<&'p TPattern>> {
+    let by_offset = |fields: &'p [(u32, Type, TPattern)]| {
+        ctor.fields
+            .iter()
+            .map(|(_, _, offset)| {
+                fields
+                    .iter()
+                    .find(|(o, _, _)| o == offset)
+                    .map_or(&WILDCARD, |(_, _, p)| p)
+            })
+            .collect()
+    };
+    match pat {
+        TPattern::Wildcard | TPattern::Binding(_) => Some(vec![&WILDCARD; ctor.fields.len()]),
+        TPattern::Bool(b) if *b as u32 == ctor.tag => Some(Vec::new()),
+        TPattern::Variant { tag, fields } if *tag == ctor.tag => Some(by_offset(fields)),
+        TPattern::Struct { fields } => Some(by_offset(fields)),
+        TPattern::Tuple(subs) => Some(subs.iter().collect()),
+        _ => None,
+    }
+}
+
+/// Rows matching `ctor` in the first column, with that column replaced by the constructor's fields.
+fn specialize_rows<'p>(rows: &[Vec<&'p TPattern>], ctor: &Ctor) -> Vec<Vec<&'p TPattern>> {
+    rows.iter()
+        .filter_map(|row| {
+            let mut subs = specialize(row[0], ctor)?;
+            subs.extend(&row[1..]);
+            Some(subs)
+        })
+        .collect()
+}
+
+/// Column types after specializing the first column to `ctor`.
+fn sub_types(ctor: &Ctor, rest: &[Type]) -> Vec<Type> {
+    let mut tys: Vec<Type> = ctor.fields.iter().map(|(_, t, _)| t.clone()).collect();
+    tys.extend(rest.iter().cloned());
+    tys
+}
+
+/// Rows whose first pattern is `p | q` replaced by one row per alternative.
+fn expand_or<'p>(rows: &[Vec<&'p TPattern>]) -> Vec<Vec<&'p TPattern>> {
+    let mut out = Vec::with_capacity(rows.len());
+    for row in rows {
+        match row[0] {
+            TPattern::Or(alts) => {
+                let alt_rows: Vec<Vec<&TPattern>> = alts
+                    .iter()
+                    .map(|alt| {
+                        let mut r = vec![alt];
+                        r.extend(&row[1..]);
+                        r
+                    })
+                    .collect();
+                out.extend(expand_or(&alt_rows));
+            }
+            _ => out.push(row.clone()),
+        }
+    }
+    out
+}
+
+/// Patterns on a type without constructors (`int`, `string`): true if `p` matches every value `q`
+/// matches. Conservative: a range covered only by several arms together is not detected.
+fn covers(p: &TPattern, q: &TPattern) -> bool {
+    let int_bounds = |pat: &TPattern| match pat {
+        TPattern::Int(n) => Some((*n, *n)),
+        TPattern::Range { start, end } => Some((*start, *end)),
+        _ => None,
+    };
+    if is_wild(p) {
+        return true;
+    }
+    match (p, q) {
+        (TPattern::Str(a), TPattern::Str(b)) => a == b,
+        _ => match (int_bounds(p), int_bounds(q)) {
+            (Some((p_lo, p_hi)), Some((q_lo, q_hi))) => p_lo <= q_lo && q_hi <= p_hi,
+            _ => false,
+        },
+    }
+}
+
+/// Point the bindings of a later or-pattern alternative at the first alternative's locals.
+fn renumber_bindings(pat: &mut TPattern, map: &HashMap<LocalId, LocalId>) {
+    match pat {
+        TPattern::Binding(id) => {
+            if let Some(first) = map.get(id) {
+                *id = *first;
+            }
+        }
+        TPattern::Variant { fields, .. } | TPattern::Struct { fields } => fields
+            .iter_mut()
+            .for_each(|(_, _, p)| renumber_bindings(p, map)),
+        TPattern::Or(alts) | TPattern::Tuple(alts) => {
+            alts.iter_mut().for_each(|p| renumber_bindings(p, map))
+        }
+        _ => {}
+    }
+}
+
+/// Write `ctor` applied to witness sub-patterns, e.g. `Some(_)`, `Shape::Rect { w: 0, .. }` or
+/// `(_, false)` (a tuple's constructor has no name).
+fn show_ctor(ctor: &Ctor, subs: &[String]) -> String {
+    match ctor.kind {
+        VariantKind::Unit => ctor.name.clone(),
+        VariantKind::Tuple => format!("{}({})", ctor.name, subs.join(", ")),
+        VariantKind::Struct => {
+            let named: Vec<String> = ctor
+                .fields
+                .iter()
+                .zip(subs)
+                .filter(|(_, w)| *w != "_")
+                .map(|((n, _, _), w)| format!("{}: {}", n, w))
+                .collect();
+            if named.is_empty() {
+                format!("{} {{ .. }}", ctor.name)
+            } else {
+                format!("{} {{ {}, .. }}", ctor.name, named.join(", "))
+            }
+        }
+    }
+}
+
+/// `1 field`, `2 fields`
+fn fields_count(n: usize) -> String {
+    format!("{} field{}", n, if n == 1 { "" } else { "s" })
+}
+
+/// `a`, `a and b`, `a, b and c`
+fn join_and(items: &[String]) -> String {
+    match items.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => items.join(""),
+    }
+}
+
+impl Checker {
+    /// Fields of struct `name` in `module` with type arguments `args`, at the byte offsets of
+    /// its layout.
+    fn struct_fields(&self, module: usize, name: &str, args: &[Type]) -> Vec<FieldSlot> {
+        self.modules[module].structs[name].fields_with(args, |t| self.repr(t))
+    }
+
+    /// Constructors of `ty` in tag order; None if its values cannot be enumerated (`int`, `string`).
+    /// A struct or tuple has a single constructor; a tuple's fields are numbered by position.
+    fn ctors(&self, ty: &Type) -> Option<Vec<Ctor>> {
+        let builtin = |v: BuiltinVariant, payload: Option<&Type>| Ctor {
+            tag: v.tag(),
+            name: v.name().to_string(),
+            kind: if payload.is_some() {
+                VariantKind::Tuple
+            } else {
+                VariantKind::Unit
+            },
+            fields: payload
+                .map(|t| vec![("0".to_string(), t.clone(), payload_offset(&self.repr(t)))])
+                .unwrap_or_default(),
+        };
+        match ty {
+            Type::Bool => Some(
+                [false, true]
+                    .into_iter()
+                    .map(|b| Ctor {
+                        tag: b as u32,
+                        name: b.to_string(),
+                        kind: VariantKind::Unit,
+                        fields: Vec::new(),
+                    })
+                    .collect(),
+            ),
+            Type::Option(inner) => Some(vec![
+                builtin(BuiltinVariant::None, None),
+                builtin(BuiltinVariant::Some, Some(inner)),
+            ]),
+            Type::Result(ok, err) => Some(vec![
+                builtin(BuiltinVariant::Ok, Some(ok)),
+                builtin(BuiltinVariant::Err, Some(err)),
+            ]),
+            Type::Tuple(elems) => Some(vec![Ctor {
+                tag: 0,
+                name: String::new(),
+                kind: VariantKind::Tuple,
+                fields: elems
+                    .iter()
+                    .enumerate()
+                    .map(|(i, t)| (i.to_string(), t.clone(), i as u32))
+                    .collect(),
+            }]),
+            Type::Generic(p, args) => {
+                let module = *self.by_name.get(&p[0])?;
+                Some(vec![Ctor {
+                    tag: 0,
+                    name: p[1].clone(),
+                    kind: VariantKind::Struct,
+                    fields: self.struct_fields(module, &p[1], args),
+                }])
+            }
+            Type::Path(p) if p.len() == 2 => {
+                let module = *self.by_name.get(&p[0])?;
+                if self.modules[module].structs.contains_key(&p[1]) {
+                    let newtype = self.modules[module].structs[&p[1]].newtype;
+                    return Some(vec![Ctor {
+                        tag: 0,
+                        name: p[1].clone(),
+                        kind: if newtype {
+                            VariantKind::Tuple
+                        } else {
+                            VariantKind::Struct
+                        },
+                        fields: self.struct_fields(module, &p[1], &[]),
+                    }]);
+                }
+                let info = self.modules[module].enums.get(&p[1])?;
+                Some(
+                    info.variants
+                        .iter()
+                        .map(|v| Ctor {
+                            tag: v.tag,
+                            name: format!("{}::{}", p[1], v.name),
+                            kind: v.kind,
+                            fields: v.fields.clone(),
+                        })
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Constructors to split the first column on: only when some row looks inside the value,
+    /// which also keeps the search from unfolding recursive types forever.
+    fn split_ctors(&self, ty: &Type, heads: impl IntoIterator<Item = bool>) -> Option<Vec<Ctor>> {
+        let mut heads = heads.into_iter();
+        self.ctors(ty).filter(|_| heads.any(|wild| !wild))
+    }
+
+    /// Values matched by none of `rows` (each row has one pattern per column of `tys`), written as
+    /// one pattern per column. With `all`, one witness per uncovered constructor of the first
+    /// column; otherwise at most one witness.
+    fn witnesses(&self, rows: &[Vec<&TPattern>], tys: &[Type], all: bool) -> Vec<Vec<String>> {
+        if rows.is_empty() {
+            return vec![vec!["_".to_string(); tys.len()]];
+        }
+        let Some((ty, rest_tys)) = tys.split_first() else {
+            return Vec::new();
+        };
+        let rows = expand_or(rows);
+        let mut out = Vec::new();
+        match self.split_ctors(ty, rows.iter().map(|row| is_wild(row[0]))) {
+            Some(ctors) => {
+                for ctor in &ctors {
+                    let specialized = specialize_rows(&rows, ctor);
+                    for w in self.witnesses(&specialized, &sub_types(ctor, rest_tys), false) {
+                        let (head, tail) = w.split_at(ctor.fields.len());
+                        let mut witness = vec![show_ctor(ctor, head)];
+                        witness.extend(tail.iter().cloned());
+                        out.push(witness);
+                    }
+                    if !all && !out.is_empty() {
+                        break;
+                    }
+                }
+            }
+            None => {
+                let defaults: Vec<Vec<&TPattern>> = rows
+                    .iter()
+                    .filter(|row| is_wild(row[0]))
+                    .map(|row| row[1..].to_vec())
+                    .collect();
+                for w in self.witnesses(&defaults, rest_tys, false) {
+                    let mut witness = vec!["_".to_string()];
+                    witness.extend(w);
+                    out.push(witness);
+                }
+            }
+        }
+        out
+    }
+
+    /// True if some value matched by `q` is matched by none of `rows` (rows have the width of `q`).
+    fn useful(&self, rows: &[Vec<&TPattern>], q: &[&TPattern], tys: &[Type]) -> bool {
+        let (Some((head, q_rest)), Some((ty, rest_tys))) = (q.split_first(), tys.split_first())
+        else {
+            return rows.is_empty();
+        };
+        let rows = expand_or(rows);
+        if let TPattern::Or(alts) = head {
+            return alts.iter().any(|alt| {
+                let mut q = vec![alt];
+                q.extend(q_rest);
+                self.useful(&rows, &q, tys)
+            });
+        }
+        let heads = std::iter::once(is_wild(head)).chain(rows.iter().map(|row| is_wild(row[0])));
+        match self.split_ctors(ty, heads) {
+            Some(ctors) => ctors.iter().any(|ctor| {
+                let Some(mut q) = specialize(head, ctor) else {
+                    return false;
+                };
+                q.extend(q_rest);
+                let sub_tys = sub_types(ctor, rest_tys);
+                self.useful(&specialize_rows(&rows, ctor), &q, &sub_tys)
+            }),
+            None => {
+                let kept: Vec<Vec<&TPattern>> = rows
+                    .iter()
+                    .filter(|row| covers(row[0], head))
+                    .map(|row| row[1..].to_vec())
+                    .collect();
+                self.useful(&kept, q_rest, rest_tys)
+            }
+        }
+    }
+
+    /// Every arm gets its own scope for the bindings of its pattern, which its guard and body see.
+    /// Arms that never complete (e.g. `{ return x; }`) do not take part in the result type. The
+    /// arms must cover every value; an arm no value can reach gets an "unreachable pattern" warning.
+    pub(super) fn check_match(
+        &mut self,
+        span: Span,
+        value: &Expr,
+        arms: &[MatchArm],
+        expected: Option<&Type>,
+    ) -> Option<TExpr> {
+        let scrutinee = self.check_expr(value);
+        let scrut_ty = scrutinee.as_ref().map(|s| s.ty.clone());
+        let mut result: Option<Type> = None;
+        let mut checked: Vec<(Span, TArm)> = Vec::with_capacity(arms.len());
+        let mut ok = scrutinee.is_some();
+        for arm in arms {
+            self.scopes.push(HashMap::new());
+            let pattern = self.check_pattern(&arm.pattern, scrut_ty.as_ref(), arm.span);
+            let guard = match &arm.guard {
+                Some(g) => self.check_expr_as(g, &Type::Bool).map(Some),
+                None => Some(None),
+            };
+            let hint = result.clone().or_else(|| expected.cloned());
+            let body = self.check_value(&arm.body, hint.as_ref());
+            self.scopes.pop();
+            let (Some(pattern), Some(guard), Some(body)) = (pattern, guard, body) else {
+                ok = false;
+                continue;
+            };
+            if !body.diverges() {
+                match &result {
+                    Some(r) if *r != body.ty => {
+                        self.error(
+                            format!("mismatched types: expected `{}`, found `{}`", r, body.ty),
+                            arm.body.span(),
+                        );
+                        ok = false;
+                        continue;
+                    }
+                    Some(_) => {}
+                    None => result = Some(body.ty.clone()),
+                }
+            }
+            let arm_checked = TArm {
+                pattern,
+                guard,
+                body,
+            };
+            checked.push((arm.span, arm_checked));
+        }
+        if arms.is_empty() {
+            self.error("match must have at least one arm", span);
+            return None;
+        }
+        if !ok {
+            return None;
+        }
+        let scrutinee = scrutinee?;
+        let tys = std::slice::from_ref(&scrutinee.ty);
+        // A guarded arm may fail, so it neither covers values nor makes later arms unreachable.
+        let mut covered: Vec<Vec<&TPattern>> = Vec::new();
+        let mut unreachable = Vec::new();
+        for (arm_span, arm) in &checked {
+            if !self.useful(&covered, &[&arm.pattern], tys) {
+                unreachable.push(*arm_span);
+            }
+            if arm.guard.is_none() {
+                covered.push(vec![&arm.pattern]);
+            }
+        }
+        let missing: Vec<String> = self
+            .witnesses(&covered, tys, true)
+            .into_iter()
+            .map(|mut w| format!("`{}`", w.remove(0)))
+            .collect();
+        for arm_span in unreachable {
+            self.warn("unreachable pattern", arm_span);
+        }
+        if !missing.is_empty() {
+            self.error(
+                format!(
+                    "non-exhaustive patterns: {} not covered",
+                    join_and(&missing)
+                ),
+                span,
+            );
+            return None;
+        }
+        // Every arm diverges: the match never produces a value.
+        let ty = result.or_else(|| expected.cloned()).unwrap_or(Type::Unit);
+        Some(TExpr {
+            span,
+            ty,
+            kind: TExprKind::Match {
+                value: Box::new(scrutinee),
+                arms: checked.into_iter().map(|(_, arm)| arm).collect(),
+            },
+        })
+    }
+
+    /// Declare every binding in `pat` with an unknown type, so the arm still checks after a pattern error.
+    fn bind_unknown(&mut self, pat: &MatchPattern, span: Span) {
+        match pat {
+            MatchPattern::Binding(name) if self.const_module(&[], name).is_none() => {
+                self.declare(name, None, span);
+            }
+            MatchPattern::Variant(_, Some(sub)) => self.bind_unknown(sub, span),
+            MatchPattern::Path {
+                fields: PatternFields::Tuple(subs),
+                ..
+            } => subs.iter().for_each(|p| self.bind_unknown(p, span)),
+            MatchPattern::Path {
+                fields: PatternFields::Struct { fields, .. },
+                ..
+            } => fields.iter().for_each(|(_, p)| self.bind_unknown(p, span)),
+            MatchPattern::Or(alts) => {
+                if let Some(first) = alts.first() {
+                    self.bind_unknown(first, span);
+                }
+            }
+            MatchPattern::Tuple(subs) => subs.iter().for_each(|p| self.bind_unknown(p, span)),
+            _ => {}
+        }
+    }
+
+    /// Check a pattern against the scrutinee type (None if the scrutinee failed to check),
+    /// declaring its bindings in the current scope.
+    fn check_pattern(
+        &mut self,
+        pat: &MatchPattern,
+        ty: Option<&Type>,
+        span: Span,
+    ) -> Option<TPattern> {
+        let Some(ty) = ty else {
+            self.bind_unknown(pat, span);
+            return None;
+        };
+        let (literal_ty, literal) = match pat {
+            MatchPattern::Underscore => {
+                if holds_resource(&self.resources, ty) {
+                    self.error(
+                        format!("the `_` pattern would drop a resource (`{}`)", ty),
+                        span,
+                    );
+                }
+                return Some(TPattern::Wildcard);
+            }
+            MatchPattern::Binding(name) => {
+                if let Some(module) = self.const_module(&[], name) {
+                    return self.check_const_pattern(module, name, ty, span);
+                }
+                if self.scopes.last().is_some_and(|s| s.contains_key(name)) {
+                    self.error(
+                        format!(
+                            "identifier `{}` is bound more than once in the same pattern",
+                            name
+                        ),
+                        span,
+                    );
+                    return None;
+                }
+                return Some(TPattern::Binding(self.declare(
+                    name,
+                    Some(ty.clone()),
+                    span,
+                )));
+            }
+            MatchPattern::Int(n) => (integer_or_int(ty), TPattern::Int(*n)),
+            MatchPattern::Bool(b) => (Type::Bool, TPattern::Bool(*b)),
+            MatchPattern::String(s) => (Type::String, TPattern::Str(s.clone())),
+            MatchPattern::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let end = if *inclusive {
+                    *end
+                } else {
+                    end.saturating_sub(1)
+                };
+                if *start > end {
+                    let msg = if *inclusive {
+                        "lower range bound must be less than or equal to upper"
+                    } else {
+                        "lower range bound must be less than upper"
+                    };
+                    self.error(msg, span);
+                    return None;
+                }
+                (integer_or_int(ty), TPattern::Range { start: *start, end })
+            }
+            MatchPattern::Variant(variant, sub) => {
+                let ctor = self
+                    .ctors(ty)
+                    .filter(|_| matches!(ty, Type::Option(_) | Type::Result(_, _)))
+                    .and_then(|cs| cs.into_iter().find(|c| c.name == variant.name()));
+                let Some(ctor) = ctor else {
+                    self.error(
+                        format!(
+                            "pattern `{}` cannot match a value of type `{}`",
+                            variant.name(),
+                            ty
+                        ),
+                        span,
+                    );
+                    self.bind_unknown(pat, span);
+                    return None;
+                };
+                let fields = match (ctor.fields.first(), sub) {
+                    (Some((_, payload, offset)), Some(sub)) => {
+                        let sub = self.check_pattern(sub, Some(payload), span)?;
+                        vec![(*offset, payload.clone(), sub)]
+                    }
+                    _ => Vec::new(),
+                };
+                return Some(TPattern::Variant {
+                    tag: ctor.tag,
+                    fields,
+                });
+            }
+            MatchPattern::Path {
+                path,
+                fields: PatternFields::Unit,
+            } if path.len() == 2 && self.const_module(&path[..1], &path[1]).is_some() => {
+                let module = self.const_module(&path[..1], &path[1])?;
+                return self.check_const_pattern(module, &path[1], ty, span);
+            }
+            MatchPattern::Path { path, fields } => {
+                let enum_ = path
+                    .split_last()
+                    .and_then(|(_, prefix)| self.enum_prefix(prefix));
+                let checked = match enum_ {
+                    Some(enum_) => self.check_enum_pattern(enum_, path, fields, ty, span),
+                    None => self.check_struct_pattern(path, fields, ty, span),
+                };
+                if checked.is_none() {
+                    self.bind_unknown(pat, span);
+                }
+                return checked;
+            }
+            MatchPattern::Or(alts) => return self.check_or_pattern(alts, ty, span),
+            MatchPattern::Tuple(pats) => return self.check_tuple_pattern(pats, ty, span),
+        };
+        self.expect_pattern_type(&literal_ty, ty, span)?;
+        Some(literal)
+    }
+
+    /// Report a pattern of type `found` used on a value of type `ty`.
+    pub(super) fn expect_pattern_type(
+        &mut self,
+        found: &Type,
+        ty: &Type,
+        span: Span,
+    ) -> Option<()> {
+        if found == ty {
+            return Some(());
+        }
+        self.error(
+            format!(
+                "pattern of type `{}` cannot match a value of type `{}`",
+                found, ty
+            ),
+            span,
+        );
+        None
+    }
+
+    /// `p | q`: every alternative must bind the same names with the same types. The bindings of
+    /// later alternatives are renumbered to the first alternative's locals.
+    fn check_or_pattern(
+        &mut self,
+        alts: &[MatchPattern],
+        ty: &Type,
+        span: Span,
+    ) -> Option<TPattern> {
+        let mut checked = Vec::with_capacity(alts.len());
+        let mut first: Option<HashMap<String, Local>> = None;
+        let mut ok = true;
+        for alt in alts {
+            self.scopes.push(HashMap::new());
+            let tp = self.check_pattern(alt, Some(ty), span);
+            let bound = self.scopes.pop().unwrap_or_default();
+            let Some(mut tp) = tp else {
+                ok = false;
+                first.get_or_insert(bound);
+                continue;
+            };
+            let Some(names) = &first else {
+                first = Some(bound);
+                checked.push(tp);
+                continue;
+            };
+            let mut all: Vec<&String> = names.keys().chain(bound.keys()).collect();
+            all.sort();
+            all.dedup();
+            let mut renumber = HashMap::new();
+            for name in all {
+                let (Some(a), Some(b)) = (names.get(name), bound.get(name)) else {
+                    self.error(
+                        format!("variable `{}` is not bound in all patterns", name),
+                        span,
+                    );
+                    ok = false;
+                    continue;
+                };
+                if let (Some(t1), Some(t2)) = (&a.ty, &b.ty) {
+                    if t1 != t2 {
+                        self.error(
+                            format!(
+                                "variable `{}` has type `{}` in one alternative and `{}` in another",
+                                name, t1, t2
+                            ),
+                            span,
+                        );
+                        ok = false;
+                    }
+                }
+                renumber.insert(b.id, a.id);
+            }
+            renumber_bindings(&mut tp, &renumber);
+            checked.push(tp);
+        }
+        let mut names: Vec<(String, Local)> = first.unwrap_or_default().into_iter().collect();
+        names.sort_by_key(|(_, local)| local.id);
+        for (name, local) in names {
+            let Some(scope) = self.scopes.last_mut() else {
+                break;
+            };
+            if let Entry::Vacant(slot) = scope.entry(name.clone()) {
+                slot.insert(local);
+                continue;
+            }
+            self.error(
+                format!(
+                    "identifier `{}` is bound more than once in the same pattern",
+                    name
+                ),
+                span,
+            );
+            ok = false;
+        }
+        ok.then_some(TPattern::Or(checked))
+    }
+
+    /// `(p, q)`: one sub-pattern per element.
+    fn check_tuple_pattern(
+        &mut self,
+        pats: &[MatchPattern],
+        ty: &Type,
+        span: Span,
+    ) -> Option<TPattern> {
+        let elems = match ty {
+            Type::Tuple(elems) if elems.len() == pats.len() => elems,
+            Type::Tuple(elems) => {
+                self.error(
+                    format!(
+                        "mismatched types: expected a tuple with {} elements, found one with {} elements",
+                        elems.len(),
+                        pats.len()
+                    ),
+                    span,
+                );
+                pats.iter().for_each(|p| self.bind_unknown(p, span));
+                return None;
+            }
+            _ => {
+                self.error(
+                    format!("tuple pattern cannot match a value of type `{}`", ty),
+                    span,
+                );
+                pats.iter().for_each(|p| self.bind_unknown(p, span));
+                return None;
+            }
+        };
+        let mut checked = Vec::with_capacity(pats.len());
+        let mut ok = true;
+        for (p, ety) in pats.iter().zip(elems) {
+            match self.check_pattern(p, Some(ety), span) {
+                Some(tp) => checked.push(tp),
+                None => ok = false,
+            }
+        }
+        ok.then_some(TPattern::Tuple(checked))
+    }
+
+    /// The pattern of `let (a, b) = value;`: checked like a match arm's, but it must match every
+    /// value of `ty`. Its bindings are declared in the current scope.
+    pub(super) fn check_let_pattern(
+        &mut self,
+        pat: &MatchPattern,
+        ty: Option<&Type>,
+        span: Span,
+    ) -> Option<TPattern> {
+        self.scopes.push(HashMap::new());
+        let checked = self.check_pattern(pat, ty, span);
+        let bound = self.scopes.pop().unwrap_or_default();
+        let mut bound: Vec<(String, Local)> = bound.into_iter().collect();
+        bound.sort_by_key(|(_, local)| local.id);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.extend(bound);
+        }
+        let (checked, ty) = (checked?, ty?);
+        let missing = self.witnesses(&[vec![&checked]], std::slice::from_ref(ty), false);
+        if let Some(w) = missing.into_iter().next() {
+            self.error(
+                format!("refutable pattern in `let`: `{}` not covered", w[0]),
+                span,
+            );
+            return None;
+        }
+        Some(checked)
+    }
+
+    /// `Enum::A`, `Enum::B(p, q)`, `Enum::C { x, y: p, .. }`
+    fn check_enum_pattern(
+        &mut self,
+        enum_: (usize, String),
+        path: &[String],
+        fields: &PatternFields,
+        ty: &Type,
+        span: Span,
+    ) -> Option<TPattern> {
+        let (enum_ty, v) = self.resolve_variant(enum_, path.last()?, span)?;
+        let path = path.join("::");
+        self.expect_pattern_type(&enum_ty, ty, span)?;
+        let subs: Vec<(&FieldSlot, &MatchPattern)> = match (v.kind, fields) {
+            (VariantKind::Unit, PatternFields::Unit) => Vec::new(),
+            (VariantKind::Tuple, PatternFields::Tuple(pats)) => {
+                if pats.len() != v.fields.len() {
+                    self.error(
+                        format!(
+                            "this pattern has {}, but variant `{}` has {}",
+                            fields_count(pats.len()),
+                            path,
+                            fields_count(v.fields.len())
+                        ),
+                        span,
+                    );
+                    return None;
+                }
+                v.fields.iter().zip(pats).collect()
+            }
+            (VariantKind::Struct, PatternFields::Struct { fields, rest }) => {
+                self.named_field_patterns("variant", &path, &v.fields, fields, *rest, span)?
+            }
+            _ => {
+                self.variant_form_error(&v, &path, span);
+                return None;
+            }
+        };
+        Some(TPattern::Variant {
+            tag: v.tag,
+            fields: self.check_field_patterns(subs, span)?,
+        })
+    }
+
+    /// `Point { x, y: 0, .. }`
+    fn check_struct_pattern(
+        &mut self,
+        path: &[String],
+        fields: &PatternFields,
+        ty: &Type,
+        span: Span,
+    ) -> Option<TPattern> {
+        let (module, name) = self.resolve_type_name(path, span)?;
+        let path = path.join("::");
+        if !self.modules[module].structs.contains_key(&name) {
+            self.error(format!("expected struct, found enum `{}`", path), span);
+            return None;
+        }
+        // A generic struct's pattern takes its type arguments from the scrutinee.
+        let args = match ty {
+            Type::Generic(p, args) if p[1] == name && p[0] == self.modules[module].name => {
+                args.clone()
+            }
+            _ => Vec::new(),
+        };
+        let struct_ty = struct_type_with(&self.modules[module].name, &name, args.clone());
+        self.expect_pattern_type(&struct_ty, ty, span)?;
+        if self.modules[module].structs[&name].newtype {
+            return self.check_newtype_pattern(&path, &struct_ty, fields, span);
+        }
+        let PatternFields::Struct { fields, rest } = fields else {
+            self.error(
+                format!(
+                    "struct `{}` has named fields; use `{} {{ .. }}`",
+                    path, path
+                ),
+                span,
+            );
+            return None;
+        };
+        let mut visible = true;
+        for (fname, _) in fields {
+            visible &= self.field_visible(&struct_ty, fname, span, false);
+        }
+        if !visible {
+            return None;
+        }
+        let declared = self.struct_fields(module, &name, &args);
+        let subs = self.named_field_patterns("struct", &path, &declared, fields, *rest, span)?;
+        Some(TPattern::Struct {
+            fields: self.check_field_patterns(subs, span)?,
+        })
+    }
+
+    /// `Meters(p)`: a newtype's pattern has a single sub-pattern, on its field `0`.
+    fn check_newtype_pattern(
+        &mut self,
+        path: &str,
+        ty: &Type,
+        fields: &PatternFields,
+        span: Span,
+    ) -> Option<TPattern> {
+        let PatternFields::Tuple(pats) = fields else {
+            self.error(format!("`{}` is a newtype; use `{}(..)`", path, path), span);
+            return None;
+        };
+        if pats.len() != 1 {
+            self.error(
+                format!(
+                    "this pattern has {}, but newtype `{}` has 1 field",
+                    fields_count(pats.len()),
+                    path
+                ),
+                span,
+            );
+            return None;
+        }
+        if !self.field_visible(ty, "0", span, false) {
+            return None;
+        }
+        let declared = match self.struct_info(ty) {
+            Some(info) => info.fields_with(&[], |t| self.repr(t)),
+            None => return None,
+        };
+        let subs = declared.iter().zip(pats).collect();
+        Some(TPattern::Struct {
+            fields: self.check_field_patterns(subs, span)?,
+        })
+    }
+
+    /// Pair each `name: pattern` with its declared field. Fields left out with `..` match anything.
+    fn named_field_patterns<'a>(
+        &mut self,
+        kind: &str,
+        path: &str,
+        declared: &'a [FieldSlot],
+        fields: &'a [(String, MatchPattern)],
+        rest: bool,
+        span: Span,
+    ) -> Option<Vec<(&'a FieldSlot, &'a MatchPattern)>> {
+        let mut subs: Vec<(&FieldSlot, &MatchPattern)> = Vec::with_capacity(fields.len());
+        for (name, p) in fields {
+            if subs.iter().any(|((n, _, _), _)| n == name) {
+                self.error(format!("field `{}` bound more than once", name), span);
+                return None;
+            }
+            let Some(field) = declared.iter().find(|(n, _, _)| n == name) else {
+                self.error(
+                    format!("{} `{}` has no field named `{}`", kind, path, name),
+                    span,
+                );
+                return None;
+            };
+            subs.push((field, p));
+        }
+        let missing = declared
+            .iter()
+            .find(|(n, _, _)| !fields.iter().any(|(f, _)| f == n));
+        if let (Some((name, _, _)), false) = (missing, rest) {
+            self.error(
+                format!(
+                    "pattern does not mention field `{}`; add it or use `..`",
+                    name
+                ),
+                span,
+            );
+            return None;
+        }
+        let dropped = declared.iter().find(|(n, ty, _)| {
+            !fields.iter().any(|(f, _)| f == n) && holds_resource(&self.resources, ty)
+        });
+        if let Some((name, ty, _)) = dropped {
+            self.error(
+                format!(
+                    "pattern drops field `{}`, which holds a resource (`{}`)",
+                    name, ty
+                ),
+                span,
+            );
+        }
+        Some(subs)
+    }
+
+    fn check_field_patterns(
+        &mut self,
+        subs: Vec<(&FieldSlot, &MatchPattern)>,
+        span: Span,
+    ) -> Option<Vec<(u32, Type, TPattern)>> {
+        let mut checked = Vec::with_capacity(subs.len());
+        let mut ok = true;
+        for ((_, fty, offset), p) in subs {
+            match self.check_pattern(p, Some(fty), span) {
+                Some(tp) => checked.push((*offset, fty.clone(), tp)),
+                None => ok = false,
+            }
+        }
+        ok.then_some(checked)
+    }
+}
@@ -0,0 +1,124 @@
+//! Strings: interpolation, the built-in string methods, and `to_string()` on numbers and `bool`.
+
+use super::Checker;
+use crate::typed::{Callee, StrMethod, TExpr, TExprKind};
+use knox_syntax::ast::{BinaryOp, Expr, InterpolationPart, Type};
+use knox_syntax::span::Span;
+
+/// Built-in method `name` of a value of type `ty`: parameter types and return type.
+pub(super) fn string_method(ty: &Type, name: &str) -> Option<(StrMethod, Vec<Type>, Type)> {
+    Some(match (ty, name) {
+        (Type::String, "len") => (StrMethod::Len, Vec::new(), Type::Int),
+        (Type::String, "slice") => (StrMethod::Slice, vec![Type::Int, Type::Int], Type::String),
+        (Type::String, "contains") => (StrMethod::Contains, vec![Type::String], Type::Bool),
+        (Type::String, "split") => (
+            StrMethod::Split,
+            vec![Type::String],
+            Type::List(Box::new(Type::String)),
+        ),
+        (Type::String, "trim") => (StrMethod::Trim, Vec::new(), Type::String),
+        (Type::String, "parse_int") => (
+            StrMethod::ParseInt,
+            Vec::new(),
+            Type::Option(Box::new(Type::Int)),
+        ),
+        (ty, "to_string") if ty.is_numeric() || *ty == Type::Bool => {
+            (StrMethod::ToString, Vec::new(), Type::String)
+        }
+        _ => return None,
+    })
+}
+
+impl Checker {
+    /// `recv.name(args)` where `name` is a built-in method of the receiver's type (see
+    /// `string_method`).
+    pub(super) fn check_string_method(
+        &mut self,
+        span: Span,
+        recv: TExpr,
+        name: &str,
+        args: &[Expr],
+    ) -> Option<TExpr> {
+        let (method, params, ret) = string_method(&recv.ty, name)?;
+        let rest = self.check_args(span, name, &params, args)?;
+        let mut all_args = vec![recv];
+        all_args.extend(rest);
+        Some(TExpr {
+            span,
+            ty: ret,
+            kind: TExprKind::Call {
+                callee: Callee::Str(method),
+                args: all_args,
+            },
+        })
+    }
+
+    /// `"Hi {name}"`: the text and the values, each converted to a string, concatenated in order.
+    /// Values are converted like `print` does: `Display::show` for a struct that implements it,
+    /// `to_string()` for numbers and `bool`.
+    pub(super) fn check_interpolation(
+        &mut self,
+        span: Span,
+        parts: &[InterpolationPart],
+    ) -> Option<TExpr> {
+        let mut pieces = Vec::with_capacity(parts.len());
+        let mut ok = true;
+        for part in parts {
+            let piece = match part {
+                InterpolationPart::Text(text) => Some(TExpr {
+                    span,
+                    ty: Type::String,
+                    kind: TExprKind::Str(text.clone()),
+                }),
+                InterpolationPart::Expr(e) => self
+                    .check_expr(e)
+                    .and_then(|value| self.interpolated_string(value)),
+            };
+            match piece {
+                Some(p) => pieces.push(p),
+                None => ok = false,
+            }
+        }
+        if !ok {
+            return None;
+        }
+        pieces.into_iter().reduce(|acc, piece| TExpr {
+            span,
+            ty: Type::String,
+            kind: TExprKind::Binary {
+                op: BinaryOp::Add,
+                lhs: Box::new(acc),
+                rhs: Box::new(piece),
+            },
+        })
+    }
+
+    /// The string an interpolated value stands for.
+    fn interpolated_string(&mut self, value: TExpr) -> Option<TExpr> {
+        let span = value.span;
+        if self.implements_builtin(&value.ty, "Display") {
+            return self.trait_call(span, "show", vec![value]);
+        }
+        match value.ty {
+            Type::String => Some(value),
+            ref ty if ty.is_numeric() || *ty == Type::Bool => Some(TExpr {
+                span,
+                ty: Type::String,
+                kind: TExprKind::Call {
+                    callee: Callee::Str(StrMethod::ToString),
+                    args: vec![value],
+                },
+            }),
+            _ => {
+                self.error(
+                    format!(
+                        "`{}` cannot be interpolated into a string: it does not implement `Display`",
+                        value.ty
+                    ),
+                    span,
+                );
+                None
+            }
+        }
+    }
+}
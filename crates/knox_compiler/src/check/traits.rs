@@ -0,0 +1,376 @@
+//! Traits: declarations, the built-in traits, and checking `impl Trait for Type` against its trait.
+//! Dispatch is static: a trait's methods join the method table of each implementing struct.
+
+use super::env::{FnSig, TraitInfo};
+use super::Checker;
+use crate::typed::{holds_resource, Callee, FnId, TExpr, TExprKind, TFunction};
+use knox_syntax::ast::{
+    Block, FnDecl, ImplBlock, Param, Receiver, TraitDecl, TraitMethod, Type, Visibility,
+};
+use knox_syntax::span::Span;
+use std::collections::HashSet;
+
+/// A default method body, checked once for each struct that relies on it.
+pub(super) struct DefaultMethod {
+    trait_key: String,
+    /// Module of the trait; names in the body resolve there.
+    trait_module: usize,
+    /// Module and name of the implementing struct.
+    owner: (usize, String),
+    decl: FnDecl,
+}
+
+/// Built-in traits (`print` uses `Display`, `==`/`!=` use `Eq`, `<`/`<=`/`>`/`>=` use `Ord`,
+/// map and set keys use `Eq` and `Hash`, and `Copy`, which has no methods, makes values copied
+/// instead of moved). A trait declared with the same name in a module shadows the built-in one there.
+fn builtin_trait(name: &str) -> Option<TraitInfo> {
+    let self_ref = || Type::Ref(false, Box::new(Type::Path(vec!["Self".to_string()])));
+    let methods = match name {
+        "Display" => vec![required("show", Vec::new(), Type::String)],
+        "Eq" => vec![required("eq", vec![("other", self_ref())], Type::Bool)],
+        "Ord" => vec![required("cmp", vec![("other", self_ref())], Type::Int)],
+        "Clone" => vec![required(
+            "clone",
+            Vec::new(),
+            Type::Path(vec!["Self".to_string()]),
+        )],
+        "Hash" => vec![required("hash", Vec::new(), Type::Int)],
+        "Copy" => Vec::new(),
+        _ => return None,
+    };
+    Some(TraitInfo {
+        key: name.to_string(),
+        module: None,
+        vis: Visibility::Exported,
+        methods,
+    })
+}
+
+/// `fn name(&self, params..) -> ret;`
+fn required(name: &str, params: Vec<(&str, Type)>, ret: Type) -> TraitMethod {
+    TraitMethod {
+        span: Span::new(0, 0),
+        name: name.to_string(),
+        receiver: Some(Receiver::Ref),
+        params: params
+            .into_iter()
+            .map(|(name, ty)| Param {
+                name: name.to_string(),
+                ty,
+                mut_: false,
+                span: Span::new(0, 0),
+            })
+            .collect(),
+        return_ty: ret,
+        default: None,
+    }
+}
+
+/// A trait method as a function declaration; a required method gets an empty body.
+fn trait_fn(m: &TraitMethod) -> FnDecl {
+    FnDecl {
+        span: m.span,
+        vis: Visibility::Exported,
+        name: m.name.clone(),
+        type_params: Vec::new(),
+        receiver: m.receiver,
+        params: m.params.clone(),
+        return_ty: m.return_ty.clone(),
+        body: m.default.clone().unwrap_or(Block {
+            span: m.span,
+            stmts: Vec::new(),
+            value: None,
+        }),
+    }
+}
+
+/// `fn(&self, int) -> bool`, for signature mismatch messages.
+fn sig_string(sig: &FnSig) -> String {
+    let receiver = match sig.receiver {
+        None => None,
+        Some(Receiver::Value) => Some("self".to_string()),
+        Some(Receiver::Ref) => Some("&self".to_string()),
+        Some(Receiver::RefMut) => Some("&mut self".to_string()),
+    };
+    let params: Vec<String> = receiver
+        .into_iter()
+        .chain(sig.params.iter().map(|t| t.to_string()))
+        .collect();
+    format!("fn({}) -> {}", params.join(", "), sig.ret)
+}
+
+impl Checker {
+    /// Report duplicate methods and unknown types in a trait declaration, once (`Self` is left abstract).
+    pub(super) fn check_trait_decl(&mut self, t: &TraitDecl) {
+        self.self_ty = Some(Type::Path(vec!["Self".to_string()]));
+        let mut seen = HashSet::new();
+        for m in &t.methods {
+            if !seen.insert(m.name.as_str()) {
+                self.error(
+                    format!(
+                        "method `{}` is declared more than once in trait `{}`",
+                        m.name, t.name
+                    ),
+                    m.span,
+                );
+            }
+            self.fn_sig(0, &trait_fn(m));
+        }
+        self.self_ty = None;
+    }
+
+    /// Resolve `Trait` (a trait of this module, else a built-in one) or `module::Trait`.
+    pub(super) fn resolve_trait(&mut self, path: &[String], span: Span) -> Option<TraitInfo> {
+        let (module, name) = match path {
+            [name] => match self.current().traits.get(name) {
+                Some(info) => return Some(info.clone()),
+                None => match builtin_trait(name) {
+                    Some(info) => return Some(info),
+                    None => (self.module, name),
+                },
+            },
+            [module, name] => match self.resolve_module(module) {
+                Some(m) => (m, name),
+                None => {
+                    self.error(format!("unresolved module `{}`", module), span);
+                    return None;
+                }
+            },
+            _ => {
+                self.error(format!("unresolved path `{}`", path.join("::")), span);
+                return None;
+            }
+        };
+        let Some(info) = self.modules[module].traits.get(name).cloned() else {
+            self.error(format!("cannot find trait `{}`", path.join("::")), span);
+            return None;
+        };
+        if module != self.module && info.vis != Visibility::Exported {
+            let owner = self.modules[module].name.clone();
+            self.error(
+                format!("trait `{}` is private to module `{}`", name, owner),
+                span,
+            );
+            return None;
+        }
+        Some(info)
+    }
+
+    /// The trait with key `key` (see `TraitInfo`).
+    fn trait_by_key(&self, key: &str) -> Option<TraitInfo> {
+        let Some((module, name)) = key.split_once("::") else {
+            return builtin_trait(key);
+        };
+        let env = self.modules.iter().find(|env| env.name == module)?;
+        env.traits.get(name).cloned()
+    }
+
+    /// Signature of a trait method for the implementing type in `self_ty`, resolved in the
+    /// trait's module. Type errors were already reported by `check_trait_decl`.
+    fn trait_method_sig(&mut self, info: &TraitInfo, m: &TraitMethod, id: FnId) -> FnSig {
+        let trait_module = info.module.unwrap_or(self.module);
+        let module = std::mem::replace(&mut self.module, trait_module);
+        let type_params = std::mem::take(&mut self.type_params);
+        let before = self.diags.len();
+        let sig = self.fn_sig(id, &trait_fn(m));
+        self.diags.truncate(before);
+        self.type_params = type_params;
+        self.module = module;
+        sig
+    }
+
+    /// Method `name` of a trait bounding type parameter `param`. Only abstract bodies call it,
+    /// so the signature's id is a placeholder.
+    pub(super) fn param_method(&mut self, param: &str, name: &str) -> Option<FnSig> {
+        let keys = self.bounds.get(param)?.clone();
+        let (info, m) = keys.iter().find_map(|key| {
+            let info = self.trait_by_key(key)?;
+            let m = info.method(name)?.clone();
+            Some((info, m))
+        })?;
+        let self_ty = self.self_ty.replace(Type::Param(param.to_string()));
+        let sig = self.trait_method_sig(&info, &m, 0);
+        self.self_ty = self_ty;
+        Some(sig)
+    }
+
+    /// `impl Trait for Name`: every required method must be provided with the trait's signature;
+    /// default methods fill in the rest. All of them are public methods of the struct.
+    pub(super) fn collect_trait_impl(&mut self, module: usize, imp: &ImplBlock, path: &[String]) {
+        let Some(ty) = self.impl_owner(module, imp) else {
+            return;
+        };
+        let Some(info) = self.resolve_trait(path, imp.span) else {
+            return;
+        };
+        if self.modules[module].structs[&imp.name]
+            .impls
+            .contains(&info.key)
+        {
+            self.error(
+                format!(
+                    "conflicting implementations of trait `{}` for `{}`",
+                    info.key, ty
+                ),
+                imp.span,
+            );
+            return;
+        }
+        if (info.key == "Clone" || info.key == "Copy") && holds_resource(&self.resources, &ty) {
+            self.error(
+                format!("resource `{}` cannot implement `{}`", ty, info.key),
+                imp.span,
+            );
+            return;
+        }
+        let params = self.enter_struct_params(&imp.type_params, imp.span);
+        self.self_ty = Some(ty);
+        for f in &imp.methods {
+            let Some(m) = info.method(&f.name) else {
+                self.error(
+                    format!(
+                        "method `{}` is not a member of trait `{}`",
+                        f.name, info.key
+                    ),
+                    f.span,
+                );
+                continue;
+            };
+            let Some(sig) = self.add_method(module, imp, f, Visibility::Exported) else {
+                continue;
+            };
+            let expected = self.trait_method_sig(&info, m, sig.id);
+            if !f.type_params.is_empty()
+                || (expected.receiver, &expected.params, &expected.ret)
+                    != (sig.receiver, &sig.params, &sig.ret)
+            {
+                self.error(
+                    format!(
+                        "method `{}` has an incompatible signature for trait `{}`: expected `{}`, found `{}`",
+                        f.name,
+                        info.key,
+                        sig_string(&expected),
+                        sig_string(&sig)
+                    ),
+                    f.span,
+                );
+            }
+        }
+        let mut missing = Vec::new();
+        for m in &info.methods {
+            if imp.methods.iter().any(|f| f.name == m.name) {
+                continue;
+            }
+            let (Some(_), Some(trait_module)) = (&m.default, info.module) else {
+                missing.push(format!("`{}`", m.name));
+                continue;
+            };
+            let decl = trait_fn(m);
+            if self.modules[module].structs[&imp.name]
+                .methods
+                .contains_key(&m.name)
+            {
+                self.error(
+                    format!(
+                        "method `{}` is defined more than once for `{}`",
+                        m.name, imp.name
+                    ),
+                    imp.span,
+                );
+                continue;
+            }
+            let generic = !imp.type_params.is_empty();
+            let id = if generic { 0 } else { self.next_fn_id() };
+            let mut sig = self.trait_method_sig(&info, m, id);
+            if generic {
+                sig.type_params = self.impl_type_params(imp);
+                let owner = Some((module, imp.name.clone()));
+                self.add_template(trait_module, owner, &decl, &mut sig);
+                if let Some(s) = self.modules[module].structs.get_mut(&imp.name) {
+                    s.methods.insert(m.name.clone(), sig);
+                }
+                continue;
+            }
+            if let Some(s) = self.modules[module].structs.get_mut(&imp.name) {
+                s.methods.insert(m.name.clone(), sig);
+            }
+            self.defaults.push(DefaultMethod {
+                trait_key: info.key.clone(),
+                trait_module,
+                owner: (module, imp.name.clone()),
+                decl,
+            });
+        }
+        if !missing.is_empty() {
+            self.error(
+                format!(
+                    "not all trait items implemented, missing: {}",
+                    missing.join(", ")
+                ),
+                imp.span,
+            );
+        }
+        if let Some(s) = self.modules[module].structs.get_mut(&imp.name) {
+            s.impls.insert(info.key.clone());
+        }
+        self.self_ty = None;
+        self.leave_type_params(&params);
+    }
+
+    /// Check the default methods used by each implementing struct. Errors in a default body are
+    /// reported for the first struct only.
+    pub(super) fn check_default_methods(&mut self) -> Vec<(FnId, TFunction)> {
+        let mut checked = HashSet::new();
+        let mut functions = Vec::new();
+        for d in std::mem::take(&mut self.defaults) {
+            let first = checked.insert((d.trait_key, d.decl.name.clone()));
+            let before = self.diags.len();
+            let owner = (d.owner.0, d.owner.1.as_str());
+            functions.extend(self.check_fn(d.trait_module, Some(owner), &d.decl));
+            if !first {
+                self.diags.truncate(before);
+            }
+        }
+        functions
+    }
+
+    /// True if `ty` is a struct that implements the built-in trait `key`, or a type parameter
+    /// bounded by it.
+    pub(super) fn implements_builtin(&self, ty: &Type, key: &str) -> bool {
+        if let Type::Param(name) = ty {
+            return self
+                .bounds
+                .get(name)
+                .is_some_and(|b| b.iter().any(|k| k == key));
+        }
+        self.struct_info(ty).is_some_and(|s| s.impls.contains(key))
+    }
+
+    /// Static call of `method` on the struct (or type parameter) of the first argument.
+    pub(super) fn trait_call(
+        &mut self,
+        span: Span,
+        method: &str,
+        args: Vec<TExpr>,
+    ) -> Option<TExpr> {
+        let ty = args.first()?.ty.clone();
+        let (id, ret) = match &ty {
+            Type::Param(name) => {
+                let sig = self.param_method(name, method)?;
+                (sig.id, sig.ret)
+            }
+            _ => {
+                let sig = self.struct_info(&ty)?.methods.get(method)?.clone();
+                self.instantiate_method(&ty, &sig, span)?
+            }
+        };
+        Some(TExpr {
+            span,
+            ty: ret,
+            kind: TExprKind::Call {
+                callee: Callee::Fn(id),
+                args,
+            },
+        })
+    }
+}
@@ -0,0 +1,157 @@
+//! User enum variants: resolving `Enum::Variant` paths and checking constructor expressions.
+
+use super::env::{struct_type, VariantInfo, VariantKind};
+use super::Checker;
+use crate::typed::{TExpr, TExprKind};
+use knox_syntax::ast::{Expr, Type, Visibility};
+use knox_syntax::span::Span;
+use std::collections::HashMap;
+
+impl Checker {
+    /// If `prefix` (`Enum` or `module::Enum`) names an enum, its module index and name.
+    /// Reports nothing: a path that is not an enum is left to the caller.
+    pub(super) fn enum_prefix(&self, prefix: &[String]) -> Option<(usize, String)> {
+        let (module, name) = match prefix {
+            [name] => (self.module, name),
+            [module, name] => (self.resolve_module(module)?, name),
+            _ => return None,
+        };
+        self.modules[module]
+            .enums
+            .contains_key(name)
+            .then(|| (module, name.clone()))
+    }
+
+    /// Look up `variant` in an enum found by `enum_prefix`, enforcing `export` across modules.
+    pub(super) fn resolve_variant(
+        &mut self,
+        (module, name): (usize, String),
+        variant: &str,
+        span: Span,
+    ) -> Option<(Type, VariantInfo)> {
+        let info = &self.modules[module].enums[&name];
+        if module != self.module && info.vis != Visibility::Exported {
+            let owner = self.modules[module].name.clone();
+            self.error(
+                format!("enum `{}` is private to module `{}`", name, owner),
+                span,
+            );
+            return None;
+        }
+        let ty = struct_type(&self.modules[module].name, &name);
+        match info.variant(variant).cloned() {
+            Some(v) => Some((ty, v)),
+            None => {
+                self.error(
+                    format!("no variant named `{}` in enum `{}`", variant, ty),
+                    span,
+                );
+                None
+            }
+        }
+    }
+
+    /// Report a constructor whose form does not match the variant's declaration.
+    pub(super) fn variant_form_error(&mut self, v: &VariantInfo, path: &str, span: Span) {
+        let msg = match v.kind {
+            VariantKind::Unit => format!("variant `{}` has no fields; use `{}`", path, path),
+            VariantKind::Tuple => format!("variant `{}` has fields; use `{}(..)`", path, path),
+            VariantKind::Struct => {
+                format!(
+                    "variant `{}` has named fields; use `{} {{ .. }}`",
+                    path, path
+                )
+            }
+        };
+        self.error(msg, span);
+    }
+
+    /// `Enum::A`
+    pub(super) fn check_unit_variant(
+        &mut self,
+        span: Span,
+        enum_: (usize, String),
+        path: &[String],
+        variant: &str,
+    ) -> Option<TExpr> {
+        let (ty, v) = self.resolve_variant(enum_, variant, span)?;
+        if v.kind != VariantKind::Unit {
+            self.variant_form_error(&v, &path.join("::"), span);
+            return None;
+        }
+        Some(variant_expr(span, ty, &v, Vec::new()))
+    }
+
+    /// `Enum::B(a, b)`
+    pub(super) fn check_tuple_variant(
+        &mut self,
+        span: Span,
+        enum_: (usize, String),
+        qualifier: &[String],
+        variant: &str,
+        args: &[Expr],
+    ) -> Option<TExpr> {
+        let path = format!("{}::{}", qualifier.join("::"), variant);
+        let Some((ty, v)) = self.resolve_variant(enum_, variant, span) else {
+            for a in args {
+                self.check_expr(a);
+            }
+            return None;
+        };
+        if v.kind != VariantKind::Tuple {
+            self.variant_form_error(&v, &path, span);
+            return None;
+        }
+        let params: Vec<Type> = v.fields.iter().map(|(_, t, _)| t.clone()).collect();
+        let args = self.check_args(span, &path, &params, args)?;
+        let fields = v.fields.iter().map(|(_, _, o)| *o).zip(args).collect();
+        Some(variant_expr(span, ty, &v, fields))
+    }
+
+    /// `Enum::C { x: a }`
+    pub(super) fn check_struct_variant(
+        &mut self,
+        span: Span,
+        enum_: (usize, String),
+        path: &[String],
+        variant: &str,
+        fields: &[(String, Expr)],
+    ) -> Option<TExpr> {
+        let Some((ty, v)) = self.resolve_variant(enum_, variant, span) else {
+            for (_, e) in fields {
+                self.check_expr(e);
+            }
+            return None;
+        };
+        let path = path.join("::");
+        if v.kind != VariantKind::Struct {
+            self.variant_form_error(&v, &path, span);
+            return None;
+        }
+        let expected: Vec<(String, Type)> = v
+            .fields
+            .iter()
+            .map(|(n, t, _)| (n.clone(), t.clone()))
+            .collect();
+        let mut map = HashMap::new();
+        let checked =
+            self.check_named_fields(span, "variant", &path, &expected, &mut map, fields)?;
+        let fields = checked
+            .into_iter()
+            .filter_map(|(name, e)| {
+                let (_, _, offset) = v.fields.iter().find(|(n, _, _)| *n == name)?;
+                Some((*offset, e))
+            })
+            .collect();
+        Some(variant_expr(span, ty, &v, fields))
+    }
+}
+
+/// Constructor of variant `v`; `fields` are (byte offset, value) in evaluation order.
+fn variant_expr(span: Span, ty: Type, v: &VariantInfo, fields: Vec<(u32, TExpr)>) -> TExpr {
+    TExpr {
+        span,
+        ty,
+        kind: TExprKind::Variant { tag: v.tag, fields },
+    }
+}
@@ -1,76 +1,146 @@
-//! Desugaring pass: collect struct layouts and generate accessor specs from @pub(get)/@pub(set) annotations.
-//! Runs after parsing; output is consumed by codegen to emit getter/setter functions generically.
+//! Desugaring pass: turn @pub(get)/@pub(set) annotations into ordinary methods.
+//! Accessors are added to the AST after parsing, so the checker and lowering treat them like any other method.
 
-use knox_syntax::ast::{Item, Root, StructDecl, Visibility};
-use knox_syntax::{AccessorSpec, StructLayout};
+use knox_syntax::ast::{
+    Block, Expr, FnDecl, ImplBlock, Item, Param, Receiver, Root, Stmt, StructDecl, StructField,
+    Type, Visibility,
+};
+use knox_syntax::setter_name;
+use std::collections::HashSet;
 
-/// Build layout for an exported struct: field order and byte offsets.
-pub fn build_struct_layout(module: &str, s: &StructDecl) -> StructLayout {
-    let mut offset = 0u32;
-    let mut fields = Vec::with_capacity(s.fields.len());
+/// Add an `impl` block with the generated accessors right after each struct that has any.
+pub fn add_accessor_methods(modules: &mut [(String, Root)]) {
+    let structs: HashSet<String> = modules
+        .iter()
+        .flat_map(|(_, root)| &root.items)
+        .filter_map(|item| match item {
+            Item::Struct(s) => Some(s.name.clone()),
+            _ => None,
+        })
+        .collect();
+    for (_, root) in modules.iter_mut() {
+        let mut items = Vec::with_capacity(root.items.len());
+        for item in root.items.drain(..) {
+            let accessors = match &item {
+                Item::Struct(s) => accessor_impl(s, &structs),
+                _ => None,
+            };
+            items.push(item);
+            items.extend(accessors.map(Item::Impl));
+        }
+        root.items = items;
+    }
+}
+
+/// Accessors of one struct, in field order: `fn f(&self) -> T { self.f }` for `@pub(get)`
+/// (`-> &T { &self.f }` when `T` is one of the `structs`, whose values are moved rather than
+/// copied), then `fn set_f(&mut self, value: T) -> () { self.f = value; }` for `@pub(set)`.
+/// Both are exported; they carry the field's span.
+pub fn accessor_impl(s: &StructDecl, structs: &HashSet<String>) -> Option<ImplBlock> {
+    let mut methods = Vec::new();
     for f in &s.fields {
-        let size = knox_syntax::field_byte_size(&f.ty);
-        fields.push((f.name.clone(), f.ty.clone(), offset));
-        offset += size;
+        if f.attrs.has_pub_get() {
+            methods.push(getter(f, &s.type_params, structs));
+        }
+        if f.attrs.has_pub_set() {
+            methods.push(setter(f));
+        }
+    }
+    if methods.is_empty() {
+        return None;
     }
-    StructLayout {
-        module: module.to_string(),
-        struct_name: s.name.clone(),
-        total_size: offset,
-        fields,
+    Some(ImplBlock {
+        span: s.span,
+        trait_: None,
+        type_params: s.type_params.clone(),
+        name: s.name.clone(),
+        methods,
+    })
+}
+
+fn self_field(f: &StructField) -> Expr {
+    Expr::Field {
+        span: f.span,
+        receiver: Box::new(Expr::Ident {
+            span: f.span,
+            name: "self".to_string(),
+        }),
+        name: f.name.clone(),
     }
 }
 
-/// Collect all exported struct layouts and their accessor specs from a module graph.
-pub fn collect_struct_layouts_and_accessors(
-    deps: &[(String, Root)],
-) -> (Vec<StructLayout>, Vec<AccessorSpec>) {
-    let mut layouts = Vec::new();
-    let mut accessors = Vec::new();
+fn getter(f: &StructField, type_params: &[String], structs: &HashSet<String>) -> FnDecl {
+    let by_ref = match &f.ty {
+        Type::Path(p) | Type::Generic(p, _) => match p.as_slice() {
+            [name] if type_params.contains(name) => false,
+            [.., name] => structs.contains(name),
+            [] => false,
+        },
+        _ => false,
+    };
+    let (return_ty, value) = if by_ref {
+        let value = Expr::Ref {
+            span: f.span,
+            mut_: false,
+            expr: Box::new(self_field(f)),
+        };
+        (Type::Ref(false, Box::new(f.ty.clone())), value)
+    } else {
+        (f.ty.clone(), self_field(f))
+    };
+    FnDecl {
+        span: f.span,
+        vis: Visibility::Exported,
+        name: f.name.clone(),
+        type_params: Vec::new(),
+        receiver: Some(Receiver::Ref),
+        params: Vec::new(),
+        return_ty,
+        body: Block {
+            span: f.span,
+            stmts: Vec::new(),
+            value: Some(Box::new(value)),
+        },
+    }
+}
 
-    for (mod_name, root) in deps {
-        for item in &root.items {
-            if let Item::Struct(s) = item {
-                if s.vis != Visibility::Exported {
-                    continue;
-                }
-                let layout = build_struct_layout(mod_name, s);
-                let field_attrs: Vec<(String, bool, bool)> = s
-                    .fields
-                    .iter()
-                    .map(|f| (f.name.clone(), f.attrs.has_pub_get(), f.attrs.has_pub_set()))
-                    .collect();
-                for (field_name, get, set) in field_attrs {
-                    if !get && !set {
-                        continue;
-                    }
-                    let (_, ty, byte_offset) = layout
-                        .fields
-                        .iter()
-                        .find(|(n, _, _)| n == &field_name)
-                        .cloned()
-                        .unwrap_or_else(|| (field_name.clone(), knox_syntax::ast::Type::Unit, 0));
-                    accessors.push(AccessorSpec {
-                        module: mod_name.clone(),
-                        struct_name: s.name.clone(),
-                        field_name,
-                        get,
-                        set,
-                        ty,
-                        byte_offset,
-                    });
-                }
-                layouts.push(layout);
-            }
-        }
+fn setter(f: &StructField) -> FnDecl {
+    let assign = Expr::Assign {
+        span: f.span,
+        target: Box::new(self_field(f)),
+        value: Box::new(Expr::Ident {
+            span: f.span,
+            name: "value".to_string(),
+        }),
+    };
+    FnDecl {
+        span: f.span,
+        vis: Visibility::Exported,
+        name: setter_name(&f.name),
+        type_params: Vec::new(),
+        receiver: Some(Receiver::RefMut),
+        params: vec![Param {
+            name: "value".to_string(),
+            ty: f.ty.clone(),
+            mut_: false,
+            span: f.span,
+        }],
+        return_ty: Type::Unit,
+        body: Block {
+            span: f.span,
+            stmts: vec![Stmt::Expr {
+                span: f.span,
+                expr: assign,
+            }],
+            value: None,
+        },
     }
-    (layouts, accessors)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use knox_syntax::ast::{FieldAttrs, StructField, Type};
+    use knox_syntax::ast::FieldAttrs;
     use knox_syntax::span::Span;
 
     fn span() -> Span {
@@ -88,14 +158,27 @@ mod tests {
         assert_eq!(knox_syntax::field_byte_size(&Type::String), 8);
         assert_eq!(knox_syntax::field_byte_size(&Type::Int), 4);
         assert_eq!(knox_syntax::field_byte_size(&Type::Bool), 4);
+        assert_eq!(knox_syntax::field_byte_size(&Type::I64), 8);
+        assert_eq!(knox_syntax::field_byte_size(&Type::U8), 1);
+        // A tuple is padded to its alignment so list elements stay aligned.
+        let tuple = Type::Tuple(vec![Type::F64, Type::U8]);
+        assert_eq!(knox_syntax::field_byte_size(&tuple), 16);
+        assert_eq!(
+            knox_syntax::struct_field_offsets(&[Type::U8, Type::I64, Type::U8, Type::Int]),
+            vec![0, 8, 16, 20]
+        );
     }
 
     #[test]
-    fn layout_and_accessors_arbitrary_struct() {
+    fn accessors_arbitrary_struct() {
         let s = StructDecl {
             span: span(),
             vis: Visibility::Exported,
             name: "Product".to_string(),
+            type_params: Vec::new(),
+            derives: Vec::new(),
+            resource: false,
+            newtype: false,
             fields: vec![
                 StructField {
                     span: span(),
@@ -117,27 +200,32 @@ mod tests {
                 },
             ],
         };
-        let layout = build_struct_layout("mymod", &s);
-        assert_eq!(layout.struct_name, "Product");
-        assert_eq!(layout.fields.len(), 2);
-        assert_eq!(layout.fields[0].0, "id");
-        assert_eq!(layout.fields[0].2, 0);
-        assert_eq!(layout.fields[1].0, "price");
-        assert_eq!(layout.fields[1].2, 4);
-        assert_eq!(layout.total_size, 8);
-
-        let root = Root {
-            items: vec![Item::Struct(s.clone())],
+        let mut modules = vec![(
+            "mymod".to_string(),
+            Root {
+                items: vec![Item::Struct(s.clone())],
+            },
+        )];
+        add_accessor_methods(&mut modules);
+        let items = &modules[0].1.items;
+        assert_eq!(items.len(), 2);
+        let Item::Impl(imp) = &items[1] else {
+            panic!("expected the accessors right after the struct");
         };
-        let (layouts, accessors) =
-            collect_struct_layouts_and_accessors(&[("mymod".to_string(), root)]);
-        assert_eq!(layouts.len(), 1);
-        assert_eq!(accessors.len(), 2);
-        let id_acc = accessors.iter().find(|a| a.field_name == "id").unwrap();
-        assert!(id_acc.get);
-        assert!(!id_acc.set);
-        let price_acc = accessors.iter().find(|a| a.field_name == "price").unwrap();
-        assert!(price_acc.get);
-        assert!(price_acc.set);
+        assert_eq!(imp.name, "Product");
+        let methods: Vec<(&str, Option<Receiver>, usize)> = imp
+            .methods
+            .iter()
+            .map(|m| (m.name.as_str(), m.receiver, m.params.len()))
+            .collect();
+        assert_eq!(
+            methods,
+            vec![
+                ("id", Some(Receiver::Ref), 0),
+                ("price", Some(Receiver::Ref), 0),
+                ("set_price", Some(Receiver::RefMut), 1),
+            ]
+        );
+        assert!(imp.methods.iter().all(|m| m.vis == Visibility::Exported));
     }
 }
@@ -0,0 +1,156 @@
+//! Desugaring pass: turn `@derive(Eq, Hash, Clone, Copy)` on a struct into ordinary trait impls.
+//! The generated methods compare, hash or clone the fields in declaration order; `Copy` has no
+//! methods.
+
+use knox_syntax::ast::{
+    BinaryOp, Block, Expr, FnDecl, ImplBlock, Item, Param, Receiver, Root, StructDecl, Type,
+    Visibility,
+};
+
+/// Add an `impl Trait for Struct` block right after each struct for every trait it derives.
+pub fn add_derived_impls(modules: &mut [(String, Root)]) {
+    for (_, root) in modules.iter_mut() {
+        let mut items = Vec::with_capacity(root.items.len());
+        for item in root.items.drain(..) {
+            let derived: Vec<ImplBlock> = match &item {
+                Item::Struct(s) => s.derives.iter().map(|t| derived_impl(s, t)).collect(),
+                _ => Vec::new(),
+            };
+            items.push(item);
+            items.extend(derived.into_iter().map(Item::Impl));
+        }
+        root.items = items;
+    }
+}
+
+/// `impl Eq for S { fn eq(&self, other: &Self) -> bool { self.a == other.a && .. } }`,
+/// `impl Hash for S { fn hash(&self) -> int { self.a.hash().wrapping_mul(31).wrapping_add(self.b.hash()) .. } }`,
+/// `impl Clone for S { fn clone(&self) -> Self { S { a: self.a.clone(), n: self.n, .. } } }`
+/// (struct fields are cloned, other fields copied; a newtype is rebuilt as `S(..)`) or
+/// `impl Copy for S {}`.
+fn derived_impl(s: &StructDecl, trait_: &str) -> ImplBlock {
+    let span = s.span;
+    if trait_ == "Copy" {
+        return ImplBlock {
+            span,
+            trait_: Some(vec![trait_.to_string()]),
+            type_params: Vec::new(),
+            name: s.name.clone(),
+            methods: Vec::new(),
+        };
+    }
+    let field = |owner: &str, name: &str| Expr::Field {
+        span,
+        receiver: Box::new(Expr::Ident {
+            span,
+            name: owner.to_string(),
+        }),
+        name: name.to_string(),
+    };
+    let binary = |op, lhs, rhs| Expr::Binary {
+        span,
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    };
+    let call = |receiver, name: &str, args| Expr::Call {
+        span,
+        receiver: Some(Box::new(receiver)),
+        qualifier: Vec::new(),
+        name: name.to_string(),
+        args,
+    };
+    let (name, params, return_ty, value) = if trait_ == "Eq" {
+        let value = s
+            .fields
+            .iter()
+            .map(|f| {
+                binary(
+                    BinaryOp::Eq,
+                    field("self", &f.name),
+                    field("other", &f.name),
+                )
+            })
+            .reduce(|acc, eq| binary(BinaryOp::And, acc, eq))
+            .unwrap_or(Expr::BoolLiteral { span, value: true });
+        let other = Param {
+            name: "other".to_string(),
+            ty: Type::Ref(false, Box::new(Type::Path(vec!["Self".to_string()]))),
+            mut_: false,
+            span,
+        };
+        ("eq", vec![other], Type::Bool, value)
+    } else if trait_ == "Clone" {
+        let mut fields: Vec<(String, Expr)> = s
+            .fields
+            .iter()
+            .map(|f| {
+                let value = match f.ty {
+                    Type::Path(_) | Type::Generic(_, _) => {
+                        call(field("self", &f.name), "clone", Vec::new())
+                    }
+                    _ => field("self", &f.name),
+                };
+                (f.name.clone(), value)
+            })
+            .collect();
+        let value = if s.newtype {
+            Expr::Call {
+                span,
+                receiver: None,
+                qualifier: Vec::new(),
+                name: s.name.clone(),
+                args: vec![fields.remove(0).1],
+            }
+        } else {
+            Expr::StructLiteral {
+                span,
+                path: vec![s.name.clone()],
+                fields,
+            }
+        };
+        let self_ty = Type::Path(vec!["Self".to_string()]);
+        ("clone", Vec::new(), self_ty, value)
+    } else {
+        let value = s
+            .fields
+            .iter()
+            .map(|f| call(field("self", &f.name), "hash", Vec::new()))
+            .reduce(|acc, h| {
+                // Combining hashes overflows routinely: wrap instead of trapping.
+                let thirty_one = Expr::IntLiteral {
+                    span,
+                    value: 31,
+                    suffix: None,
+                };
+                let scaled = call(acc, "wrapping_mul", vec![thirty_one]);
+                call(scaled, "wrapping_add", vec![h])
+            })
+            .unwrap_or(Expr::IntLiteral {
+                span,
+                value: 0,
+                suffix: None,
+            });
+        ("hash", Vec::new(), Type::Int, value)
+    };
+    ImplBlock {
+        span,
+        trait_: Some(vec![trait_.to_string()]),
+        type_params: Vec::new(),
+        name: s.name.clone(),
+        methods: vec![FnDecl {
+            span,
+            vis: Visibility::Exported,
+            name: name.to_string(),
+            type_params: Vec::new(),
+            receiver: Some(Receiver::Ref),
+            params,
+            return_ty,
+            body: Block {
+                span,
+                stmts: Vec::new(),
+                value: Some(Box::new(value)),
+            },
+        }],
+    }
+}
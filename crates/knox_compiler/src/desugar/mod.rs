@@ -1,5 +1,7 @@
-//! Desugaring passes: accessor generation from @pub(get)/@pub(set), etc.
+//! Desugaring passes: accessor generation from @pub(get)/@pub(set), trait impls from @derive, etc.
 
 pub mod accessors;
+pub mod derive;
 
-pub use accessors::collect_struct_layouts_and_accessors;
+pub use accessors::add_accessor_methods;
+pub use derive::add_derived_impls;
@@ -0,0 +1,289 @@
+//! Newtype erasure, on the typed program once it has been checked: a newtype (`struct
+//! Meters(int);`) is represented as the value it wraps, so every type loses its newtypes (see
+//! `TypedProgram::newtypes`), `Meters(x)` becomes `x`, `m.0` becomes `m` (or `*r` through a
+//! `&mut` reference), and a `Meters(p)` pattern becomes `p`. Lowering never sees a newtype.
+//!
+//! A `&Meters` is represented as the wrapped value itself: nothing can change the value while
+//! it is borrowed, so a copy serves as well as a pointer. A `&mut Meters` is a `&mut int`, so a
+//! method taking `&mut self` gets its receiver explicitly borrowed.
+
+use crate::typed::{Callee, TArm, TBlock, TExpr, TExprKind, TPattern, TStmt, TypedProgram};
+use knox_syntax::ast::Type;
+use std::collections::HashMap;
+
+/// Erase the newtypes of every function of the program.
+pub fn erase_newtypes(program: &mut TypedProgram) {
+    if program.newtypes.is_empty() {
+        return;
+    }
+    let params = program
+        .functions
+        .iter()
+        .map(|f| {
+            let params = &f.locals[..f.param_count as usize];
+            params.iter().map(|p| p.ty.clone()).collect()
+        })
+        .collect();
+    let eraser = Eraser {
+        newtypes: &program.newtypes,
+        params,
+    };
+    for f in &mut program.functions {
+        for local in &mut f.locals {
+            local.ty = eraser.ty(&local.ty);
+        }
+        f.ret = eraser.ty(&f.ret);
+        eraser.block(&mut f.body);
+    }
+}
+
+struct Eraser<'a> {
+    newtypes: &'a HashMap<Vec<String>, Type>,
+    /// Parameter types of each function, before erasure.
+    params: Vec<Vec<Type>>,
+}
+
+impl Eraser<'_> {
+    /// The type it wraps if `ty` is a newtype.
+    fn wrapped(&self, ty: &Type) -> Option<&Type> {
+        match ty {
+            Type::Path(path) => self.newtypes.get(path),
+            _ => None,
+        }
+    }
+
+    /// The newtype behind `ty`, if it is a `&` reference to one.
+    fn shared<'t>(&self, ty: &'t Type) -> Option<&'t Type> {
+        match ty {
+            Type::Ref(false, inner) => self.wrapped(inner).map(|_| &**inner),
+            _ => None,
+        }
+    }
+
+    /// `ty` as represented: every newtype replaced by the type it wraps.
+    fn ty(&self, ty: &Type) -> Type {
+        let each = |ts: &[Type]| ts.iter().map(|t| self.ty(t)).collect();
+        match ty {
+            Type::Path(_) => self.wrapped(ty).unwrap_or(ty).clone(),
+            Type::Ref(false, inner) if self.wrapped(inner).is_some() => self.ty(inner),
+            Type::Ref(mut_, inner) => Type::Ref(*mut_, Box::new(self.ty(inner))),
+            Type::Option(inner) => Type::Option(Box::new(self.ty(inner))),
+            Type::List(elem) => Type::List(Box::new(self.ty(elem))),
+            Type::Set(elem) => Type::Set(Box::new(self.ty(elem))),
+            Type::Map(key, value) => Type::Map(Box::new(self.ty(key)), Box::new(self.ty(value))),
+            Type::Result(ok, err) => Type::Result(Box::new(self.ty(ok)), Box::new(self.ty(err))),
+            Type::Generic(path, args) => Type::Generic(path.clone(), each(args)),
+            Type::Tuple(elems) => Type::Tuple(each(elems)),
+            Type::Fn(params, ret) => Type::Fn(each(params), Box::new(self.ty(ret))),
+            _ => ty.clone(),
+        }
+    }
+
+    fn block(&self, block: &mut TBlock) {
+        for stmt in &mut block.stmts {
+            match stmt {
+                TStmt::Let { pattern, init } => {
+                    self.pattern(pattern, &init.ty);
+                    self.expr(init);
+                }
+                TStmt::Expr(e) | TStmt::Return { value: Some(e), .. } => self.expr(e),
+                TStmt::Return { value: None, .. } => {}
+            }
+        }
+        if let Some(value) = &mut block.value {
+            self.expr(value);
+        }
+    }
+
+    fn expr(&self, e: &mut TExpr) {
+        match &mut e.kind {
+            // `Meters(x)` is `x`.
+            TExprKind::StructLit(fields) if self.wrapped(&e.ty).is_some() => {
+                let (_, value) = fields.remove(0);
+                *e = value;
+                return self.expr(e);
+            }
+            // `m.0` is `m`, and `r.0` is `r` or, through a `&mut` reference, `*r`.
+            TExprKind::Field { base, .. } => match &base.ty {
+                Type::Ref(true, inner) if self.wrapped(inner).is_some() => {
+                    let base = std::mem::replace(&mut **base, unit(e.span));
+                    e.kind = TExprKind::Deref(Box::new(base));
+                }
+                ty if self.wrapped(ty).or_else(|| self.shared(ty)).is_some() => {
+                    let base = std::mem::replace(&mut **base, unit(e.span));
+                    *e = base;
+                    return self.expr(e);
+                }
+                _ => {}
+            },
+            // `&m` is `m`, and `*r` is `r`.
+            TExprKind::Ref {
+                mut_: false,
+                expr: inner,
+            } if self.wrapped(&inner.ty).is_some() => {
+                *e = std::mem::replace(&mut **inner, unit(e.span));
+                return self.expr(e);
+            }
+            TExprKind::Deref(inner) if self.shared(&inner.ty).is_some() => {
+                *e = std::mem::replace(&mut **inner, unit(e.span));
+                return self.expr(e);
+            }
+            // A receiver is passed as it is, whatever the method takes: a `&mut self` one must
+            // now be borrowed, and a `self` one read through its `&mut` reference.
+            TExprKind::Call {
+                callee: Callee::Fn(id),
+                args,
+            } => {
+                for (arg, param) in args.iter_mut().zip(&self.params[*id as usize]) {
+                    let span = arg.span;
+                    match (&arg.ty, param) {
+                        (Type::Path(_), Type::Ref(true, p)) if self.wrapped(p).is_some() => {
+                            let value = std::mem::replace(arg, unit(span));
+                            *arg = TExpr {
+                                span,
+                                ty: param.clone(),
+                                kind: TExprKind::Ref {
+                                    mut_: true,
+                                    expr: Box::new(value),
+                                },
+                            };
+                        }
+                        (Type::Ref(true, a), Type::Path(_)) if self.wrapped(a).is_some() => {
+                            let reference = std::mem::replace(arg, unit(span));
+                            *arg = TExpr {
+                                span,
+                                ty: param.clone(),
+                                kind: TExprKind::Deref(Box::new(reference)),
+                            };
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        e.ty = self.ty(&e.ty);
+        match &mut e.kind {
+            TExprKind::Unit
+            | TExprKind::Int(_)
+            | TExprKind::Float(_)
+            | TExprKind::Str(_)
+            | TExprKind::Bool(_)
+            | TExprKind::Local(_)
+            | TExprKind::Continue(_) => {}
+            TExprKind::List(items) | TExprKind::Tuple(items) => {
+                items.iter_mut().for_each(|i| self.expr(i))
+            }
+            TExprKind::StructLit(fields) => fields.iter_mut().for_each(|(_, f)| self.expr(f)),
+            TExprKind::Variant { fields, .. } => fields.iter_mut().for_each(|(_, f)| self.expr(f)),
+            TExprKind::Closure { captures, .. } => captures.iter_mut().for_each(|c| self.expr(c)),
+            TExprKind::Call { args, .. } => args.iter_mut().for_each(|a| self.expr(a)),
+            TExprKind::Field { base: inner, .. }
+            | TExprKind::TupleField { base: inner, .. }
+            | TExprKind::Print(inner)
+            | TExprKind::Try { expr: inner, .. }
+            | TExprKind::Deref(inner)
+            | TExprKind::Cast(inner)
+            | TExprKind::Ref { expr: inner, .. }
+            | TExprKind::Unary { expr: inner, .. } => self.expr(inner),
+            TExprKind::Index { base: a, index: b }
+            | TExprKind::Assign {
+                target: a,
+                value: b,
+            }
+            | TExprKind::Binary { lhs: a, rhs: b, .. } => {
+                self.expr(a);
+                self.expr(b);
+            }
+            TExprKind::Match { value, arms } => {
+                for TArm {
+                    pattern,
+                    guard,
+                    body,
+                } in arms.iter_mut()
+                {
+                    self.pattern(pattern, &value.ty);
+                    if let Some(guard) = guard {
+                        self.expr(guard);
+                    }
+                    self.expr(body);
+                }
+                self.expr(value);
+            }
+            TExprKind::Block(block) | TExprKind::Loop { body: block, .. } => self.block(block),
+            TExprKind::If {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                self.expr(cond);
+                self.block(then_block);
+                if let Some(block) = else_block {
+                    self.block(block);
+                }
+            }
+            TExprKind::While { cond, body } => {
+                self.expr(cond);
+                self.block(body);
+            }
+            TExprKind::ForRange {
+                start, end, body, ..
+            } => {
+                self.expr(start);
+                self.expr(end);
+                self.block(body);
+            }
+            TExprKind::ForEach { list, body, .. } => {
+                self.expr(list);
+                self.block(body);
+            }
+            TExprKind::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+        }
+    }
+
+    /// Erase a pattern matched against a value of type `ty`, before that type is erased:
+    /// `Meters(p)` is `p`.
+    fn pattern(&self, pat: &mut TPattern, ty: &Type) {
+        match pat {
+            TPattern::Struct { fields }
+                if self.wrapped(ty).or_else(|| self.shared(ty)).is_some() =>
+            {
+                match fields.pop() {
+                    Some((_, field_ty, sub)) => {
+                        *pat = sub;
+                        self.pattern(pat, &field_ty);
+                    }
+                    None => *pat = TPattern::Wildcard,
+                }
+            }
+            TPattern::Struct { fields } | TPattern::Variant { fields, .. } => {
+                for (_, field_ty, sub) in fields {
+                    self.pattern(sub, field_ty);
+                    *field_ty = self.ty(field_ty);
+                }
+            }
+            TPattern::Tuple(subs) => {
+                if let Type::Tuple(elems) = ty {
+                    for (sub, elem) in subs.iter_mut().zip(elems) {
+                        self.pattern(sub, elem);
+                    }
+                }
+            }
+            TPattern::Or(alts) => alts.iter_mut().for_each(|alt| self.pattern(alt, ty)),
+            _ => {}
+        }
+    }
+}
+
+/// Placeholder left behind while an expression is moved out of its parent.
+fn unit(span: knox_syntax::span::Span) -> TExpr {
+    TExpr {
+        span,
+        ty: Type::Unit,
+        kind: TExprKind::Unit,
+    }
+}
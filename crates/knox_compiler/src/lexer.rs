@@ -1,7 +1,7 @@
 //! Lexer: source text → tokens.
 
 use knox_syntax::span::{FileId, Span};
-use knox_syntax::token::{Token, TokenKind};
+use knox_syntax::token::{StringSegment, Token, TokenKind};
 use std::iter::Peekable;
 use std::str::Chars;
 
@@ -11,6 +11,8 @@ pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
     offset: u32,
     file: FileId,
+    /// The last token was `.`: digits after it are a tuple index, never a float (`t.0.1`).
+    after_dot: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -20,6 +22,7 @@ impl<'a> Lexer<'a> {
             chars: source.chars().peekable(),
             offset: 0,
             file,
+            after_dot: false,
         }
     }
 
@@ -62,9 +65,12 @@ impl<'a> Lexer<'a> {
         (s, self.span_from(start))
     }
 
-    fn read_string(&mut self) -> Result<(String, Span), String> {
+    /// Read a string literal. `{expr}` interpolates an expression, lexed here and parsed by the
+    /// parser; `{{` and `}}` stand for literal braces.
+    fn read_string(&mut self) -> Result<(Vec<StringSegment>, Span), String> {
         let start = self.start_offset();
         self.next(); // "
+        let mut segments = Vec::new();
         let mut s = String::new();
         loop {
             match self.next() {
@@ -77,20 +83,92 @@ impl<'a> Lexer<'a> {
                     Some('\\') => s.push('\\'),
                     _ => return Err("Invalid escape in string".into()),
                 },
+                Some(c @ ('{' | '}')) if self.peek() == Some(c) => {
+                    self.next();
+                    s.push(c);
+                }
+                Some('{') => {
+                    if !s.is_empty() {
+                        segments.push(StringSegment::Text(std::mem::take(&mut s)));
+                    }
+                    segments.push(self.read_interpolation());
+                }
                 Some(c) => s.push(c),
             }
         }
-        Ok((s, self.span_from(start)))
+        if !s.is_empty() || segments.is_empty() {
+            segments.push(StringSegment::Text(s));
+        }
+        Ok((segments, self.span_from(start)))
     }
 
-    fn read_number(&mut self) -> (i64, Span) {
+    /// The code of `{expr}` in a string, up to the matching `}`; the `{` has been consumed.
+    fn read_interpolation(&mut self) -> StringSegment {
+        let open = self.offset - 1;
+        let code_start = self.offset;
+        let mut code = String::new();
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None | Some('"') | Some('\n') => {
+                    return StringSegment::Unclosed(self.span_from(open));
+                }
+                Some('}') if depth == 0 => {
+                    self.next();
+                    break;
+                }
+                Some(c) => {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    code.push(c);
+                    self.next();
+                }
+            }
+        }
+        let inner = Lexer {
+            source: &code,
+            chars: code.chars().peekable(),
+            offset: code_start,
+            file: self.file,
+            after_dot: false,
+        };
+        StringSegment::Code(inner.collect_tokens())
+    }
+
+    /// Digits, an optional fraction (`1.5`), and an optional type suffix (`10i64`, `2.0f64`).
+    /// Integers too large for any type saturate; the parser reports them.
+    fn read_number(&mut self) -> Token {
         let start = self.start_offset();
         let mut s = String::new();
+        self.read_digits(&mut s);
+        let mut after = self.chars.clone();
+        let is_float = !self.after_dot
+            && after.next() == Some('.')
+            && matches!(after.next(), Some(c) if c.is_ascii_digit());
+        if is_float {
+            s.push(self.next().unwrap());
+            self.read_digits(&mut s);
+        }
+        let mut suffix = String::new();
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_') {
+            suffix.push(self.next().unwrap());
+        }
+        let suffix = (!suffix.is_empty()).then_some(suffix);
+        let kind = if is_float {
+            TokenKind::FloatLiteral(s.parse().unwrap_or(0.0), suffix)
+        } else {
+            TokenKind::IntLiteral(s.parse().unwrap_or(u128::MAX), suffix)
+        };
+        Token::new(kind, self.span_from(start))
+    }
+
+    fn read_digits(&mut self, s: &mut String) {
         while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
             s.push(self.next().unwrap());
         }
-        let n = s.parse().unwrap_or(0);
-        (n, self.span_from(start))
     }
 
     pub fn next_token(&mut self) -> Token {
@@ -112,8 +190,7 @@ impl<'a> Lexer<'a> {
                     self.skip_line_comment();
                     continue;
                 }
-                // single / not part of // — treat as invalid or skip; MVP: skip
-                continue;
+                return Token::new(TokenKind::Slash, self.span_from(start));
             }
 
             if c.is_ascii_alphabetic() || c == '_' {
@@ -127,7 +204,14 @@ impl<'a> Lexer<'a> {
                     "match" => TokenKind::Match,
                     "return" => TokenKind::Return,
                     "struct" => TokenKind::Struct,
+                    "resource" => TokenKind::Resource,
+                    "enum" => TokenKind::Enum,
+                    "const" => TokenKind::Const,
+                    "type" => TokenKind::Type,
+                    "trait" => TokenKind::Trait,
                     "import" => TokenKind::Import,
+                    "impl" => TokenKind::Impl,
+                    "self" => TokenKind::SelfValue,
                     "pub" => TokenKind::Pub,
                     "export" => TokenKind::Export,
                     "as" => TokenKind::As,
@@ -138,6 +222,12 @@ impl<'a> Lexer<'a> {
                     "dynamic" => TokenKind::Dynamic,
                     "Some" => TokenKind::Some,
                     "None" => TokenKind::None,
+                    "while" => TokenKind::While,
+                    "loop" => TokenKind::Loop,
+                    "for" => TokenKind::For,
+                    "in" => TokenKind::In,
+                    "break" => TokenKind::Break,
+                    "continue" => TokenKind::Continue,
                     "true" => TokenKind::True,
                     "false" => TokenKind::False,
                     "_" => TokenKind::Underscore,
@@ -148,7 +238,13 @@ impl<'a> Lexer<'a> {
 
             if c == '"' {
                 match self.read_string() {
-                    Ok((s, span)) => return Token::new(TokenKind::StringLiteral(s), span),
+                    Ok((mut segments, span)) => {
+                        let kind = match segments.as_mut_slice() {
+                            [StringSegment::Text(s)] => TokenKind::StringLiteral(std::mem::take(s)),
+                            _ => TokenKind::InterpolatedString(segments),
+                        };
+                        return Token::new(kind, span);
+                    }
                     Err(_) => {
                         return Token::new(
                             TokenKind::StringLiteral(String::new()),
@@ -158,14 +254,17 @@ impl<'a> Lexer<'a> {
                 }
             }
 
+            if c == '\'' {
+                self.next();
+                let (name, _) = self.read_ident_or_keyword();
+                return Token::new(TokenKind::Label(name), self.span_from(start));
+            }
+
             if c.is_ascii_digit() {
-                let (n, span) = self.read_number();
-                return Token::new(TokenKind::IntLiteral(n), span);
+                return self.read_number();
             }
 
             self.next();
-            let span = self.span_from(start);
-
             let kind = match c {
                 '(' => TokenKind::LParen,
                 ')' => TokenKind::RParen,
@@ -183,11 +282,37 @@ impl<'a> Lexer<'a> {
                 }
                 '@' => TokenKind::At,
                 ',' => TokenKind::Comma,
-                '.' => TokenKind::Dot,
+                '.' => {
+                    if self.peek() == Some('.') {
+                        self.next();
+                        if self.peek() == Some('=') {
+                            self.next();
+                            TokenKind::DotDotEq
+                        } else {
+                            TokenKind::DotDot
+                        }
+                    } else {
+                        TokenKind::Dot
+                    }
+                }
                 '?' => TokenKind::Question,
                 '_' => TokenKind::Underscore,
-                '<' => TokenKind::Lt,
-                '>' => TokenKind::Gt,
+                '<' => {
+                    if self.peek() == Some('=') {
+                        self.next();
+                        TokenKind::Le
+                    } else {
+                        TokenKind::Lt
+                    }
+                }
+                '>' => {
+                    if self.peek() == Some('=') {
+                        self.next();
+                        TokenKind::Ge
+                    } else {
+                        TokenKind::Gt
+                    }
+                }
                 '=' => {
                     if self.peek() == Some('=') {
                         self.next();
@@ -233,12 +358,11 @@ impl<'a> Lexer<'a> {
                     }
                 }
                 '*' => TokenKind::Star,
-                '/' => TokenKind::Slash,
                 '%' => TokenKind::Percent,
                 ';' => TokenKind::Semicolon,
                 _ => continue,
             };
-            return Token::new(kind, span);
+            return Token::new(kind, self.span_from(start));
         }
     }
 
@@ -247,6 +371,7 @@ impl<'a> Lexer<'a> {
         let mut tokens = Vec::new();
         loop {
             let t = self.next_token();
+            self.after_dot = matches!(t.kind, TokenKind::Dot);
             let is_eof = t.is_eof();
             tokens.push(t);
             if is_eof {
@@ -280,20 +405,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn lex_interpolated_string() {
+        let src = r#""a{x + 1}{{b}}""#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let TokenKind::InterpolatedString(segments) = &tokens[0].kind else {
+            panic!("expected interpolated string");
+        };
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0], StringSegment::Text("a".to_string()));
+        let StringSegment::Code(code) = &segments[1] else {
+            panic!("expected code");
+        };
+        assert!(matches!(code[0].kind, TokenKind::Ident(ref s) if s == "x"));
+        assert!(matches!(code[1].kind, TokenKind::Plus));
+        assert_eq!(code[1].span.start, 5);
+        assert_eq!(segments[2], StringSegment::Text("{b}".to_string()));
+    }
+
     #[test]
     fn lex_int_literal() {
         let src = "42 0";
         let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
         match tokens[0].kind {
-            TokenKind::IntLiteral(n) => assert_eq!(n, 42),
+            TokenKind::IntLiteral(n, None) => assert_eq!(n, 42),
             _ => panic!("expected int"),
         }
         match tokens[1].kind {
-            TokenKind::IntLiteral(n) => assert_eq!(n, 0),
+            TokenKind::IntLiteral(n, None) => assert_eq!(n, 0),
             _ => panic!("expected int"),
         }
     }
 
+    #[test]
+    fn lex_number_suffixes_and_floats() {
+        let src = "255u8 1.5 2.0f64 1..3 t.0.1";
+        let kinds: Vec<TokenKind> = Lexer::new(src, FileId::new(0))
+            .collect_tokens()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(kinds[0], TokenKind::IntLiteral(255, Some("u8".to_string())));
+        assert_eq!(kinds[1], TokenKind::FloatLiteral(1.5, None));
+        assert_eq!(
+            kinds[2],
+            TokenKind::FloatLiteral(2.0, Some("f64".to_string()))
+        );
+        assert_eq!(kinds[3], TokenKind::IntLiteral(1, None));
+        assert!(matches!(kinds[4], TokenKind::DotDot));
+        assert_eq!(kinds[5], TokenKind::IntLiteral(3, None));
+        assert_eq!(kinds[8], TokenKind::IntLiteral(0, None));
+        assert!(matches!(kinds[9], TokenKind::Dot));
+        assert_eq!(kinds[10], TokenKind::IntLiteral(1, None));
+    }
+
     #[test]
     fn lex_arrow_and_fat_arrow() {
         let src = "-> =>";
@@ -316,4 +481,50 @@ mod tests {
         assert!(matches!(tokens[0].kind, TokenKind::Export));
         assert!(matches!(tokens[1].kind, TokenKind::Struct));
     }
+
+    #[test]
+    fn lex_operators() {
+        let src = "a <= b >= c < d > e == f != g / h % i && j || !k";
+        let kinds: Vec<TokenKind> = Lexer::new(src, FileId::new(0))
+            .collect_tokens()
+            .into_iter()
+            .map(|t| t.kind)
+            .filter(|k| !matches!(k, TokenKind::Ident(_) | TokenKind::Eof))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Le,
+                TokenKind::Ge,
+                TokenKind::Lt,
+                TokenKind::Gt,
+                TokenKind::Eq,
+                TokenKind::Ne,
+                TokenKind::Slash,
+                TokenKind::Percent,
+                TokenKind::AndAnd,
+                TokenKind::OrOr,
+                TokenKind::Not,
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_loops_labels_and_ranges() {
+        let src = "'outer: for i in 0..n { break 'outer; continue; } while loop";
+        let kinds: Vec<TokenKind> = Lexer::new(src, FileId::new(0))
+            .collect_tokens()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(kinds[0], TokenKind::Label("outer".to_string()));
+        assert_eq!(kinds[2], TokenKind::For);
+        assert_eq!(kinds[4], TokenKind::In);
+        assert_eq!(kinds[6], TokenKind::DotDot);
+        assert_eq!(kinds[9], TokenKind::Break);
+        assert_eq!(kinds[10], TokenKind::Label("outer".to_string()));
+        assert_eq!(kinds[12], TokenKind::Continue);
+        assert_eq!(kinds[15], TokenKind::While);
+        assert_eq!(kinds[16], TokenKind::Loop);
+    }
 }
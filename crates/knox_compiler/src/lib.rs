@@ -1,27 +1,85 @@
 //! Knox compiler: lexer, parser, desugar, type checker, pipeline, IR, lowering.
 
+mod borrowck;
+mod check;
 mod desugar;
+mod erase;
 mod ir;
 mod lexer;
 mod lower;
 mod modules;
+mod moves;
 mod parser;
+mod typed;
 
+pub use knox_syntax::diagnostics::SourceFile;
 use knox_syntax::diagnostics::{format_diagnostic, Diagnostic};
 use knox_syntax::span::FileId;
 use std::path::Path;
 
-/// Print diagnostics to stderr with source context.
-pub fn print_diagnostics(source: &str, file_id: FileId, diags: &[Diagnostic]) {
+/// Print diagnostics to stderr with source context. `files` is indexed by `FileId`, as in
+/// `Compilation::files`.
+pub fn print_diagnostics(files: &[SourceFile], diags: &[Diagnostic]) {
     for d in diags {
-        eprintln!("{}", format_diagnostic(source, file_id, d));
+        eprintln!("{}", format_diagnostic(files, d));
     }
 }
 
-/// Compile a single file or package entry point to Wasm.
+/// Settings that change the generated code, not which programs compile.
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    /// Trap with a message when integer `+`, `-`, `*` or negation overflows (the default);
+    /// when off, they wrap. Division by zero traps either way.
+    pub overflow_checks: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            overflow_checks: true,
+        }
+    }
+}
+
+/// The outcome of `compile_file_with`.
+pub struct Compilation {
+    /// Every source file read, indexed by `FileId` (the entry file first); diagnostics point
+    /// into them.
+    pub files: Vec<SourceFile>,
+    /// Warnings, also when compilation failed after type checking.
+    pub warnings: Vec<Diagnostic>,
+    /// Wasm bytes, or the errors that stopped compilation.
+    pub wasm: Result<Vec<u8>, Vec<Diagnostic>>,
+}
+
+/// Compile a single file or package entry point to Wasm with the default options.
 /// When path is inside a package (has knox.toml), resolves imports from src/.
-/// Returns either Wasm bytes or a list of diagnostics.
+/// Returns either Wasm bytes or a list of diagnostics; warnings are dropped.
 pub fn compile_file(path: &Path) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    compile_file_with(path, &CompileOptions::default()).wasm
+}
+
+/// Like `compile_file`, with explicit options; also returns the warnings and the source files
+/// for printing diagnostics.
+pub fn compile_file_with(path: &Path, options: &CompileOptions) -> Compilation {
+    let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    let wasm = compile_into(path, options, &mut files, &mut warnings);
+    Compilation {
+        files,
+        warnings,
+        wasm,
+    }
+}
+
+/// Compile `path`, appending every source file read to `files` and the checker's warnings to
+/// `warnings`.
+fn compile_into(
+    path: &Path,
+    options: &CompileOptions,
+    files: &mut Vec<SourceFile>,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<Vec<u8>, Vec<Diagnostic>> {
     let path = path.canonicalize().map_err(|e| {
         vec![Diagnostic::error(
             format!("failed to canonicalize: {}", e),
@@ -36,7 +94,6 @@ pub fn compile_file(path: &Path) -> Result<Vec<u8>, Vec<Diagnostic>> {
     })?;
     let file_id = FileId::new(0);
     let tokens = lexer::Lexer::new(&source, file_id).collect_tokens();
-    let root = parser::parse(tokens, file_id)?;
 
     // Package root: nearest ancestor with knox.toml, or if under examples/<name>/src/ use that directory (monorepo convention).
     let package_root = path.ancestors().find(|p| p.join("knox.toml").exists());
@@ -63,34 +120,43 @@ pub fn compile_file(path: &Path) -> Result<Vec<u8>, Vec<Diagnostic>> {
             eprintln!("[KNOX_DEBUG] compiler package_root: (none)");
         }
     }
-    let mut deps: Vec<(String, knox_syntax::ast::Root)> = Vec::new();
-    for item in &root.items {
-        if let knox_syntax::ast::Item::Import(imp) = item {
-            if imp.path.len() == 1 && imp.alias.is_none() {
-                let mod_name = &imp.path[0];
-                if let Some(pkg) = package_root {
-                    let mod_path = vec![mod_name.clone()];
-                    let dep_path = modules::resolve_internal(pkg, &mod_path);
-                    if let Some(ref dep_path) = dep_path {
-                        if let Ok(dep_src) = std::fs::read_to_string(dep_path) {
-                            let dep_tokens =
-                                lexer::Lexer::new(&dep_src, FileId::new(1)).collect_tokens();
-                            if let Ok(dep_root) = parser::parse(dep_tokens, FileId::new(1)) {
-                                deps.push((mod_name.clone(), dep_root));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+    let main_name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "main".to_string());
+    let display_name = |p: &Path| {
+        package_root
+            .and_then(|pkg| p.strip_prefix(pkg).ok())
+            .or_else(|| p.file_name().map(Path::new))
+            .unwrap_or(p)
+            .display()
+            .to_string()
+    };
+    files.push(SourceFile {
+        name: display_name(&path),
+        text: source,
+    });
+    let root = parser::parse(tokens, file_id)?;
+    let deps = load_imports(&main_name, &root, package_root, files)?;
+    // Module 0 is the entry file; deps[i] is module i + 1 (matching its FileId).
+    let mut modules = vec![(main_name, root)];
+    modules.extend(deps);
+    desugar::add_accessor_methods(&mut modules);
+    desugar::add_derived_impls(&mut modules);
+    let mut typed = check::check_program(&modules)?;
+    warnings.append(&mut typed.warnings);
+    borrowck::check_borrows(&typed)?;
+    moves::check_moves(&typed)?;
 
-    let (layouts, accessors) = desugar::collect_struct_layouts_and_accessors(&deps);
+    erase::erase_newtypes(&mut typed);
 
-    let program = match lower::lower_to_ir(&root, &deps, &layouts, &accessors) {
-        Ok(p) => p,
-        Err(e) => return Err(vec![Diagnostic::error(e, None)]),
-    };
+    let program = lower::lower_to_ir(
+        &typed,
+        &typed.struct_layouts,
+        files,
+        options.overflow_checks,
+    )
+    .map_err(|d| vec![d])?;
     if debug {
         eprintln!(
             "[KNOX_DEBUG] compiler: lowered to IR: {} functions, {} struct layouts, {} string data",
@@ -102,3 +168,76 @@ pub fn compile_file(path: &Path) -> Result<Vec<u8>, Vec<Diagnostic>> {
     let wasm = knox_codegen_wasm::emit_from_ir(&program, debug);
     Ok(wasm)
 }
+
+/// Load every module reachable through imports from the entry module (transitively, each once).
+/// Module i in the returned list is parsed with FileId(i + 1), and its source is appended to `files`.
+/// Imports that cannot be resolved are left out and reported by the checker.
+fn load_imports(
+    main_name: &str,
+    root: &knox_syntax::ast::Root,
+    package_root: Option<&Path>,
+    files: &mut Vec<SourceFile>,
+) -> Result<Vec<(String, knox_syntax::ast::Root)>, Vec<Diagnostic>> {
+    let mut deps: Vec<(String, knox_syntax::ast::Root)> = Vec::new();
+    let Some(pkg) = package_root else {
+        return Ok(deps);
+    };
+    let mut diags = Vec::new();
+    let mut pending: Vec<Vec<String>> = import_paths(root);
+    while let Some(mod_path) = pending.pop() {
+        let mod_name = mod_path.join("::");
+        if mod_name == main_name || deps.iter().any(|(n, _)| n == &mod_name) {
+            continue;
+        }
+        let Some(dep_path) = modules::resolve_internal(pkg, &mod_path) else {
+            continue;
+        };
+        let dep_src = match std::fs::read_to_string(&dep_path) {
+            Ok(s) => s,
+            Err(e) => {
+                diags.push(Diagnostic::error(
+                    format!("failed to read module {}: {}", mod_name, e),
+                    None,
+                ));
+                continue;
+            }
+        };
+        let file_id = FileId::new(deps.len() as u32 + 1);
+        let dep_tokens = lexer::Lexer::new(&dep_src, file_id).collect_tokens();
+        files.push(SourceFile {
+            name: dep_path
+                .strip_prefix(pkg)
+                .unwrap_or(&dep_path)
+                .display()
+                .to_string(),
+            text: dep_src,
+        });
+        match parser::parse(dep_tokens, file_id) {
+            Ok(dep_root) => {
+                pending.extend(import_paths(&dep_root));
+                deps.push((mod_name, dep_root));
+            }
+            Err(d) => {
+                diags.extend(d);
+                // Keep the slot so later modules still get distinct file ids.
+                deps.push((mod_name, knox_syntax::ast::Root { items: Vec::new() }));
+            }
+        }
+    }
+    if diags.is_empty() {
+        Ok(deps)
+    } else {
+        Err(diags)
+    }
+}
+
+fn import_paths(root: &knox_syntax::ast::Root) -> Vec<Vec<String>> {
+    root.items
+        .iter()
+        .rev()
+        .filter_map(|item| match item {
+            knox_syntax::ast::Item::Import(imp) => Some(imp.path.clone()),
+            _ => None,
+        })
+        .collect()
+}
@@ -1,4 +1,4 @@
-//! Lower AST to IR. Consumes typed AST + layouts + accessors, produces a single Program.
+//! Lower AST to IR. Consumes typed AST + layouts, produces a single Program.
 
 mod to_ir;
 
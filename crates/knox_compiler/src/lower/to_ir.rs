@@ -1,599 +1,2368 @@
-//! Lower AST to IR. One pass over main + accessors; produces Program.
+//! Lower the typed AST to IR. One IrFunction per checked function (including methods and accessors).
 
-use knox_syntax::ast::{Block, Expr, Item, Root, Stmt, Type};
-use knox_syntax::{AccessorSpec, StructLayout};
+use knox_syntax::ast::{BinaryOp, Type, UnaryOp};
+use knox_syntax::span::{FileId, Location, Span};
+use knox_syntax::{
+    closure_capture_offsets, closure_env_size, field_byte_size, mangle, offset_to_line_col,
+    option_layout, payload_offset, result_layout, struct_field_offsets, Diagnostic, SourceFile,
+    StructLayout, LIST_DATA_OFFSET, LIST_HEADER_SIZE, LIST_LEN_OFFSET, MAP_CAP_OFFSET,
+    MAP_HEADER_SIZE, MAP_INDEX_OFFSET, MAP_INITIAL_CAPACITY, MAP_KEYS_OFFSET, MAP_VALUES_OFFSET,
+};
 use std::collections::HashMap;
 
-use crate::ir::{IrFunction, IrInstr, Program, StructLayoutIr};
+use crate::ir::{IrBinOp, IrFunction, IrInstr, Program, StructLayoutIr};
+use crate::typed::{
+    Callee, KeyOps, ListMethod, LocalId, MapMethod, NumMethod, StrMethod, TArm, TBlock, TExpr,
+    TExprKind, TFunction, TPattern, TStmt, TypedProgram,
+};
 
-/// Lower main module + deps + layouts + accessors into a single IR Program.
-/// Function index 0 = main; then getters/setters in deterministic order.
+/// Lower a checked program + layouts into a single IR Program.
+/// Function indices are the FnIds assigned by the checker (main = 0); the map helpers the
+/// program uses follow them.
+/// With `overflow_checks` off, integer `+`, `-`, `*` and negation wrap instead of trapping.
 pub fn lower_to_ir(
-    main_root: &Root,
-    deps: &[(String, Root)],
+    typed: &TypedProgram,
     layouts: &[StructLayout],
-    accessors: &[AccessorSpec],
-) -> Result<Program, String> {
+    files: &[SourceFile],
+    overflow_checks: bool,
+) -> Result<Program, Diagnostic> {
     let mut program = Program::default();
 
-    // 1. Struct layouts
-    for l in layouts {
-        program.struct_layouts.push(StructLayoutIr {
-            module: l.module.clone(),
-            struct_name: l.struct_name.clone(),
-            fields: l.fields.clone(),
-            total_size: l.total_size,
-        });
+    // 1. Struct layouts (tagged unions are added as functions use them)
+    let mut layouts = Layouts {
+        ids: layouts
+            .iter()
+            .enumerate()
+            .map(|(i, l)| ((l.module.clone(), l.struct_name.clone()), i as u32))
+            .collect(),
+        list: layouts.iter().map(layout_ir).collect(),
+    };
+
+    // 2. Lower every function in FnId order
+    let mut helpers = Helpers {
+        base: typed.functions.len() as u32,
+        ids: HashMap::new(),
+        list: Vec::new(),
+    };
+    for f in &typed.functions {
+        let ir = lower_function(
+            f,
+            &mut layouts,
+            &mut program.string_data,
+            files,
+            &mut helpers,
+            overflow_checks,
+        )?;
+        program.functions.push(ir);
     }
 
-    // 2. Function order: main first, then accessors (getters then setters, sorted by module/struct/field)
-    let main_fn = main_root
-        .items
+    // 3. Then the map helpers requested while lowering
+    for i in 0..helpers.list.len() {
+        let (helper, key, ops) = helpers.list[i].clone();
+        let ir = lower_map_helper(
+            helper,
+            &key,
+            ops,
+            &mut layouts,
+            &mut program.string_data,
+            &mut helpers,
+        );
+        program.functions.push(ir);
+    }
+
+    program.struct_layouts = layouts.list;
+    Ok(program)
+}
+
+/// Functions generated once per key type for `Map<K, V>` and `Set<K>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum MapHelper {
+    /// `find(map, key) -> slot`: address of the index slot holding the key's entry number,
+    /// or of the empty slot where it would go.
+    Find,
+    /// `reindex(map, capacity)`: a fresh index of `capacity` slots holding every key.
+    Reindex,
+}
+
+/// Map helpers in function index order, starting after the checked functions.
+struct Helpers {
+    base: u32,
+    ids: HashMap<(MapHelper, Type), u32>,
+    list: Vec<(MapHelper, Type, KeyOps)>,
+}
+
+impl Helpers {
+    /// Function index of `helper` for keys of type `key`, added on first use.
+    fn id(&mut self, helper: MapHelper, key: &Type, ops: KeyOps) -> u32 {
+        if let Some(id) = self.ids.get(&(helper, key.clone())) {
+            return *id;
+        }
+        let id = self.base + self.list.len() as u32;
+        self.list.push((helper, key.clone(), ops));
+        self.ids.insert((helper, key.clone()), id);
+        id
+    }
+}
+
+/// Struct layouts in layout id order, keyed by (module, struct name); a generic struct instance
+/// is keyed by its mangled name. Tagged unions are keyed by ("", type) and added on first use,
+/// so each concrete `Option<T>`/`Result<T, E>` gets one layout.
+struct Layouts {
+    ids: HashMap<(String, String), u32>,
+    list: Vec<StructLayoutIr>,
+}
+
+impl Layouts {
+    /// Layout id of a struct or tagged union type.
+    fn id(&mut self, ty: &Type) -> Option<u32> {
+        let key = match ty {
+            Type::Path(p) if p.len() == 2 => (p[0].clone(), p[1].clone()),
+            Type::Generic(p, args) if p.len() == 2 => (p[0].clone(), mangle(&p[1], args)),
+            Type::Option(_) | Type::Result(_, _) => (String::new(), ty.to_string()),
+            _ => return None,
+        };
+        if let Some(id) = self.ids.get(&key) {
+            return Some(*id);
+        }
+        let layout = match ty {
+            Type::Option(inner) => option_layout(inner),
+            Type::Result(ok, err) => result_layout(ok, err),
+            _ => return None,
+        };
+        let id = self.list.len() as u32;
+        self.list.push(layout_ir(&layout));
+        self.ids.insert(key, id);
+        Some(id)
+    }
+}
+
+fn layout_ir(l: &StructLayout) -> StructLayoutIr {
+    StructLayoutIr {
+        module: l.module.clone(),
+        struct_name: l.struct_name.clone(),
+        fields: l.fields.clone(),
+        total_size: l.total_size,
+    }
+}
+
+/// Wasm slots for a value of this type: strings are (ptr, len), unit has none, a tuple has its
+/// elements' slots in order, a number is one slot of its own type, everything else is one i32.
+fn slot_types(ty: &Type) -> Vec<Type> {
+    match ty {
+        Type::Unit => Vec::new(),
+        Type::String => vec![Type::Int, Type::Int],
+        Type::Tuple(elems) => elems.iter().flat_map(slot_types).collect(),
+        Type::I64 | Type::U64 | Type::U8 | Type::F64 => vec![ty.clone()],
+        _ => vec![Type::Int],
+    }
+}
+
+/// First slot of each element of a tuple whose slots start at `base`.
+fn element_slots(base: u32, elems: &[Type]) -> Vec<u32> {
+    let mut slot = base;
+    elems
         .iter()
-        .find_map(|i| {
-            if let Item::Fn(f) = i {
-                if f.name == "main" {
-                    return Some(f);
-                }
-            }
-            None
+        .map(|e| {
+            let at = slot;
+            slot += slot_types(e).len() as u32;
+            at
         })
-        .ok_or_else(|| "main function not found".to_string())?;
+        .collect()
+}
 
-    // One entry per getter and per setter (a field with both get and set yields two entries).
-    let mut accessor_list: Vec<(&AccessorSpec, bool)> = accessors
-        .iter()
-        .flat_map(|a| {
-            let mut v = Vec::new();
-            if a.get {
-                v.push((a, true));
-            }
-            if a.set {
-                v.push((a, false));
+fn store_field(out: &mut Vec<IrInstr>, ptr: u32, offset: u32, ty: &Type, val: u32) {
+    if let Type::Tuple(elems) = ty {
+        let offsets = struct_field_offsets(elems);
+        for ((e, o), v) in elems.iter().zip(offsets).zip(element_slots(val, elems)) {
+            store_field(out, ptr, offset + o, e, v);
+        }
+        return;
+    }
+    match slot_types(ty).len() {
+        0 => {}
+        1 => out.push(IrInstr::StructSet(ptr, offset, val)),
+        _ => out.push(IrInstr::StructSetStr(ptr, offset, val, val + 1)),
+    }
+}
+
+fn load_field(out: &mut Vec<IrInstr>, ptr: u32, offset: u32, ty: &Type, dest: u32) {
+    if let Type::Tuple(elems) = ty {
+        let offsets = struct_field_offsets(elems);
+        for ((e, o), d) in elems.iter().zip(offsets).zip(element_slots(dest, elems)) {
+            load_field(out, ptr, offset + o, e, d);
+        }
+        return;
+    }
+    match slot_types(ty).len() {
+        0 => {}
+        1 => out.push(IrInstr::StructGet(ptr, offset, dest)),
+        _ => out.push(IrInstr::StructGetStr(ptr, offset, dest, dest + 1)),
+    }
+}
+
+fn return_instr(base: u32, ty: &Type) -> IrInstr {
+    match slot_types(ty).len() as u32 {
+        0 => IrInstr::Return,
+        1 => IrInstr::ReturnInt(base),
+        count => IrInstr::ReturnMulti { base, count },
+    }
+}
+
+/// The place a tuple element `place.0.1` lives in: `place`, and the element's first slot and byte
+/// offset within it (both 0 when `target` is not a tuple element).
+fn tuple_element_place(target: &TExpr) -> (&TExpr, u32, u32) {
+    let (mut place, mut slot, mut offset) = (target, 0, 0);
+    while let TExprKind::TupleField { base, index } = &place.kind {
+        // An element of a tuple behind a reference is in memory, like a field.
+        let Type::Tuple(elems) = &base.ty else { break };
+        slot += element_slots(0, elems)[*index];
+        offset += struct_field_offsets(elems)[*index];
+        place = base;
+    }
+    (place, slot, offset)
+}
+
+/// The type a tuple element's base refers to, when the base is a reference.
+fn place_ref(place: &TExpr) -> Option<&Type> {
+    match &place.kind {
+        TExprKind::TupleField { base, .. } => match &base.ty {
+            Type::Ref(_, inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// For each local, whether a reference to it (or to one of its tuple elements) is taken
+/// that is not simply the pointer of a heap value: such a local needs a cell in memory.
+fn borrowed_locals(f: &TFunction) -> Vec<bool> {
+    fn visit(expr: &TExpr, borrowed: &mut Vec<bool>) {
+        if let TExprKind::Ref { expr: inner, .. } = &expr.kind {
+            if let (TExprKind::Local(id), false) =
+                (&tuple_element_place(inner).0.kind, on_heap(&inner.ty))
+            {
+                borrowed[*id as usize] = true;
             }
-            v
-        })
-        .collect();
-    accessor_list.sort_by(|(a, is_get), (b, is_get_b)| {
-        (&a.module, &a.struct_name, !*is_get, &a.field_name).cmp(&(
-            &b.module,
-            &b.struct_name,
-            !*is_get_b,
-            &b.field_name,
-        ))
-    });
-
-    let mut func_index: HashMap<(String, String, String, bool), u32> = HashMap::new();
-    let mut idx = 0u32;
-    idx += 1; // main = 0
-    for (a, is_getter) in &accessor_list {
-        let key = (
-            a.module.clone(),
-            a.struct_name.clone(),
-            a.field_name.clone(),
-            *is_getter,
-        );
-        func_index.insert(key, idx);
-        idx += 1;
+        }
+        expr.for_each_child(&mut |e| visit(e, borrowed));
     }
+    let mut borrowed = vec![false; f.locals.len()];
+    f.body.for_each_expr(&mut |e| visit(e, &mut borrowed));
+    borrowed
+}
 
-    let layout_id: HashMap<(String, String), u32> = program
-        .struct_layouts
-        .iter()
-        .enumerate()
-        .map(|(i, l)| ((l.module.clone(), l.struct_name.clone()), i as u32))
-        .collect();
-
-    // 3. Lower main
-    let main_ir = lower_function(
-        main_fn.name.as_str(),
-        &main_fn.params,
-        &main_fn.body,
-        deps,
-        &layout_id,
-        &program.struct_layouts,
-        &func_index,
-        &mut program.string_data,
-    )?;
-    program.functions.push(main_ir);
-
-    // 4. Lower accessors
-    for (a, is_getter) in &accessor_list {
-        let layout_id = *layout_id
-            .get(&(a.module.clone(), a.struct_name.clone()))
-            .ok_or_else(|| format!("layout not found for {}/{}", a.module, a.struct_name))?;
-        let (_, _, byte_offset) = program.struct_layouts[layout_id as usize]
-            .fields
-            .iter()
-            .find(|(n, _, _)| n == &a.field_name)
-            .cloned()
-            .unwrap_or_else(|| (a.field_name.clone(), a.ty.clone(), 0));
-
-        let body = if *is_getter {
-            if matches!(a.ty, Type::String) {
-                vec![
-                    IrInstr::StructGetStr(0, byte_offset, 1, 2),
-                    IrInstr::ReturnStr(1, 2),
-                ]
-            } else {
-                vec![IrInstr::StructGet(0, byte_offset, 1), IrInstr::ReturnInt(1)]
-            }
-        } else {
-            vec![IrInstr::StructSet(0, byte_offset, 1), IrInstr::Return]
-        };
+/// Copy the `count` slots starting at `src` to those starting at `dest`.
+fn copy_slots(out: &mut Vec<IrInstr>, src: u32, dest: u32, count: usize) {
+    for i in 0..count as u32 {
+        out.push(IrInstr::LocalGet(src + i));
+        out.push(IrInstr::LocalSet(dest + i));
+    }
+}
 
-        let params = if *is_getter {
-            vec![Type::Int] // ptr
-        } else {
-            vec![Type::Int, Type::Int] // ptr, value
-        };
-        let locals: Vec<Type> = if *is_getter {
-            if matches!(a.ty, Type::String) {
-                vec![Type::Int, Type::Int] // ptr_dest, len_dest
-            } else {
-                vec![Type::Int]
-            }
-        } else {
-            vec![]
-        };
+/// Highest tag tested at the top of `pat`, if it looks at a tagged union's tag at all.
+fn max_tag(pat: &TPattern) -> Option<u32> {
+    match pat {
+        TPattern::Variant { tag, .. } => Some(*tag),
+        TPattern::Or(alts) => alts.iter().filter_map(max_tag).max(),
+        _ => None,
+    }
+}
 
-        let name = if *is_getter {
-            format!("{}_{}_{}", a.module, a.struct_name, a.field_name)
-        } else {
-            format!("{}_{}_set_{}", a.module, a.struct_name, a.field_name)
-        };
-        program.functions.push(IrFunction {
-            name,
-            params,
-            locals,
-            body,
-        });
+/// Whether `pat` can match a tagged union with this tag (None: a tag no pattern names).
+fn admits(pat: &TPattern, tag: Option<u32>) -> bool {
+    match pat {
+        TPattern::Wildcard | TPattern::Binding(_) => true,
+        TPattern::Variant { tag: t, .. } => tag == Some(*t),
+        TPattern::Or(alts) => alts.iter().any(|p| admits(p, tag)),
+        _ => false,
     }
+}
 
-    Ok(program)
+/// The i32 instruction for a binary operator on `int` or `bool` operands.
+fn int_op(op: BinaryOp) -> IrBinOp {
+    match op {
+        BinaryOp::Add => IrBinOp::Add,
+        BinaryOp::Sub => IrBinOp::Sub,
+        BinaryOp::Mul => IrBinOp::Mul,
+        BinaryOp::Div => IrBinOp::DivS,
+        BinaryOp::Rem => IrBinOp::RemS,
+        BinaryOp::Eq => IrBinOp::Eq,
+        BinaryOp::Ne => IrBinOp::Ne,
+        BinaryOp::Lt => IrBinOp::LtS,
+        BinaryOp::Le => IrBinOp::LeS,
+        BinaryOp::Gt => IrBinOp::GtS,
+        BinaryOp::Ge => IrBinOp::GeS,
+        BinaryOp::And | BinaryOp::Or => unreachable!("short-circuiting operators branch instead"),
+    }
+}
+
+/// The instruction for a binary operator on two numbers of type `ty` (or two `bool`s).
+fn number_op(op: BinaryOp, ty: &Type) -> IrInstr {
+    let unsigned = |op| match op {
+        BinaryOp::Div => IrBinOp::DivU,
+        BinaryOp::Rem => IrBinOp::RemU,
+        BinaryOp::Lt => IrBinOp::LtU,
+        BinaryOp::Le => IrBinOp::LeU,
+        BinaryOp::Gt => IrBinOp::GtU,
+        BinaryOp::Ge => IrBinOp::GeU,
+        _ => int_op(op),
+    };
+    match ty {
+        Type::I64 => IrInstr::BinI64(int_op(op)),
+        Type::U64 => IrInstr::BinI64(unsigned(op)),
+        Type::F64 => IrInstr::BinF64(int_op(op)),
+        Type::U8 => IrInstr::Bin(unsigned(op)),
+        _ => IrInstr::Bin(int_op(op)),
+    }
+}
+
+/// An `IrBinOp` on two integers of type `ty`, in the width the type uses.
+fn bits_op(op: IrBinOp, ty: &Type) -> IrInstr {
+    match ty {
+        Type::I64 | Type::U64 => IrInstr::BinI64(op),
+        _ => IrInstr::Bin(op),
+    }
+}
+
+/// Smallest and largest value of an integer type, as `const_number` takes them (`u64::MAX` as
+/// its bit pattern, -1).
+fn integer_bounds(ty: &Type) -> (i64, i64) {
+    match ty {
+        Type::I64 => (i64::MIN, i64::MAX),
+        Type::U64 => (0, -1),
+        Type::U8 => (0, 255),
+        _ => (i32::MIN as i64, i32::MAX as i64),
+    }
 }
 
-/// (module, struct) for a variable's type (e.g. p -> ("product", "Product")).
-type VarType = (String, String);
+fn overflow_message(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "attempt to add with overflow",
+        BinaryOp::Sub => "attempt to subtract with overflow",
+        _ => "attempt to multiply with overflow",
+    }
+}
+
+/// The constant `value` as a number of type `ty`.
+fn const_number(value: i64, ty: &Type) -> IrInstr {
+    match ty {
+        Type::I64 | Type::U64 => IrInstr::ConstI64(value),
+        Type::F64 => IrInstr::ConstF64(value as f64),
+        _ => IrInstr::ConstInt(value),
+    }
+}
+
+fn block_type(block: &TBlock) -> Type {
+    block.value.as_ref().map_or(Type::Unit, |v| v.ty.clone())
+}
+
+/// Whether values of `ty` are pointers to heap memory (structs, tagged unions, collections and
+/// closures).
+fn on_heap(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::Path(_)
+            | Type::Generic(_, _)
+            | Type::Fn(_, _)
+            | Type::Option(_)
+            | Type::Result(_, _)
+            | Type::List(_)
+            | Type::Map(_, _)
+            | Type::Set(_)
+    )
+}
+
+/// Element type of a list or of a reference to one (both are the list's header pointer).
+fn list_elem(ty: &Type) -> &Type {
+    match ty {
+        Type::List(elem) => elem,
+        Type::Ref(_, inner) => list_elem(inner),
+        _ => &Type::Unit,
+    }
+}
 
-/// Lower one function body. Tracks local indices: params 0..params.len(), then new locals for lets and temps.
-#[allow(clippy::too_many_arguments)]
+/// Key type and value type (None for a set) of a map or set, or of a reference to one.
+fn map_parts(ty: &Type) -> Option<(&Type, Option<&Type>)> {
+    match ty {
+        Type::Map(key, value) => Some((key, Some(value))),
+        Type::Set(key) => Some((key, None)),
+        Type::Ref(_, inner) => map_parts(inner),
+        _ => None,
+    }
+}
+
+/// How a loop advances: a `for` over a range or a list keeps a counter `i` and stops at `end`
+/// (a slot) or at the list's current length.
+enum Iteration {
+    Range {
+        i: u32,
+        end: u32,
+    },
+    List {
+        i: u32,
+        list: u32,
+        var: u32,
+        elem: Type,
+    },
+}
+
+/// Lower one function. Slots: params first (in order), then one slot range per local, then temps.
 fn lower_function(
-    name: &str,
-    params: &[knox_syntax::ast::Param],
-    body: &Block,
-    deps: &[(String, Root)],
-    layout_id: &HashMap<(String, String), u32>,
-    struct_layouts: &[StructLayoutIr],
-    func_index: &HashMap<(String, String, String, bool), u32>,
+    f: &TFunction,
+    layouts: &mut Layouts,
     string_data: &mut Vec<String>,
-) -> Result<IrFunction, String> {
-    let mut instructions = Vec::new();
-    let mut local_types: Vec<Type> = params.iter().map(|p| p.ty.clone()).collect();
-    let mut var_to_local: HashMap<String, u32> = params
-        .iter()
-        .enumerate()
-        .map(|(i, p)| (p.name.clone(), i as u32))
-        .collect();
-    let mut var_to_type: HashMap<String, VarType> = HashMap::new();
-
-    let mut next_local = |local_types: &mut Vec<Type>| {
-        let idx = local_types.len() as u32;
-        local_types.push(Type::Int); // temps and ptrs are i32 in wasm
-        idx
+    files: &[SourceFile],
+    helpers: &mut Helpers,
+    overflow_checks: bool,
+) -> Result<IrFunction, Diagnostic> {
+    let mut fl = FnLowerer {
+        layouts,
+        string_data,
+        files,
+        helpers,
+        overflow_checks,
+        file: f.file,
+        slots: Vec::new(),
+        local_slot: Vec::with_capacity(f.locals.len()),
+        cells: Vec::with_capacity(f.locals.len()),
+        out: Vec::new(),
+        depth: 0,
+        loops: Vec::new(),
     };
-
-    for stmt in &body.stmts {
-        lower_stmt(
-            stmt,
-            &mut instructions,
-            &mut var_to_local,
-            &mut var_to_type,
-            &mut local_types,
-            &mut next_local,
-            deps,
-            layout_id,
-            struct_layouts,
-            func_index,
-            string_data,
-        )?;
+    for local in &f.locals {
+        let base = fl.alloc(&local.ty);
+        fl.local_slot.push(base);
+    }
+    for (local, borrowed) in f.locals.iter().zip(borrowed_locals(f)) {
+        let cell = borrowed.then(|| Cell {
+            ptr: fl.alloc(&Type::Int),
+            spilled: fl.alloc(&Type::Bool),
+            ty: local.ty.clone(),
+        });
+        fl.cells.push(cell);
+    }
+    let param_slots = f
+        .locals
+        .iter()
+        .take(f.param_count as usize)
+        .map(|l| slot_types(&l.ty).len())
+        .sum::<usize>();
+    // A closure starts by copying its captured values out of its environment (local 0).
+    if let Some(captures) = &f.captures {
+        let tys: Vec<&Type> = captures.iter().map(|c| &f.locals[*c as usize].ty).collect();
+        let offsets = closure_capture_offsets(tys.iter().copied());
+        for ((c, ty), offset) in captures.iter().zip(tys).zip(offsets) {
+            let dest = fl.local_slot[*c as usize];
+            load_field(&mut fl.out, fl.local_slot[0], offset, ty, dest);
+        }
     }
 
-    instructions.push(IrInstr::Return);
+    // The body's value (if any) is the function result.
+    let result = fl.alloc(&f.ret);
+    fl.lower_block(&f.body, result)?;
+    if f.body.value.is_some() && !f.body.diverges() {
+        fl.out.push(return_instr(result, &f.ret));
+    }
 
+    let mut slots = fl.slots;
+    let locals = slots.split_off(param_slots);
     Ok(IrFunction {
-        name: name.to_string(),
-        params: params.iter().map(|p| p.ty.clone()).collect(),
-        locals: local_types.split_off(params.len()),
-        body: instructions,
+        name: format!("{}_{}", f.module, f.name),
+        params: slots,
+        results: slot_types(&f.ret),
+        locals,
+        body: fl.out,
     })
 }
 
-#[allow(clippy::too_many_arguments)]
-fn lower_stmt(
-    stmt: &Stmt,
-    out: &mut Vec<IrInstr>,
-    var_to_local: &mut HashMap<String, u32>,
-    var_to_type: &mut HashMap<String, VarType>,
-    local_types: &mut Vec<Type>,
-    mut next_local: &mut dyn FnMut(&mut Vec<Type>) -> u32,
-    deps: &[(String, Root)],
-    layout_id: &HashMap<(String, String), u32>,
-    struct_layouts: &[StructLayoutIr],
-    func_index: &HashMap<(String, String, String, bool), u32>,
+/// Lower a map helper for keys of type `key`. Slots: the map, then the key or the capacity.
+fn lower_map_helper(
+    helper: MapHelper,
+    key: &Type,
+    ops: KeyOps,
+    layouts: &mut Layouts,
     string_data: &mut Vec<String>,
-) -> Result<(), String> {
-    match stmt {
-        Stmt::Let { name, init, .. } => {
-            let local = next_local(local_types);
-            var_to_local.insert(name.clone(), local);
-            if let Expr::StructLiteral { path, .. } = init {
-                if path.len() == 2 {
-                    var_to_type.insert(name.clone(), (path[0].clone(), path[1].clone()));
-                }
+    helpers: &mut Helpers,
+) -> IrFunction {
+    let mut fl = FnLowerer {
+        layouts,
+        string_data,
+        files: &[],
+        helpers,
+        overflow_checks: false,
+        file: FileId::new(0),
+        slots: Vec::new(),
+        local_slot: Vec::new(),
+        cells: Vec::new(),
+        out: Vec::new(),
+        depth: 0,
+        loops: Vec::new(),
+    };
+    let map = fl.alloc(&Type::Int);
+    let (name, results) = match helper {
+        MapHelper::Find => {
+            let key_slot = fl.alloc(key);
+            fl.lower_map_find(map, key, ops, key_slot);
+            ("find", vec![Type::Int])
+        }
+        MapHelper::Reindex => {
+            let cap = fl.alloc(&Type::Int);
+            fl.lower_map_reindex(map, key, ops, cap);
+            ("reindex", Vec::new())
+        }
+    };
+    let param_slots = match helper {
+        MapHelper::Find => 1 + slot_types(key).len(),
+        MapHelper::Reindex => 2,
+    };
+    let mut slots = fl.slots;
+    let locals = slots.split_off(param_slots);
+    IrFunction {
+        name: mangle(&format!("Map_{}", name), std::slice::from_ref(key)),
+        params: slots,
+        results,
+        locals,
+        body: fl.out,
+    }
+}
+
+struct FnLowerer<'a> {
+    layouts: &'a mut Layouts,
+    string_data: &'a mut Vec<String>,
+    files: &'a [SourceFile],
+    helpers: &'a mut Helpers,
+    /// Trap when integer `+`, `-`, `*` or negation overflows (see `lower_to_ir`).
+    overflow_checks: bool,
+    file: FileId,
+    slots: Vec<Type>,
+    /// LocalId -> first slot of that local.
+    local_slot: Vec<u32>,
+    /// LocalId -> the memory behind a local that is borrowed (see `borrowed_locals`).
+    cells: Vec<Option<Cell>>,
+    out: Vec<IrInstr>,
+    /// Number of open `If`/`Block`/`Loop` constructs.
+    depth: u32,
+    /// Enclosing loops, outermost first; indexed by `LoopId`.
+    loops: Vec<LoopFrame>,
+}
+
+/// A borrowed local's copy in memory, which references to it point into. It is written when
+/// the local is borrowed, and read back when the local is next used after a `&mut` borrow.
+struct Cell {
+    /// Slot of the pointer to the cell; 0 until the first borrow.
+    ptr: u32,
+    /// Slot of a flag set while the cell, not the local's slots, holds the current value.
+    spilled: u32,
+    ty: Type,
+}
+
+/// Branch targets of a loop being lowered, as control depths (see `FnLowerer::br`).
+struct LoopFrame {
+    /// Block around the whole loop: `break` exits it.
+    break_depth: u32,
+    /// Block around the body: `continue` exits it and falls through to the next iteration.
+    continue_depth: u32,
+    /// Where `break value` writes its value.
+    dest: u32,
+}
+
+impl FnLowerer<'_> {
+    /// Open an `If`, `Block` or `Loop`; returns its depth for later `br`.
+    fn open(&mut self, instr: IrInstr) -> u32 {
+        self.out.push(instr);
+        self.depth += 1;
+        self.depth - 1
+    }
+
+    fn close(&mut self) {
+        self.out.push(IrInstr::End);
+        self.depth -= 1;
+    }
+
+    /// Branch to the construct opened at `target` depth.
+    fn br(&mut self, target: u32) {
+        self.out.push(IrInstr::Br(self.depth - 1 - target));
+    }
+
+    /// Allocate consecutive slots for a value of type `ty`; returns the first one.
+    fn alloc(&mut self, ty: &Type) -> u32 {
+        let base = self.slots.len() as u32;
+        self.slots.extend(slot_types(ty));
+        base
+    }
+
+    fn unsupported(&self, what: &str, span: Span) -> Diagnostic {
+        Diagnostic::error(
+            format!("unsupported expression: {}", what),
+            Some(Location::new(self.file, span)),
+        )
+    }
+
+    /// Trap with `panic at <file>:<line>:<col>: <what>` on stderr, located at `span`.
+    fn panic(&mut self, what: &str, span: Span) {
+        let message = match self.files.get(self.file.0 as usize) {
+            Some(file) => {
+                let (line, col) = offset_to_line_col(&file.text, span.start);
+                format!("panic at {}:{}:{}: {}", file.name, line, col, what)
             }
-            lower_expr_to_local(
-                init,
-                local,
-                out,
-                local_types,
-                &mut next_local,
-                deps,
-                layout_id,
-                struct_layouts,
-                func_index,
-                string_data,
-                var_to_type,
-                var_to_local,
-            )?;
-        }
-        Stmt::Expr { expr, .. } => {
-            let tmp = next_local(local_types);
-            lower_expr_to_local(
-                expr,
-                tmp,
-                out,
-                local_types,
-                &mut next_local,
-                deps,
-                layout_id,
-                struct_layouts,
-                func_index,
-                string_data,
-                var_to_type,
-                var_to_local,
-            )?;
-        }
-        Stmt::Return { value, .. } => {
-            if let Some(expr) = value {
-                let tmp = next_local(local_types);
-                lower_expr_to_local(
-                    expr,
-                    tmp,
-                    out,
-                    local_types,
-                    &mut next_local,
-                    deps,
-                    layout_id,
-                    struct_layouts,
-                    func_index,
-                    string_data,
-                    var_to_type,
-                    var_to_local,
-                )?;
-                out.push(IrInstr::ReturnInt(tmp));
-            } else {
-                out.push(IrInstr::Return);
+            None => format!("panic: {}", what),
+        };
+        let data_id = self.string_data.len() as u32;
+        self.string_data.push(message);
+        self.out.push(IrInstr::Panic(data_id));
+    }
+
+    /// Panic unless the `bool` at `flag` is false.
+    fn panic_if(&mut self, flag: u32, what: &str, span: Span) {
+        self.out.push(IrInstr::LocalGet(flag));
+        self.open(IrInstr::If);
+        self.panic(what, span);
+        self.close();
+    }
+
+    /// `dest = a <instr> b`.
+    fn binary_into(&mut self, instr: IrInstr, a: u32, b: u32, dest: u32) {
+        self.out.push(IrInstr::LocalGet(a));
+        self.out.push(IrInstr::LocalGet(b));
+        self.out.push(instr);
+        self.out.push(IrInstr::LocalSet(dest));
+    }
+
+    /// Push every slot of the value at `base` onto the stack.
+    fn push_value(&mut self, base: u32, ty: &Type) {
+        for i in 0..slot_types(ty).len() as u32 {
+            self.out.push(IrInstr::LocalGet(base + i));
+        }
+    }
+
+    /// Make the slots of local `id` hold its current value again if it was lent out by `&mut`.
+    /// The borrow checker ensures no reference to it is in use once the local is accessed.
+    fn settle(&mut self, id: LocalId) {
+        let Some(cell) = &self.cells[id as usize] else {
+            return;
+        };
+        let (ptr, spilled, ty) = (cell.ptr, cell.spilled, cell.ty.clone());
+        self.out.push(IrInstr::LocalGet(spilled));
+        self.open(IrInstr::If);
+        load_field(&mut self.out, ptr, 0, &ty, self.local_slot[id as usize]);
+        self.out.push(IrInstr::ConstInt(0));
+        self.out.push(IrInstr::LocalSet(spilled));
+        self.close();
+    }
+
+    /// A pointer, into `dest`, to local `id` (which must be borrowed, see `borrowed_locals`)
+    /// plus `offset` bytes: its value is copied into its cell, allocated on first use.
+    /// After a `&mut` borrow the cell holds the value until the local is used again.
+    fn lend_local(&mut self, id: LocalId, offset: u32, mutable: bool, dest: u32) {
+        self.settle(id);
+        let Some(cell) = &self.cells[id as usize] else {
+            return;
+        };
+        let (ptr, spilled, ty) = (cell.ptr, cell.spilled, cell.ty.clone());
+        self.out.push(IrInstr::LocalGet(ptr));
+        self.out.push(IrInstr::Eqz);
+        self.open(IrInstr::If);
+        self.out
+            .push(IrInstr::ConstInt(field_byte_size(&ty).max(1) as i64));
+        self.out.push(IrInstr::Alloc);
+        self.out.push(IrInstr::LocalSet(ptr));
+        self.close();
+        store_field(&mut self.out, ptr, 0, &ty, self.local_slot[id as usize]);
+        if mutable {
+            self.out.push(IrInstr::ConstInt(1));
+            self.out.push(IrInstr::LocalSet(spilled));
+        }
+        self.offset_pointer(ptr, offset, dest);
+    }
+
+    /// Address of a place reached through a reference: `*r`, or an element of the tuple `r`
+    /// points to. None (and nothing emitted) for any other place.
+    fn referent_address(&mut self, place: &TExpr) -> Result<Option<u32>, Diagnostic> {
+        let (base, offset) = match (&place.kind, place_ref(place)) {
+            (TExprKind::Deref(base), _) => (base, 0),
+            (TExprKind::TupleField { base, index }, Some(Type::Tuple(elems))) => {
+                (base, struct_field_offsets(elems)[*index])
+            }
+            _ => return Ok(None),
+        };
+        let ptr = self.alloc(&Type::Int);
+        self.lower_expr(base, ptr)?;
+        if offset != 0 {
+            self.offset_pointer(ptr, offset, ptr);
+        }
+        Ok(Some(ptr))
+    }
+
+    /// Overwrite the heap value at `dest` with a copy of the one at `src`, both of type `ty`:
+    /// every field of its layout, or the header of a collection.
+    fn copy_heap_value(
+        &mut self,
+        src: u32,
+        dest: u32,
+        ty: &Type,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        let fields: Vec<(Type, u32)> = match ty {
+            Type::List(_) => (0..LIST_HEADER_SIZE / 4)
+                .map(|i| (Type::Int, i * 4))
+                .collect(),
+            Type::Map(_, _) | Type::Set(_) => (0..MAP_HEADER_SIZE / 4)
+                .map(|i| (Type::Int, i * 4))
+                .collect(),
+            Type::Fn(_, _) => {
+                return Err(self.unsupported("assignment of a closure through a reference", span))
+            }
+            _ => {
+                // The fields of a tagged union overlap; each copies the same bytes.
+                let lid = self.layout_id(ty)?;
+                self.layouts.list[lid as usize]
+                    .fields
+                    .iter()
+                    .map(|(_, ty, offset)| (ty.clone(), *offset))
+                    .collect()
             }
+        };
+        for (ty, offset) in fields {
+            let tmp = self.alloc(&ty);
+            load_field(&mut self.out, src, offset, &ty, tmp);
+            store_field(&mut self.out, dest, offset, &ty, tmp);
+        }
+        Ok(())
+    }
+
+    /// `dest = ptr + offset`.
+    fn offset_pointer(&mut self, ptr: u32, offset: u32, dest: u32) {
+        self.out.push(IrInstr::LocalGet(ptr));
+        self.out.push(IrInstr::ConstInt(offset as i64));
+        self.out.push(IrInstr::Bin(IrBinOp::Add));
+        self.out.push(IrInstr::LocalSet(dest));
+    }
+
+    /// `&place` or `&mut place` for a value that is not on the heap: a pointer to where it is
+    /// stored, or to a copy in a new heap cell when it is not a place.
+    fn lower_ref(&mut self, inner: &TExpr, mutable: bool, dest: u32) -> Result<(), Diagnostic> {
+        let (place, _, offset) = tuple_element_place(inner);
+        if let Some(addr) = self.referent_address(place)? {
+            self.offset_pointer(addr, offset, dest);
             return Ok(());
         }
+        match &place.kind {
+            TExprKind::Local(id) => self.lend_local(*id, offset, mutable, dest),
+            TExprKind::Field {
+                base,
+                offset: field,
+            } => {
+                let ptr = self.alloc(&base.ty);
+                self.lower_expr(base, ptr)?;
+                self.offset_pointer(ptr, field + offset, dest);
+            }
+            TExprKind::Index { base, index } => {
+                let addr = self.lower_element(base, index, place.span)?;
+                self.offset_pointer(addr, offset, dest);
+            }
+            _ => {
+                let val = self.alloc(&inner.ty);
+                self.lower_expr(inner, val)?;
+                self.out
+                    .push(IrInstr::ConstInt(field_byte_size(&inner.ty).max(1) as i64));
+                self.out.push(IrInstr::Alloc);
+                self.out.push(IrInstr::LocalSet(dest));
+                store_field(&mut self.out, dest, 0, &inner.ty, val);
+            }
+        }
+        Ok(())
     }
-    Ok(())
-}
 
-/// Lower expr and ensure its value ends up in dest_local. May push instructions that leave value in dest.
-#[allow(clippy::too_many_arguments)]
-fn lower_expr_to_local(
-    expr: &Expr,
-    dest_local: u32,
-    out: &mut Vec<IrInstr>,
-    local_types: &mut Vec<Type>,
-    next_local: &mut dyn FnMut(&mut Vec<Type>) -> u32,
-    deps: &[(String, Root)],
-    layout_id: &HashMap<(String, String), u32>,
-    struct_layouts: &[StructLayoutIr],
-    func_index: &HashMap<(String, String, String, bool), u32>,
-    string_data: &mut Vec<String>,
-    var_to_type: &HashMap<String, VarType>,
-    var_to_local: &HashMap<String, u32>,
-) -> Result<(), String> {
-    match expr {
-        Expr::IntLiteral { value, .. } => {
-            out.push(IrInstr::ConstInt(*value));
-            out.push(IrInstr::LocalSet(dest_local));
-        }
-        Expr::StringLiteral { value, .. } => {
-            let data_id = string_data.len() as u32;
-            string_data.push(value.clone());
-            let len_local = next_local(local_types);
-            out.push(IrInstr::ConstString {
-                ptr_local: dest_local,
-                len_local,
-                data_id,
-            });
-        }
-        Expr::StructLiteral { path, fields, .. } => {
-            if path.len() != 2 {
-                return Err("struct literal path must be module::Struct".to_string());
-            }
-            let key = (path[0].clone(), path[1].clone());
-            let lid = *layout_id
-                .get(&key)
-                .ok_or_else(|| format!("layout not found for {}::{}", path[0], path[1]))?;
-            let layout = struct_layouts
-                .iter()
-                .find(|l| l.module == key.0 && l.struct_name == key.1)
-                .ok_or_else(|| format!("struct layout not found for {}::{}", key.0, key.1))?;
-            out.push(IrInstr::StructAlloc(lid));
-            out.push(IrInstr::LocalSet(dest_local));
-            for (fname, fexpr) in fields {
-                let (_, fty, offset) =
-                    layout
+    /// Lower the statements of a block, then its value (if any) into `dest`.
+    fn lower_block(&mut self, block: &TBlock, dest: u32) -> Result<(), Diagnostic> {
+        for stmt in &block.stmts {
+            self.lower_stmt(stmt)?;
+        }
+        if let Some(value) = &block.value {
+            self.lower_expr(value, dest)?;
+        }
+        Ok(())
+    }
+
+    fn lower_stmt(&mut self, stmt: &TStmt) -> Result<(), Diagnostic> {
+        match stmt {
+            TStmt::Let {
+                pattern: TPattern::Binding(local),
+                init,
+            } => {
+                self.settle(*local);
+                let dest = self.local_slot[*local as usize];
+                self.lower_expr(init, dest)?;
+            }
+            TStmt::Let { pattern, init } => {
+                // The checker proved the pattern cannot fail, so `fail` is never taken.
+                let tmp = self.alloc(&init.ty);
+                self.lower_expr(init, tmp)?;
+                let fail = self.open(IrInstr::Block);
+                self.lower_pattern(pattern, tmp, &init.ty, fail, init.span)?;
+                self.close();
+            }
+            TStmt::Expr(expr) => {
+                let tmp = self.alloc(&expr.ty);
+                self.lower_expr(expr, tmp)?;
+            }
+            TStmt::Return {
+                value: Some(expr), ..
+            } => {
+                let tmp = self.alloc(&expr.ty);
+                self.lower_expr(expr, tmp)?;
+                self.out.push(return_instr(tmp, &expr.ty));
+            }
+            TStmt::Return { value: None, .. } => self.out.push(IrInstr::Return),
+        }
+        Ok(())
+    }
+
+    /// Lower expr and ensure its value ends up in the slots starting at `dest`.
+    fn lower_expr(&mut self, expr: &TExpr, dest: u32) -> Result<(), Diagnostic> {
+        match &expr.kind {
+            TExprKind::Int(value) => {
+                self.out.push(const_number(*value, &expr.ty));
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            TExprKind::Float(value) => {
+                self.out.push(IrInstr::ConstF64(*value));
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            TExprKind::Cast(inner) => {
+                let tmp = self.alloc(&inner.ty);
+                self.lower_expr(inner, tmp)?;
+                self.out.push(IrInstr::LocalGet(tmp));
+                self.out.push(IrInstr::Cast {
+                    from: inner.ty.clone(),
+                    to: expr.ty.clone(),
+                });
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            TExprKind::Bool(value) => {
+                self.out.push(IrInstr::ConstInt(*value as i64));
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            TExprKind::Str(value) => self.const_string(value, dest),
+            TExprKind::Local(id) => {
+                self.settle(*id);
+                let src = self.local_slot[*id as usize];
+                for i in 0..slot_types(&expr.ty).len() as u32 {
+                    self.out.push(IrInstr::LocalGet(src + i));
+                    self.out.push(IrInstr::LocalSet(dest + i));
+                }
+            }
+            TExprKind::StructLit(fields) => {
+                let lid = self.layout_id(&expr.ty)?;
+                self.out.push(IrInstr::StructAlloc(lid));
+                self.out.push(IrInstr::LocalSet(dest));
+                for (fname, fexpr) in fields {
+                    let layout = &self.layouts.list[lid as usize];
+                    let offset = layout
                         .fields
                         .iter()
                         .find(|(n, _, _)| n == fname)
-                        .cloned()
-                        .ok_or_else(|| format!("field {} not in {}::{}", fname, key.0, key.1))?;
-                if matches!(fty, Type::String) {
-                    let ptr_local = next_local(local_types);
-                    let len_local = next_local(local_types);
-                    if let Expr::StringLiteral { value, .. } = fexpr {
-                        let data_id = string_data.len() as u32;
-                        string_data.push(value.clone());
-                        out.push(IrInstr::ConstString {
-                            ptr_local,
-                            len_local,
-                            data_id,
-                        });
-                        out.push(IrInstr::StructSetStr(
-                            dest_local, offset, ptr_local, len_local,
-                        ));
-                    } else {
-                        return Err(
-                            "string field in struct literal must be a string literal".to_string()
-                        );
+                        .map(|(_, _, o)| *o)
+                        .ok_or_else(|| {
+                            Diagnostic::error(format!("field {} not in {}", fname, expr.ty), None)
+                        })?;
+                    let val = self.alloc(&fexpr.ty);
+                    self.lower_expr(fexpr, val)?;
+                    store_field(&mut self.out, dest, offset, &fexpr.ty, val);
+                }
+            }
+            TExprKind::Tuple(elements) => {
+                let tys: Vec<Type> = elements.iter().map(|e| e.ty.clone()).collect();
+                for (e, slot) in elements.iter().zip(element_slots(dest, &tys)) {
+                    self.lower_expr(e, slot)?;
+                }
+            }
+            TExprKind::TupleField { .. } if place_ref(expr).is_some() => {
+                let addr = self
+                    .referent_address(expr)?
+                    .expect("tuple behind a reference");
+                load_field(&mut self.out, addr, 0, &expr.ty, dest);
+            }
+            TExprKind::TupleField { base, index } => {
+                let Type::Tuple(elems) = &base.ty else {
+                    return Err(self.unsupported("tuple field", expr.span));
+                };
+                let tmp = self.alloc(&base.ty);
+                self.lower_expr(base, tmp)?;
+                let src = element_slots(tmp, elems)[*index];
+                copy_slots(&mut self.out, src, dest, slot_types(&expr.ty).len());
+            }
+            TExprKind::Field { base, offset } => {
+                // A struct and a reference to it are both the struct's heap pointer.
+                let ptr = self.alloc(&base.ty);
+                self.lower_expr(base, ptr)?;
+                load_field(&mut self.out, ptr, *offset, &expr.ty, dest);
+            }
+            TExprKind::Call {
+                callee: Callee::List(method),
+                args,
+            } => self.lower_list_method(*method, args, expr, dest)?,
+            TExprKind::Call {
+                callee: Callee::Map(method, ops),
+                args,
+            } => self.lower_map_method(*method, *ops, args, expr, dest)?,
+            TExprKind::Call {
+                callee: Callee::Str(method),
+                args,
+            } => self.lower_str_method(*method, args, expr, dest)?,
+            TExprKind::Call {
+                callee: Callee::Num(method),
+                args,
+            } => self.lower_num_method(*method, args, expr, dest)?,
+            TExprKind::Closure { func, captures } => {
+                let tys: Vec<Type> = captures.iter().map(|c| c.ty.clone()).collect();
+                let env = self.alloc(&Type::Int);
+                self.out
+                    .push(IrInstr::ConstInt(closure_env_size(&tys) as i64));
+                self.out.push(IrInstr::Alloc);
+                self.out.push(IrInstr::LocalSet(env));
+                let index = self.alloc(&Type::Int);
+                self.out.push(IrInstr::ConstInt(*func as i64));
+                self.out.push(IrInstr::LocalSet(index));
+                self.out.push(IrInstr::StructSet(env, 0, index));
+                for (c, offset) in captures.iter().zip(closure_capture_offsets(&tys)) {
+                    let val = self.alloc(&c.ty);
+                    self.lower_expr(c, val)?;
+                    store_field(&mut self.out, env, offset, &c.ty, val);
+                }
+                self.out.push(IrInstr::LocalGet(env));
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            TExprKind::Call {
+                callee: Callee::Indirect,
+                args,
+            } => {
+                let mut arg_slots = Vec::with_capacity(args.len());
+                for a in args {
+                    let tmp = self.alloc(&a.ty);
+                    self.lower_expr(a, tmp)?;
+                    arg_slots.push(tmp);
+                }
+                // The closure's environment is its function's first argument.
+                for (a, base) in args.iter().zip(&arg_slots) {
+                    self.push_value(*base, &a.ty);
+                }
+                let index = self.alloc(&Type::Int);
+                self.out.push(IrInstr::StructGet(arg_slots[0], 0, index));
+                self.out.push(IrInstr::LocalGet(index));
+                self.out.push(IrInstr::CallIndirect {
+                    params: args.iter().flat_map(|a| slot_types(&a.ty)).collect(),
+                    results: slot_types(&expr.ty),
+                    dest,
+                });
+            }
+            TExprKind::Call {
+                callee: Callee::Hash,
+                args,
+            } => {
+                let val = self.alloc(&args[0].ty);
+                self.lower_expr(&args[0], val)?;
+                self.push_value(val, &args[0].ty);
+                self.builtin_hash(&args[0].ty);
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            TExprKind::Call {
+                callee: Callee::Fn(idx),
+                args,
+            } => {
+                let idx = *idx;
+                let mut arg_slots = Vec::with_capacity(args.len());
+                for a in args {
+                    let tmp = self.alloc(&a.ty);
+                    self.lower_expr(a, tmp)?;
+                    arg_slots.push(tmp);
+                }
+                for (a, base) in args.iter().zip(arg_slots) {
+                    self.push_value(base, &a.ty);
+                }
+                match slot_types(&expr.ty).len() as u32 {
+                    0 => self.out.push(IrInstr::Call(idx)),
+                    1 => {
+                        self.out.push(IrInstr::Call(idx));
+                        self.out.push(IrInstr::LocalSet(dest));
                     }
-                } else {
-                    let val_local = next_local(local_types);
-                    lower_expr_to_local(
-                        fexpr,
-                        val_local,
-                        out,
-                        local_types,
-                        next_local,
-                        deps,
-                        layout_id,
-                        struct_layouts,
-                        func_index,
-                        string_data,
-                        var_to_type,
-                        var_to_local,
-                    )?;
-                    out.push(IrInstr::StructSet(dest_local, offset, val_local));
-                }
-            }
-        }
-        Expr::Ident { name, .. } => {
-            let &local = var_to_local
-                .get(name)
-                .ok_or_else(|| format!("variable not found: {}", name))?;
-            out.push(IrInstr::LocalGet(local));
-            out.push(IrInstr::LocalSet(dest_local));
-        }
-        Expr::Call {
-            receiver,
-            name,
-            args,
-            ..
-        } => {
-            if name == "print" && args.len() == 1 && receiver.is_none() {
-                match &args[0] {
-                    Expr::StringLiteral { value, .. } => {
-                        let ptr_local = next_local(local_types);
-                        let len_local = next_local(local_types);
-                        let data_id = string_data.len() as u32;
-                        string_data.push(value.clone());
-                        out.push(IrInstr::ConstString {
-                            ptr_local,
-                            len_local,
-                            data_id,
-                        });
-                        out.push(IrInstr::PrintStr(ptr_local, len_local));
+                    count => self.out.push(IrInstr::CallMulti {
+                        func: idx,
+                        dest,
+                        count,
+                    }),
+                }
+            }
+            TExprKind::Print(arg) => {
+                let tmp = self.alloc(&arg.ty);
+                self.lower_expr(arg, tmp)?;
+                match &arg.ty {
+                    Type::String => self.out.push(IrInstr::PrintStr(tmp, tmp + 1)),
+                    Type::I64 | Type::U64 | Type::F64 => {
+                        let s = self.alloc(&Type::String);
+                        self.number_to_string(tmp, &arg.ty, s);
+                        self.out.push(IrInstr::PrintStr(s, s + 1));
                     }
-                    Expr::Call {
-                        receiver: Some(receiver_expr),
-                        name: method,
-                        args: margs,
-                        ..
-                    } if margs.is_empty() => {
-                        let arg_local = next_local(local_types);
-                        let rec_local = next_local(local_types);
-                        lower_expr_to_local(
-                            receiver_expr,
-                            rec_local,
-                            out,
-                            local_types,
-                            next_local,
-                            deps,
-                            layout_id,
-                            struct_layouts,
-                            func_index,
-                            string_data,
-                            var_to_type,
-                            var_to_local,
-                        )?;
-                        let (mod_name, struct_name) =
-                            resolve_receiver_type(receiver_expr, var_to_type)?;
-                        let key_get = (mod_name.clone(), struct_name.clone(), method.clone(), true);
-                        let &idx = func_index.get(&key_get).ok_or_else(|| {
-                            format!("getter not found: {} for {}", method, struct_name)
-                        })?;
-                        let is_string = is_getter_string(deps, &key_get.0, &key_get.1, method);
-                        if is_string {
-                            let len_local = next_local(local_types);
-                            out.push(IrInstr::LocalGet(rec_local));
-                            out.push(IrInstr::CallStr(idx, arg_local, len_local));
-                            out.push(IrInstr::PrintStr(arg_local, len_local));
-                        } else {
-                            out.push(IrInstr::LocalGet(rec_local));
-                            out.push(IrInstr::Call(idx));
-                            out.push(IrInstr::LocalSet(arg_local));
-                            out.push(IrInstr::PrintInt(arg_local));
+                    Type::Bool => {
+                        let s = self.alloc(&Type::String);
+                        self.bool_to_string(tmp, s);
+                        self.out.push(IrInstr::PrintStr(s, s + 1));
+                    }
+                    _ => self.out.push(IrInstr::PrintInt(tmp)),
+                }
+            }
+            TExprKind::If {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                let c = self.alloc(&cond.ty);
+                self.lower_expr(cond, c)?;
+                self.out.push(IrInstr::LocalGet(c));
+                self.open(IrInstr::If);
+                self.lower_block(then_block, dest)?;
+                if let Some(else_block) = else_block {
+                    self.out.push(IrInstr::Else);
+                    self.lower_block(else_block, dest)?;
+                }
+                self.close();
+            }
+            TExprKind::While { cond, body } => {
+                self.lower_loop(dest, Some(cond), None, body)?;
+            }
+            TExprKind::Loop { body, .. } => {
+                self.lower_loop(dest, None, None, body)?;
+            }
+            TExprKind::ForRange {
+                var,
+                start,
+                end,
+                body,
+            } => {
+                let i = self.local_slot[*var as usize];
+                self.lower_expr(start, i)?;
+                let end_slot = self.alloc(&Type::Int);
+                self.lower_expr(end, end_slot)?;
+                let range = Iteration::Range { i, end: end_slot };
+                self.lower_loop(dest, None, Some(range), body)?;
+            }
+            TExprKind::ForEach { var, list, body } => {
+                let mut list_slot = self.alloc(&list.ty);
+                self.lower_expr(list, list_slot)?;
+                let mut elem = list_elem(&list.ty).clone();
+                if let Some((key, _)) = map_parts(&list.ty) {
+                    // A map or set is iterated over its list of keys.
+                    let keys = self.alloc(&Type::Int);
+                    self.out
+                        .push(IrInstr::StructGet(list_slot, MAP_KEYS_OFFSET, keys));
+                    (list_slot, elem) = (keys, key.clone());
+                }
+                let i = self.alloc(&Type::Int);
+                self.out.push(IrInstr::ConstInt(0));
+                self.out.push(IrInstr::LocalSet(i));
+                let each = Iteration::List {
+                    i,
+                    list: list_slot,
+                    var: self.local_slot[*var as usize],
+                    elem,
+                };
+                self.lower_loop(dest, None, Some(each), body)?;
+            }
+            TExprKind::Break { target, value } => {
+                let frame = &self.loops[*target as usize];
+                let (break_depth, loop_dest) = (frame.break_depth, frame.dest);
+                if let Some(value) = value {
+                    self.lower_expr(value, loop_dest)?;
+                }
+                self.br(break_depth);
+            }
+            TExprKind::Continue(target) => {
+                let continue_depth = self.loops[*target as usize].continue_depth;
+                self.br(continue_depth);
+            }
+            TExprKind::Binary { op, lhs, rhs } => {
+                self.lower_binary(*op, lhs, rhs, dest, expr.span)?
+            }
+            TExprKind::Unary { op, expr: inner } => {
+                let tmp = self.alloc(&inner.ty);
+                self.lower_expr(inner, tmp)?;
+                match op {
+                    UnaryOp::Neg => {
+                        if self.overflow_checks && inner.ty.is_integer() {
+                            let (min, _) = integer_bounds(&inner.ty);
+                            let overflow = self.alloc(&Type::Bool);
+                            self.out.push(IrInstr::LocalGet(tmp));
+                            self.out.push(const_number(min, &inner.ty));
+                            self.out.push(number_op(BinaryOp::Eq, &inner.ty));
+                            self.out.push(IrInstr::LocalSet(overflow));
+                            self.panic_if(overflow, "attempt to negate with overflow", expr.span);
                         }
+                        self.out.push(const_number(0, &inner.ty));
+                        self.out.push(IrInstr::LocalGet(tmp));
+                        self.out.push(number_op(BinaryOp::Sub, &inner.ty));
+                    }
+                    UnaryOp::Not => {
+                        self.out.push(IrInstr::LocalGet(tmp));
+                        self.out.push(IrInstr::Eqz);
+                    }
+                }
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            TExprKind::Assign { target, value } => {
+                // `place.0.1 = v` stores into part of the tuple at `place`.
+                let (place, slot, offset) = tuple_element_place(target);
+                match &place.kind {
+                    TExprKind::Local(id) => {
+                        // Evaluate into a temporary first: the value may read the target.
+                        let tmp = self.alloc(&value.ty);
+                        self.lower_expr(value, tmp)?;
+                        self.settle(*id);
+                        let local = self.local_slot[*id as usize] + slot;
+                        copy_slots(&mut self.out, tmp, local, slot_types(&value.ty).len());
+                    }
+                    TExprKind::Field {
+                        base,
+                        offset: field,
+                    } => {
+                        let tmp = self.alloc(&value.ty);
+                        self.lower_expr(value, tmp)?;
+                        let ptr = self.alloc(&base.ty);
+                        self.lower_expr(base, ptr)?;
+                        store_field(&mut self.out, ptr, field + offset, &value.ty, tmp);
+                    }
+                    TExprKind::Index { base, index } => {
+                        let tmp = self.alloc(&value.ty);
+                        self.lower_expr(value, tmp)?;
+                        let addr = self.lower_element(base, index, target.span)?;
+                        store_field(&mut self.out, addr, offset, &value.ty, tmp);
+                    }
+                    // `*r = v` on a heap value overwrites what `r` points to.
+                    TExprKind::Deref(r) if on_heap(&place.ty) => {
+                        let tmp = self.alloc(&value.ty);
+                        self.lower_expr(value, tmp)?;
+                        let ptr = self.alloc(&r.ty);
+                        self.lower_expr(r, ptr)?;
+                        self.copy_heap_value(tmp, ptr, &place.ty, expr.span)?;
                     }
                     _ => {
-                        let arg_local = next_local(local_types);
-                        lower_expr_to_local(
-                            &args[0],
-                            arg_local,
-                            out,
-                            local_types,
-                            next_local,
-                            deps,
-                            layout_id,
-                            struct_layouts,
-                            func_index,
-                            string_data,
-                            var_to_type,
-                            var_to_local,
-                        )?;
-                        out.push(IrInstr::PrintInt(arg_local));
+                        let tmp = self.alloc(&value.ty);
+                        self.lower_expr(value, tmp)?;
+                        let Some(addr) = self.referent_address(place)? else {
+                            return Err(self.unsupported("assignment target", expr.span));
+                        };
+                        store_field(&mut self.out, addr, offset, &value.ty, tmp);
                     }
                 }
-                return Ok(());
-            }
-
-            if let Some(receiver) = receiver {
-                if name.starts_with("set_") && args.len() == 1 {
-                    let field = name.strip_prefix("set_").unwrap_or(name);
-                    let rec_local = next_local(local_types);
-                    lower_expr_to_local(
-                        receiver,
-                        rec_local,
-                        out,
-                        local_types,
-                        next_local,
-                        deps,
-                        layout_id,
-                        struct_layouts,
-                        func_index,
-                        string_data,
-                        var_to_type,
-                        var_to_local,
-                    )?;
-                    let val_local = next_local(local_types);
-                    lower_expr_to_local(
-                        &args[0],
-                        val_local,
-                        out,
-                        local_types,
-                        next_local,
-                        deps,
-                        layout_id,
-                        struct_layouts,
-                        func_index,
-                        string_data,
-                        var_to_type,
-                        var_to_local,
-                    )?;
-                    let (mod_name, struct_name) = resolve_receiver_type(receiver, var_to_type)?;
-                    let key_set = (mod_name, struct_name.clone(), field.to_string(), false);
-                    let &idx = func_index.get(&key_set).ok_or_else(|| {
-                        format!("setter not found: set_{} for {}", field, struct_name)
-                    })?;
-                    out.push(IrInstr::LocalGet(rec_local));
-                    out.push(IrInstr::LocalGet(val_local));
-                    out.push(IrInstr::Call(idx));
-                    return Ok(());
+            }
+            TExprKind::Unit => {}
+            TExprKind::Block(block) => self.lower_block(block, dest)?,
+            TExprKind::Variant { tag, fields } => {
+                let lid = self.layout_id(&expr.ty)?;
+                self.out.push(IrInstr::StructAlloc(lid));
+                self.out.push(IrInstr::LocalSet(dest));
+                let tag_slot = self.alloc(&Type::Int);
+                self.out.push(IrInstr::ConstInt(*tag as i64));
+                self.out.push(IrInstr::LocalSet(tag_slot));
+                self.out.push(IrInstr::StructSet(dest, 0, tag_slot));
+                for (offset, fexpr) in fields {
+                    let val = self.alloc(&fexpr.ty);
+                    self.lower_expr(fexpr, val)?;
+                    store_field(&mut self.out, dest, *offset, &fexpr.ty, val);
+                }
+            }
+            TExprKind::Try {
+                expr: inner,
+                fail_tag,
+            } => {
+                // Both unions keep the failure payload at the same offset as the function's
+                // return type, so the operand itself is returned on failure.
+                let ptr = self.alloc(&inner.ty);
+                self.lower_expr(inner, ptr)?;
+                let tag = self.alloc(&Type::Int);
+                self.out.push(IrInstr::StructGet(ptr, 0, tag));
+                self.out.push(IrInstr::LocalGet(tag));
+                self.out.push(IrInstr::ConstInt(*fail_tag as i64));
+                self.out.push(IrInstr::Bin(IrBinOp::Eq));
+                self.open(IrInstr::If);
+                self.out.push(IrInstr::ReturnInt(ptr));
+                self.close();
+                load_field(&mut self.out, ptr, payload_offset(&expr.ty), &expr.ty, dest);
+            }
+            TExprKind::Match { value, arms } => {
+                let scrutinee = self.alloc(&value.ty);
+                self.lower_expr(value, scrutinee)?;
+                self.lower_match(scrutinee, &value.ty, arms, dest)?;
+            }
+            TExprKind::List(elements) => {
+                self.out.push(IrInstr::ConstInt(LIST_HEADER_SIZE as i64));
+                self.out.push(IrInstr::Alloc);
+                self.out.push(IrInstr::LocalSet(dest));
+                for e in elements {
+                    let val = self.alloc(&e.ty);
+                    self.lower_expr(e, val)?;
+                    self.push_element(dest, &e.ty, val);
                 }
-                let (mod_name, struct_name) = resolve_receiver_type(receiver, var_to_type)?;
-                let key_get = (mod_name.clone(), struct_name.clone(), name.clone(), true);
-                if let Some(&idx) = func_index.get(&key_get) {
-                    let rec_local = next_local(local_types);
-                    lower_expr_to_local(
-                        receiver,
-                        rec_local,
-                        out,
-                        local_types,
-                        next_local,
-                        deps,
-                        layout_id,
-                        struct_layouts,
-                        func_index,
-                        string_data,
-                        var_to_type,
-                        var_to_local,
-                    )?;
-                    let is_string = is_getter_string(deps, &mod_name, &struct_name, name);
-                    if is_string {
-                        let len_local = next_local(local_types);
-                        out.push(IrInstr::LocalGet(rec_local));
-                        out.push(IrInstr::CallStr(idx, dest_local, len_local));
-                    } else {
-                        out.push(IrInstr::LocalGet(rec_local));
-                        out.push(IrInstr::Call(idx));
-                        out.push(IrInstr::LocalSet(dest_local));
+            }
+            TExprKind::Index { base, index } => {
+                let addr = self.lower_element(base, index, expr.span)?;
+                load_field(&mut self.out, addr, 0, &expr.ty, dest);
+            }
+            // A heap value and a reference to it are both its pointer; a reference to any
+            // other value points to where it is stored.
+            TExprKind::Deref(inner) if on_heap(&expr.ty) => self.lower_expr(inner, dest)?,
+            TExprKind::Ref { expr: inner, .. } if on_heap(&inner.ty) => {
+                self.lower_expr(inner, dest)?
+            }
+            TExprKind::Deref(_) => {
+                let addr = self.referent_address(expr)?.expect("dereference");
+                load_field(&mut self.out, addr, 0, &expr.ty, dest);
+            }
+            TExprKind::Ref { mut_, expr: inner } => self.lower_ref(inner, *mut_, dest)?,
+        }
+        Ok(())
+    }
+
+    /// `push`, `pop`, `len` and `get` on the list in the first argument.
+    /// `pop` and `get` build an `Option`, which starts out zeroed, i.e. `None`.
+    fn lower_list_method(
+        &mut self,
+        method: ListMethod,
+        args: &[TExpr],
+        expr: &TExpr,
+        dest: u32,
+    ) -> Result<(), Diagnostic> {
+        let elem = list_elem(&args[0].ty).clone();
+        let list = self.alloc(&args[0].ty);
+        self.lower_expr(&args[0], list)?;
+        let arg = match args.get(1) {
+            Some(a) => {
+                let slot = self.alloc(&a.ty);
+                self.lower_expr(a, slot)?;
+                slot
+            }
+            None => 0,
+        };
+        match method {
+            ListMethod::Len => self
+                .out
+                .push(IrInstr::StructGet(list, LIST_LEN_OFFSET, dest)),
+            ListMethod::Push => self.push_element(list, &elem, arg),
+            ListMethod::Get => {
+                let lid = self.layout_id(&expr.ty)?;
+                self.out.push(IrInstr::StructAlloc(lid));
+                self.out.push(IrInstr::LocalSet(dest));
+                let len = self.alloc(&Type::Int);
+                self.out
+                    .push(IrInstr::StructGet(list, LIST_LEN_OFFSET, len));
+                self.out.push(IrInstr::LocalGet(arg));
+                self.out.push(IrInstr::LocalGet(len));
+                self.out.push(IrInstr::Bin(IrBinOp::GeU));
+                self.out.push(IrInstr::Eqz);
+                self.open(IrInstr::If);
+                self.store_some(dest, list, arg, &elem);
+                self.close();
+            }
+            ListMethod::Pop => {
+                let lid = self.layout_id(&expr.ty)?;
+                self.out.push(IrInstr::StructAlloc(lid));
+                self.out.push(IrInstr::LocalSet(dest));
+                let len = self.alloc(&Type::Int);
+                self.out
+                    .push(IrInstr::StructGet(list, LIST_LEN_OFFSET, len));
+                self.out.push(IrInstr::LocalGet(len));
+                self.open(IrInstr::If);
+                self.out.push(IrInstr::LocalGet(len));
+                self.out.push(IrInstr::ConstInt(1));
+                self.out.push(IrInstr::Bin(IrBinOp::Sub));
+                self.out.push(IrInstr::LocalSet(len));
+                self.out
+                    .push(IrInstr::StructSet(list, LIST_LEN_OFFSET, len));
+                self.store_some(dest, list, len, &elem);
+                self.close();
+            }
+        }
+        Ok(())
+    }
+
+    /// The built-in string methods, and `to_string()` of an `int` or `bool`, on the first
+    /// argument.
+    fn lower_str_method(
+        &mut self,
+        method: StrMethod,
+        args: &[TExpr],
+        expr: &TExpr,
+        dest: u32,
+    ) -> Result<(), Diagnostic> {
+        let mut slots = Vec::with_capacity(args.len());
+        for a in args {
+            let slot = self.alloc(&a.ty);
+            self.lower_expr(a, slot)?;
+            slots.push(slot);
+        }
+        let s = slots[0];
+        match method {
+            StrMethod::Len => copy_slots(&mut self.out, s + 1, dest, 1),
+            StrMethod::Slice => {
+                // `start <= end <= len`, compared unsigned so a negative offset is out of range.
+                let (start, end) = (slots[1], slots[2]);
+                self.out.push(IrInstr::LocalGet(end));
+                self.out.push(IrInstr::LocalGet(start));
+                self.out.push(IrInstr::Bin(IrBinOp::GeU));
+                self.out.push(IrInstr::LocalGet(s + 1));
+                self.out.push(IrInstr::LocalGet(end));
+                self.out.push(IrInstr::Bin(IrBinOp::GeU));
+                self.out.push(IrInstr::Bin(IrBinOp::And));
+                self.out.push(IrInstr::Eqz);
+                self.open(IrInstr::If);
+                self.panic("string slice out of range", expr.span);
+                self.close();
+                self.string_piece(s, start, end, dest);
+            }
+            StrMethod::Contains => {
+                self.push_value(s, &Type::String);
+                self.push_value(slots[1], &Type::String);
+                self.out.push(IrInstr::ConstInt(0));
+                self.out.push(IrInstr::StrFind);
+                self.out.push(IrInstr::ConstInt(0));
+                self.out.push(IrInstr::Bin(IrBinOp::GeS));
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            StrMethod::Split => self.lower_split(s, slots[1], expr.span, dest),
+            StrMethod::Trim => {
+                self.push_value(s, &Type::String);
+                self.out.push(IrInstr::StrTrim);
+                self.out.push(IrInstr::LocalSet(dest + 1));
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            StrMethod::ParseInt => {
+                // The `Option` starts out zeroed, i.e. `None`.
+                let lid = self.layout_id(&expr.ty)?;
+                self.out.push(IrInstr::StructAlloc(lid));
+                self.out.push(IrInstr::LocalSet(dest));
+                let (ok, value) = (self.alloc(&Type::Bool), self.alloc(&Type::Int));
+                self.push_value(s, &Type::String);
+                self.out.push(IrInstr::StrParseInt);
+                self.out.push(IrInstr::LocalSet(value));
+                self.out.push(IrInstr::LocalSet(ok));
+                self.out.push(IrInstr::LocalGet(ok));
+                self.open(IrInstr::If);
+                self.out.push(IrInstr::StructSet(dest, 0, ok));
+                self.out
+                    .push(IrInstr::StructSet(dest, payload_offset(&Type::Int), value));
+                self.close();
+            }
+            StrMethod::ToString if args[0].ty == Type::Bool => self.bool_to_string(s, dest),
+            StrMethod::ToString => self.number_to_string(s, &args[0].ty, dest),
+        }
+        Ok(())
+    }
+
+    /// `true` or `false` for the `bool` at `src`, into the two slots at `dest`.
+    fn bool_to_string(&mut self, src: u32, dest: u32) {
+        self.out.push(IrInstr::LocalGet(src));
+        self.open(IrInstr::If);
+        self.const_string("true", dest);
+        self.out.push(IrInstr::Else);
+        self.const_string("false", dest);
+        self.close();
+    }
+
+    /// Decimal text of the number of type `ty` at `src`, into the two slots at `dest`.
+    fn number_to_string(&mut self, src: u32, ty: &Type, dest: u32) {
+        self.out.push(IrInstr::LocalGet(src));
+        self.out.push(match ty {
+            Type::I64 => IrInstr::I64ToStr { unsigned: false },
+            Type::U64 => IrInstr::I64ToStr { unsigned: true },
+            Type::F64 => IrInstr::F64ToStr,
+            _ => IrInstr::IntToStr,
+        });
+        self.out.push(IrInstr::LocalSet(dest + 1));
+        self.out.push(IrInstr::LocalSet(dest));
+    }
+
+    /// Store the constant string `value` in the two slots at `dest`. Equal strings (such as the
+    /// uses of a string constant) share one entry of `string_data`.
+    fn const_string(&mut self, value: &str, dest: u32) {
+        let data_id = match self.string_data.iter().position(|s| s == value) {
+            Some(id) => id as u32,
+            None => {
+                self.string_data.push(value.to_string());
+                self.string_data.len() as u32 - 1
+            }
+        };
+        self.out.push(IrInstr::ConstString {
+            ptr_local: dest,
+            len_local: dest + 1,
+            data_id,
+        });
+    }
+
+    /// `s.split(sep)` into a new `List<string>`: the pieces point into `s`, which is never
+    /// mutated. An empty separator panics, since it would match at every offset.
+    fn lower_split(&mut self, s: u32, sep: u32, span: Span, dest: u32) {
+        self.out.push(IrInstr::ConstInt(LIST_HEADER_SIZE as i64));
+        self.out.push(IrInstr::Alloc);
+        self.out.push(IrInstr::LocalSet(dest));
+        self.out.push(IrInstr::LocalGet(sep + 1));
+        self.out.push(IrInstr::Eqz);
+        self.open(IrInstr::If);
+        self.panic("split with an empty separator", span);
+        self.close();
+        let start = self.alloc(&Type::Int);
+        self.out.push(IrInstr::ConstInt(0));
+        self.out.push(IrInstr::LocalSet(start));
+        let at = self.alloc(&Type::Int);
+        let piece = self.alloc(&Type::String);
+        let done = self.open(IrInstr::Block);
+        let top = self.open(IrInstr::Loop);
+        self.push_value(s, &Type::String);
+        self.push_value(sep, &Type::String);
+        self.out.push(IrInstr::LocalGet(start));
+        self.out.push(IrInstr::StrFind);
+        self.out.push(IrInstr::LocalSet(at));
+        self.out.push(IrInstr::LocalGet(at));
+        self.out.push(IrInstr::ConstInt(0));
+        self.out.push(IrInstr::Bin(IrBinOp::LtS));
+        self.out.push(IrInstr::BrIf(self.depth - 1 - done));
+        self.string_piece(s, start, at, piece);
+        self.push_element(dest, &Type::String, piece);
+        self.out.push(IrInstr::LocalGet(at));
+        self.out.push(IrInstr::LocalGet(sep + 1));
+        self.out.push(IrInstr::Bin(IrBinOp::Add));
+        self.out.push(IrInstr::LocalSet(start));
+        self.br(top);
+        self.close();
+        self.close();
+        // What follows the last separator (the whole string if there is none).
+        self.string_piece(s, start, s + 1, piece);
+        self.push_element(dest, &Type::String, piece);
+    }
+
+    /// The bytes of the string at `s` from the offset in `start` up to the offset in `end`.
+    fn string_piece(&mut self, s: u32, start: u32, end: u32, dest: u32) {
+        self.out.push(IrInstr::LocalGet(s));
+        self.out.push(IrInstr::LocalGet(start));
+        self.out.push(IrInstr::Bin(IrBinOp::Add));
+        self.out.push(IrInstr::LocalSet(dest));
+        self.out.push(IrInstr::LocalGet(end));
+        self.out.push(IrInstr::LocalGet(start));
+        self.out.push(IrInstr::Bin(IrBinOp::Sub));
+        self.out.push(IrInstr::LocalSet(dest + 1));
+    }
+
+    /// Append the value at `val` to the list at `list`.
+    fn push_element(&mut self, list: u32, elem: &Type, val: u32) {
+        let addr = self.alloc(&Type::Int);
+        self.out.push(IrInstr::ListPush {
+            list,
+            stride: field_byte_size(elem),
+        });
+        self.out.push(IrInstr::LocalSet(addr));
+        store_field(&mut self.out, addr, 0, elem, val);
+    }
+
+    /// Make the zeroed `Option` at `option` a `Some` holding element `i` of the list at `list`.
+    fn store_some(&mut self, option: u32, list: u32, i: u32, elem: &Type) {
+        let tag = self.alloc(&Type::Int);
+        self.out.push(IrInstr::ConstInt(1));
+        self.out.push(IrInstr::LocalSet(tag));
+        self.out.push(IrInstr::StructSet(option, 0, tag));
+        let addr = self.element_addr(list, i, elem);
+        let val = self.alloc(elem);
+        load_field(&mut self.out, addr, 0, elem, val);
+        store_field(&mut self.out, option, payload_offset(elem), elem, val);
+    }
+
+    /// Slot holding the address of element `i` of the list at `list` (not bounds checked).
+    fn element_addr(&mut self, list: u32, i: u32, elem: &Type) -> u32 {
+        let addr = self.alloc(&Type::Int);
+        self.out
+            .push(IrInstr::StructGet(list, LIST_DATA_OFFSET, addr));
+        self.out.push(IrInstr::LocalGet(addr));
+        self.out.push(IrInstr::LocalGet(i));
+        self.out
+            .push(IrInstr::ConstInt(field_byte_size(elem) as i64));
+        self.out.push(IrInstr::Bin(IrBinOp::Mul));
+        self.out.push(IrInstr::Bin(IrBinOp::Add));
+        self.out.push(IrInstr::LocalSet(addr));
+        addr
+    }
+
+    /// Evaluate `base[index]` to the element's address, panicking when the index is out of
+    /// bounds (a negative index compares as a huge unsigned one).
+    fn lower_element(
+        &mut self,
+        base: &TExpr,
+        index: &TExpr,
+        span: Span,
+    ) -> Result<u32, Diagnostic> {
+        let list = self.alloc(&base.ty);
+        self.lower_expr(base, list)?;
+        let i = self.alloc(&index.ty);
+        self.lower_expr(index, i)?;
+        let len = self.alloc(&Type::Int);
+        self.out
+            .push(IrInstr::StructGet(list, LIST_LEN_OFFSET, len));
+        self.out.push(IrInstr::LocalGet(i));
+        self.out.push(IrInstr::LocalGet(len));
+        self.out.push(IrInstr::Bin(IrBinOp::GeU));
+        self.open(IrInstr::If);
+        self.panic("index out of bounds", span);
+        self.close();
+        Ok(self.element_addr(list, i, list_elem(&base.ty)))
+    }
+
+    /// `Map::new()`/`Set::new()` and the methods of a map or set in the first argument.
+    /// Lookups call the key type's `find` helper; `get` and `remove` build an `Option` that
+    /// starts out `None`.
+    fn lower_map_method(
+        &mut self,
+        method: MapMethod,
+        ops: KeyOps,
+        args: &[TExpr],
+        expr: &TExpr,
+        dest: u32,
+    ) -> Result<(), Diagnostic> {
+        if method == MapMethod::New {
+            let Some((key, value)) = map_parts(&expr.ty) else {
+                return Err(self.unsupported("map constructor", expr.span));
+            };
+            self.out.push(IrInstr::ConstInt(MAP_HEADER_SIZE as i64));
+            self.out.push(IrInstr::Alloc);
+            self.out.push(IrInstr::LocalSet(dest));
+            let list = self.alloc(&Type::Int);
+            let offsets = [Some(MAP_KEYS_OFFSET), value.map(|_| MAP_VALUES_OFFSET)];
+            for offset in offsets.into_iter().flatten() {
+                self.out.push(IrInstr::ConstInt(LIST_HEADER_SIZE as i64));
+                self.out.push(IrInstr::Alloc);
+                self.out.push(IrInstr::LocalSet(list));
+                self.out.push(IrInstr::StructSet(dest, offset, list));
+            }
+            let reindex = self.helpers.id(MapHelper::Reindex, key, ops);
+            self.out.push(IrInstr::LocalGet(dest));
+            self.out
+                .push(IrInstr::ConstInt(MAP_INITIAL_CAPACITY as i64));
+            self.out.push(IrInstr::Call(reindex));
+            return Ok(());
+        }
+        let Some((key, value)) = map_parts(&args[0].ty) else {
+            return Err(self.unsupported("map method", expr.span));
+        };
+        let (key, value) = (key.clone(), value.cloned());
+        let mut slots = Vec::with_capacity(args.len());
+        for a in args {
+            let slot = self.alloc(&a.ty);
+            self.lower_expr(a, slot)?;
+            slots.push(slot);
+        }
+        let map = slots[0];
+        let keys = self.alloc(&Type::Int);
+        self.out
+            .push(IrInstr::StructGet(map, MAP_KEYS_OFFSET, keys));
+        let values = self.alloc(&Type::Int);
+        if value.is_some() {
+            self.out
+                .push(IrInstr::StructGet(map, MAP_VALUES_OFFSET, values));
+        }
+        if method == MapMethod::Len {
+            self.out
+                .push(IrInstr::StructGet(keys, LIST_LEN_OFFSET, dest));
+            return Ok(());
+        }
+        // `slot` is the key's index slot; `entry` its entry number plus one, or 0 if absent.
+        let find = self.helpers.id(MapHelper::Find, &key, ops);
+        self.out.push(IrInstr::LocalGet(map));
+        self.push_value(slots[1], &key);
+        self.out.push(IrInstr::Call(find));
+        let slot = self.alloc(&Type::Int);
+        self.out.push(IrInstr::LocalSet(slot));
+        let entry = self.alloc(&Type::Int);
+        self.out.push(IrInstr::StructGet(slot, 0, entry));
+        let i = self.alloc(&Type::Int);
+        let entry_index = |out: &mut Vec<IrInstr>| {
+            out.push(IrInstr::LocalGet(entry));
+            out.push(IrInstr::ConstInt(1));
+            out.push(IrInstr::Bin(IrBinOp::Sub));
+            out.push(IrInstr::LocalSet(i));
+        };
+        let found = |out: &mut Vec<IrInstr>| {
+            out.push(IrInstr::LocalGet(entry));
+            out.push(IrInstr::ConstInt(0));
+            out.push(IrInstr::Bin(IrBinOp::Ne));
+        };
+        match (method, &value) {
+            (MapMethod::Contains, _) | (MapMethod::Remove, None) => {
+                found(&mut self.out);
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            (MapMethod::Get, _) | (MapMethod::Remove, Some(_)) => {
+                let lid = self.layout_id(&expr.ty)?;
+                self.out.push(IrInstr::StructAlloc(lid));
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            (MapMethod::Insert, Some(_)) if matches!(expr.ty, Type::Option(_)) => {
+                let lid = self.layout_id(&expr.ty)?;
+                self.out.push(IrInstr::StructAlloc(lid));
+                self.out.push(IrInstr::LocalSet(dest));
+            }
+            _ => {}
+        }
+        found(&mut self.out);
+        self.open(IrInstr::If);
+        entry_index(&mut self.out);
+        match method {
+            MapMethod::Get => {
+                if let Some(v) = &value {
+                    self.store_some(dest, values, i, v);
+                }
+            }
+            MapMethod::Insert => {
+                // Present: overwrite the value, handing back the old one if the caller gets it.
+                // Absent: append the entry, then grow the index once it is half full.
+                if let Some(v) = &value {
+                    if matches!(expr.ty, Type::Option(_)) {
+                        self.store_some(dest, values, i, v);
                     }
-                    return Ok(());
+                    let addr = self.element_addr(values, i, v);
+                    store_field(&mut self.out, addr, 0, v, slots[2]);
                 }
+                self.out.push(IrInstr::Else);
+                self.push_element(keys, &key, slots[1]);
+                if let Some(v) = &value {
+                    self.push_element(values, v, slots[2]);
+                }
+                let len = self.alloc(&Type::Int);
+                self.out
+                    .push(IrInstr::StructGet(keys, LIST_LEN_OFFSET, len));
+                self.out.push(IrInstr::StructSet(slot, 0, len));
+                let cap = self.alloc(&Type::Int);
+                self.out.push(IrInstr::StructGet(map, MAP_CAP_OFFSET, cap));
+                self.out.push(IrInstr::LocalGet(len));
+                self.out.push(IrInstr::ConstInt(2));
+                self.out.push(IrInstr::Bin(IrBinOp::Mul));
+                self.out.push(IrInstr::LocalGet(cap));
+                self.out.push(IrInstr::Bin(IrBinOp::GtS));
+                self.open(IrInstr::If);
+                let reindex = self.helpers.id(MapHelper::Reindex, &key, ops);
+                self.out.push(IrInstr::LocalGet(map));
+                self.out.push(IrInstr::LocalGet(cap));
+                self.out.push(IrInstr::ConstInt(2));
+                self.out.push(IrInstr::Bin(IrBinOp::Mul));
+                self.out.push(IrInstr::Call(reindex));
+                self.close();
+            }
+            MapMethod::Remove => {
+                // Removing keeps the insertion order of the other entries, so the index is
+                // rebuilt.
+                if let Some(v) = &value {
+                    self.store_some(dest, values, i, v);
+                    self.out.push(IrInstr::ListRemove {
+                        list: values,
+                        index: i,
+                        stride: field_byte_size(v),
+                    });
+                }
+                self.out.push(IrInstr::ListRemove {
+                    list: keys,
+                    index: i,
+                    stride: field_byte_size(&key),
+                });
+                let reindex = self.helpers.id(MapHelper::Reindex, &key, ops);
+                let cap = self.alloc(&Type::Int);
+                self.out.push(IrInstr::StructGet(map, MAP_CAP_OFFSET, cap));
+                self.out.push(IrInstr::LocalGet(map));
+                self.out.push(IrInstr::LocalGet(cap));
+                self.out.push(IrInstr::Call(reindex));
             }
+            MapMethod::New | MapMethod::Contains | MapMethod::Len => {}
+        }
+        self.close();
+        Ok(())
+    }
 
-            return Err(format!(
-                "unsupported call: {} (receiver: {:?})",
-                name, receiver
-            ));
+    /// Body of the `find` helper (see `MapHelper::Find`): linear probing from the key's hash.
+    fn lower_map_find(&mut self, map: u32, key: &Type, ops: KeyOps, key_slot: u32) {
+        let (index, mask, h) = self.map_probe_start(map, key, ops, key_slot);
+        let keys = self.alloc(&Type::Int);
+        self.out
+            .push(IrInstr::StructGet(map, MAP_KEYS_OFFSET, keys));
+        let slot = self.alloc(&Type::Int);
+        let entry = self.alloc(&Type::Int);
+        let other = self.alloc(key);
+        let done = self.open(IrInstr::Block);
+        let probe = self.open(IrInstr::Loop);
+        self.map_slot_addr(index, h, slot);
+        self.out.push(IrInstr::StructGet(slot, 0, entry));
+        self.out.push(IrInstr::LocalGet(entry));
+        self.out.push(IrInstr::Eqz);
+        self.out.push(IrInstr::BrIf(self.depth - 1 - done));
+        self.out.push(IrInstr::LocalGet(entry));
+        self.out.push(IrInstr::ConstInt(1));
+        self.out.push(IrInstr::Bin(IrBinOp::Sub));
+        self.out.push(IrInstr::LocalSet(entry));
+        let addr = self.element_addr(keys, entry, key);
+        load_field(&mut self.out, addr, 0, key, other);
+        self.keys_equal(key, ops, other, key_slot);
+        self.out.push(IrInstr::BrIf(self.depth - 1 - done));
+        self.map_next_slot(h, mask);
+        self.br(probe);
+        self.close();
+        self.close();
+        self.out.push(IrInstr::ReturnInt(slot));
+    }
+
+    /// Body of the `reindex` helper (see `MapHelper::Reindex`).
+    fn lower_map_reindex(&mut self, map: u32, key: &Type, ops: KeyOps, cap: u32) {
+        self.out.push(IrInstr::StructSet(map, MAP_CAP_OFFSET, cap));
+        let index = self.alloc(&Type::Int);
+        self.out.push(IrInstr::LocalGet(cap));
+        self.out.push(IrInstr::ConstInt(4));
+        self.out.push(IrInstr::Bin(IrBinOp::Mul));
+        self.out.push(IrInstr::Alloc);
+        self.out.push(IrInstr::LocalSet(index));
+        self.out
+            .push(IrInstr::StructSet(map, MAP_INDEX_OFFSET, index));
+        let keys = self.alloc(&Type::Int);
+        self.out
+            .push(IrInstr::StructGet(map, MAP_KEYS_OFFSET, keys));
+        let len = self.alloc(&Type::Int);
+        self.out
+            .push(IrInstr::StructGet(keys, LIST_LEN_OFFSET, len));
+        let i = self.alloc(&Type::Int);
+        self.out.push(IrInstr::ConstInt(0));
+        self.out.push(IrInstr::LocalSet(i));
+        let k = self.alloc(key);
+        let slot = self.alloc(&Type::Int);
+        let entry = self.alloc(&Type::Int);
+        let done = self.open(IrInstr::Block);
+        let next = self.open(IrInstr::Loop);
+        self.out.push(IrInstr::LocalGet(i));
+        self.out.push(IrInstr::LocalGet(len));
+        self.out.push(IrInstr::Bin(IrBinOp::GeS));
+        self.out.push(IrInstr::BrIf(self.depth - 1 - done));
+        let addr = self.element_addr(keys, i, key);
+        load_field(&mut self.out, addr, 0, key, k);
+        let (_, mask, h) = self.map_probe_start(map, key, ops, k);
+        let free = self.open(IrInstr::Block);
+        let probe = self.open(IrInstr::Loop);
+        self.map_slot_addr(index, h, slot);
+        self.out.push(IrInstr::StructGet(slot, 0, entry));
+        self.out.push(IrInstr::LocalGet(entry));
+        self.out.push(IrInstr::Eqz);
+        self.out.push(IrInstr::BrIf(self.depth - 1 - free));
+        self.map_next_slot(h, mask);
+        self.br(probe);
+        self.close();
+        self.close();
+        self.out.push(IrInstr::LocalGet(i));
+        self.out.push(IrInstr::ConstInt(1));
+        self.out.push(IrInstr::Bin(IrBinOp::Add));
+        self.out.push(IrInstr::LocalSet(i));
+        self.out.push(IrInstr::StructSet(slot, 0, i));
+        self.br(next);
+        self.close();
+        self.close();
+        self.out.push(IrInstr::Return);
+    }
+
+    /// Load the map's index and capacity mask and hash the key at `key_slot` into its first
+    /// slot number; returns the (index, mask, slot number) slots.
+    fn map_probe_start(
+        &mut self,
+        map: u32,
+        key: &Type,
+        ops: KeyOps,
+        key_slot: u32,
+    ) -> (u32, u32, u32) {
+        let index = self.alloc(&Type::Int);
+        self.out
+            .push(IrInstr::StructGet(map, MAP_INDEX_OFFSET, index));
+        let mask = self.alloc(&Type::Int);
+        self.out.push(IrInstr::StructGet(map, MAP_CAP_OFFSET, mask));
+        self.out.push(IrInstr::LocalGet(mask));
+        self.out.push(IrInstr::ConstInt(1));
+        self.out.push(IrInstr::Bin(IrBinOp::Sub));
+        self.out.push(IrInstr::LocalSet(mask));
+        // The key's hash, scrambled (Fibonacci hashing, then folding the high bits down) so that
+        // nearby ints spread over the index.
+        let h = self.alloc(&Type::Int);
+        self.push_value(key_slot, key);
+        match ops.hash {
+            Some(hash) => self.out.push(IrInstr::Call(hash)),
+            None => self.builtin_hash(key),
         }
-        _ => return Err(format!("unsupported expression: {:?}", expr)),
+        self.out
+            .push(IrInstr::ConstInt(0x9E37_79B1_u32 as i32 as i64));
+        self.out.push(IrInstr::Bin(IrBinOp::Mul));
+        self.out.push(IrInstr::LocalSet(h));
+        self.out.push(IrInstr::LocalGet(h));
+        self.out.push(IrInstr::LocalGet(h));
+        self.out.push(IrInstr::ConstInt(16));
+        self.out.push(IrInstr::Bin(IrBinOp::ShrU));
+        self.out.push(IrInstr::Bin(IrBinOp::Xor));
+        self.out.push(IrInstr::LocalGet(mask));
+        self.out.push(IrInstr::Bin(IrBinOp::And));
+        self.out.push(IrInstr::LocalSet(h));
+        (index, mask, h)
     }
-    Ok(())
-}
 
-fn resolve_receiver_type(
-    receiver: &Expr,
-    var_to_type: &HashMap<String, VarType>,
-) -> Result<(String, String), String> {
-    match receiver {
-        Expr::Ident { name, .. } => var_to_type.get(name).cloned().ok_or_else(|| {
-            format!(
-                "variable '{}' has unknown type (not a struct literal?)",
-                name
-            )
-        }),
-        _ => Err("receiver must be ident".to_string()),
+    /// `slot = index + h * 4`
+    fn map_slot_addr(&mut self, index: u32, h: u32, slot: u32) {
+        self.out.push(IrInstr::LocalGet(index));
+        self.out.push(IrInstr::LocalGet(h));
+        self.out.push(IrInstr::ConstInt(4));
+        self.out.push(IrInstr::Bin(IrBinOp::Mul));
+        self.out.push(IrInstr::Bin(IrBinOp::Add));
+        self.out.push(IrInstr::LocalSet(slot));
+    }
+
+    /// `h = (h + 1) & mask`
+    fn map_next_slot(&mut self, h: u32, mask: u32) {
+        self.out.push(IrInstr::LocalGet(h));
+        self.out.push(IrInstr::ConstInt(1));
+        self.out.push(IrInstr::Bin(IrBinOp::Add));
+        self.out.push(IrInstr::LocalGet(mask));
+        self.out.push(IrInstr::Bin(IrBinOp::And));
+        self.out.push(IrInstr::LocalSet(h));
+    }
+
+    /// Push 1 if the keys at slots `a` and `b` are equal, else 0.
+    fn keys_equal(&mut self, key: &Type, ops: KeyOps, a: u32, b: u32) {
+        self.push_value(a, key);
+        self.push_value(b, key);
+        match (key, ops.eq) {
+            (Type::String, _) => self.out.push(IrInstr::StrEq),
+            (_, Some(eq)) => self.out.push(IrInstr::Call(eq)),
+            (Type::I64 | Type::U64, _) => self.out.push(IrInstr::BinI64(IrBinOp::Eq)),
+            _ => self.out.push(IrInstr::Bin(IrBinOp::Eq)),
+        }
     }
-}
 
-fn is_getter_string(
-    deps: &[(String, Root)],
-    module: &str,
-    struct_name: &str,
-    field_name: &str,
-) -> bool {
-    for (mod_name, root) in deps.iter() {
-        if mod_name.as_str() != module {
-            continue;
+    /// Replace the value of built-in type `ty` on the stack with its `int` hash: a string's
+    /// bytes are hashed, a 64-bit integer has its high half folded into the low one, and any
+    /// other value is its own hash.
+    fn builtin_hash(&mut self, ty: &Type) {
+        match ty {
+            Type::String => self.out.push(IrInstr::StrHash),
+            Type::I64 | Type::U64 => {
+                let v = self.alloc(ty);
+                self.out.push(IrInstr::LocalSet(v));
+                self.out.push(IrInstr::LocalGet(v));
+                self.out.push(IrInstr::LocalGet(v));
+                self.out.push(IrInstr::ConstI64(32));
+                self.out.push(IrInstr::BinI64(IrBinOp::ShrU));
+                self.out.push(IrInstr::BinI64(IrBinOp::Xor));
+                self.out.push(IrInstr::Cast {
+                    from: Type::I64,
+                    to: Type::Int,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Layout id of a struct or tagged union type.
+    fn layout_id(&mut self, ty: &Type) -> Result<u32, Diagnostic> {
+        self.layouts
+            .id(ty)
+            .ok_or_else(|| Diagnostic::error(format!("layout not found for {}", ty), None))
+    }
+
+    /// Arms are tried in order and a failing arm branches to the next one:
+    /// `block $end { block $fail { block $arm_n-1 .. { block $arm_0 { dispatch } arm 0 } .. arm n-1 } unreachable }`,
+    /// where leaving block `$arm_i` starts arm i. On a tagged union the dispatch is a `br_table`
+    /// on the tag that jumps straight to the first arm able to match it.
+    fn lower_match(
+        &mut self,
+        scrutinee: u32,
+        ty: &Type,
+        arms: &[TArm],
+        dest: u32,
+    ) -> Result<(), Diagnostic> {
+        let end = self.open(IrInstr::Block);
+        let fail = self.open(IrInstr::Block);
+        let mut starts: Vec<u32> = (0..arms.len()).map(|_| self.open(IrInstr::Block)).collect();
+        starts.reverse();
+        if let Some(max_tag) = arms.iter().filter_map(|a| max_tag(&a.pattern)).max() {
+            let first_arm = |tag: Option<u32>| {
+                arms.iter()
+                    .position(|a| admits(&a.pattern, tag))
+                    .map_or(fail, |i| starts[i])
+            };
+            let targets = (0..=max_tag)
+                .map(|t| self.depth - 1 - first_arm(Some(t)))
+                .collect();
+            let default = self.depth - 1 - first_arm(None);
+            let tag = self.alloc(&Type::Int);
+            self.out.push(IrInstr::StructGet(scrutinee, 0, tag));
+            self.out.push(IrInstr::LocalGet(tag));
+            self.out.push(IrInstr::BrTable { targets, default });
+        }
+        for (i, arm) in arms.iter().enumerate() {
+            self.close();
+            let next = starts.get(i + 1).copied().unwrap_or(fail);
+            self.lower_pattern(&arm.pattern, scrutinee, ty, next, arm.body.span)?;
+            if let Some(guard) = &arm.guard {
+                let c = self.alloc(&Type::Bool);
+                self.lower_expr(guard, c)?;
+                self.out.push(IrInstr::LocalGet(c));
+                self.out.push(IrInstr::Eqz);
+                self.out.push(IrInstr::BrIf(self.depth - 1 - next));
+            }
+            self.lower_expr(&arm.body, dest)?;
+            self.br(end);
+        }
+        self.close();
+        // The checker proved the arms exhaustive.
+        self.out.push(IrInstr::Unreachable);
+        self.close();
+        Ok(())
+    }
+
+    /// Test the value at `src` (of type `ty`) against `pat`, branching to the block opened at
+    /// `fail` depth when it does not match. Bindings are assigned as they are reached.
+    fn lower_pattern(
+        &mut self,
+        pat: &TPattern,
+        src: u32,
+        ty: &Type,
+        fail: u32,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        match pat {
+            TPattern::Wildcard => {}
+            TPattern::Binding(id) => {
+                self.settle(*id);
+                let slot = self.local_slot[*id as usize];
+                for i in 0..slot_types(ty).len() as u32 {
+                    self.out.push(IrInstr::LocalGet(src + i));
+                    self.out.push(IrInstr::LocalSet(slot + i));
+                }
+            }
+            TPattern::Int(n) => self.branch_unless_eq(src, *n, ty, fail),
+            TPattern::Bool(b) => self.branch_unless_eq(src, *b as i64, ty, fail),
+            TPattern::Str(value) => {
+                let lit = self.alloc(&Type::String);
+                self.const_string(value, lit);
+                self.push_value(src, ty);
+                self.push_value(lit, ty);
+                self.out.push(IrInstr::StrEq);
+                self.out.push(IrInstr::Eqz);
+                self.out.push(IrInstr::BrIf(self.depth - 1 - fail));
+            }
+            TPattern::Range { start, end } => {
+                for (bound, op) in [(*start, BinaryOp::Lt), (*end, BinaryOp::Gt)] {
+                    self.out.push(IrInstr::LocalGet(src));
+                    self.out.push(const_number(bound, ty));
+                    self.out.push(number_op(op, ty));
+                    self.out.push(IrInstr::BrIf(self.depth - 1 - fail));
+                }
+            }
+            TPattern::Variant { tag, fields } => {
+                let tag_slot = self.alloc(&Type::Int);
+                self.out.push(IrInstr::StructGet(src, 0, tag_slot));
+                self.branch_unless_eq(tag_slot, *tag as i64, &Type::Int, fail);
+                self.lower_field_patterns(fields, src, fail, span)?;
+            }
+            TPattern::Struct { fields } => self.lower_field_patterns(fields, src, fail, span)?,
+            TPattern::Tuple(subs) => {
+                let Type::Tuple(elems) = ty else {
+                    return Err(self.unsupported("tuple pattern", span));
+                };
+                for ((sub, ety), slot) in subs.iter().zip(elems).zip(element_slots(src, elems)) {
+                    self.lower_pattern(sub, slot, ety, fail, span)?;
+                }
+            }
+            TPattern::Or(alts) => {
+                // `block $ok { block $next { alt 0 → br $ok } .. last alt }`
+                let ok = self.open(IrInstr::Block);
+                if let Some((last, rest)) = alts.split_last() {
+                    for alt in rest {
+                        let next = self.open(IrInstr::Block);
+                        self.lower_pattern(alt, src, ty, next, span)?;
+                        self.br(ok);
+                        self.close();
+                    }
+                    self.lower_pattern(last, src, ty, fail, span)?;
+                }
+                self.close();
+            }
+        }
+        Ok(())
+    }
+
+    /// Match each (offset, type, pattern) field of the struct or union at `ptr`.
+    fn lower_field_patterns(
+        &mut self,
+        fields: &[(u32, Type, TPattern)],
+        ptr: u32,
+        fail: u32,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        for (offset, fty, sub) in fields {
+            if matches!(sub, TPattern::Wildcard) {
+                continue;
+            }
+            let val = self.alloc(fty);
+            load_field(&mut self.out, ptr, *offset, fty, val);
+            self.lower_pattern(sub, val, fty, fail, span)?;
         }
-        for item in &root.items {
-            if let Item::Struct(s) = item {
-                if s.name == struct_name {
-                    for f in &s.fields {
-                        if f.name == field_name {
-                            return matches!(f.ty, Type::String);
+        Ok(())
+    }
+
+    /// Branch to `target` depth unless the value of type `ty` at `slot` equals `value`.
+    fn branch_unless_eq(&mut self, slot: u32, value: i64, ty: &Type, target: u32) {
+        self.out.push(IrInstr::LocalGet(slot));
+        self.out.push(const_number(value, ty));
+        self.out.push(number_op(BinaryOp::Ne, ty));
+        self.out.push(IrInstr::BrIf(self.depth - 1 - target));
+    }
+
+    /// Every loop has the shape
+    /// `block { loop { [exit test] block { body } [i += 1] br loop } }`:
+    /// `break` exits the outer block, `continue` exits the body block.
+    /// `cond` is a `while` condition; `each` is how a `for` loop steps (a list element is
+    /// loaded into the loop variable before the body).
+    fn lower_loop(
+        &mut self,
+        dest: u32,
+        cond: Option<&TExpr>,
+        each: Option<Iteration>,
+        body: &TBlock,
+    ) -> Result<(), Diagnostic> {
+        let break_depth = self.open(IrInstr::Block);
+        let top = self.open(IrInstr::Loop);
+        if let Some(cond) = cond {
+            let c = self.alloc(&Type::Bool);
+            self.lower_expr(cond, c)?;
+            self.out.push(IrInstr::LocalGet(c));
+            self.out.push(IrInstr::Eqz);
+            self.out.push(IrInstr::BrIf(self.depth - 1 - break_depth));
+        }
+        let counter = match &each {
+            Some(Iteration::Range { i, end }) => {
+                self.out.push(IrInstr::LocalGet(*i));
+                self.out.push(IrInstr::LocalGet(*end));
+                self.out.push(IrInstr::Bin(IrBinOp::GeS));
+                self.out.push(IrInstr::BrIf(self.depth - 1 - break_depth));
+                Some(*i)
+            }
+            Some(Iteration::List { i, list, var, elem }) => {
+                // The length is read every iteration: the body may push or pop.
+                let len = self.alloc(&Type::Int);
+                self.out
+                    .push(IrInstr::StructGet(*list, LIST_LEN_OFFSET, len));
+                self.out.push(IrInstr::LocalGet(*i));
+                self.out.push(IrInstr::LocalGet(len));
+                self.out.push(IrInstr::Bin(IrBinOp::GeS));
+                self.out.push(IrInstr::BrIf(self.depth - 1 - break_depth));
+                let addr = self.element_addr(*list, *i, elem);
+                load_field(&mut self.out, addr, 0, elem, *var);
+                Some(*i)
+            }
+            None => None,
+        };
+        let continue_depth = self.open(IrInstr::Block);
+        self.loops.push(LoopFrame {
+            break_depth,
+            continue_depth,
+            dest,
+        });
+        let body_value = self.alloc(&block_type(body));
+        self.lower_block(body, body_value)?;
+        self.loops.pop();
+        self.close();
+        if let Some(i) = counter {
+            self.out.push(IrInstr::LocalGet(i));
+            self.out.push(IrInstr::ConstInt(1));
+            self.out.push(IrInstr::Bin(IrBinOp::Add));
+            self.out.push(IrInstr::LocalSet(i));
+        }
+        self.br(top);
+        self.close();
+        self.close();
+        Ok(())
+    }
+
+    /// `&&` and `||` short-circuit: the right operand is only evaluated inside an `If`.
+    /// Integer arithmetic panics on overflow (unless checks are off) and on division by zero.
+    fn lower_binary(
+        &mut self,
+        op: BinaryOp,
+        lhs: &TExpr,
+        rhs: &TExpr,
+        dest: u32,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        if matches!(op, BinaryOp::And | BinaryOp::Or) {
+            self.lower_expr(lhs, dest)?;
+            self.out.push(IrInstr::LocalGet(dest));
+            if op == BinaryOp::Or {
+                self.out.push(IrInstr::Eqz);
+            }
+            self.open(IrInstr::If);
+            self.lower_expr(rhs, dest)?;
+            self.close();
+            return Ok(());
+        }
+        if lhs.ty == Type::String {
+            let l = self.alloc(&lhs.ty);
+            self.lower_expr(lhs, l)?;
+            let r = self.alloc(&rhs.ty);
+            self.lower_expr(rhs, r)?;
+            self.push_value(l, &lhs.ty);
+            self.push_value(r, &rhs.ty);
+            match op {
+                BinaryOp::Add => {
+                    self.out.push(IrInstr::StrConcat);
+                    self.out.push(IrInstr::LocalSet(dest + 1));
+                }
+                BinaryOp::Eq => self.out.push(IrInstr::StrEq),
+                BinaryOp::Ne => {
+                    self.out.push(IrInstr::StrEq);
+                    self.out.push(IrInstr::Eqz);
+                }
+                _ => {
+                    // Compare the -1/0/1 ordering with 0 as two ints would be compared.
+                    self.out.push(IrInstr::StrCmp);
+                    self.out.push(IrInstr::ConstInt(0));
+                    self.out.push(IrInstr::Bin(int_op(op)));
+                }
+            }
+            self.out.push(IrInstr::LocalSet(dest));
+            return Ok(());
+        }
+        let l = self.alloc(&lhs.ty);
+        self.lower_expr(lhs, l)?;
+        let r = self.alloc(&rhs.ty);
+        self.lower_expr(rhs, r)?;
+        if lhs.ty.is_integer() {
+            match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                    let overflow = self.wrapping_arith(op, &lhs.ty, l, r, dest);
+                    if self.overflow_checks {
+                        self.panic_if(overflow, overflow_message(op), span);
+                    }
+                    return Ok(());
+                }
+                BinaryOp::Div | BinaryOp::Rem => self.check_divisor(op, &lhs.ty, l, r, span),
+                _ => {}
+            }
+        }
+        self.binary_into(number_op(op, &lhs.ty), l, r, dest);
+        Ok(())
+    }
+
+    /// `l op r` for `+`, `-` or `*` on integers of type `ty`, wrapped to the type, into `dest`.
+    /// Returns a `bool` slot that is set when the exact result does not fit.
+    fn wrapping_arith(&mut self, op: BinaryOp, ty: &Type, l: u32, r: u32, dest: u32) -> u32 {
+        let overflow = self.alloc(&Type::Bool);
+        match (ty, op) {
+            (Type::U8, _) => {
+                // Exact in an i32; out of range (or negative) means overflow.
+                let exact = self.alloc(&Type::Int);
+                self.binary_into(IrInstr::Bin(int_op(op)), l, r, exact);
+                self.out.push(IrInstr::ConstInt(255));
+                self.out.push(IrInstr::LocalSet(dest));
+                self.binary_into(IrInstr::Bin(IrBinOp::GtU), exact, dest, overflow);
+                self.binary_into(IrInstr::Bin(IrBinOp::And), exact, dest, dest);
+            }
+            (Type::Int, BinaryOp::Mul) => {
+                // The exact product of two i32s fits in an i64.
+                let wide = self.alloc(&Type::I64);
+                let widen = || IrInstr::Cast {
+                    from: Type::Int,
+                    to: Type::I64,
+                };
+                self.out.push(IrInstr::LocalGet(l));
+                self.out.push(widen());
+                self.out.push(IrInstr::LocalGet(r));
+                self.out.push(widen());
+                self.out.push(IrInstr::BinI64(IrBinOp::Mul));
+                self.out.push(IrInstr::LocalSet(wide));
+                self.out.push(IrInstr::LocalGet(wide));
+                self.out.push(IrInstr::Cast {
+                    from: Type::I64,
+                    to: Type::Int,
+                });
+                self.out.push(IrInstr::LocalSet(dest));
+                self.out.push(IrInstr::LocalGet(dest));
+                self.out.push(widen());
+                self.out.push(IrInstr::LocalGet(wide));
+                self.out.push(IrInstr::BinI64(IrBinOp::Ne));
+                self.out.push(IrInstr::LocalSet(overflow));
+            }
+            (_, BinaryOp::Mul) => {
+                // A 64-bit product overflowed if dividing it by a non-zero `l` does not give
+                // `r` back. For `i64`, `l = -1` only overflows with `r = MIN` (and `MIN / -1`
+                // would trap), so it is tested on its own.
+                self.binary_into(number_op(op, ty), l, r, dest);
+                self.out.push(IrInstr::ConstInt(0));
+                self.out.push(IrInstr::LocalSet(overflow));
+                self.out.push(IrInstr::LocalGet(l));
+                self.out.push(const_number(0, ty));
+                self.out.push(number_op(BinaryOp::Ne, ty));
+                self.open(IrInstr::If);
+                if *ty == Type::I64 {
+                    self.out.push(IrInstr::LocalGet(l));
+                    self.out.push(IrInstr::ConstI64(-1));
+                    self.out.push(IrInstr::BinI64(IrBinOp::Eq));
+                    self.open(IrInstr::If);
+                    self.out.push(IrInstr::LocalGet(r));
+                    self.out.push(IrInstr::ConstI64(i64::MIN));
+                    self.out.push(IrInstr::BinI64(IrBinOp::Eq));
+                    self.out.push(IrInstr::LocalSet(overflow));
+                    self.out.push(IrInstr::Else);
+                }
+                self.out.push(IrInstr::LocalGet(dest));
+                self.out.push(IrInstr::LocalGet(l));
+                self.out.push(number_op(BinaryOp::Div, ty));
+                self.out.push(IrInstr::LocalGet(r));
+                self.out.push(number_op(BinaryOp::Ne, ty));
+                self.out.push(IrInstr::LocalSet(overflow));
+                if *ty == Type::I64 {
+                    self.close();
+                }
+                self.close();
+            }
+            (Type::U64, BinaryOp::Add) => {
+                self.binary_into(number_op(op, ty), l, r, dest);
+                self.binary_into(number_op(BinaryOp::Lt, ty), dest, l, overflow);
+            }
+            (Type::U64, _) => {
+                self.binary_into(number_op(op, ty), l, r, dest);
+                self.binary_into(number_op(BinaryOp::Lt, ty), l, r, overflow);
+            }
+            _ => {
+                // Signed: `l + r` overflowed iff its sign differs from both operands' signs,
+                // `l - r` iff `l` and `r` differ in sign and the result's sign differs from `l`'s.
+                self.binary_into(number_op(op, ty), l, r, dest);
+                let (a, b) = match op {
+                    BinaryOp::Add => ((l, dest), (r, dest)),
+                    _ => ((l, r), (l, dest)),
+                };
+                for (x, y) in [a, b] {
+                    self.out.push(IrInstr::LocalGet(x));
+                    self.out.push(IrInstr::LocalGet(y));
+                    self.out.push(bits_op(IrBinOp::Xor, ty));
+                }
+                self.out.push(bits_op(IrBinOp::And, ty));
+                self.out.push(const_number(0, ty));
+                self.out.push(number_op(BinaryOp::Lt, ty));
+                self.out.push(IrInstr::LocalSet(overflow));
+            }
+        }
+        overflow
+    }
+
+    /// Panic on `x / 0` and `x % 0`, and on `MIN / -1` (or `MIN % -1`) for a signed type,
+    /// whose quotient does not fit.
+    fn check_divisor(&mut self, op: BinaryOp, ty: &Type, l: u32, r: u32, span: Span) {
+        let (by_zero, overflow) = match op {
+            BinaryOp::Div => (
+                "attempt to divide by zero",
+                "attempt to divide with overflow",
+            ),
+            _ => (
+                "attempt to calculate the remainder with a divisor of zero",
+                "attempt to calculate the remainder with overflow",
+            ),
+        };
+        let flag = self.alloc(&Type::Bool);
+        self.out.push(IrInstr::LocalGet(r));
+        self.out.push(const_number(0, ty));
+        self.out.push(number_op(BinaryOp::Eq, ty));
+        self.out.push(IrInstr::LocalSet(flag));
+        self.panic_if(flag, by_zero, span);
+        if !ty.is_unsigned() {
+            let (min, _) = integer_bounds(ty);
+            self.out.push(IrInstr::LocalGet(l));
+            self.out.push(const_number(min, ty));
+            self.out.push(number_op(BinaryOp::Eq, ty));
+            self.out.push(IrInstr::LocalGet(r));
+            self.out.push(const_number(-1, ty));
+            self.out.push(number_op(BinaryOp::Eq, ty));
+            self.out.push(IrInstr::Bin(IrBinOp::And));
+            self.out.push(IrInstr::LocalSet(flag));
+            self.panic_if(flag, overflow, span);
+        }
+    }
+
+    /// `wrapping_*`, `checked_*` and `saturating_*` on the integer in the first argument.
+    fn lower_num_method(
+        &mut self,
+        method: NumMethod,
+        args: &[TExpr],
+        expr: &TExpr,
+        dest: u32,
+    ) -> Result<(), Diagnostic> {
+        let ty = &args[0].ty;
+        let (l, r) = (self.alloc(ty), self.alloc(ty));
+        self.lower_expr(&args[0], l)?;
+        self.lower_expr(&args[1], r)?;
+        match method {
+            NumMethod::Wrapping(op) => {
+                self.wrapping_arith(op, ty, l, r, dest);
+            }
+            NumMethod::Checked(op) => {
+                // The `Option` starts out zeroed, i.e. `None`.
+                let lid = self.layout_id(&expr.ty)?;
+                self.out.push(IrInstr::StructAlloc(lid));
+                self.out.push(IrInstr::LocalSet(dest));
+                let value = self.alloc(ty);
+                let overflow = self.wrapping_arith(op, ty, l, r, value);
+                self.out.push(IrInstr::LocalGet(overflow));
+                self.out.push(IrInstr::Eqz);
+                self.open(IrInstr::If);
+                let tag = self.alloc(&Type::Int);
+                self.out.push(IrInstr::ConstInt(1));
+                self.out.push(IrInstr::LocalSet(tag));
+                self.out.push(IrInstr::StructSet(dest, 0, tag));
+                store_field(&mut self.out, dest, payload_offset(ty), ty, value);
+                self.close();
+            }
+            NumMethod::Saturating(op) => {
+                let overflow = self.wrapping_arith(op, ty, l, r, dest);
+                let (min, max) = integer_bounds(ty);
+                self.out.push(IrInstr::LocalGet(overflow));
+                self.open(IrInstr::If);
+                if ty.is_unsigned() {
+                    let bound = if op == BinaryOp::Sub { min } else { max };
+                    self.out.push(const_number(bound, ty));
+                    self.out.push(IrInstr::LocalSet(dest));
+                } else {
+                    // The exact result is below `MIN` when `r < 0` for `+`, `r > 0` for `-`,
+                    // and the operands' signs differ for `*`.
+                    match op {
+                        BinaryOp::Add | BinaryOp::Sub => {
+                            let below = if op == BinaryOp::Add {
+                                BinaryOp::Lt
+                            } else {
+                                BinaryOp::Gt
+                            };
+                            self.out.push(IrInstr::LocalGet(r));
+                            self.out.push(const_number(0, ty));
+                            self.out.push(number_op(below, ty));
+                        }
+                        _ => {
+                            self.out.push(IrInstr::LocalGet(l));
+                            self.out.push(IrInstr::LocalGet(r));
+                            self.out.push(bits_op(IrBinOp::Xor, ty));
+                            self.out.push(const_number(0, ty));
+                            self.out.push(number_op(BinaryOp::Lt, ty));
                         }
                     }
+                    self.open(IrInstr::If);
+                    self.out.push(const_number(min, ty));
+                    self.out.push(IrInstr::LocalSet(dest));
+                    self.out.push(IrInstr::Else);
+                    self.out.push(const_number(max, ty));
+                    self.out.push(IrInstr::LocalSet(dest));
+                    self.close();
                 }
+                self.close();
             }
         }
+        Ok(())
     }
-    false
 }
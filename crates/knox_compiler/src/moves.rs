@@ -0,0 +1,874 @@
+//! Move checking, on the typed program. A struct that does not implement `Copy`, or a tuple,
+//! `Option`, `Result` or enum holding one (see `is_moved`), is never copied: using it by value
+//! moves it out of its local, which cannot be used again unless it is assigned anew, and it
+//! cannot be moved out of a field, element or reference. A value holding a resource (see `holds_resource`) is moved the same way,
+//! and is never dropped either: by the end of its scope, and before a `return`, `break`,
+//! `continue` or `?` leaves that scope, a local holding a resource must have been moved away —
+//! passed to a function, returned, stored in a struct, list or variant, or destructured.
+
+use crate::typed::{
+    holds_resource, is_moved, Callee, ListMethod, LocalId, MapMethod, TBlock, TExpr, TExprKind,
+    TFunction, TPattern, TStmt, TypedProgram,
+};
+use knox_syntax::ast::Type;
+use knox_syntax::span::{Location, Span};
+use knox_syntax::Diagnostic;
+use std::collections::HashSet;
+
+/// Check every function of the program; all violations are reported.
+pub fn check_moves(program: &TypedProgram) -> Result<(), Vec<Diagnostic>> {
+    let mut diags = Vec::new();
+    for f in &program.functions {
+        let mut checker = MoveChecker {
+            program,
+            f,
+            state: Some(vec![Status::Live; f.locals.len()]),
+            scope: Vec::new(),
+            loops: Vec::new(),
+            diags: Vec::new(),
+            reported: HashSet::new(),
+        };
+        checker.function();
+        diags.extend(checker.diags);
+    }
+    if diags.is_empty() {
+        Ok(())
+    } else {
+        Err(diags)
+    }
+}
+
+/// What has happened to a local's value on the paths reaching a point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Status {
+    /// The local holds its value.
+    Live,
+    /// Moved away on every path; the span is one of the moves.
+    Moved(Span),
+    /// Moved away on some paths only.
+    MaybeMoved(Span),
+}
+
+/// The status of each local, or None where no path reaches (after `return`, `break`, ...).
+type State = Option<Vec<Status>>;
+
+/// The state where paths with states `a` and `b` join.
+fn merge(a: State, b: State) -> State {
+    let (Some(a), Some(b)) = (&a, &b) else {
+        return a.or(b);
+    };
+    let joined = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| match (*x, *y) {
+            (Status::Live, Status::Live) => Status::Live,
+            (Status::Moved(s), Status::Moved(_)) => Status::Moved(s),
+            (Status::Moved(s) | Status::MaybeMoved(s), _)
+            | (_, Status::Moved(s) | Status::MaybeMoved(s)) => Status::MaybeMoved(s),
+        })
+        .collect();
+    Some(joined)
+}
+
+struct LoopFrame {
+    /// Length of `MoveChecker::scope` when the loop was entered.
+    mark: usize,
+    /// States at the loop's `break`s and `continue`s.
+    breaks: State,
+    continues: State,
+    /// The collection of resources a `for` loop consumes, which it must visit to the end.
+    consumes: Option<Type>,
+}
+
+struct MoveChecker<'a> {
+    program: &'a TypedProgram,
+    f: &'a TFunction,
+    state: State,
+    /// Locals holding resources that are in scope, innermost last.
+    scope: Vec<LocalId>,
+    /// Enclosing loops, outermost first; indexed by `LoopId`.
+    loops: Vec<LoopFrame>,
+    diags: Vec<Diagnostic>,
+    /// (span, message) of each error, so a loop checked twice reports it once.
+    reported: HashSet<(u32, u32, String)>,
+}
+
+impl MoveChecker<'_> {
+    fn function(&mut self) {
+        for id in 0..self.f.param_count {
+            self.bind_local(id);
+        }
+        let body = &self.f.body;
+        for stmt in &body.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(value) = &body.value {
+            self.expr(value);
+        }
+        self.end_scope(0);
+    }
+
+    fn report(&mut self, msg: String, span: Span, note: Option<(String, Span)>) {
+        if !self.reported.insert((span.start, span.end, msg.clone())) {
+            return;
+        }
+        let mut diag = Diagnostic::error(msg, Some(Location::new(self.f.file, span)));
+        if let Some((note, at)) = note {
+            diag = diag.with_note(note, Location::new(self.f.file, at));
+        }
+        self.diags.push(diag);
+    }
+
+    fn holds_resource(&self, ty: &Type) -> bool {
+        holds_resource(&self.program.resources, ty)
+    }
+
+    /// True if using a value of type `ty` moves it (see `is_moved`).
+    fn moves(&self, ty: &Type) -> bool {
+        is_moved(&self.program.moved_types, &self.program.resources, ty)
+    }
+
+    /// True if matching `pattern` moves the value out: it binds a value that moves.
+    fn binds_moved(&self, pattern: &TPattern) -> bool {
+        match pattern {
+            TPattern::Binding(id) => self.moves(&self.f.locals[*id as usize].ty),
+            TPattern::Variant { fields, .. } | TPattern::Struct { fields } => {
+                fields.iter().any(|(_, _, p)| self.binds_moved(p))
+            }
+            TPattern::Or(pats) | TPattern::Tuple(pats) => pats.iter().any(|p| self.binds_moved(p)),
+            _ => false,
+        }
+    }
+
+    /// Evaluate a value that `patterns` are matched against. Unless it is a resource, it stays
+    /// where it is when the patterns only bind values that are copied.
+    fn scrutinee<'p>(&mut self, value: &TExpr, mut patterns: impl Iterator<Item = &'p TPattern>) {
+        if self.holds_resource(&value.ty) || patterns.any(|p| self.binds_moved(p)) {
+            self.expr(value);
+        } else {
+            self.inspect(value);
+        }
+    }
+
+    fn status(&self, id: LocalId) -> Option<Status> {
+        self.state.as_ref().map(|s| s[id as usize])
+    }
+
+    fn set_status(&mut self, id: LocalId, status: Status) {
+        if let Some(state) = &mut self.state {
+            state[id as usize] = status;
+        }
+    }
+
+    /// The local now holds a value; if it is a resource, it must be consumed in this scope.
+    fn bind_local(&mut self, id: LocalId) {
+        self.set_status(id, Status::Live);
+        if self.holds_resource(&self.f.locals[id as usize].ty) && !self.scope.contains(&id) {
+            self.scope.push(id);
+        }
+    }
+
+    fn bind(&mut self, pattern: &TPattern) {
+        match pattern {
+            TPattern::Binding(id) => self.bind_local(*id),
+            TPattern::Variant { fields, .. } | TPattern::Struct { fields } => {
+                fields.iter().for_each(|(_, _, p)| self.bind(p))
+            }
+            TPattern::Or(pats) | TPattern::Tuple(pats) => pats.iter().for_each(|p| self.bind(p)),
+            _ => {}
+        }
+    }
+
+    /// Report the resources in scope since `mark` that a jump at `span` (`what`) would drop.
+    fn check_exit(&mut self, mark: usize, span: Span, what: &str) {
+        let in_scope = self.scope[mark..].to_vec();
+        for id in in_scope {
+            if matches!(self.status(id), Some(Status::Live | Status::MaybeMoved(_))) {
+                let local = &self.f.locals[id as usize];
+                let note = (format!("`{}` is bound here", local.name), local.span);
+                self.report(
+                    format!("resource `{}` is not consumed before {}", local.name, what),
+                    span,
+                    Some(note),
+                );
+            }
+        }
+    }
+
+    /// An exit at `span` leaves the loops from `from` outwards; one that consumes a collection
+    /// of resources would drop the items it has not reached.
+    fn check_loop_exit(&mut self, from: usize, span: Span, what: &str) {
+        let consumed: Vec<_> = self.loops[from..]
+            .iter()
+            .filter_map(|l| l.consumes.clone())
+            .collect();
+        for ty in consumed {
+            self.report(
+                format!(
+                    "{} leaves a `for` loop that consumes `{}`, dropping the resources it has not reached",
+                    what, ty
+                ),
+                span,
+                None,
+            );
+        }
+    }
+
+    /// The locals bound since `mark` go out of scope; the resources among them must be gone.
+    fn end_scope(&mut self, mark: usize) {
+        for id in self.scope.split_off(mark) {
+            let local = &self.f.locals[id as usize];
+            match self.status(id) {
+                Some(Status::Live) => self.report(
+                    format!(
+                        "resource `{}` is never consumed: move, destructure or store it before the end of its scope",
+                        local.name
+                    ),
+                    local.span,
+                    None,
+                ),
+                Some(Status::MaybeMoved(at)) => self.report(
+                    format!(
+                        "resource `{}` is not consumed on every path to the end of its scope",
+                        local.name
+                    ),
+                    local.span,
+                    Some((format!("`{}` is only moved here on some paths", local.name), at)),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    /// A use of local `id` at `span`, which moves its value if `moves`.
+    fn use_local(&mut self, id: LocalId, span: Span, moves: bool) {
+        if let Some(Status::Moved(at) | Status::MaybeMoved(at)) = self.status(id) {
+            let name = &self.f.locals[id as usize].name;
+            let note = if at.start >= span.start {
+                "value moved here, in the previous iteration of the loop"
+            } else {
+                "value moved here"
+            };
+            self.report(
+                format!("use of moved value `{}`", name),
+                span,
+                Some((note.to_string(), at)),
+            );
+        }
+        if moves {
+            self.set_status(id, Status::Moved(span));
+        }
+    }
+
+    fn dropped(&mut self, e: &TExpr) {
+        self.report(
+            format!(
+                "a resource (`{}`) is dropped here: move, destructure or store it",
+                e.ty
+            ),
+            e.span,
+            None,
+        );
+    }
+
+    fn stmt(&mut self, stmt: &TStmt) {
+        match stmt {
+            TStmt::Let { pattern, init } => {
+                self.scrutinee(init, std::iter::once(pattern));
+                self.bind(pattern);
+            }
+            TStmt::Expr(e) => {
+                self.expr(e);
+                if self.holds_resource(&e.ty) && !e.diverges() {
+                    self.dropped(e);
+                }
+            }
+            TStmt::Return { span, value } => {
+                if let Some(e) = value {
+                    self.expr(e);
+                }
+                self.check_exit(0, *span, "`return`");
+                self.check_loop_exit(0, *span, "`return`");
+                self.state = None;
+            }
+        }
+    }
+
+    fn block(&mut self, block: &TBlock) {
+        let mark = self.scope.len();
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(value) = &block.value {
+            self.expr(value);
+        }
+        self.end_scope(mark);
+    }
+
+    /// Use a place without moving it: borrow it, read a part of it, or call a method that
+    /// takes it by reference. A temporary resource used this way is dropped afterwards.
+    fn inspect(&mut self, e: &TExpr) {
+        match &e.kind {
+            TExprKind::Local(id) => self.use_local(*id, e.span, false),
+            TExprKind::Field { base, .. }
+            | TExprKind::TupleField { base, .. }
+            | TExprKind::Deref(base) => self.inspect(base),
+            TExprKind::Index { base, index } => {
+                self.inspect(base);
+                self.expr(index);
+            }
+            _ => {
+                self.expr(e);
+                if self.holds_resource(&e.ty) {
+                    self.dropped(e);
+                }
+            }
+        }
+    }
+
+    /// A call: arguments are moved into it, except a receiver passed by reference.
+    fn call(&mut self, e: &TExpr, callee: &Callee, args: &[TExpr]) {
+        let params: &[_] = match callee {
+            Callee::Fn(id) => {
+                let f = &self.program.functions[*id as usize];
+                &f.locals[..f.param_count as usize]
+            }
+            _ => &[],
+        };
+        let builtin_receiver = matches!(
+            callee,
+            Callee::List(_) | Callee::Map(_, _) | Callee::Str(_) | Callee::Num(_) | Callee::Hash
+        );
+        for (i, arg) in args.iter().enumerate() {
+            let by_ref = match params.get(i) {
+                Some(p) => matches!(p.ty, Type::Ref(_, _)) && !matches!(arg.ty, Type::Ref(_, _)),
+                None => i == 0 && builtin_receiver,
+            };
+            if by_ref {
+                self.inspect(arg);
+            } else {
+                self.expr(arg);
+            }
+        }
+        let get = matches!(
+            callee,
+            Callee::List(ListMethod::Get) | Callee::Map(MapMethod::Get, _)
+        );
+        if get && self.holds_resource(&e.ty) {
+            self.report(
+                "`get` would copy a resource out of the collection; take it out with `pop` or `remove`"
+                    .to_string(),
+                e.span,
+                None,
+            );
+        } else if let (true, Type::Option(item)) = (get && self.moves(&e.ty), &e.ty) {
+            // Structs and enums are read by reference (see `Checker::read_type`); a tuple
+            // holding one would be copied.
+            self.report(
+                format!(
+                    "`get` would copy `{}` out of the collection; take it out with `pop` or `remove`",
+                    item
+                ),
+                e.span,
+                None,
+            );
+        }
+    }
+
+    /// A loop whose body runs after `cond` (if any) holds; `exits` if it ends when `cond`
+    /// fails or its items run out, and `consumes` the collection of resources it moves its
+    /// items out of.
+    fn run_loop(
+        &mut self,
+        cond: Option<&TExpr>,
+        var: Option<LocalId>,
+        body: &TBlock,
+        exits: bool,
+        consumes: Option<&Type>,
+    ) {
+        let entry = self.state.clone();
+        self.loops.push(LoopFrame {
+            mark: self.scope.len(),
+            breaks: None,
+            continues: None,
+            consumes: consumes.cloned(),
+        });
+        let mut exit = None;
+        // Twice, so that moves in one iteration are seen by the next.
+        for _ in 0..2 {
+            let continues = self.loops.last_mut().and_then(|l| l.continues.take());
+            self.state = merge(merge(entry.clone(), self.state.take()), continues);
+            if let Some(cond) = cond {
+                self.expr(cond);
+            }
+            if exits {
+                exit = self.state.clone();
+            }
+            let mark = self.scope.len();
+            if let Some(var) = var {
+                self.bind_local(var);
+            }
+            self.block(body);
+            self.end_scope(mark);
+        }
+        let frame = self.loops.pop().expect("loop frame");
+        self.state = merge(exit, frame.breaks);
+    }
+
+    /// Evaluate `e` where its value is moved: into a local, a call, a struct, or out of a block.
+    fn expr(&mut self, e: &TExpr) {
+        self.value(e, self.moves(&e.ty));
+    }
+
+    /// Evaluate `e` where its value is used up, which moves it if `moves`.
+    fn value(&mut self, e: &TExpr, moves: bool) {
+        match &e.kind {
+            TExprKind::Local(id) => self.use_local(*id, e.span, moves),
+            TExprKind::Field { .. }
+            | TExprKind::TupleField { .. }
+            | TExprKind::Index { .. }
+            | TExprKind::Deref(_) => {
+                self.inspect(e);
+                let from = match &e.kind {
+                    TExprKind::Field { .. } => "a field; destructure the struct instead",
+                    TExprKind::TupleField { .. } => {
+                        "a tuple element; destructure the tuple instead"
+                    }
+                    TExprKind::Index { .. } => "a list; take it out with `pop`",
+                    _ => "a reference",
+                };
+                if self.holds_resource(&e.ty) {
+                    self.report(
+                        format!("cannot move a resource out of {}", from),
+                        e.span,
+                        None,
+                    );
+                } else if moves {
+                    self.report(
+                        format!(
+                            "cannot move `{}` out of {}, or borrow it with `&`",
+                            e.ty, from
+                        ),
+                        e.span,
+                        None,
+                    );
+                }
+            }
+            TExprKind::Ref { expr, .. } => self.inspect(expr),
+            TExprKind::Assign { target, value } => {
+                self.expr(value);
+                match &target.kind {
+                    TExprKind::Local(id) => {
+                        let name = &self.f.locals[*id as usize].name;
+                        let holds = match self.status(*id) {
+                            _ if !self.holds_resource(&target.ty) => None,
+                            Some(Status::Live) => Some("holds"),
+                            Some(Status::MaybeMoved(_)) => Some("may hold"),
+                            _ => None,
+                        };
+                        if let Some(holds) = holds {
+                            self.report(
+                                format!(
+                                    "assigning to `{}` would drop the resource it {}",
+                                    name, holds
+                                ),
+                                target.span,
+                                None,
+                            );
+                        }
+                        self.set_status(*id, Status::Live);
+                    }
+                    _ => {
+                        self.inspect(target);
+                        if self.holds_resource(&target.ty) {
+                            self.report(
+                                "cannot assign over a resource: the value it replaces would be dropped"
+                                    .to_string(),
+                                target.span,
+                                None,
+                            );
+                        }
+                    }
+                }
+            }
+            TExprKind::Call { callee, args } => self.call(e, callee, args),
+            TExprKind::Closure { captures, .. } => {
+                for c in captures {
+                    if self.holds_resource(&c.ty) {
+                        self.inspect(c);
+                        self.report(
+                            format!("cannot capture a resource (`{}`) in a closure", c.ty),
+                            c.span,
+                            None,
+                        );
+                    } else {
+                        // Captured by value: a struct that is not `Copy` moves into the closure.
+                        self.expr(c);
+                    }
+                }
+            }
+            TExprKind::Block(block) => self.block(block),
+            TExprKind::If {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                self.expr(cond);
+                let before = self.state.clone();
+                self.block(then_block);
+                let after_then = std::mem::replace(&mut self.state, before);
+                if let Some(block) = else_block {
+                    self.block(block);
+                }
+                self.state = merge(after_then, self.state.take());
+            }
+            TExprKind::Match { value, arms } => {
+                self.scrutinee(value, arms.iter().map(|arm| &arm.pattern));
+                let before = self.state.clone();
+                let mut after = None;
+                for arm in arms {
+                    self.state = before.clone();
+                    let mark = self.scope.len();
+                    self.bind(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&arm.body);
+                    self.end_scope(mark);
+                    after = merge(after, self.state.take());
+                }
+                self.state = after;
+            }
+            TExprKind::While { cond, body } => self.run_loop(Some(cond), None, body, true, None),
+            TExprKind::Loop { body, .. } => self.run_loop(None, None, body, false, None),
+            TExprKind::ForRange {
+                var,
+                start,
+                end,
+                body,
+            } => {
+                self.expr(start);
+                self.expr(end);
+                self.run_loop(None, Some(*var), body, true, None);
+            }
+            TExprKind::ForEach { var, list, body } => {
+                // A loop over a collection of resources, or of tuples holding values that move,
+                // consumes it, moving each item out. Other structs and enums are visited by
+                // reference (see `Checker::read_type`).
+                let item_moves = self.moves(&self.f.locals[*var as usize].ty);
+                if let (true, Type::Ref(_, _)) = (item_moves, &list.ty) {
+                    self.report(
+                        format!("cannot move the items of `{}` out of a reference", list.ty),
+                        list.span,
+                        None,
+                    );
+                    self.inspect(list);
+                } else if !self.holds_resource(&list.ty) {
+                    if item_moves {
+                        self.value(list, true);
+                    } else {
+                        self.inspect(list);
+                    }
+                } else if let Type::Map(_, value) = &list.ty {
+                    if self.holds_resource(value) {
+                        self.report(
+                            format!(
+                                "a `for` loop over a map visits only its keys and would drop the resources in its values (`{}`)",
+                                value
+                            ),
+                            list.span,
+                            None,
+                        );
+                    }
+                    self.expr(list);
+                } else {
+                    self.expr(list);
+                }
+                let consumes = match &list.ty {
+                    Type::Ref(_, _) => None,
+                    ty => Some(ty).filter(|ty| self.holds_resource(ty)),
+                };
+                self.run_loop(None, Some(*var), body, true, consumes);
+            }
+            TExprKind::Break { target, value } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                let frame = &self.loops[*target as usize];
+                self.check_exit(frame.mark, e.span, "`break`");
+                self.check_loop_exit(*target as usize, e.span, "`break`");
+                let frame = &mut self.loops[*target as usize];
+                frame.breaks = merge(frame.breaks.take(), self.state.take());
+            }
+            TExprKind::Continue(target) => {
+                let frame = &self.loops[*target as usize];
+                self.check_exit(frame.mark, e.span, "`continue`");
+                self.check_loop_exit(*target as usize + 1, e.span, "`continue`");
+                let frame = &mut self.loops[*target as usize];
+                frame.continues = merge(frame.continues.take(), self.state.take());
+            }
+            TExprKind::Print(value) => self.inspect(value),
+            TExprKind::Try { expr, .. } => {
+                self.expr(expr);
+                self.check_exit(0, e.span, "`?` returns early");
+                self.check_loop_exit(0, e.span, "`?`");
+            }
+            _ => {
+                let mut children = Vec::new();
+                e.for_each_child(&mut |c| children.push(c));
+                for c in children {
+                    self.expr(c);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::parse;
+    use knox_syntax::span::FileId;
+
+    fn move_errors(src: &str) -> Vec<String> {
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let mut modules = vec![(
+            "main".to_string(),
+            parse(tokens, FileId::new(0)).expect("parse should succeed"),
+        )];
+        crate::desugar::add_accessor_methods(&mut modules);
+        crate::desugar::add_derived_impls(&mut modules);
+        let typed = crate::check::check_program(&modules).expect("program should type-check");
+        match check_moves(&typed) {
+            Ok(()) => Vec::new(),
+            Err(diags) => diags.into_iter().map(|d| d.message).collect(),
+        }
+    }
+
+    const COIN: &str = r#"
+resource struct Coin { value: int }
+fn mint(v: int) -> Coin { main::Coin { value: v } }
+fn burn(c: Coin) -> int { let Coin { value } = c; value }
+"#;
+
+    #[test]
+    fn consumed_resources_are_accepted() {
+        let errors = move_errors(&format!(
+            "{}{}",
+            COIN,
+            r#"
+fn peek(c: &Coin) -> int { c.value }
+fn main() -> () {
+  let c = mint(5);
+  print(peek(&c) + c.value);
+  let mut purse = [mint(1)];
+  purse.push(c);
+  let total = match purse.pop() { Some(k) => burn(k), None => 0 };
+  print(total);
+  for k in purse { print(burn(k)); }
+  let d = mint(2);
+  if total > 1 { burn(d); } else { burn(d); };
+}
+"#
+        ));
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn copies_and_drops_are_rejected() {
+        let errors = move_errors(&format!(
+            "{}{}",
+            COIN,
+            r#"
+resource struct Wallet { coin: Coin }
+fn main() -> () {
+  let c = mint(1);
+  burn(c);
+  burn(c);
+  mint(2);
+  let e = mint(3);
+  if true { burn(e); }
+  let w = main::Wallet { coin: mint(4) };
+  burn(w.coin);
+  let Wallet { coin } = w;
+  burn(coin);
+  let mut h = mint(5);
+  h = mint(6);
+  burn(h);
+  let k = mint(7);
+  if e.value > 0 { return; }
+  burn(k);
+}
+"#
+        ));
+        assert_eq!(
+            errors,
+            [
+                "use of moved value `c`",
+                "a resource (`main::Coin`) is dropped here: move, destructure or store it",
+                "cannot move a resource out of a field; destructure the struct instead",
+                "assigning to `h` would drop the resource it holds",
+                "use of moved value `e`",
+                "resource `e` is not consumed before `return`",
+                "resource `k` is not consumed before `return`",
+                "resource `e` is not consumed on every path to the end of its scope",
+            ]
+        );
+    }
+
+    #[test]
+    fn consuming_loops_run_to_the_end() {
+        let errors = move_errors(&format!(
+            "{}{}",
+            COIN,
+            r#"
+fn check(v: int) -> Option<int> { if v > 0 { Some(v) } else { None } }
+fn spend(coins: List<Coin>) -> Option<int> {
+  let mut total = 0;
+  for c in coins {
+    let v = burn(c);
+    check(v)?;
+    if v > 10 { return None; }
+    if v > 5 { break; }
+    if v > 3 { continue; }
+    for i in 0..v { if i > 1 { break; } }
+    total = total + v;
+  }
+  Some(total)
+}
+fn main() -> () {
+  let coins = [mint(1), mint(2)];
+  for c in coins { burn(c); }
+  let more = [mint(3)];
+  loop { for c in more { burn(c); } break; }
+  spend([mint(4)]);
+}
+"#
+        ));
+        assert_eq!(
+            errors,
+            [
+                "`?` leaves a `for` loop that consumes `List<main::Coin>`, dropping the resources it has not reached",
+                "`return` leaves a `for` loop that consumes `List<main::Coin>`, dropping the resources it has not reached",
+                "`break` leaves a `for` loop that consumes `List<main::Coin>`, dropping the resources it has not reached",
+            ]
+        );
+    }
+
+    #[test]
+    fn displaced_map_values_must_be_consumed() {
+        let errors = move_errors(&format!(
+            "{}{}",
+            COIN,
+            r#"
+fn main() -> () {
+  let mut m: Map<int, Coin> = Map::new();
+  m.insert(1, mint(1));
+  m.insert(1, mint(2));
+  match m.insert(1, mint(3)) { Some(c) => print(burn(c)), None => {} };
+  for k in m { print(k); }
+}
+"#
+        ));
+        assert_eq!(
+            errors,
+            [
+                "a resource (`Option<main::Coin>`) is dropped here: move, destructure or store it",
+                "a resource (`Option<main::Coin>`) is dropped here: move, destructure or store it",
+                "a `for` loop over a map visits only its keys and would drop the resources in its values (`main::Coin`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn struct_values_move() {
+        let errors = move_errors(
+            r#"
+@derive(Copy)
+struct V { x: int }
+struct P { name: string }
+struct Line { p: P }
+fn take(p: P) -> () { print(p.name); }
+fn main() -> () {
+  let v = main::V { x: 1 };
+  let w = v;
+  print(v.x + w.x);
+  let a = main::P { name: "a" };
+  let r = &a;
+  print(r.name);
+  let b = a;
+  print(a.name);
+  take(b);
+  take(b);
+  let l = main::Line { p: main::P { name: "l" } };
+  let q = l.p;
+  let Line { p } = l;
+  let P { name } = p;
+  print(p.name);
+  let c = main::P { name: "c" };
+  for i in 0..2 { take(c); }
+  let mut s = main::P { name: "s" };
+  let f = || s.name;
+  let t = s;
+  s.name = "changed";
+  let u = main::V { x: 2 };
+  let g = || u.x;
+  print(u.x + g());
+}
+"#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "use of moved value `a`",
+                "use of moved value `b`",
+                "cannot move `main::P` out of a field; destructure the struct instead, or borrow it with `&`",
+                "use of moved value `c`",
+                "use of moved value `s`",
+                "use of moved value `s`",
+            ]
+        );
+    }
+
+    #[test]
+    fn values_holding_structs_move() {
+        let errors = move_errors(
+            r#"
+struct P { x: int }
+enum E { One(P), Two }
+fn main() -> () {
+  let o = Some(main::P { x: 1 });
+  let o2 = o;
+  match o { Some(p) => print(p.x), None => {} };
+  let e = E::One(main::P { x: 2 });
+  let e2 = e;
+  match e { E::One(p) => print(p.x), E::Two => {} };
+  let t = (main::P { x: 3 }, 4);
+  let t2 = t;
+  print(t.1);
+  let ps = [main::P { x: 5 }];
+  for p in ps { print(p.x); }
+  match ps.get(0) { Some(p) => print(p.x), None => {} };
+  let ts = [(main::P { x: 6 }, 7)];
+  match ts.get(0) { Some(t) => print(t.1), None => {} };
+  let r = &ts;
+  for t in r { print(t.1); }
+  for t in ts { print(t.1); }
+  print(ts.len());
+}
+"#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "use of moved value `o`",
+                "use of moved value `e`",
+                "use of moved value `t`",
+                "`get` would copy `(main::P, int)` out of the collection; take it out with `pop` or `remove`",
+                "cannot move the items of `&List<(main::P, int)>` out of a reference",
+                "use of moved value `ts`",
+            ]
+        );
+    }
+}
@@ -2,7 +2,7 @@
 
 use knox_syntax::ast::{MatchPattern, *};
 use knox_syntax::span::{FileId, Span};
-use knox_syntax::token::{Token, TokenKind};
+use knox_syntax::token::{StringSegment, Token, TokenKind};
 use knox_syntax::Diagnostic;
 use std::iter::Peekable;
 use std::vec::IntoIter;
@@ -12,6 +12,8 @@ pub fn parse(tokens: Vec<Token>, file_id: FileId) -> Result<Root, Vec<Diagnostic
         tokens: tokens.into_iter().peekable(),
         file_id,
         diags: Vec::new(),
+        no_struct_lit: false,
+        last_end: 0,
     };
     let root = p.parse_root();
     if p.diags.is_empty() {
@@ -21,10 +23,23 @@ pub fn parse(tokens: Vec<Token>, file_id: FileId) -> Result<Root, Vec<Diagnostic
     }
 }
 
+/// A function signature as written, shared by functions and trait methods.
+struct FnSig {
+    name: String,
+    type_params: Vec<TypeParam>,
+    receiver: Option<Receiver>,
+    params: Vec<Param>,
+    return_ty: Type,
+}
+
 struct Parser {
     tokens: Peekable<IntoIter<Token>>,
     file_id: FileId,
     diags: Vec<Diagnostic>,
+    /// Set while parsing an `if` condition or `match` scrutinee, where `{` starts the body, not a struct literal.
+    no_struct_lit: bool,
+    /// End offset of the last consumed token.
+    last_end: u32,
 }
 
 impl Parser {
@@ -33,7 +48,9 @@ impl Parser {
     }
 
     fn advance(&mut self) -> Option<Token> {
-        self.tokens.next()
+        let t = self.tokens.next()?;
+        self.last_end = t.span.end;
+        Some(t)
     }
 
     fn loc(&self, span: Span) -> knox_syntax::span::Location {
@@ -85,20 +102,50 @@ impl Parser {
     }
 
     fn parse_item(&mut self) -> Option<Item> {
+        let derives = if matches!(self.peek(), Some(TokenKind::At)) {
+            Some(self.parse_derive()?)
+        } else {
+            None
+        };
         let vis = if matches!(self.peek(), Some(TokenKind::Export) | Some(TokenKind::Pub)) {
             self.advance();
             Visibility::Exported
         } else {
             Visibility::Private
         };
+        let resource = matches!(self.peek(), Some(TokenKind::Resource));
+        if resource {
+            self.advance();
+        }
         let t = self.advance()?;
         let start = t.span.start;
+        if resource && t.kind != TokenKind::Struct {
+            self.error("expected `struct` after `resource`", t.span);
+            return None;
+        }
+        if let Some((span, _)) = &derives {
+            if t.kind != TokenKind::Struct {
+                self.error("`@derive` is only allowed on structs", *span);
+                return None;
+            }
+        }
         let item = match t.kind {
-            TokenKind::Struct => self.parse_struct(vis, start)?,
-            TokenKind::Fn => self.parse_fn(vis, start)?,
+            TokenKind::Struct => {
+                let (span, derives) = derives.unwrap_or_default();
+                self.parse_struct(vis, resource, start, span, derives)?
+            }
+            TokenKind::Enum => self.parse_enum(vis, start)?,
+            TokenKind::Trait => self.parse_trait(vis, start)?,
+            TokenKind::Fn => Item::Fn(self.parse_fn(vis, start, false)?),
+            TokenKind::Impl => self.parse_impl(start)?,
             TokenKind::Import => self.parse_import(start)?,
+            TokenKind::Const => self.parse_const(vis, start)?,
+            TokenKind::Type => self.parse_type_alias(vis, start)?,
             _ => {
-                self.error("expected struct, fn, or import", t.span);
+                self.error(
+                    "expected struct, enum, trait, fn, impl, const, type, or import",
+                    t.span,
+                );
                 return None;
             }
         };
@@ -134,6 +181,63 @@ impl Parser {
         }))
     }
 
+    /// `const NAME: Type = value;`: the `const` keyword has been consumed.
+    fn parse_const(&mut self, vis: Visibility, start: u32) -> Option<Item> {
+        let t = self.advance()?;
+        let name = match t.kind {
+            TokenKind::Ident(s) => s,
+            _ => {
+                self.error("expected constant name", t.span);
+                return None;
+            }
+        };
+        self.expect(TokenKind::Colon)?;
+        let ty = self.parse_type()?;
+        self.expect(TokenKind::Assign)?;
+        let value = self.parse_expr()?;
+        let end = value.span().end;
+        self.expect_semicolon(value.span());
+        Some(Item::Const(ConstDecl {
+            span: Span::new(start, end),
+            vis,
+            name,
+            ty,
+            value,
+        }))
+    }
+
+    /// `type Name = Type;`: the `type` keyword has been consumed.
+    fn parse_type_alias(&mut self, vis: Visibility, start: u32) -> Option<Item> {
+        let t = self.advance()?;
+        let name = match t.kind {
+            TokenKind::Ident(s) => s,
+            _ => {
+                self.error("expected type alias name", t.span);
+                return None;
+            }
+        };
+        if matches!(self.peek(), Some(TokenKind::Lt)) {
+            let t = self.advance().unwrap();
+            self.error("type aliases cannot have type parameters", t.span);
+            return None;
+        }
+        let eq = self.expect(TokenKind::Assign)?;
+        let ty = self.parse_type()?;
+        let end = match self.peek() {
+            Some(TokenKind::Semicolon) => self.advance().unwrap().span.end,
+            _ => {
+                self.expect_semicolon(eq);
+                eq.end
+            }
+        };
+        Some(Item::TypeAlias(TypeAliasDecl {
+            span: Span::new(start, end),
+            vis,
+            name,
+            ty,
+        }))
+    }
+
     fn parse_path_segments(&mut self) -> Option<Vec<String>> {
         let mut segs = Vec::new();
         match self.advance()?.kind {
@@ -150,11 +254,64 @@ impl Parser {
         Some(segs)
     }
 
-    fn parse_struct(&mut self, vis: Visibility, start: u32) -> Option<Item> {
+    /// `@derive(Eq, Hash, Clone, Copy)` before a struct; returns the attribute's span and the trait names.
+    fn parse_derive(&mut self) -> Option<(Span, Vec<String>)> {
+        let at = self.advance()?.span;
+        let t = self.advance()?;
+        if t.kind != TokenKind::Ident("derive".to_string()) {
+            self.error("expected `derive` after `@`", t.span);
+            return None;
+        }
+        self.expect(TokenKind::LParen)?;
+        let mut traits = Vec::new();
+        loop {
+            let t = self.advance()?;
+            match t.kind {
+                TokenKind::Ident(name)
+                    if matches!(name.as_str(), "Eq" | "Hash" | "Clone" | "Copy") =>
+                {
+                    traits.push(name)
+                }
+                TokenKind::Ident(name) => {
+                    self.error(
+                        format!(
+                            "cannot derive `{}`: only `Eq`, `Hash`, `Clone` and `Copy` can be derived",
+                            name
+                        ),
+                        t.span,
+                    );
+                    return None;
+                }
+                TokenKind::RParen => return Some((Span::new(at.start, t.span.end), traits)),
+                TokenKind::Comma => {}
+                _ => {
+                    self.error("expected a trait name in `@derive(..)`", t.span);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn parse_struct(
+        &mut self,
+        vis: Visibility,
+        resource: bool,
+        start: u32,
+        derive_span: Span,
+        derives: Vec<String>,
+    ) -> Option<Item> {
         let name = match self.advance()?.kind {
             TokenKind::Ident(s) => s,
             _ => return None,
         };
+        let type_params = self.parse_type_param_names()?;
+        if !type_params.is_empty() && !derives.is_empty() {
+            self.error("`@derive` is not supported on generic structs", derive_span);
+            return None;
+        }
+        if matches!(self.peek(), Some(TokenKind::LParen)) {
+            return self.parse_newtype(vis, resource, start, name, type_params, derives);
+        }
         self.expect(TokenKind::LBrace);
         let mut fields = Vec::new();
         while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
@@ -181,7 +338,118 @@ impl Parser {
             span: Span::new(start, end),
             vis,
             name,
+            type_params,
             fields,
+            derives,
+            resource,
+            newtype: false,
+        }))
+    }
+
+    /// `struct Name(Type);` after the name: a newtype, whose single field is `0`.
+    fn parse_newtype(
+        &mut self,
+        vis: Visibility,
+        resource: bool,
+        start: u32,
+        name: String,
+        type_params: Vec<String>,
+        derives: Vec<String>,
+    ) -> Option<Item> {
+        let open = self.advance()?.span;
+        if !type_params.is_empty() {
+            self.error("newtype structs cannot have type parameters", open);
+            return None;
+        }
+        let ty = self.parse_type()?;
+        let close = self.expect(TokenKind::RParen)?;
+        self.expect_semicolon(close);
+        Some(Item::Struct(StructDecl {
+            span: Span::new(start, close.end),
+            vis,
+            name,
+            type_params,
+            fields: vec![StructField {
+                span: Span::new(open.end, close.start),
+                name: "0".to_string(),
+                ty,
+                attrs: FieldAttrs::default(),
+            }],
+            derives,
+            resource,
+            newtype: true,
+        }))
+    }
+
+    /// Variants are comma-separated; each is `Name`, `Name(T, ...)` or `Name { field: T, ... }`.
+    fn parse_enum(&mut self, vis: Visibility, start: u32) -> Option<Item> {
+        let name = match self.advance()?.kind {
+            TokenKind::Ident(s) => s,
+            _ => return None,
+        };
+        self.expect(TokenKind::LBrace)?;
+        let mut variants = Vec::new();
+        while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
+            let t = self.advance()?;
+            let vname = match t.kind {
+                TokenKind::Ident(s) => s,
+                _ => {
+                    self.error("expected variant name", t.span);
+                    return None;
+                }
+            };
+            let mut end = t.span.end;
+            let payload = match self.peek() {
+                Some(TokenKind::LParen) => {
+                    self.advance();
+                    let mut tys = Vec::new();
+                    while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
+                        tys.push(self.parse_type()?);
+                        if matches!(self.peek(), Some(TokenKind::Comma)) {
+                            self.advance();
+                        }
+                    }
+                    end = self.expect(TokenKind::RParen)?.end;
+                    VariantPayload::Tuple(tys)
+                }
+                Some(TokenKind::LBrace) => {
+                    self.advance();
+                    let mut fields = Vec::new();
+                    while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
+                        let f = self.parse_struct_field()?;
+                        fields.push((f.name, f.ty));
+                        if matches!(self.peek(), Some(TokenKind::Comma)) {
+                            self.advance();
+                        }
+                    }
+                    end = self.expect(TokenKind::RBrace)?.end;
+                    VariantPayload::Struct(fields)
+                }
+                _ => VariantPayload::Unit,
+            };
+            variants.push(EnumVariant {
+                span: Span::new(t.span.start, end),
+                name: vname,
+                payload,
+            });
+            match self.peek() {
+                Some(TokenKind::Comma) => {
+                    self.advance();
+                }
+                Some(TokenKind::RBrace) | None => {}
+                _ => {
+                    let t = self.advance().unwrap();
+                    self.error("enum variants must be separated by commas", t.span);
+                    return None;
+                }
+            }
+        }
+        let end = self.advance().map(|t| t.span.end).unwrap_or(start);
+        Some(Item::Enum(EnumDecl {
+            span: Span::new(start, end),
+            vis,
+            name,
+            variants,
         }))
     }
 
@@ -244,24 +512,49 @@ impl Parser {
                         path.push(n);
                     }
                 }
-                if path.len() == 1 {
-                    match path[0].as_str() {
-                        "int" => Type::Int,
-                        "string" => Type::String,
-                        "bool" => Type::Bool,
-                        _ => Type::Path(path),
+                if matches!(self.peek(), Some(TokenKind::Lt)) {
+                    self.advance();
+                    let mut args = vec![self.parse_type()?];
+                    while matches!(self.peek(), Some(TokenKind::Comma)) {
+                        self.advance();
+                        args.push(self.parse_type()?);
+                    }
+                    self.expect(TokenKind::Gt)?;
+                    match (path.as_slice(), args.len()) {
+                        ([name], 1) if name == "List" => Type::List(Box::new(args.remove(0))),
+                        ([name], 1) if name == "Set" => Type::Set(Box::new(args.remove(0))),
+                        ([name], 2) if name == "Map" => {
+                            let value = args.remove(1);
+                            Type::Map(Box::new(args.remove(0)), Box::new(value))
+                        }
+                        _ => Type::Generic(path, args),
                     }
+                } else if path.len() == 1 {
+                    Type::primitive(&path[0]).unwrap_or(Type::Path(path))
                 } else {
                     Type::Path(path)
                 }
             }
             TokenKind::LParen => {
-                if matches!(self.peek(), Some(TokenKind::RParen)) {
+                let mut elems = Vec::new();
+                while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
+                    elems.push(self.parse_type()?);
+                    if !matches!(self.peek(), Some(TokenKind::Comma)) {
+                        break;
+                    }
                     self.advance();
-                    Type::Unit
-                } else {
-                    self.error("expected () for unit type", t.span);
-                    return None;
+                }
+                let end = self.expect(TokenKind::RParen)?.end;
+                match elems.len() {
+                    0 => Type::Unit,
+                    1 => {
+                        self.error(
+                            "a tuple type needs at least two elements",
+                            Span::new(t.span.start, end),
+                        );
+                        return None;
+                    }
+                    _ => Type::Tuple(elems),
                 }
             }
             TokenKind::Amp => {
@@ -272,6 +565,35 @@ impl Parser {
                 let inner = self.parse_type()?;
                 Type::Ref(mut_, Box::new(inner))
             }
+            TokenKind::Fn => {
+                self.expect(TokenKind::LParen)?;
+                let mut params = Vec::new();
+                while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
+                    params.push(self.parse_type()?);
+                    if !matches!(self.peek(), Some(TokenKind::Comma)) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(TokenKind::RParen)?;
+                self.expect(TokenKind::Arrow)?;
+                let ret = self.parse_type()?;
+                Type::Fn(params, Box::new(ret))
+            }
+            TokenKind::Option => {
+                self.expect(TokenKind::Lt)?;
+                let inner = self.parse_type()?;
+                self.expect(TokenKind::Gt)?;
+                Type::Option(Box::new(inner))
+            }
+            TokenKind::Result => {
+                self.expect(TokenKind::Lt)?;
+                let ok = self.parse_type()?;
+                self.expect(TokenKind::Comma)?;
+                let err = self.parse_type()?;
+                self.expect(TokenKind::Gt)?;
+                Type::Result(Box::new(ok), Box::new(err))
+            }
             _ => {
                 self.error("expected type", t.span);
                 return None;
@@ -280,14 +602,238 @@ impl Parser {
         Some(ty)
     }
 
-    fn parse_fn(&mut self, vis: Visibility, start: u32) -> Option<Item> {
+    /// `impl Name { fn ... }` or `impl Trait for Name { fn ... }`; each function may be `pub`.
+    /// A generic struct is named with the block's parameters: `impl<A, B> Pair<A, B>`.
+    fn parse_impl(&mut self, start: u32) -> Option<Item> {
+        let type_params = self.parse_type_param_names()?;
+        let path = self.parse_path_segments()?;
+        let (trait_, name) = if matches!(self.peek(), Some(TokenKind::For)) {
+            self.advance();
+            let t = self.advance()?;
+            let TokenKind::Ident(name) = t.kind else {
+                self.error("expected a struct name after `for`", t.span);
+                return None;
+            };
+            (Some(path), name)
+        } else {
+            match <[String; 1]>::try_from(path) {
+                Ok([name]) => (None, name),
+                Err(_) => {
+                    self.error(
+                        "impl blocks must be in the module that declares the struct",
+                        Span::new(start, self.last_end),
+                    );
+                    return None;
+                }
+            }
+        };
+        if matches!(self.peek(), Some(TokenKind::ColonColon)) {
+            let t = self.advance().unwrap();
+            self.error(
+                "impl blocks must be in the module that declares the struct",
+                t.span,
+            );
+            return None;
+        }
+        let name_start = self.last_end;
+        if self.parse_type_param_names()? != type_params {
+            let expected = if type_params.is_empty() {
+                name.clone()
+            } else {
+                format!("{}<{}>", name, type_params.join(", "))
+            };
+            self.error(
+                format!(
+                    "expected `{}`: the struct must be named with the impl's type parameters in order",
+                    expected
+                ),
+                Span::new(name_start, self.last_end),
+            );
+            return None;
+        }
+        self.expect(TokenKind::LBrace)?;
+        let mut methods = Vec::new();
+        while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
+            let vis = if matches!(self.peek(), Some(TokenKind::Export) | Some(TokenKind::Pub)) {
+                self.advance();
+                Visibility::Exported
+            } else {
+                Visibility::Private
+            };
+            let t = self.advance()?;
+            if t.kind != TokenKind::Fn {
+                self.error("expected fn in impl block", t.span);
+                return None;
+            }
+            methods.push(self.parse_fn(vis, t.span.start, true)?);
+        }
+        let end = self.advance().map(|t| t.span.end).unwrap_or(start);
+        Some(Item::Impl(ImplBlock {
+            span: Span::new(start, end),
+            trait_,
+            type_params,
+            name,
+            methods,
+        }))
+    }
+
+    /// `trait Name { fn sig; fn sig { default body } }`
+    fn parse_trait(&mut self, vis: Visibility, start: u32) -> Option<Item> {
+        let name = match self.advance()?.kind {
+            TokenKind::Ident(s) => s,
+            _ => return None,
+        };
+        self.expect(TokenKind::LBrace)?;
+        let mut methods = Vec::new();
+        while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
+            let t = self.advance()?;
+            if t.kind != TokenKind::Fn {
+                self.error("expected fn in trait", t.span);
+                return None;
+            }
+            let sig = self.parse_fn_sig(t.span.start, true)?;
+            if !sig.type_params.is_empty() {
+                self.error(
+                    "trait methods cannot have type parameters",
+                    Span::new(t.span.start, self.last_end),
+                );
+            }
+            let (default, end) = if matches!(self.peek(), Some(TokenKind::LBrace)) {
+                let body = self.parse_block()?;
+                let end = body.span.end;
+                (Some(body), end)
+            } else {
+                let end = self.expect(TokenKind::Semicolon)?.end;
+                (None, end)
+            };
+            methods.push(TraitMethod {
+                span: Span::new(t.span.start, end),
+                name: sig.name,
+                receiver: sig.receiver,
+                params: sig.params,
+                return_ty: sig.return_ty,
+                default,
+            });
+        }
+        let end = self.advance().map(|t| t.span.end).unwrap_or(start);
+        Some(Item::Trait(TraitDecl {
+            span: Span::new(start, end),
+            vis,
+            name,
+            methods,
+        }))
+    }
+
+    /// `self`, `&self` or `&mut self` at the start of a parameter list, if present.
+    /// A parameter never starts with `&`, so one that does must be a receiver.
+    fn parse_receiver(&mut self) -> Option<Receiver> {
+        let receiver = match self.peek() {
+            Some(TokenKind::SelfValue) => Receiver::Value,
+            Some(TokenKind::Amp) => {
+                self.advance();
+                if matches!(self.peek(), Some(TokenKind::Mut)) {
+                    self.advance();
+                    Receiver::RefMut
+                } else {
+                    Receiver::Ref
+                }
+            }
+            _ => return None,
+        };
+        let t = self.advance()?;
+        if t.kind != TokenKind::SelfValue {
+            self.error("expected `self`", t.span);
+            return None;
+        }
+        if matches!(self.peek(), Some(TokenKind::Comma)) {
+            self.advance();
+        }
+        Some(receiver)
+    }
+
+    /// The `fn` keyword has been consumed. A `self` receiver is only accepted `in_impl`.
+    fn parse_fn(&mut self, vis: Visibility, start: u32, in_impl: bool) -> Option<FnDecl> {
+        let sig = self.parse_fn_sig(start, in_impl)?;
+        let body = self.parse_block()?;
+        let end = body.span.end;
+        Some(FnDecl {
+            span: Span::new(start, end),
+            vis,
+            name: sig.name,
+            type_params: sig.type_params,
+            receiver: sig.receiver,
+            params: sig.params,
+            return_ty: sig.return_ty,
+            body,
+        })
+    }
+
+    /// `<A, B>` after a struct name or `impl`; empty when there is no `<`.
+    fn parse_type_param_names(&mut self) -> Option<Vec<String>> {
+        Some(
+            self.parse_type_params()?
+                .into_iter()
+                .map(|p| p.name)
+                .collect(),
+        )
+    }
+
+    /// `<T, U: Display + Eq>`; empty when there is no `<`. Bounds are only meaningful on functions.
+    fn parse_type_params(&mut self) -> Option<Vec<TypeParam>> {
+        let mut params = Vec::new();
+        if !matches!(self.peek(), Some(TokenKind::Lt)) {
+            return Some(params);
+        }
+        self.advance();
+        while !matches!(self.peek(), Some(TokenKind::Gt) | None) {
+            let t = self.advance()?;
+            let TokenKind::Ident(name) = t.kind else {
+                self.error("expected a type parameter name", t.span);
+                return None;
+            };
+            let mut bounds = Vec::new();
+            if matches!(self.peek(), Some(TokenKind::Colon)) {
+                self.advance();
+                bounds.push(self.parse_path_segments()?);
+                while matches!(self.peek(), Some(TokenKind::Plus)) {
+                    self.advance();
+                    bounds.push(self.parse_path_segments()?);
+                }
+            }
+            params.push(TypeParam { name, bounds });
+            if !matches!(self.peek(), Some(TokenKind::Comma)) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(TokenKind::Gt)?;
+        Some(params)
+    }
+
+    /// Name, type parameters, receiver, parameters and return type of a function, up to its body.
+    fn parse_fn_sig(&mut self, start: u32, in_impl: bool) -> Option<FnSig> {
         let name = match self.advance()?.kind {
             TokenKind::Ident(s) => s,
             _ => return None,
         };
+        let type_params = self.parse_type_params()?;
         self.expect(TokenKind::LParen);
+        let receiver_start = self.tokens.peek().map(|t| t.span.start).unwrap_or(start);
+        let receiver = self.parse_receiver();
+        if receiver.is_some() && !in_impl {
+            self.error(
+                "`self` parameter is only allowed in methods",
+                Span::new(receiver_start, self.last_end),
+            );
+        }
         let mut params = Vec::new();
         while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
+            if let Some(TokenKind::SelfValue) = self.peek() {
+                let t = self.advance().unwrap();
+                self.error("`self` must be the first parameter of a method", t.span);
+                return None;
+            }
+            let pstart = self.tokens.peek().map(|t| t.span.start).unwrap_or(start);
             let mut_ = matches!(self.peek(), Some(TokenKind::Mut));
             if mut_ {
                 self.advance();
@@ -302,6 +848,7 @@ impl Parser {
                 name: pname,
                 ty: pty,
                 mut_,
+                span: Span::new(pstart, self.last_end),
             });
             if matches!(self.peek(), Some(TokenKind::Comma)) {
                 self.advance();
@@ -310,32 +857,53 @@ impl Parser {
         self.expect(TokenKind::RParen);
         self.expect(TokenKind::Arrow);
         let return_ty = self.parse_type()?;
-        let body = self.parse_block()?;
-        let end = body.span.end;
-        Some(Item::Fn(FnDecl {
-            span: Span::new(start, end),
-            vis,
+        Some(FnSig {
             name,
+            type_params,
+            receiver,
             params,
             return_ty,
-            body,
-        }))
+        })
     }
 
     fn parse_block(&mut self) -> Option<Block> {
         let start = self.advance().map(|t| t.span.start).unwrap_or(0);
+        self.parse_block_body(start)
+    }
+
+    /// The opening `{` (starting at `start`) has been consumed.
+    fn parse_block_body(&mut self, start: u32) -> Option<Block> {
+        let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, false);
         let mut stmts = Vec::new();
+        let mut value = None;
         while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
-            let s = self.parse_stmt()?;
-            stmts.push(s);
+            if matches!(self.peek(), Some(TokenKind::Let) | Some(TokenKind::Return)) {
+                stmts.push(self.parse_stmt()?);
+                continue;
+            }
+            let expr = self.parse_expr()?;
+            if matches!(self.peek(), Some(TokenKind::RBrace)) {
+                value = Some(Box::new(expr));
+                break;
+            }
+            if !expr.is_block_like() || matches!(self.peek(), Some(TokenKind::Semicolon)) {
+                self.expect_semicolon(expr.span());
+            }
+            stmts.push(Stmt::Expr {
+                span: expr.span(),
+                expr,
+            });
         }
+        self.no_struct_lit = no_struct_lit;
         let end = self.advance().map(|t| t.span.end).unwrap_or(start);
         Some(Block {
             span: Span::new(start, end),
             stmts,
+            value,
         })
     }
 
+    /// `let` and `return` statements; expression statements are handled by `parse_block`.
     fn parse_stmt(&mut self) -> Option<Stmt> {
         let stmt = if matches!(self.peek(), Some(TokenKind::Let)) {
             let t = self.advance().unwrap();
@@ -344,10 +912,7 @@ impl Parser {
             if mut_ {
                 self.advance();
             }
-            let name = match self.advance()?.kind {
-                TokenKind::Ident(s) => s,
-                _ => return None,
-            };
+            let pattern = self.parse_single_pattern()?;
             let ty = if matches!(self.peek(), Some(TokenKind::Colon)) {
                 self.advance();
                 Some(self.parse_type()?)
@@ -360,11 +925,11 @@ impl Parser {
             Stmt::Let {
                 span: Span::new(start, init.span().end),
                 mut_,
-                name,
+                pattern,
                 ty,
                 init,
             }
-        } else if matches!(self.peek(), Some(TokenKind::Return)) {
+        } else {
             let t = self.advance().unwrap();
             let start = t.span.start;
             let value = if !matches!(self.peek(), Some(TokenKind::Semicolon) | None) {
@@ -378,23 +943,16 @@ impl Parser {
                 span: Span::new(start, end),
                 value,
             }
-        } else {
-            let expr = self.parse_expr()?;
-            self.expect_semicolon(expr.span());
-            Stmt::Expr {
-                span: expr.span(),
-                expr,
-            }
         };
         Some(stmt)
     }
 
     fn parse_expr(&mut self) -> Option<Expr> {
-        let lhs = self.parse_expr_add()?;
+        let lhs = self.parse_expr_binary(1)?;
         if matches!(self.peek(), Some(TokenKind::Assign)) {
             let start = lhs.span().start;
             self.advance();
-            let rhs = self.parse_expr_add()?;
+            let rhs = self.parse_expr_binary(1)?;
             return Some(Expr::Assign {
                 span: Span::new(start, rhs.span().end),
                 target: Box::new(lhs),
@@ -404,14 +962,25 @@ impl Parser {
         Some(lhs)
     }
 
-    fn parse_expr_add(&mut self) -> Option<Expr> {
-        let mut base = self.parse_expr_unary()?;
-        while matches!(self.peek(), Some(TokenKind::Plus)) {
-            let start = base.span().start;
-            self.advance();
-            let rhs = self.parse_expr_unary()?;
-            base = Expr::Add {
-                span: Span::new(start, rhs.span().end),
+    /// Precedence climbing over `BinaryOp::precedence`: only operators binding at least `min_prec` are consumed here.
+    fn parse_expr_binary(&mut self, min_prec: u8) -> Option<Expr> {
+        let mut base = self.parse_expr_cast()?;
+        let mut chained_comparison = false;
+        while let Some(op) = self.peek().and_then(binary_op) {
+            if op.precedence() < min_prec {
+                break;
+            }
+            let t = self.advance().unwrap();
+            if op.is_comparison() {
+                if chained_comparison {
+                    self.error("comparison operators cannot be chained", t.span);
+                }
+                chained_comparison = true;
+            }
+            let rhs = self.parse_expr_binary(op.precedence() + 1)?;
+            base = Expr::Binary {
+                span: Span::new(base.span().start, rhs.span().end),
+                op,
                 lhs: Box::new(base),
                 rhs: Box::new(rhs),
             };
@@ -419,7 +988,36 @@ impl Parser {
         Some(base)
     }
 
+    /// `expr as T`: binds tighter than any binary operator, looser than a unary one.
+    fn parse_expr_cast(&mut self) -> Option<Expr> {
+        let mut base = self.parse_expr_unary()?;
+        while matches!(self.peek(), Some(TokenKind::As)) {
+            self.advance();
+            let ty = self.parse_type()?;
+            base = Expr::Cast {
+                span: Span::new(base.span().start, self.last_end),
+                expr: Box::new(base),
+                ty,
+            };
+        }
+        Some(base)
+    }
+
     fn parse_expr_unary(&mut self) -> Option<Expr> {
+        let unary = match self.peek() {
+            Some(TokenKind::Minus) => Some(UnaryOp::Neg),
+            Some(TokenKind::Not) => Some(UnaryOp::Not),
+            _ => None,
+        };
+        if let Some(op) = unary {
+            let t = self.advance().unwrap();
+            let operand = self.parse_expr_unary()?;
+            return Some(Expr::Unary {
+                span: Span::new(t.span.start, operand.span().end),
+                op,
+                expr: Box::new(operand),
+            });
+        }
         if matches!(self.peek(), Some(TokenKind::Star)) {
             let t = self.advance().unwrap();
             let start = t.span.start;
@@ -446,56 +1044,101 @@ impl Parser {
         self.parse_expr_call()
     }
 
+    /// Arguments of a call up to and including the `)`, whose end is returned if present. The
+    /// parentheses delimit the arguments, so they may be struct literals even in a condition.
+    fn parse_call_args(&mut self) -> Option<(Vec<Expr>, Option<u32>)> {
+        let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, false);
+        let mut args = Vec::new();
+        while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
+            match self.parse_expr() {
+                Some(arg) => args.push(arg),
+                None => {
+                    self.no_struct_lit = no_struct_lit;
+                    return None;
+                }
+            }
+            if matches!(self.peek(), Some(TokenKind::Comma)) {
+                self.advance();
+            }
+        }
+        self.no_struct_lit = no_struct_lit;
+        Some((args, self.advance().map(|t| t.span.end)))
+    }
+
     fn parse_expr_call(&mut self) -> Option<Expr> {
         let mut base = self.parse_expr_primary()?;
         loop {
             if matches!(self.peek(), Some(TokenKind::Dot)) {
                 self.advance();
-                let name = match self.advance()?.kind {
+                let t = self.advance()?;
+                let name = match t.kind {
                     TokenKind::Ident(s) => s,
+                    // Tuple element: `pair.0`
+                    TokenKind::IntLiteral(n, None) => {
+                        base = Expr::Field {
+                            span: Span::new(base.span().start, t.span.end),
+                            receiver: Box::new(base),
+                            name: n.to_string(),
+                        };
+                        continue;
+                    }
                     _ => return None,
                 };
-                self.expect(TokenKind::LParen);
-                let mut args = Vec::new();
-                while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
-                    args.push(self.parse_expr()?);
-                    if matches!(self.peek(), Some(TokenKind::Comma)) {
-                        self.advance();
-                    }
+                if !matches!(self.peek(), Some(TokenKind::LParen)) {
+                    base = Expr::Field {
+                        span: Span::new(base.span().start, t.span.end),
+                        receiver: Box::new(base),
+                        name,
+                    };
+                    continue;
                 }
-                let end = self
-                    .advance()
-                    .map(|t| t.span.end)
-                    .unwrap_or(base.span().end);
+                self.advance();
+                let (args, end) = self.parse_call_args()?;
+                let end = end.unwrap_or(base.span().end);
                 base = Expr::Call {
                     span: Span::new(base.span().start, end),
                     receiver: Some(Box::new(base)),
+                    qualifier: Vec::new(),
                     name,
                     args,
                 };
             } else if matches!(self.peek(), Some(TokenKind::LParen)) {
-                let name = match &base {
-                    Expr::Ident { name, .. } => name.clone(),
+                let (qualifier, name) = match &base {
+                    Expr::Ident { name, .. } => (Vec::new(), name.clone()),
+                    Expr::Path { segments, .. } => {
+                        let (name, qualifier) = segments.split_last()?;
+                        (qualifier.to_vec(), name.clone())
+                    }
                     _ => return Some(base),
                 };
                 self.advance();
-                let mut args = Vec::new();
-                while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
-                    args.push(self.parse_expr()?);
-                    if matches!(self.peek(), Some(TokenKind::Comma)) {
-                        self.advance();
-                    }
-                }
-                let end = self
-                    .advance()
-                    .map(|t| t.span.end)
-                    .unwrap_or(base.span().end);
+                let (args, end) = self.parse_call_args()?;
+                let end = end.unwrap_or(base.span().end);
                 base = Expr::Call {
                     span: Span::new(base.span().start, end),
                     receiver: None,
+                    qualifier,
                     name,
                     args,
                 };
+            } else if matches!(self.peek(), Some(TokenKind::Question)) {
+                let end = self.advance().unwrap().span.end;
+                base = Expr::Try {
+                    span: Span::new(base.span().start, end),
+                    expr: Box::new(base),
+                };
+            } else if matches!(self.peek(), Some(TokenKind::LBracket)) {
+                self.advance();
+                let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, false);
+                let index = self.parse_expr();
+                self.no_struct_lit = no_struct_lit;
+                let index = index?;
+                let end = self.expect(TokenKind::RBracket)?.end;
+                base = Expr::Index {
+                    span: Span::new(base.span().start, end),
+                    base: Box::new(base),
+                    index: Box::new(index),
+                };
             } else {
                 break;
             }
@@ -507,14 +1150,31 @@ impl Parser {
         let t = self.advance()?;
         let start = t.span.start;
         let expr = match &t.kind {
-            TokenKind::IntLiteral(n) => Expr::IntLiteral {
-                span: t.span,
-                value: *n,
+            TokenKind::IntLiteral(n, suffix) => self.number_literal(t.span, *n, suffix.as_deref()),
+            TokenKind::FloatLiteral(value, suffix) => match suffix.as_deref() {
+                None | Some("f64") => Expr::FloatLiteral {
+                    span: t.span,
+                    value: *value,
+                },
+                Some(other) => {
+                    self.error(
+                        format!("invalid suffix `{}` for a float literal", other),
+                        t.span,
+                    );
+                    Expr::FloatLiteral {
+                        span: t.span,
+                        value: *value,
+                    }
+                }
             },
             TokenKind::StringLiteral(s) => Expr::StringLiteral {
                 span: t.span,
                 value: s.clone(),
             },
+            TokenKind::InterpolatedString(segments) => Expr::Interpolation {
+                span: t.span,
+                parts: self.parse_interpolation(segments)?,
+            },
             TokenKind::True => Expr::BoolLiteral {
                 span: t.span,
                 value: true,
@@ -523,15 +1183,150 @@ impl Parser {
                 span: t.span,
                 value: false,
             },
+            TokenKind::If => self.parse_if(start)?,
+            TokenKind::While | TokenKind::Loop | TokenKind::For => {
+                self.parse_loop(t.kind.clone(), None, start)?
+            }
+            TokenKind::Label(label) => {
+                self.expect(TokenKind::Colon);
+                let kw = self.advance()?;
+                if !matches!(kw.kind, TokenKind::While | TokenKind::Loop | TokenKind::For) {
+                    self.error("expected `while`, `loop` or `for` after a label", kw.span);
+                    return None;
+                }
+                self.parse_loop(kw.kind, Some(label.clone()), start)?
+            }
+            TokenKind::Break => {
+                let label = self.parse_opt_label();
+                let value = if matches!(
+                    self.peek(),
+                    Some(TokenKind::Semicolon) | Some(TokenKind::RBrace) | None
+                ) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expr()?))
+                };
+                let end = value.as_ref().map(|v| v.span().end).unwrap_or(t.span.end);
+                Expr::Break {
+                    span: Span::new(start, end),
+                    label,
+                    value,
+                }
+            }
+            TokenKind::Continue => Expr::Continue {
+                span: t.span,
+                label: self.parse_opt_label(),
+            },
+            TokenKind::None => Expr::Variant {
+                span: t.span,
+                variant: BuiltinVariant::None,
+                value: None,
+            },
+            TokenKind::Some | TokenKind::Ok | TokenKind::Err => {
+                let variant = builtin_variant(&t.kind).unwrap();
+                self.expect(TokenKind::LParen)?;
+                let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, false);
+                let value = self.parse_expr();
+                self.no_struct_lit = no_struct_lit;
+                let value = value?;
+                let end = self.expect(TokenKind::RParen).map(|s| s.end)?;
+                Expr::Variant {
+                    span: Span::new(start, end),
+                    variant,
+                    value: Some(Box::new(value)),
+                }
+            }
+            TokenKind::LBrace => {
+                let block = self.parse_block_body(start)?;
+                Expr::Block {
+                    span: block.span,
+                    block,
+                }
+            }
+            TokenKind::LParen if matches!(self.peek(), Some(TokenKind::RParen)) => {
+                let end = self.advance().unwrap().span.end;
+                Expr::UnitLiteral {
+                    span: Span::new(start, end),
+                }
+            }
+            TokenKind::Pipe | TokenKind::OrOr => self.parse_closure(start, &t.kind)?,
+            TokenKind::LBracket => {
+                let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, false);
+                let mut elements = Vec::new();
+                while !matches!(self.peek(), Some(TokenKind::RBracket) | None) {
+                    let Some(e) = self.parse_expr() else {
+                        self.no_struct_lit = no_struct_lit;
+                        return None;
+                    };
+                    elements.push(e);
+                    if elements.len() == 1 && matches!(self.peek(), Some(TokenKind::Semicolon)) {
+                        // `[value; count]`
+                        self.advance();
+                        let count = self.parse_expr();
+                        self.no_struct_lit = no_struct_lit;
+                        let count = count?;
+                        let end = self.expect(TokenKind::RBracket)?.end;
+                        return Some(Expr::ArrayRepeat {
+                            span: Span::new(start, end),
+                            value: Box::new(elements.remove(0)),
+                            count: Box::new(count),
+                        });
+                    }
+                    if !matches!(self.peek(), Some(TokenKind::Comma)) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.no_struct_lit = no_struct_lit;
+                let end = self.expect(TokenKind::RBracket)?.end;
+                Expr::ArrayLiteral {
+                    span: Span::new(start, end),
+                    elements,
+                }
+            }
+            TokenKind::LParen => {
+                let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, false);
+                let inner = self.parse_expr();
+                // `(a, b, ..)` is a tuple; `(a)` is just `a`.
+                let mut elements = Vec::new();
+                let mut comma = false;
+                while inner.is_some() && matches!(self.peek(), Some(TokenKind::Comma)) {
+                    self.advance();
+                    comma = true;
+                    if matches!(self.peek(), Some(TokenKind::RParen)) {
+                        break;
+                    }
+                    let Some(e) = self.parse_expr() else {
+                        self.no_struct_lit = no_struct_lit;
+                        return None;
+                    };
+                    elements.push(e);
+                }
+                self.no_struct_lit = no_struct_lit;
+                let inner = inner?;
+                let end = self.expect(TokenKind::RParen)?.end;
+                if !comma {
+                    return Some(inner);
+                }
+                if elements.is_empty() {
+                    self.error("a tuple needs at least two elements", Span::new(start, end));
+                    return None;
+                }
+                elements.insert(0, inner);
+                Expr::Tuple {
+                    span: Span::new(start, end),
+                    elements,
+                }
+            }
             TokenKind::Match => {
-                let value = Box::new(self.parse_expr()?);
+                let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, true);
+                let value = self.parse_expr();
+                self.no_struct_lit = no_struct_lit;
+                let value = Box::new(value?);
                 self.expect(TokenKind::LBrace);
                 let mut arms = Vec::new();
                 while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
-                    let pat = self.parse_match_pattern()?;
-                    self.expect(TokenKind::FatArrow);
-                    let arm_expr = self.parse_expr()?;
-                    arms.push((pat, arm_expr));
+                    arms.push(self.parse_match_arm()?);
                     if matches!(self.peek(), Some(TokenKind::Comma)) {
                         self.advance();
                     }
@@ -543,6 +1338,10 @@ impl Parser {
                     arms,
                 }
             }
+            TokenKind::SelfValue => Expr::Ident {
+                span: t.span,
+                name: "self".to_string(),
+            },
             TokenKind::Ident(name) => {
                 let mut segments = vec![name.clone()];
                 while matches!(self.peek(), Some(TokenKind::ColonColon)) {
@@ -556,7 +1355,7 @@ impl Parser {
                         span: t.span,
                         name: name.clone(),
                     }
-                } else if matches!(self.peek(), Some(TokenKind::LBrace)) {
+                } else if !self.no_struct_lit && matches!(self.peek(), Some(TokenKind::LBrace)) {
                     self.advance();
                     let mut fields = Vec::new();
                     while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
@@ -592,21 +1391,467 @@ impl Parser {
         Some(expr)
     }
 
+    /// The parts of an interpolated string: each `{..}` must hold exactly one expression.
+    fn parse_interpolation(
+        &mut self,
+        segments: &[StringSegment],
+    ) -> Option<Vec<InterpolationPart>> {
+        let mut parts = Vec::with_capacity(segments.len());
+        for segment in segments {
+            let tokens = match segment {
+                StringSegment::Text(s) => {
+                    parts.push(InterpolationPart::Text(s.clone()));
+                    continue;
+                }
+                StringSegment::Unclosed(span) => {
+                    self.error(
+                        "unclosed `{` in string: write `{{` for a literal brace",
+                        *span,
+                    );
+                    return None;
+                }
+                StringSegment::Code(tokens) => tokens,
+            };
+            if let [only] = tokens.as_slice() {
+                self.error(
+                    "empty `{}` in string: write `{{}}` for literal braces",
+                    only.span,
+                );
+                return None;
+            }
+            let mut inner = Parser {
+                tokens: tokens.clone().into_iter().peekable(),
+                file_id: self.file_id,
+                diags: Vec::new(),
+                no_struct_lit: false,
+                last_end: tokens[0].span.start,
+            };
+            let expr = inner.parse_expr();
+            if expr.is_some() {
+                if let Some(t) = inner.tokens.next().filter(|t| !t.is_eof()) {
+                    inner.error("expected `}` after the interpolated expression", t.span);
+                }
+            }
+            let failed = !inner.diags.is_empty();
+            self.diags.append(&mut inner.diags);
+            if failed {
+                return None;
+            }
+            parts.push(InterpolationPart::Expr(expr?));
+        }
+        Some(parts)
+    }
+
+    /// `|x, y: int| body` or `|| body`; `opening` (`|` or `||`) has been consumed. A declared
+    /// return type must be followed by a block: `|x| -> int { x }`.
+    fn parse_closure(&mut self, start: u32, opening: &TokenKind) -> Option<Expr> {
+        let mut params = Vec::new();
+        if *opening == TokenKind::Pipe {
+            while !matches!(self.peek(), Some(TokenKind::Pipe) | None) {
+                let t = self.advance()?;
+                let TokenKind::Ident(name) = t.kind else {
+                    self.error("expected a closure parameter name", t.span);
+                    return None;
+                };
+                let ty = if matches!(self.peek(), Some(TokenKind::Colon)) {
+                    self.advance();
+                    Some(self.parse_type()?)
+                } else {
+                    None
+                };
+                params.push((name, ty));
+                if !matches!(self.peek(), Some(TokenKind::Comma)) {
+                    break;
+                }
+                self.advance();
+            }
+            self.expect(TokenKind::Pipe)?;
+        }
+        let ret = if matches!(self.peek(), Some(TokenKind::Arrow)) {
+            self.advance();
+            let ret = self.parse_type()?;
+            if !matches!(self.peek(), Some(TokenKind::LBrace)) {
+                self.error(
+                    "expected `{` after the closure's return type",
+                    Span::new(start, self.last_end),
+                );
+                return None;
+            }
+            Some(ret)
+        } else {
+            None
+        };
+        let body = self.parse_expr()?;
+        Some(Expr::Closure {
+            span: Span::new(start, body.span().end),
+            params,
+            ret,
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_opt_label(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(TokenKind::Label(l)) => {
+                let l = l.clone();
+                self.advance();
+                Some(l)
+            }
+            _ => None,
+        }
+    }
+
+    /// The loop keyword `kw` (and any label) has been consumed.
+    fn parse_loop(&mut self, kw: TokenKind, label: Option<String>, start: u32) -> Option<Expr> {
+        let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, true);
+        let header = match kw {
+            TokenKind::While => self.parse_expr().map(|c| (None, Some(c))),
+            TokenKind::For => self.parse_for_header().map(|(v, i)| (Some(v), Some(i))),
+            _ => Some((None, None)),
+        };
+        self.no_struct_lit = no_struct_lit;
+        let (var, head) = header?;
+        if !matches!(self.peek(), Some(TokenKind::LBrace)) {
+            let span = self
+                .tokens
+                .peek()
+                .map(|t| t.span)
+                .unwrap_or(Span::new(start, start));
+            self.error("expected `{` before loop body", span);
+            return None;
+        }
+        let body = self.parse_block()?;
+        let span = Span::new(start, body.span.end);
+        Some(match (var, head) {
+            (Some((var, var_span)), Some(iter)) => Expr::For {
+                span,
+                label,
+                var,
+                var_span,
+                iter: Box::new(iter),
+                body,
+            },
+            (None, Some(cond)) => Expr::While {
+                span,
+                label,
+                cond: Box::new(cond),
+                body,
+            },
+            _ => Expr::Loop { span, label, body },
+        })
+    }
+
+    /// `var in start..end` (or any expression after `in`).
+    fn parse_for_header(&mut self) -> Option<((String, Span), Expr)> {
+        let t = self.advance()?;
+        let var = match t.kind {
+            TokenKind::Ident(s) => (s, t.span),
+            _ => {
+                self.error("expected loop variable after `for`", t.span);
+                return None;
+            }
+        };
+        self.expect(TokenKind::In);
+        let iter = self.parse_expr_binary(1)?;
+        if !matches!(self.peek(), Some(TokenKind::DotDot)) {
+            return Some((var, iter));
+        }
+        self.advance();
+        let end = self.parse_expr_binary(1)?;
+        Some((
+            var,
+            Expr::Range {
+                span: Span::new(iter.span().start, end.span().end),
+                start: Box::new(iter),
+                end: Box::new(end),
+            },
+        ))
+    }
+
+    /// `if` has been consumed. `else if` nests the chained `If` as the value of the else block.
+    fn parse_if(&mut self, start: u32) -> Option<Expr> {
+        let no_struct_lit = std::mem::replace(&mut self.no_struct_lit, true);
+        let cond = self.parse_expr();
+        self.no_struct_lit = no_struct_lit;
+        let cond = Box::new(cond?);
+        if !matches!(self.peek(), Some(TokenKind::LBrace)) {
+            let span = self.tokens.peek().map(|t| t.span).unwrap_or(cond.span());
+            self.error("expected `{` after if condition", span);
+            return None;
+        }
+        let then_block = self.parse_block()?;
+        let mut end = then_block.span.end;
+        let else_block = if matches!(self.peek(), Some(TokenKind::Else)) {
+            self.advance();
+            let block = match self.peek() {
+                Some(TokenKind::If) => {
+                    let t = self.advance().unwrap();
+                    let nested = self.parse_if(t.span.start)?;
+                    Block {
+                        span: nested.span(),
+                        stmts: Vec::new(),
+                        value: Some(Box::new(nested)),
+                    }
+                }
+                Some(TokenKind::LBrace) => self.parse_block()?,
+                _ => {
+                    let span = self.tokens.peek().map(|t| t.span).unwrap_or(cond.span());
+                    self.error("expected `{` or `if` after else", span);
+                    return None;
+                }
+            };
+            end = block.span.end;
+            Some(block)
+        } else {
+            None
+        };
+        Some(Expr::If {
+            span: Span::new(start, end),
+            cond,
+            then_block,
+            else_block,
+        })
+    }
+
+    /// `pattern => body` or `pattern if guard => body`.
+    fn parse_match_arm(&mut self) -> Option<MatchArm> {
+        let start = self.tokens.peek()?.span.start;
+        let pattern = self.parse_match_pattern()?;
+        let guard = if matches!(self.peek(), Some(TokenKind::If)) {
+            self.advance();
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        let span = Span::new(start, self.last_end);
+        self.expect(TokenKind::FatArrow);
+        let body = self.parse_expr()?;
+        Some(MatchArm {
+            span,
+            pattern,
+            guard,
+            body,
+        })
+    }
+
+    /// Alternatives separated by `|`.
     fn parse_match_pattern(&mut self) -> Option<MatchPattern> {
+        let first = self.parse_single_pattern()?;
+        if !matches!(self.peek(), Some(TokenKind::Pipe)) {
+            return Some(first);
+        }
+        let mut alts = vec![first];
+        while matches!(self.peek(), Some(TokenKind::Pipe)) {
+            self.advance();
+            alts.push(self.parse_single_pattern()?);
+        }
+        Some(MatchPattern::Or(alts))
+    }
+
+    fn parse_single_pattern(&mut self) -> Option<MatchPattern> {
         let t = self.advance()?;
         let pat = match &t.kind {
-            TokenKind::IntLiteral(n) => MatchPattern::Int(*n),
+            TokenKind::IntLiteral(..) | TokenKind::Minus => {
+                let start = self.pattern_int(t)?;
+                let inclusive = match self.peek() {
+                    Some(TokenKind::DotDot) => false,
+                    Some(TokenKind::DotDotEq) => true,
+                    _ => return Some(MatchPattern::Int(start)),
+                };
+                self.advance();
+                let t = self.advance()?;
+                let end = self.pattern_int(t)?;
+                MatchPattern::Range {
+                    start,
+                    end,
+                    inclusive,
+                }
+            }
             TokenKind::True => MatchPattern::Bool(true),
             TokenKind::False => MatchPattern::Bool(false),
             TokenKind::StringLiteral(s) => MatchPattern::String(s.clone()),
             TokenKind::Underscore => MatchPattern::Underscore,
+            TokenKind::Ident(name)
+                if matches!(
+                    self.peek(),
+                    Some(TokenKind::ColonColon | TokenKind::LBrace | TokenKind::LParen)
+                ) =>
+            {
+                let mut path = vec![name.clone()];
+                while matches!(self.peek(), Some(TokenKind::ColonColon)) {
+                    self.advance();
+                    match self.advance()?.kind {
+                        TokenKind::Ident(s) => path.push(s),
+                        _ => return None,
+                    }
+                }
+                let fields = match self.peek() {
+                    Some(TokenKind::LParen) => {
+                        self.advance();
+                        let mut pats = Vec::new();
+                        while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
+                            pats.push(self.parse_match_pattern()?);
+                            if matches!(self.peek(), Some(TokenKind::Comma)) {
+                                self.advance();
+                            }
+                        }
+                        self.expect(TokenKind::RParen)?;
+                        PatternFields::Tuple(pats)
+                    }
+                    Some(TokenKind::LBrace) => self.parse_struct_pattern_fields()?,
+                    _ => PatternFields::Unit,
+                };
+                MatchPattern::Path { path, fields }
+            }
+            TokenKind::Ident(name) => MatchPattern::Binding(name.clone()),
+            TokenKind::LParen => {
+                let mut pats = Vec::new();
+                while !matches!(self.peek(), Some(TokenKind::RParen) | None) {
+                    pats.push(self.parse_match_pattern()?);
+                    if !matches!(self.peek(), Some(TokenKind::Comma)) {
+                        break;
+                    }
+                    self.advance();
+                }
+                let end = self.expect(TokenKind::RParen)?.end;
+                if pats.len() < 2 {
+                    self.error(
+                        "a tuple pattern needs at least two elements",
+                        Span::new(t.span.start, end),
+                    );
+                    return None;
+                }
+                MatchPattern::Tuple(pats)
+            }
+            TokenKind::None => MatchPattern::Variant(BuiltinVariant::None, None),
+            TokenKind::Some | TokenKind::Ok | TokenKind::Err => {
+                let variant = builtin_variant(&t.kind).unwrap();
+                self.expect(TokenKind::LParen)?;
+                let inner = self.parse_match_pattern()?;
+                self.expect(TokenKind::RParen)?;
+                MatchPattern::Variant(variant, Some(Box::new(inner)))
+            }
             _ => {
-                self.error("expected match pattern (literal or _)", t.span);
+                self.error("expected match pattern", t.span);
                 return None;
             }
         };
         Some(pat)
     }
+
+    /// `42`, `10i64`, `255u8`: an integer literal with an optional type suffix; `2f64` is a float.
+    /// After an error the literal is kept without its suffix so that parsing goes on.
+    fn number_literal(&mut self, span: Span, value: u128, suffix: Option<&str>) -> Expr {
+        if value > u64::MAX as u128 {
+            self.error("integer literal is too large", span);
+        }
+        let suffix = match suffix {
+            None => None,
+            Some(name) => match Type::primitive(name) {
+                Some(Type::F64) => {
+                    return Expr::FloatLiteral {
+                        span,
+                        value: value as f64,
+                    }
+                }
+                Some(ty) if ty.is_numeric() => Some(ty),
+                _ => {
+                    self.error(
+                        format!("invalid suffix `{}` for a number literal", name),
+                        span,
+                    );
+                    None
+                }
+            },
+        };
+        Expr::IntLiteral {
+            span,
+            value: value.min(u64::MAX as u128) as u64,
+            suffix,
+        }
+    }
+
+    /// Integer in a pattern: a literal, optionally negated (`t` is its first token).
+    fn pattern_int(&mut self, t: Token) -> Option<i64> {
+        let (negate, t) = match t.kind {
+            TokenKind::Minus => (true, self.advance()?),
+            _ => (false, t),
+        };
+        match t.kind {
+            TokenKind::IntLiteral(n, None) if n <= u64::MAX as u128 => {
+                let n = n as u64 as i64;
+                Some(if negate { n.wrapping_neg() } else { n })
+            }
+            TokenKind::IntLiteral(_, None) => {
+                self.error("integer literal is too large", t.span);
+                None
+            }
+            _ => {
+                self.error("expected integer literal in pattern", t.span);
+                None
+            }
+        }
+    }
+
+    /// `{ x, y: pattern, .. }`: `{` is next. A bare field name binds the field to a local of the same name.
+    fn parse_struct_pattern_fields(&mut self) -> Option<PatternFields> {
+        self.advance();
+        let mut fields = Vec::new();
+        let mut rest = false;
+        while !matches!(self.peek(), Some(TokenKind::RBrace) | None) {
+            let t = self.advance()?;
+            match t.kind {
+                TokenKind::DotDot => rest = true,
+                TokenKind::Ident(name) => {
+                    let pat = if matches!(self.peek(), Some(TokenKind::Colon)) {
+                        self.advance();
+                        self.parse_match_pattern()?
+                    } else {
+                        MatchPattern::Binding(name.clone())
+                    };
+                    fields.push((name, pat));
+                }
+                _ => {
+                    self.error("expected field pattern", t.span);
+                    return None;
+                }
+            }
+            if matches!(self.peek(), Some(TokenKind::Comma)) {
+                self.advance();
+            }
+        }
+        self.expect(TokenKind::RBrace)?;
+        Some(PatternFields::Struct { fields, rest })
+    }
+}
+
+fn builtin_variant(kind: &TokenKind) -> Option<BuiltinVariant> {
+    Some(match kind {
+        TokenKind::Some => BuiltinVariant::Some,
+        TokenKind::None => BuiltinVariant::None,
+        TokenKind::Ok => BuiltinVariant::Ok,
+        TokenKind::Err => BuiltinVariant::Err,
+        _ => return None,
+    })
+}
+
+fn binary_op(kind: &TokenKind) -> Option<BinaryOp> {
+    Some(match kind {
+        TokenKind::OrOr => BinaryOp::Or,
+        TokenKind::AndAnd => BinaryOp::And,
+        TokenKind::Eq => BinaryOp::Eq,
+        TokenKind::Ne => BinaryOp::Ne,
+        TokenKind::Lt => BinaryOp::Lt,
+        TokenKind::Le => BinaryOp::Le,
+        TokenKind::Gt => BinaryOp::Gt,
+        TokenKind::Ge => BinaryOp::Ge,
+        TokenKind::Plus => BinaryOp::Add,
+        TokenKind::Minus => BinaryOp::Sub,
+        TokenKind::Star => BinaryOp::Mul,
+        TokenKind::Slash => BinaryOp::Div,
+        TokenKind::Percent => BinaryOp::Rem,
+        _ => return None,
+    })
 }
 
 #[cfg(test)]
@@ -635,6 +1880,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_resource_struct() {
+        let src = "export resource struct Coin { value: int }\nstruct Point { x: int }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        match (&root.items[0], &root.items[1]) {
+            (Item::Struct(coin), Item::Struct(point)) => {
+                assert_eq!(coin.vis, Visibility::Exported);
+                assert!(coin.resource);
+                assert!(!point.resource);
+            }
+            _ => panic!("expected structs"),
+        }
+    }
+
     #[test]
     fn parse_struct_fields_comma_delimited() {
         let src = "struct Point { x: int, y: int }";
@@ -697,6 +1957,225 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_qualified_call() {
+        let src = "fn main() -> () { greet::greet(1); }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        match &root.items[0] {
+            Item::Fn(f) => match &f.body.stmts[0] {
+                knox_syntax::ast::Stmt::Expr {
+                    expr:
+                        knox_syntax::ast::Expr::Call {
+                            qualifier,
+                            name,
+                            args,
+                            receiver: None,
+                            ..
+                        },
+                    ..
+                } => {
+                    assert_eq!(qualifier, &["greet"]);
+                    assert_eq!(name, "greet");
+                    assert_eq!(args.len(), 1);
+                }
+                _ => panic!("expected qualified call statement"),
+            },
+            _ => panic!("expected fn main"),
+        }
+    }
+
+    #[test]
+    fn parse_if_else_chain() {
+        let src = r#"
+fn main() -> () {
+  if a { print(1); } else if b { print(2); } else { print(3); }
+  let x = if c { 1 } else { 2 };
+}
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn main");
+        };
+        assert_eq!(f.body.stmts.len(), 2);
+        assert!(f.body.value.is_none());
+        match &f.body.stmts[0] {
+            Stmt::Expr {
+                expr:
+                    Expr::If {
+                        else_block: Some(else_block),
+                        ..
+                    },
+                ..
+            } => {
+                assert!(else_block.stmts.is_empty());
+                assert!(matches!(
+                    else_block.value.as_deref(),
+                    Some(Expr::If {
+                        else_block: Some(_),
+                        ..
+                    })
+                ));
+            }
+            other => panic!("expected if statement, got {:?}", other),
+        }
+        match &f.body.stmts[1] {
+            Stmt::Let {
+                init: Expr::If { then_block, .. },
+                ..
+            } => assert!(then_block.value.is_some()),
+            other => panic!("expected let with if, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_if_condition_is_not_struct_literal() {
+        let src = "fn main() -> () { if m::flag { print(1); } }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn main");
+        };
+        match f.body.value.as_deref() {
+            Some(Expr::If { cond, .. }) => assert!(matches!(**cond, Expr::Path { .. })),
+            other => panic!("expected if, got {:?}", other),
+        }
+
+        // Inside a call's parentheses a struct literal is allowed again.
+        let src = "fn main() -> () { match m.get(m::K { a: 1 }) { _ => 0 }; if ok(m::S { a: 1 }) { print(1); } }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn main");
+        };
+        match f.body.value.as_deref() {
+            Some(Expr::If { cond, .. }) => assert!(matches!(
+                &**cond,
+                Expr::Call { args, .. } if matches!(args[..], [Expr::StructLiteral { .. }])
+            )),
+            other => panic!("expected if, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_loops_with_labels() {
+        let src = r#"
+fn main() -> () {
+  'outer: for i in 0..n + 1 {
+    while ok { continue 'outer; }
+  }
+  let v = loop { break 5; };
+}
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn main");
+        };
+        assert_eq!(f.body.stmts.len(), 2);
+        match &f.body.stmts[0] {
+            Stmt::Expr {
+                expr:
+                    Expr::For {
+                        label,
+                        var,
+                        iter,
+                        body,
+                        ..
+                    },
+                ..
+            } => {
+                assert_eq!(label.as_deref(), Some("outer"));
+                assert_eq!(var, "i");
+                assert!(matches!(
+                    &**iter,
+                    Expr::Range { end, .. } if matches!(**end, Expr::Binary { .. })
+                ));
+                assert!(matches!(
+                    body.value.as_deref(),
+                    Some(Expr::While { body, .. })
+                        if matches!(
+                            &body.stmts[0],
+                            Stmt::Expr { expr: Expr::Continue { label: Some(l), .. }, .. } if l == "outer"
+                        )
+                ));
+            }
+            other => panic!("expected for loop, got {:?}", other),
+        }
+        match &f.body.stmts[1] {
+            Stmt::Let {
+                init: Expr::Loop { body, .. },
+                ..
+            } => assert!(matches!(
+                &body.stmts[0],
+                Stmt::Expr {
+                    expr: Expr::Break { value: Some(_), .. },
+                    ..
+                }
+            )),
+            other => panic!("expected let with loop, got {:?}", other),
+        }
+    }
+
+    /// Parse a single expression statement and render it fully parenthesized.
+    fn parse_expr_src(expr: &str) -> String {
+        fn show(e: &Expr) -> String {
+            match e {
+                Expr::IntLiteral { value, .. } => value.to_string(),
+                Expr::Ident { name, .. } => name.clone(),
+                Expr::Binary { op, lhs, rhs, .. } => {
+                    format!("({} {} {})", show(lhs), op.as_str(), show(rhs))
+                }
+                Expr::Unary { op, expr, .. } => format!("({:?} {})", op, show(expr)),
+                Expr::Cast { expr, ty, .. } => format!("({} as {})", show(expr), ty),
+                other => panic!("unexpected expression {:?}", other),
+            }
+        }
+        let src = format!("fn main() -> () {{ let r = {}; }}", expr);
+        let tokens = Lexer::new(&src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        match &root.items[0] {
+            Item::Fn(f) => match &f.body.stmts[0] {
+                Stmt::Let { init, .. } => show(init),
+                other => panic!("expected let, got {:?}", other),
+            },
+            _ => panic!("expected fn main"),
+        }
+    }
+
+    #[test]
+    fn parse_operator_precedence() {
+        assert_eq!(parse_expr_src("a + b * c - d"), "((a + (b * c)) - d)");
+        assert_eq!(parse_expr_src("(a + b) * c % 2"), "(((a + b) * c) % 2)");
+        assert_eq!(
+            parse_expr_src("a < b && c == d || !e"),
+            "(((a < b) && (c == d)) || (Not e))"
+        );
+        assert_eq!(parse_expr_src("-a - -b"), "((Neg a) - (Neg b))");
+        assert_eq!(parse_expr_src("a / b >= c"), "((a / b) >= c)");
+        assert_eq!(
+            parse_expr_src("-x as i64 * 2 as i64"),
+            "(((Neg x) as i64) * (2 as i64))"
+        );
+    }
+
+    #[test]
+    fn parse_invalid_number_suffix_errors() {
+        let src = "fn main() -> () { let r = 3str; }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let errs = parse(tokens, FileId::new(0)).expect_err("expected parse error");
+        assert_eq!(errs[0].message, "invalid suffix `str` for a number literal");
+    }
+
+    #[test]
+    fn parse_chained_comparison_errors() {
+        let src = "fn main() -> () { let r = a < b < c; }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let errs = parse(tokens, FileId::new(0)).expect_err("expected parse error");
+        assert_eq!(errs[0].message, "comparison operators cannot be chained");
+    }
+
     #[test]
     fn parse_match_expr() {
         let src = r#"
@@ -707,6 +2186,7 @@ fn main() -> () {
     1 => 20,
     _ => 30
   };
+  match y { 10 => print("ten"), _ => print("other") }
   print("ok");
 }
 "#;
@@ -716,7 +2196,7 @@ fn main() -> () {
         match &root.items[0] {
             Item::Fn(f) => {
                 assert_eq!(f.name, "main");
-                assert_eq!(f.body.stmts.len(), 3); // let x, let y, print
+                assert_eq!(f.body.stmts.len(), 4); // let x, let y, match (no `;` needed), print
                 if let knox_syntax::ast::Stmt::Let {
                     init: knox_syntax::ast::Expr::Match { arms, .. },
                     ..
@@ -730,4 +2210,627 @@ fn main() -> () {
             _ => panic!("expected fn main"),
         }
     }
+
+    #[test]
+    fn parse_option_result_and_try() {
+        let src = r#"
+fn f(r: Result<int, string>) -> Option<Option<int>> {
+  let v = r?;
+  match Some(v) {
+    Some(n) => Some(Some(n)),
+    None => { return None; }
+  }
+}
+fn main() -> () { let u: Result<(), string> = Ok(()); }
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn f");
+        };
+        assert_eq!(f.params[0].ty.to_string(), "Result<int, string>");
+        assert_eq!(f.return_ty.to_string(), "Option<Option<int>>");
+        assert!(matches!(
+            &f.body.stmts[0],
+            Stmt::Let {
+                init: Expr::Try { .. },
+                ..
+            }
+        ));
+        let Some(Expr::Match { value, arms, .. }) = f.body.value.as_deref() else {
+            panic!("expected match as the block value");
+        };
+        assert!(matches!(
+            **value,
+            Expr::Variant {
+                variant: BuiltinVariant::Some,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &arms[0].pattern,
+            MatchPattern::Variant(BuiltinVariant::Some, Some(p)) if matches!(**p, MatchPattern::Binding(ref n) if n == "n")
+        ));
+        assert!(matches!(
+            arms[1],
+            MatchArm {
+                pattern: MatchPattern::Variant(BuiltinVariant::None, None),
+                body: Expr::Block { .. },
+                ..
+            }
+        ));
+        let Item::Fn(main) = &root.items[1] else {
+            panic!("expected fn main");
+        };
+        assert!(matches!(
+            &main.body.stmts[0],
+            Stmt::Let { init: Expr::Variant { variant: BuiltinVariant::Ok, value: Some(v), .. }, .. }
+                if matches!(**v, Expr::UnitLiteral { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_enum_decl_and_patterns() {
+        let src = r#"
+export enum Shape { Empty, Circle(int), Rect { w: int, h: int }, }
+fn area(s: Shape) -> int {
+  match s {
+    Shape::Empty => 0,
+    Shape::Circle(r) => r * r * 3,
+    Shape::Rect { w, h: 0, .. } => w,
+    shapes::Shape::Rect { .. } => 1,
+  }
+}
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Enum(e) = &root.items[0] else {
+            panic!("expected enum Shape");
+        };
+        assert_eq!(e.vis, Visibility::Exported);
+        assert_eq!(e.variants.len(), 3);
+        assert!(matches!(e.variants[0].payload, VariantPayload::Unit));
+        assert!(matches!(&e.variants[1].payload, VariantPayload::Tuple(t) if t.len() == 1));
+        assert!(matches!(&e.variants[2].payload, VariantPayload::Struct(f) if f.len() == 2));
+        let Item::Fn(f) = &root.items[1] else {
+            panic!("expected fn area");
+        };
+        let Some(Expr::Match { arms, .. }) = f.body.value.as_deref() else {
+            panic!("expected match as the block value");
+        };
+        let pats: Vec<&MatchPattern> = arms.iter().map(|a| &a.pattern).collect();
+        assert!(matches!(
+            pats[0],
+            MatchPattern::Path { path, fields: PatternFields::Unit } if path.join("::") == "Shape::Empty"
+        ));
+        assert!(matches!(
+            pats[1],
+            MatchPattern::Path { fields: PatternFields::Tuple(p), .. }
+                if matches!(p.as_slice(), [MatchPattern::Binding(n)] if n == "r")
+        ));
+        let MatchPattern::Path {
+            fields: PatternFields::Struct { fields, rest },
+            ..
+        } = pats[2]
+        else {
+            panic!("expected struct variant pattern");
+        };
+        assert!(*rest);
+        assert!(matches!(&fields[0], (n, MatchPattern::Binding(b)) if n == "w" && b == "w"));
+        assert!(matches!(&fields[1], (n, MatchPattern::Int(0)) if n == "h"));
+        assert!(matches!(
+            pats[3],
+            MatchPattern::Path { path, fields: PatternFields::Struct { rest: true, .. } }
+                if path.len() == 3
+        ));
+    }
+
+    #[test]
+    fn parse_guards_or_and_range_patterns() {
+        let src = r#"
+fn f(n: int, p: Point) -> int {
+  match n {
+    1 | 2 => 0,
+    -5..=-1 => 1,
+    10..20 => 2,
+    x if x > 100 => 3,
+    _ => 4,
+  };
+  match p { Point { x: 0 | 1, .. } => 5, _ => 6 }
+}
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn f");
+        };
+        let Stmt::Expr {
+            expr: Expr::Match { arms, .. },
+            ..
+        } = &f.body.stmts[0]
+        else {
+            panic!("expected match statement");
+        };
+        assert!(matches!(
+            &arms[0].pattern,
+            MatchPattern::Or(alts) if matches!(alts.as_slice(), [MatchPattern::Int(1), MatchPattern::Int(2)])
+        ));
+        assert!(matches!(
+            arms[1].pattern,
+            MatchPattern::Range {
+                start: -5,
+                end: -1,
+                inclusive: true
+            }
+        ));
+        assert!(matches!(
+            arms[2].pattern,
+            MatchPattern::Range {
+                start: 10,
+                end: 20,
+                inclusive: false
+            }
+        ));
+        assert!(arms[2].guard.is_none());
+        assert!(matches!(
+            &arms[3],
+            MatchArm { pattern: MatchPattern::Binding(x), guard: Some(Expr::Binary { .. }), .. } if x == "x"
+        ));
+        // The arm span covers the pattern and guard: `x if x > 100`.
+        assert_eq!(arms[3].span.end - arms[3].span.start, 12);
+        let Some(Expr::Match { arms, .. }) = f.body.value.as_deref() else {
+            panic!("expected match as the block value");
+        };
+        let MatchPattern::Path {
+            path,
+            fields: PatternFields::Struct { fields, rest: true },
+        } = &arms[0].pattern
+        else {
+            panic!("expected struct pattern");
+        };
+        assert_eq!(path, &["Point".to_string()]);
+        assert!(matches!(&fields[0], (x, MatchPattern::Or(alts)) if x == "x" && alts.len() == 2));
+    }
+
+    #[test]
+    fn parse_impl_receivers_and_field_access() {
+        let src = r#"
+impl User {
+  pub fn new(name: string) -> User { main::User { name: name } }
+  fn name(&self) -> string { self.name }
+  fn rename(&mut self, name: string) -> () { self.name = name; }
+  fn into_name(self) -> string { self.name }
+}
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Impl(imp) = &root.items[0] else {
+            panic!("expected impl User");
+        };
+        assert_eq!(imp.name, "User");
+        let sigs: Vec<(&str, Visibility, Option<Receiver>, usize)> = imp
+            .methods
+            .iter()
+            .map(|m| (m.name.as_str(), m.vis, m.receiver, m.params.len()))
+            .collect();
+        assert_eq!(
+            sigs,
+            vec![
+                ("new", Visibility::Exported, None, 1),
+                ("name", Visibility::Private, Some(Receiver::Ref), 0),
+                ("rename", Visibility::Private, Some(Receiver::RefMut), 1),
+                ("into_name", Visibility::Private, Some(Receiver::Value), 0),
+            ]
+        );
+        assert!(matches!(
+            imp.methods[1].body.value.as_deref(),
+            Some(Expr::Field { receiver, name, .. })
+                if name == "name" && matches!(&**receiver, Expr::Ident { name, .. } if name == "self")
+        ));
+        let Stmt::Expr { expr, .. } = &imp.methods[2].body.stmts[0] else {
+            panic!("expected assignment statement");
+        };
+        assert!(
+            matches!(expr, Expr::Assign { target, .. } if matches!(**target, Expr::Field { .. }))
+        );
+
+        for (src, msg) in [
+            (
+                "fn f(&self) -> () {}",
+                "`self` parameter is only allowed in methods",
+            ),
+            (
+                "impl m::User { }",
+                "impl blocks must be in the module that declares the struct",
+            ),
+            (
+                "impl User { fn f(x: int, self) -> () {} }",
+                "`self` must be the first parameter of a method",
+            ),
+        ] {
+            let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+            let diags = parse(tokens, FileId::new(0)).expect_err("expected a parse error");
+            assert_eq!(diags[0].message, msg);
+        }
+    }
+
+    #[test]
+    fn parse_trait_and_trait_impl() {
+        let src = r#"
+pub trait Shape {
+  fn area(&self) -> int;
+  fn describe(&self) -> string { "shape" }
+}
+impl Shape for Sq {
+  fn area(&self) -> int { self.side * self.side }
+}
+impl shapes::Shape for Sq { }
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Trait(t) = &root.items[0] else {
+            panic!("expected trait Shape");
+        };
+        assert_eq!((t.name.as_str(), t.vis), ("Shape", Visibility::Exported));
+        let methods: Vec<(&str, Option<Receiver>, bool)> = t
+            .methods
+            .iter()
+            .map(|m| (m.name.as_str(), m.receiver, m.default.is_some()))
+            .collect();
+        assert_eq!(
+            methods,
+            vec![
+                ("area", Some(Receiver::Ref), false),
+                ("describe", Some(Receiver::Ref), true),
+            ]
+        );
+        let Item::Impl(imp) = &root.items[1] else {
+            panic!("expected impl Shape for Sq");
+        };
+        assert_eq!(imp.name, "Sq");
+        assert_eq!(imp.trait_.as_deref(), Some(&["Shape".to_string()][..]));
+        assert_eq!(imp.methods.len(), 1);
+        let Item::Impl(imp) = &root.items[2] else {
+            panic!("expected impl shapes::Shape for Sq");
+        };
+        assert_eq!(
+            imp.trait_.as_deref(),
+            Some(&["shapes".to_string(), "Shape".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn parse_generics() {
+        let src = r#"
+struct Pair<A, B> { first: A, second: B }
+impl<A, B> Pair<A, B> {
+  fn first(&self) -> A { self.first }
+}
+fn max<T: Ord + shapes::Shape, U>(a: T, b: Pair<T, Option<U>>) -> T { a }
+"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Struct(s) = &root.items[0] else {
+            panic!("expected struct Pair");
+        };
+        assert_eq!(s.type_params, vec!["A".to_string(), "B".to_string()]);
+        let Item::Impl(imp) = &root.items[1] else {
+            panic!("expected impl Pair");
+        };
+        assert_eq!((imp.name.as_str(), imp.type_params.len()), ("Pair", 2));
+        let Item::Fn(f) = &root.items[2] else {
+            panic!("expected fn max");
+        };
+        assert_eq!(
+            f.type_params,
+            vec![
+                TypeParam {
+                    name: "T".to_string(),
+                    bounds: vec![
+                        vec!["Ord".to_string()],
+                        vec!["shapes".to_string(), "Shape".to_string()],
+                    ],
+                },
+                TypeParam {
+                    name: "U".to_string(),
+                    bounds: Vec::new(),
+                },
+            ]
+        );
+        assert_eq!(
+            f.params[1].ty,
+            Type::Generic(
+                vec!["Pair".to_string()],
+                vec![
+                    Type::Path(vec!["T".to_string()]),
+                    Type::Option(Box::new(Type::Path(vec!["U".to_string()]))),
+                ],
+            )
+        );
+
+        for (src, msg) in [
+            (
+                "impl<A, B> Pair<B, A> { }",
+                "expected `Pair<A, B>`: the struct must be named with the impl's type parameters in order",
+            ),
+            (
+                "trait T { fn f<U>(&self) -> int; }",
+                "trait methods cannot have type parameters",
+            ),
+        ] {
+            let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+            let diags = parse(tokens, FileId::new(0)).expect_err("expected a parse error");
+            assert_eq!(diags[0].message, msg);
+        }
+    }
+
+    #[test]
+    fn parse_lists() {
+        let src =
+            "fn f(xs: List<int>) -> () { let ys = [1, 2,]; ys[0] = xs[i + 1]; for x in xs { } }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn f");
+        };
+        assert_eq!(f.params[0].ty, Type::List(Box::new(Type::Int)));
+        let Stmt::Let { init, .. } = &f.body.stmts[0] else {
+            panic!("expected let");
+        };
+        let Expr::ArrayLiteral { elements, .. } = init else {
+            panic!("expected array literal");
+        };
+        assert_eq!(elements.len(), 2);
+        let Stmt::Expr {
+            expr: Expr::Assign { target, value, .. },
+            ..
+        } = &f.body.stmts[1]
+        else {
+            panic!("expected assignment");
+        };
+        assert!(matches!(**target, Expr::Index { .. }));
+        let Expr::Index { index, .. } = &**value else {
+            panic!("expected index");
+        };
+        assert!(matches!(**index, Expr::Binary { .. }));
+    }
+
+    #[test]
+    fn parse_consts_and_array_repeat() {
+        let src = "const SIZE: int = 2 * 4; export const NAME: string = \"knox\";\nfn f() -> () { let xs = [0u8; SIZE]; }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let consts: Vec<(&str, Visibility, &Type)> = root
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Const(c) => Some((c.name.as_str(), c.vis, &c.ty)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            consts,
+            vec![
+                ("SIZE", Visibility::Private, &Type::Int),
+                ("NAME", Visibility::Exported, &Type::String),
+            ]
+        );
+        let Item::Fn(f) = &root.items[2] else {
+            panic!("expected fn f");
+        };
+        let Stmt::Let { init, .. } = &f.body.stmts[0] else {
+            panic!("expected let");
+        };
+        let Expr::ArrayRepeat { value, count, .. } = init else {
+            panic!("expected array repeat");
+        };
+        assert!(matches!(**value, Expr::IntLiteral { value: 0, .. }));
+        assert!(matches!(&**count, Expr::Ident { name, .. } if name == "SIZE"));
+    }
+
+    #[test]
+    fn parse_type_aliases_and_newtypes() {
+        let src = "export type UserId = int;\n@derive(Copy) struct Meters(i64);\nfn f(m: Meters) -> () { let Meters(n) = m; }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::TypeAlias(alias) = &root.items[0] else {
+            panic!("expected type alias");
+        };
+        assert_eq!(
+            (alias.name.as_str(), alias.vis, &alias.ty),
+            ("UserId", Visibility::Exported, &Type::Int)
+        );
+        let Item::Struct(s) = &root.items[1] else {
+            panic!("expected newtype struct");
+        };
+        assert!(s.newtype);
+        assert_eq!(s.derives, vec!["Copy"]);
+        assert_eq!(s.fields.len(), 1);
+        assert_eq!(
+            (s.fields[0].name.as_str(), &s.fields[0].ty),
+            ("0", &Type::I64)
+        );
+        let Item::Fn(f) = &root.items[2] else {
+            panic!("expected fn f");
+        };
+        let Stmt::Let { pattern, .. } = &f.body.stmts[0] else {
+            panic!("expected let");
+        };
+        assert!(matches!(
+            pattern,
+            MatchPattern::Path { path, fields: PatternFields::Tuple(subs) }
+                if path == &["Meters"] && subs.len() == 1
+        ));
+
+        for (src, message) in [
+            (
+                "type Pair<T> = (T, T);",
+                "type aliases cannot have type parameters",
+            ),
+            (
+                "struct Wrap<T>(T);",
+                "newtype structs cannot have type parameters",
+            ),
+        ] {
+            let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+            let diags = parse(tokens, FileId::new(0)).unwrap_err();
+            assert_eq!(diags[0].message, message);
+        }
+    }
+
+    #[test]
+    fn parse_tuples() {
+        let src =
+            "fn f(p: (int, string)) -> (int, (bool, int)) { let (a, _) = p; (p.0, (true, a)) }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn f");
+        };
+        assert_eq!(f.params[0].ty.to_string(), "(int, string)");
+        assert_eq!(f.return_ty.to_string(), "(int, (bool, int))");
+        let Stmt::Let { pattern, .. } = &f.body.stmts[0] else {
+            panic!("expected let");
+        };
+        assert!(matches!(pattern, MatchPattern::Tuple(pats) if pats.len() == 2));
+        let Some(Expr::Tuple { elements, .. }) = f.body.value.as_deref() else {
+            panic!("expected tuple literal");
+        };
+        assert!(matches!(&elements[0], Expr::Field { name, .. } if name == "0"));
+        assert!(matches!(&elements[1], Expr::Tuple { .. }));
+
+        for (src, msg) in [
+            (
+                "fn f() -> () { let x = (1,); }",
+                "a tuple needs at least two elements",
+            ),
+            (
+                "fn f(p: (int)) -> () { }",
+                "a tuple type needs at least two elements",
+            ),
+        ] {
+            let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+            let diags = parse(tokens, FileId::new(0)).expect_err("expected a parse error");
+            assert_eq!(diags[0].message, msg);
+        }
+    }
+
+    #[test]
+    fn parse_derive_and_map_types() {
+        let src =
+            "@derive(Eq, Hash) struct P { x: int } fn f(m: Map<string, P>, s: Set<int>) -> () { }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Struct(p) = &root.items[0] else {
+            panic!("expected struct P");
+        };
+        assert_eq!(p.derives, vec!["Eq".to_string(), "Hash".to_string()]);
+        let Item::Fn(f) = &root.items[1] else {
+            panic!("expected fn f");
+        };
+        assert_eq!(
+            f.params[0].ty,
+            Type::Map(
+                Box::new(Type::String),
+                Box::new(Type::Path(vec!["P".to_string()]))
+            )
+        );
+        assert_eq!(f.params[1].ty, Type::Set(Box::new(Type::Int)));
+
+        let src = "@derive(Clone, Copy) struct V { x: int }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Struct(v) = &root.items[0] else {
+            panic!("expected struct V");
+        };
+        assert_eq!(v.derives, vec!["Clone".to_string(), "Copy".to_string()]);
+
+        let bad = "@derive(Debug) struct P { x: int }";
+        let tokens = Lexer::new(bad, FileId::new(0)).collect_tokens();
+        let diags = parse(tokens, FileId::new(0)).expect_err("expected a parse error");
+        assert_eq!(
+            diags[0].message,
+            "cannot derive `Debug`: only `Eq`, `Hash`, `Clone` and `Copy` can be derived"
+        );
+    }
+
+    #[test]
+    fn parse_closures() {
+        let src = "fn f(g: fn(int, string) -> bool) -> () { let h = |x, y: int| x + y; let k = || -> int { 1 }; }";
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn f");
+        };
+        assert_eq!(f.params[0].ty.to_string(), "fn(int, string) -> bool");
+        let Stmt::Let { init, .. } = &f.body.stmts[0] else {
+            panic!("expected let");
+        };
+        let Expr::Closure {
+            params, ret, body, ..
+        } = init
+        else {
+            panic!("expected closure");
+        };
+        assert_eq!(params.len(), 2);
+        assert!(params[0].1.is_none());
+        assert_eq!(params[1].1, Some(Type::Int));
+        assert!(ret.is_none());
+        assert!(matches!(**body, Expr::Binary { .. }));
+        let Stmt::Let { init, .. } = &f.body.stmts[1] else {
+            panic!("expected let");
+        };
+        assert!(
+            matches!(init, Expr::Closure { params, ret: Some(Type::Int), .. } if params.is_empty())
+        );
+
+        let bad = "fn f() -> () { let k = |x| -> int x; }";
+        let tokens = Lexer::new(bad, FileId::new(0)).collect_tokens();
+        let diags = parse(tokens, FileId::new(0)).expect_err("expected a parse error");
+        assert_eq!(
+            diags[0].message,
+            "expected `{` after the closure's return type"
+        );
+    }
+
+    #[test]
+    fn parse_string_interpolation() {
+        let src = r#"fn f() -> () { let s = "n = {n * 2}!"; }"#;
+        let tokens = Lexer::new(src, FileId::new(0)).collect_tokens();
+        let root = parse(tokens, FileId::new(0)).expect("parse should succeed");
+        let Item::Fn(f) = &root.items[0] else {
+            panic!("expected fn f");
+        };
+        let Stmt::Let { init, .. } = &f.body.stmts[0] else {
+            panic!("expected let");
+        };
+        let Expr::Interpolation { parts, .. } = init else {
+            panic!("expected interpolation");
+        };
+        assert!(matches!(&parts[0], InterpolationPart::Text(t) if t == "n = "));
+        assert!(matches!(
+            &parts[1],
+            InterpolationPart::Expr(Expr::Binary { .. })
+        ));
+        assert!(matches!(&parts[2], InterpolationPart::Text(t) if t == "!"));
+
+        for (bad, message) in [
+            (
+                r#"fn f() -> () { let s = "a {b"; }"#,
+                "unclosed `{` in string: write `{{` for a literal brace",
+            ),
+            (
+                r#"fn f() -> () { let s = "a {}"; }"#,
+                "empty `{}` in string: write `{{}}` for literal braces",
+            ),
+            (
+                r#"fn f() -> () { let s = "{a b}"; }"#,
+                "expected `}` after the interpolated expression",
+            ),
+        ] {
+            let tokens = Lexer::new(bad, FileId::new(0)).collect_tokens();
+            let diags = parse(tokens, FileId::new(0)).expect_err("expected a parse error");
+            assert_eq!(diags[0].message, message);
+        }
+    }
 }
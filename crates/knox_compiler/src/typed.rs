@@ -0,0 +1,512 @@
+//! Typed AST produced by the checker and consumed by lowering.
+//! Names are resolved (locals to `LocalId`, calls to `Callee`) and every expression carries its type.
+
+use knox_syntax::ast::{BinaryOp, Type, UnaryOp};
+use knox_syntax::span::{FileId, Span};
+use knox_syntax::{Diagnostic, StructLayout};
+use std::collections::{HashMap, HashSet};
+
+/// Index into `TFunction::locals`.
+pub type LocalId = u32;
+/// Index into `TypedProgram::functions`. The entry `main` is always 0.
+pub type FnId = u32;
+/// Nesting depth of a loop within its function (0 = outermost); the target of `break`/`continue`.
+pub type LoopId = u32;
+
+/// All checked user functions in a stable order: entry `main`, then every other function and method by module and source order,
+/// then the instantiations of generic ones in the order they were first used, then the functions
+/// behind closures and function values.
+#[derive(Clone, Debug, Default)]
+pub struct TypedProgram {
+    pub functions: Vec<TFunction>,
+    /// Layouts of every struct and enum, then of the generic struct instances the program builds
+    /// (see `knox_syntax::mangle`). Field types are as represented: newtypes have been erased.
+    pub struct_layouts: Vec<StructLayout>,
+    /// Paths (`["main", "Meters"]`) of the newtype structs with the type each is represented as,
+    /// which mentions no newtype (see `erase::erase_newtypes`).
+    pub newtypes: HashMap<Vec<String>, Type>,
+    /// Diagnostics that do not stop compilation (e.g. unreachable match arms).
+    pub warnings: Vec<Diagnostic>,
+    /// Paths (`["main", "P"]`) of the structs and enums whose values are moved rather than
+    /// copied: the structs that do not implement `Copy`, and the enums with a variant holding a
+    /// value that moves (see `is_moved`).
+    pub moved_types: HashSet<Vec<String>>,
+    /// Paths (`["main", "Coin"]`) of the structs declared `resource struct`.
+    pub resources: HashSet<Vec<String>>,
+    /// Paths of the structs and enums with a field that can hold a reference (see `carries_ref`).
+    pub ref_structs: HashSet<Vec<String>>,
+}
+
+/// True if a value of type `ty` holds a resource: it is a resource struct, or a tuple,
+/// collection, `Option`, `Result` or generic struct instance with one inside.
+pub fn holds_resource(resources: &HashSet<Vec<String>>, ty: &Type) -> bool {
+    let holds = |t: &Type| holds_resource(resources, t);
+    match ty {
+        Type::Path(path) => resources.contains(path),
+        Type::Generic(path, args) => resources.contains(path) || args.iter().any(holds),
+        Type::Option(t) | Type::List(t) | Type::Set(t) => holds(t),
+        Type::Result(a, b) | Type::Map(a, b) => holds(a) || holds(b),
+        Type::Tuple(ts) => ts.iter().any(holds),
+        _ => false,
+    }
+}
+
+/// True if using a value of type `ty` moves it: it is one of `moved_types`, a tuple, `Option`
+/// or `Result` with such a value inside, or it holds a resource. Lists, maps and sets are shared
+/// by the variables they are assigned to instead.
+pub fn is_moved(
+    moved_types: &HashSet<Vec<String>>,
+    resources: &HashSet<Vec<String>>,
+    ty: &Type,
+) -> bool {
+    let moved = |t: &Type| is_moved(moved_types, resources, t);
+    match ty {
+        Type::Path(path) | Type::Generic(path, _) if moved_types.contains(path) => true,
+        Type::Option(t) => moved(t),
+        Type::Result(a, b) => moved(a) || moved(b),
+        Type::Tuple(ts) => ts.iter().any(moved),
+        _ => holds_resource(resources, ty),
+    }
+}
+
+/// True if a value of type `ty` can hold a reference: it is a reference or function, one of
+/// `ref_structs`, or a tuple, collection, `Option`, `Result` or generic struct instance with one
+/// inside.
+pub fn carries_ref(ref_structs: &HashSet<Vec<String>>, ty: &Type) -> bool {
+    let carries = |t: &Type| carries_ref(ref_structs, t);
+    match ty {
+        Type::Ref(_, _) | Type::Fn(_, _) => true,
+        Type::Path(path) => ref_structs.contains(path),
+        Type::Generic(path, args) => ref_structs.contains(path) || args.iter().any(carries),
+        Type::Option(t) | Type::List(t) | Type::Set(t) => carries(t),
+        Type::Result(a, b) | Type::Map(a, b) => carries(a) || carries(b),
+        Type::Tuple(ts) => ts.iter().any(carries),
+        _ => false,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TFunction {
+    pub module: String,
+    pub name: String,
+    pub file: FileId,
+    /// Parameters are the first `param_count` locals.
+    pub param_count: u32,
+    pub locals: Vec<TLocal>,
+    pub ret: Type,
+    pub body: TBlock,
+    /// Set for the function behind a closure or a function value. Local 0 is then its
+    /// environment, and these locals are loaded from it on entry, in order.
+    pub captures: Option<Vec<LocalId>>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct TLocal {
+    pub name: String,
+    pub ty: Type,
+    /// Declared `mut`: the local can be assigned and borrowed with `&mut`.
+    pub mutable: bool,
+    /// Bound by a `let` or as a parameter, where it could be declared `mut`.
+    pub can_be_mut: bool,
+    /// Where the local is bound: its `let`, pattern, loop or function.
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TBlock {
+    pub stmts: Vec<TStmt>,
+    /// Trailing expression; the block's value. `()` when absent.
+    pub value: Option<Box<TExpr>>,
+}
+
+impl TBlock {
+    /// Call `f` on the expression of each statement, then on the block's value.
+    pub fn for_each_expr<'a>(&'a self, f: &mut impl FnMut(&'a TExpr)) {
+        for stmt in &self.stmts {
+            match stmt {
+                TStmt::Let { init: e, .. }
+                | TStmt::Expr(e)
+                | TStmt::Return { value: Some(e), .. } => f(e),
+                TStmt::Return { value: None, .. } => {}
+            }
+        }
+        if let Some(value) = &self.value {
+            f(value);
+        }
+    }
+
+    /// True if control never reaches the end of the block (every path returns).
+    pub fn diverges(&self) -> bool {
+        self.stmts.iter().any(|s| match s {
+            TStmt::Return { .. } => true,
+            TStmt::Let { init: e, .. } | TStmt::Expr(e) => e.diverges(),
+        }) || self.value.as_ref().is_some_and(|v| v.diverges())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TStmt {
+    /// `let x = init` binds with `TPattern::Binding`; a tuple pattern destructures and cannot fail.
+    Let {
+        pattern: TPattern,
+        init: TExpr,
+    },
+    Expr(TExpr),
+    Return {
+        span: Span,
+        value: Option<TExpr>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct TExpr {
+    pub span: Span,
+    pub ty: Type,
+    pub kind: TExprKind,
+}
+
+impl TExpr {
+    /// True if evaluating the expression never completes normally.
+    pub fn diverges(&self) -> bool {
+        match &self.kind {
+            TExprKind::If {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                cond.diverges()
+                    || (then_block.diverges() && else_block.as_ref().is_some_and(|b| b.diverges()))
+            }
+            TExprKind::Loop { has_break, .. } => !has_break,
+            TExprKind::Block(block) => block.diverges(),
+            TExprKind::Match { value, arms } => {
+                value.diverges() || (!arms.is_empty() && arms.iter().all(|a| a.body.diverges()))
+            }
+            TExprKind::Break { .. } | TExprKind::Continue(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Call `f` on each direct subexpression in evaluation order, including the expressions of
+    /// nested blocks and match arms (a guard before its body).
+    pub fn for_each_child<'a>(&'a self, f: &mut impl FnMut(&'a TExpr)) {
+        match &self.kind {
+            TExprKind::Unit
+            | TExprKind::Int(_)
+            | TExprKind::Float(_)
+            | TExprKind::Str(_)
+            | TExprKind::Bool(_)
+            | TExprKind::Local(_)
+            | TExprKind::Continue(_) => {}
+            TExprKind::List(items) | TExprKind::Tuple(items) => items.iter().for_each(f),
+            TExprKind::StructLit(fields) => fields.iter().for_each(|(_, e)| f(e)),
+            TExprKind::Variant { fields, .. } => fields.iter().for_each(|(_, e)| f(e)),
+            TExprKind::Closure { captures, .. } => captures.iter().for_each(f),
+            TExprKind::Call { args, .. } => args.iter().for_each(f),
+            TExprKind::Field { base: e, .. }
+            | TExprKind::TupleField { base: e, .. }
+            | TExprKind::Print(e)
+            | TExprKind::Try { expr: e, .. }
+            | TExprKind::Deref(e)
+            | TExprKind::Cast(e)
+            | TExprKind::Ref { expr: e, .. }
+            | TExprKind::Unary { expr: e, .. } => f(e),
+            TExprKind::Index { base: a, index: b }
+            | TExprKind::Assign {
+                target: a,
+                value: b,
+            }
+            | TExprKind::Binary { lhs: a, rhs: b, .. } => {
+                f(a);
+                f(b);
+            }
+            TExprKind::Match { value, arms } => {
+                f(value);
+                for arm in arms {
+                    if let Some(guard) = &arm.guard {
+                        f(guard);
+                    }
+                    f(&arm.body);
+                }
+            }
+            TExprKind::Block(block) | TExprKind::Loop { body: block, .. } => block.for_each_expr(f),
+            TExprKind::If {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                f(cond);
+                then_block.for_each_expr(f);
+                if let Some(block) = else_block {
+                    block.for_each_expr(f);
+                }
+            }
+            TExprKind::While { cond, body } => {
+                f(cond);
+                body.for_each_expr(f);
+            }
+            TExprKind::ForRange {
+                start, end, body, ..
+            } => {
+                f(start);
+                f(end);
+                body.for_each_expr(f);
+            }
+            TExprKind::ForEach { list, body, .. } => {
+                f(list);
+                body.for_each_expr(f);
+            }
+            TExprKind::Break { value, .. } => {
+                if let Some(value) = value {
+                    f(value);
+                }
+            }
+        }
+    }
+}
+
+/// Resolved call target.
+#[derive(Clone, Debug)]
+pub enum Callee {
+    /// A function, method or associated function; a method's receiver is the first argument.
+    Fn(FnId),
+    /// A method of `List<T>`, lowered inline or to a runtime call; the list is the first argument.
+    List(ListMethod),
+    /// `Map::new()`, `Set::new()` or a method of `Map<K, V>`/`Set<K>` (the receiver is the first
+    /// argument), with the functions that compare and hash keys of type `K`.
+    Map(MapMethod, KeyOps),
+    /// `hash()` of an `int`, `u8` or `bool` (the value itself), of an `i64` or `u64` (its two
+    /// halves combined) or of a `string` (a hash of its bytes).
+    Hash,
+    /// A call through a value of type `fn(..) -> R`, which is the first argument.
+    Indirect,
+    /// A built-in method of `string` (or `to_string()` of a number or `bool`); the receiver is
+    /// the first argument.
+    Str(StrMethod),
+    /// `wrapping_add` and the other overflow-aware arithmetic methods of an integer; the
+    /// receiver is the first argument.
+    Num(NumMethod),
+}
+
+/// Built-in methods of `string`. Offsets and lengths count bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrMethod {
+    /// `len() -> int`
+    Len,
+    /// `slice(start, end) -> string`: bytes `start..end`; traps when out of range.
+    Slice,
+    /// `contains(string) -> bool`
+    Contains,
+    /// `split(string) -> List<string>`: the pieces between occurrences of a non-empty separator.
+    Split,
+    /// `trim() -> string`: without leading and trailing ASCII whitespace.
+    Trim,
+    /// `parse_int() -> Option<int>`: the decimal value, or `None` if it is not one.
+    ParseInt,
+    /// `to_string() -> string` of a number or `bool`.
+    ToString,
+}
+
+/// Integer `+`, `-` or `*` (the operator) that does not trap on overflow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumMethod {
+    /// `wrapping_add(T) -> T`: the result modulo 2^bits.
+    Wrapping(BinaryOp),
+    /// `checked_add(T) -> Option<T>`: `None` on overflow.
+    Checked(BinaryOp),
+    /// `saturating_add(T) -> T`: the type's bound nearest to the exact result on overflow.
+    Saturating(BinaryOp),
+}
+
+/// Built-in methods of `Map<K, V>` and `Set<K>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapMethod {
+    /// `Map::new()` / `Set::new()`
+    New,
+    /// `insert(K, V) -> ()` / `insert(K) -> ()`
+    Insert,
+    /// `get(K) -> Option<V>` (maps only)
+    Get,
+    /// `remove(K) -> Option<V>` / `remove(K) -> bool`
+    Remove,
+    /// `contains(K) -> bool`
+    Contains,
+    /// `len() -> int`
+    Len,
+}
+
+/// How keys are compared and hashed: the `eq` and `hash` methods of a struct key, or None for
+/// `int`, `bool` and `string` keys, which the runtime handles itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyOps {
+    pub eq: Option<FnId>,
+    pub hash: Option<FnId>,
+}
+
+/// Built-in methods of `List<T>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListMethod {
+    /// `push(T) -> ()`
+    Push,
+    /// `pop() -> Option<T>`
+    Pop,
+    /// `len() -> int`
+    Len,
+    /// `get(int) -> Option<T>`
+    Get,
+}
+
+/// Some forms are checked but not lowered yet; lowering reports them as unsupported.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub enum TExprKind {
+    /// `()`
+    Unit,
+    /// Integer literal of any integer type; a `u64` above `i64::MAX` is stored as its bit pattern.
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Local(LocalId),
+    /// Array literal; the `List<T>` is `TExpr::ty`.
+    List(Vec<TExpr>),
+    /// Struct literal; the struct is `TExpr::ty`.
+    StructLit(Vec<(String, TExpr)>),
+    /// Tuple literal; the tuple is `TExpr::ty`.
+    Tuple(Vec<TExpr>),
+    /// Struct field at this byte offset; `base` is the struct or a reference to it.
+    Field {
+        base: Box<TExpr>,
+        offset: u32,
+    },
+    /// Closure or function value: a heap environment holding `func` (its index in the function
+    /// table) followed by the captured values, copied when the closure is created.
+    Closure {
+        func: FnId,
+        captures: Vec<TExpr>,
+    },
+    /// Element `index` of the tuple `base`.
+    TupleField {
+        base: Box<TExpr>,
+        index: usize,
+    },
+    /// `base[index]`: list element; traps when out of bounds. `base` is the list or a reference to it.
+    Index {
+        base: Box<TExpr>,
+        index: Box<TExpr>,
+    },
+    Call {
+        callee: Callee,
+        args: Vec<TExpr>,
+    },
+    /// Builtin `print`.
+    Print(Box<TExpr>),
+    Assign {
+        target: Box<TExpr>,
+        value: Box<TExpr>,
+    },
+    /// Arms are tried in order; the checker guarantees one of them matches.
+    Match {
+        value: Box<TExpr>,
+        arms: Vec<TArm>,
+    },
+    Block(TBlock),
+    /// Tagged union constructor (`Some(x)`, `None`, `Ok(x)`, `Err(e)`); the union is `TExpr::ty`.
+    /// `fields` are (byte offset, value) pairs stored after the tag.
+    Variant {
+        tag: u32,
+        fields: Vec<(u32, TExpr)>,
+    },
+    /// `expr?`: returns the operand from the function when its tag is `fail_tag`,
+    /// otherwise evaluates to the payload at its `payload_offset`.
+    Try {
+        expr: Box<TExpr>,
+        fail_tag: u32,
+    },
+    Deref(Box<TExpr>),
+    /// `expr as T`; the target is `TExpr::ty`.
+    Cast(Box<TExpr>),
+    Ref {
+        mut_: bool,
+        expr: Box<TExpr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<TExpr>,
+        rhs: Box<TExpr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<TExpr>,
+    },
+    If {
+        cond: Box<TExpr>,
+        then_block: TBlock,
+        else_block: Option<TBlock>,
+    },
+    While {
+        cond: Box<TExpr>,
+        body: TBlock,
+    },
+    /// `has_break` is false for a loop that can only be left by `return`.
+    Loop {
+        body: TBlock,
+        has_break: bool,
+    },
+    /// `for var in start..end`; `end` is evaluated once.
+    ForRange {
+        var: LocalId,
+        start: Box<TExpr>,
+        end: Box<TExpr>,
+        body: TBlock,
+    },
+    /// `for var in list`; the list is evaluated once, its length read before each iteration.
+    ForEach {
+        var: LocalId,
+        list: Box<TExpr>,
+        body: TBlock,
+    },
+    Break {
+        target: LoopId,
+        value: Option<Box<TExpr>>,
+    },
+    Continue(LoopId),
+}
+
+/// Checked match arm. The guard is evaluated after the pattern's bindings are assigned.
+#[derive(Clone, Debug)]
+pub struct TArm {
+    pub pattern: TPattern,
+    pub guard: Option<TExpr>,
+    pub body: TExpr,
+}
+
+/// Checked match pattern.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub enum TPattern {
+    Wildcard,
+    Binding(LocalId),
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    /// `start..=end` on `int` (an exclusive range is stored with `end - 1`).
+    Range {
+        start: i64,
+        end: i64,
+    },
+    /// Matches a tagged union with this tag; each field is (byte offset, type, sub-pattern).
+    Variant {
+        tag: u32,
+        fields: Vec<(u32, Type, TPattern)>,
+    },
+    /// Matches a struct; each field is (byte offset, type, sub-pattern).
+    Struct {
+        fields: Vec<(u32, Type, TPattern)>,
+    },
+    /// Alternatives; each one assigns the same bindings.
+    Or(Vec<TPattern>),
+    /// One sub-pattern per element of a tuple.
+    Tuple(Vec<TPattern>),
+}
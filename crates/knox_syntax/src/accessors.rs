@@ -1,17 +1,156 @@
-//! Shared types for accessor desugaring: struct layout and accessor specs.
-//! Used by the compiler desugar pass and by codegen to emit getters/setters generically.
+//! Shared types for struct layout and accessor desugaring.
+//! Used by the compiler desugar pass (which turns `@pub(get)`/`@pub(set)` into methods) and by lowering.
 
 use crate::ast::Type;
 
 /// Byte size of a type for struct layout (Wasm ABI: string = ptr+len = 8, int = 4, etc.).
+/// Always a multiple of `field_alignment`, so list elements stay aligned.
 pub fn field_byte_size(ty: &Type) -> u32 {
     match ty {
         Type::String => 8,
         Type::Int => 4,
+        Type::I64 | Type::U64 | Type::F64 => 8,
+        Type::U8 => 1,
         Type::Bool => 4,
         Type::Unit => 0,
         Type::Path(_) => 4,
         Type::Ref(_, _) => 4,
+        // A closure is a pointer to its function and captured values.
+        Type::Fn(_, _) => 4,
+        // Tagged unions, collections and generic structs live on the heap; the field holds a pointer.
+        Type::Option(_)
+        | Type::Result(_, _)
+        | Type::List(_)
+        | Type::Map(_, _)
+        | Type::Set(_)
+        | Type::Generic(_, _) => 4,
+        // Tuples are stored inline, their elements packed in order.
+        Type::Tuple(elems) => align_to(packed_end(0, elems), field_alignment(ty)),
+        // Only seen while checking a generic body; instantiations never lay one out.
+        Type::Param(_) => 4,
+    }
+}
+
+/// Byte alignment of a type in memory: its size for numbers, 4 for strings and pointers, the
+/// largest of its elements' for a tuple.
+pub fn field_alignment(ty: &Type) -> u32 {
+    match ty {
+        Type::I64 | Type::U64 | Type::F64 => 8,
+        Type::U8 | Type::Unit => 1,
+        Type::Tuple(elems) => elems.iter().map(field_alignment).max().unwrap_or(1),
+        _ => 4,
+    }
+}
+
+fn align_to(offset: u32, align: u32) -> u32 {
+    offset.next_multiple_of(align)
+}
+
+/// Name of a generic function or struct instantiated with `args`: `id[int]`, `Pair[int, main::User]`.
+/// Used for the instance's `IrFunction` and `StructLayoutIr`.
+pub fn mangle(name: &str, args: &[Type]) -> String {
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    format!("{}[{}]", name, args.join(", "))
+}
+
+/// Heap header of a `List<T>`: element count, capacity, and a pointer to the elements, which are
+/// stored `field_byte_size(T)` bytes apart. Pushing past the capacity moves the elements.
+pub const LIST_LEN_OFFSET: u32 = 0;
+pub const LIST_CAP_OFFSET: u32 = 4;
+pub const LIST_DATA_OFFSET: u32 = 8;
+pub const LIST_HEADER_SIZE: u32 = 12;
+
+/// Heap header of a `Map<K, V>` or `Set<K>`: the keys and values (0 for a set) as lists in
+/// insertion order, and an open-addressing index of `capacity` i32 slots, each 0 (empty) or an
+/// entry number plus one. The capacity is a power of two, kept at least twice the entry count.
+pub const MAP_KEYS_OFFSET: u32 = 0;
+pub const MAP_VALUES_OFFSET: u32 = 4;
+pub const MAP_INDEX_OFFSET: u32 = 8;
+pub const MAP_CAP_OFFSET: u32 = 12;
+pub const MAP_HEADER_SIZE: u32 = 16;
+pub const MAP_INITIAL_CAPACITY: u32 = 8;
+
+/// Byte offset of the payload in a tagged union; the i32 tag is at offset 0.
+pub const VARIANT_PAYLOAD_OFFSET: u32 = 4;
+
+/// Byte offset of a payload of type `ty` in an `Option` or `Result`: just after the tag, or at
+/// 8 for a payload that needs 8-byte alignment.
+pub fn payload_offset(ty: &Type) -> u32 {
+    align_to(VARIANT_PAYLOAD_OFFSET, field_alignment(ty))
+}
+
+/// Heap environment of a closure: the i32 index of its function in the function table at
+/// offset 0, then the captured values packed in order.
+pub const CLOSURE_CAPTURES_OFFSET: u32 = 4;
+
+/// Byte offsets of a closure's captured values.
+pub fn closure_capture_offsets<'a>(tys: impl IntoIterator<Item = &'a Type>) -> Vec<u32> {
+    packed_offsets(CLOSURE_CAPTURES_OFFSET, tys)
+}
+
+/// Byte size of a closure's environment.
+pub fn closure_env_size(tys: &[Type]) -> u32 {
+    packed_end(CLOSURE_CAPTURES_OFFSET, tys)
+}
+
+/// Byte offsets of a variant's payload fields: packed in order after the tag.
+pub fn variant_field_offsets<'a>(tys: impl IntoIterator<Item = &'a Type>) -> Vec<u32> {
+    packed_offsets(VARIANT_PAYLOAD_OFFSET, tys)
+}
+
+/// Byte offsets of a struct's fields: packed in declaration order (as in `Checker::struct_layouts`).
+pub fn struct_field_offsets<'a>(tys: impl IntoIterator<Item = &'a Type>) -> Vec<u32> {
+    packed_offsets(0, tys)
+}
+
+/// Each value at the next offset its type's alignment allows.
+fn packed_offsets<'a>(start: u32, tys: impl IntoIterator<Item = &'a Type>) -> Vec<u32> {
+    let mut offset = start;
+    tys.into_iter()
+        .map(|ty| {
+            let at = align_to(offset, field_alignment(ty));
+            offset = at + field_byte_size(ty);
+            at
+        })
+        .collect()
+}
+
+/// End of the last value when `tys` are packed from `start` (`start` when there are none).
+fn packed_end(start: u32, tys: &[Type]) -> u32 {
+    let offsets = packed_offsets(start, tys);
+    match (offsets.last(), tys.last()) {
+        (Some(at), Some(ty)) => at + field_byte_size(ty),
+        _ => start,
+    }
+}
+
+/// Heap layout of `Option<T>`: tag 0 = `None`, tag 1 = `Some` with its payload in field `Some`.
+pub fn option_layout(inner: &Type) -> StructLayout {
+    tagged_union_layout(Type::Option(Box::new(inner.clone())), &[("Some", inner)])
+}
+
+/// Heap layout of `Result<T, E>`: tag 0 = `Ok`, tag 1 = `Err`; each payload is at its `payload_offset`.
+pub fn result_layout(ok: &Type, err: &Type) -> StructLayout {
+    tagged_union_layout(
+        Type::Result(Box::new(ok.clone()), Box::new(err.clone())),
+        &[("Ok", ok), ("Err", err)],
+    )
+}
+
+/// Tag field first, then one (overlapping) field per payload-carrying variant.
+fn tagged_union_layout(ty: Type, payloads: &[(&str, &Type)]) -> StructLayout {
+    let mut fields = vec![("tag".to_string(), Type::Int, 0)];
+    let mut total_size = VARIANT_PAYLOAD_OFFSET;
+    for (name, ty) in payloads {
+        let offset = payload_offset(ty);
+        fields.push((name.to_string(), (*ty).clone(), offset));
+        total_size = total_size.max(offset + field_byte_size(ty));
+    }
+    StructLayout {
+        module: String::new(),
+        struct_name: ty.to_string(),
+        fields,
+        total_size,
     }
 }
 
@@ -25,18 +164,6 @@ pub struct StructLayout {
     pub total_size: u32,
 }
 
-/// Descriptor for one generated accessor (getter or setter).
-#[derive(Clone, Debug)]
-pub struct AccessorSpec {
-    pub module: String,
-    pub struct_name: String,
-    pub field_name: String,
-    pub get: bool,
-    pub set: bool,
-    pub ty: Type,
-    pub byte_offset: u32,
-}
-
 /// Setter name in snake_case: set_<field>.
 pub fn setter_name(field_name: &str) -> String {
     format!("set_{}", field_name)
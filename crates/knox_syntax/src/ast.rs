@@ -1,6 +1,7 @@
 //! AST types for Knox (functions, structs, imports, expressions).
 
 use crate::span::Span;
+use std::fmt;
 
 /// Visibility for cross-module access. Only exported items can be imported.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -20,7 +21,12 @@ pub struct Root {
 pub enum Item {
     Fn(FnDecl),
     Struct(StructDecl),
+    Enum(EnumDecl),
+    Trait(TraitDecl),
+    Impl(ImplBlock),
     Import(ImportDecl),
+    Const(ConstDecl),
+    TypeAlias(TypeAliasDecl),
 }
 
 /// Function declaration.
@@ -29,16 +35,75 @@ pub struct FnDecl {
     pub span: Span,
     pub vis: Visibility,
     pub name: String,
+    /// `<T: Bound, U>`; empty for a non-generic function.
+    pub type_params: Vec<TypeParam>,
+    /// Set for methods (`self`, `&self`, `&mut self`); None for free and associated functions.
+    pub receiver: Option<Receiver>,
     pub params: Vec<Param>,
     pub return_ty: Type,
     pub body: Block,
 }
 
+/// How a method takes its receiver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Receiver {
+    /// `self`
+    Value,
+    /// `&self`
+    Ref,
+    /// `&mut self`
+    RefMut,
+}
+
+/// Type parameter of a generic function, with the traits it must implement (`T: Display + Eq`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeParam {
+    pub name: String,
+    /// Trait paths (`Display`, `shapes::Shape`).
+    pub bounds: Vec<Vec<String>>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Param {
     pub name: String,
     pub ty: Type,
     pub mut_: bool,
+    /// `mut? name: ty`
+    pub span: Span,
+}
+
+/// `impl Name { fn ... }`: methods and associated functions of a struct declared in the same module.
+/// `impl Trait for Name { fn ... }` implements a trait for that struct.
+/// A generic struct is written with its parameters: `impl<A, B> Pair<A, B> { fn ... }`.
+#[derive(Clone, Debug)]
+pub struct ImplBlock {
+    pub span: Span,
+    /// Path of the implemented trait (`Display`, `fmt::Show`), if any.
+    pub trait_: Option<Vec<String>>,
+    /// Type parameters of a generic struct, as named in this block.
+    pub type_params: Vec<String>,
+    pub name: String,
+    pub methods: Vec<FnDecl>,
+}
+
+/// Trait declaration: `trait Show { fn show(&self) -> string; fn twice(&self) -> string { ... } }`.
+#[derive(Clone, Debug)]
+pub struct TraitDecl {
+    pub span: Span,
+    pub vis: Visibility,
+    pub name: String,
+    pub methods: Vec<TraitMethod>,
+}
+
+/// Method of a trait: a required signature, or one with a default body. `Self` is the implementing type.
+#[derive(Clone, Debug)]
+pub struct TraitMethod {
+    pub span: Span,
+    pub name: String,
+    pub receiver: Option<Receiver>,
+    pub params: Vec<Param>,
+    pub return_ty: Type,
+    pub default: Option<Block>,
 }
 
 /// Struct declaration.
@@ -47,7 +112,16 @@ pub struct StructDecl {
     pub span: Span,
     pub vis: Visibility,
     pub name: String,
+    /// `struct Pair<A, B>`; empty for a non-generic struct.
+    pub type_params: Vec<String>,
     pub fields: Vec<StructField>,
+    /// Traits listed in `@derive(..)` (`Eq`, `Hash`); their impls are generated by desugaring.
+    pub derives: Vec<String>,
+    /// `resource struct`: its values cannot be copied or dropped, only moved or destructured.
+    pub resource: bool,
+    /// `struct Meters(int);`: a distinct type wrapping a single value, its field `0`. It is
+    /// represented as that value alone.
+    pub newtype: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -58,6 +132,48 @@ pub struct StructField {
     pub attrs: FieldAttrs,
 }
 
+/// Enum (sum type) declaration: `enum Name { A, B(int), C { x: string } }`.
+#[derive(Clone, Debug)]
+pub struct EnumDecl {
+    pub span: Span,
+    pub vis: Visibility,
+    pub name: String,
+    pub variants: Vec<EnumVariant>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EnumVariant {
+    pub span: Span,
+    pub name: String,
+    pub payload: VariantPayload,
+}
+
+/// Payload of an enum variant.
+#[derive(Clone, Debug)]
+pub enum VariantPayload {
+    /// `A`
+    Unit,
+    /// `B(int, string)`
+    Tuple(Vec<Type>),
+    /// `C { x: string }`
+    Struct(Vec<(String, Type)>),
+}
+
+impl VariantPayload {
+    /// Payload fields in declaration order; tuple fields are named `0`, `1`, ...
+    pub fn fields(&self) -> Vec<(String, Type)> {
+        match self {
+            VariantPayload::Unit => Vec::new(),
+            VariantPayload::Tuple(tys) => tys
+                .iter()
+                .enumerate()
+                .map(|(i, t)| (i.to_string(), t.clone()))
+                .collect(),
+            VariantPayload::Struct(fields) => fields.clone(),
+        }
+    }
+}
+
 /// @pub(get), @pub(set), or @pub(get, set)
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct FieldAttrs {
@@ -76,6 +192,26 @@ impl FieldAttrs {
     }
 }
 
+/// Constant: `const MAX: int = 100;`. The value is evaluated at compile time and inlined at
+/// each use.
+#[derive(Clone, Debug)]
+pub struct ConstDecl {
+    pub span: Span,
+    pub vis: Visibility,
+    pub name: String,
+    pub ty: Type,
+    pub value: Expr,
+}
+
+/// Type alias: `type UserId = int;`. The alias is another name for the type, not a new type.
+#[derive(Clone, Debug)]
+pub struct TypeAliasDecl {
+    pub span: Span,
+    pub vis: Visibility,
+    pub name: String,
+    pub ty: Type,
+}
+
 /// Import declaration: `import user` or `import user as u`
 #[derive(Clone, Debug)]
 pub struct ImportDecl {
@@ -85,40 +221,220 @@ pub struct ImportDecl {
 }
 
 /// Type reference.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Type {
+    /// 32-bit signed integer; also written `i32`.
     Int,
+    I64,
+    U64,
+    U8,
+    F64,
     String,
     Bool,
     Unit,
     Path(Vec<String>),
     /// Reference: &T or &mut T
     Ref(bool, Box<Type>),
+    /// Option<T>
+    Option(Box<Type>),
+    /// Result<T, E>
+    Result(Box<Type>, Box<Type>),
+    /// List<T>: growable array on the heap
+    List(Box<Type>),
+    /// Map<K, V>: hash map on the heap, iterated in insertion order
+    Map(Box<Type>, Box<Type>),
+    /// Set<T>: hash set on the heap, iterated in insertion order
+    Set(Box<Type>),
+    /// (T, U, ..): two or more values held side by side, like a struct without names
+    Tuple(Vec<Type>),
+    /// fn(T, U) -> R: a function or closure taking these parameters
+    Fn(Vec<Type>, Box<Type>),
+    /// Generic struct with type arguments: Pair<int, string>
+    Generic(Vec<String>, Vec<Type>),
+    /// Type parameter of the generic function or struct being checked (resolved form of `T`).
+    Param(String),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::I64 => write!(f, "i64"),
+            Type::U64 => write!(f, "u64"),
+            Type::U8 => write!(f, "u8"),
+            Type::F64 => write!(f, "f64"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Unit => write!(f, "()"),
+            Type::Path(segs) => write!(f, "{}", segs.join("::")),
+            Type::Ref(true, inner) => write!(f, "&mut {}", inner),
+            Type::Ref(false, inner) => write!(f, "&{}", inner),
+            Type::Option(inner) => write!(f, "Option<{}>", inner),
+            Type::Result(ok, err) => write!(f, "Result<{}, {}>", ok, err),
+            Type::List(elem) => write!(f, "List<{}>", elem),
+            Type::Map(key, value) => write!(f, "Map<{}, {}>", key, value),
+            Type::Set(elem) => write!(f, "Set<{}>", elem),
+            Type::Tuple(elems) => {
+                let elems: Vec<String> = elems.iter().map(|e| e.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            Type::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "fn({}) -> {}", params.join(", "), ret)
+            }
+            Type::Generic(segs, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}<{}>", segs.join("::"), args.join(", "))
+            }
+            Type::Param(name) => write!(f, "{}", name),
+        }
+    }
 }
 
-/// Block: `{ stmts }`
+impl Type {
+    /// Primitive type named `name` (`int`, `i32`, `i64`, `u64`, `u8`, `f64`, `string`, `bool`).
+    /// Also used for the suffix of a number literal.
+    pub fn primitive(name: &str) -> Option<Type> {
+        Some(match name {
+            "int" | "i32" => Type::Int,
+            "i64" => Type::I64,
+            "u64" => Type::U64,
+            "u8" => Type::U8,
+            "f64" => Type::F64,
+            "string" => Type::String,
+            "bool" => Type::Bool,
+            _ => return None,
+        })
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Int | Type::I64 | Type::U64 | Type::U8)
+    }
+
+    /// An integer type or `f64`.
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || *self == Type::F64
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Type::U64 | Type::U8)
+    }
+
+    /// Largest literal of an integer type (its MAX). A negated literal of a signed type may be
+    /// one larger, so that `-2147483648` can be written.
+    pub fn max_literal(&self) -> u64 {
+        match self {
+            Type::I64 => i64::MAX as u64,
+            Type::U64 => u64::MAX,
+            Type::U8 => u8::MAX as u64,
+            _ => i32::MAX as u64,
+        }
+    }
+}
+
+/// Block: `{ stmts value? }`
 #[derive(Clone, Debug)]
 pub struct Block {
     pub span: Span,
     pub stmts: Vec<Stmt>,
+    /// Trailing expression without a semicolon; the value of the block.
+    pub value: Option<Box<Expr>>,
+}
+
+/// Constructors of the built-in tagged unions `Option<T>` and `Result<T, E>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinVariant {
+    Some,
+    None,
+    Ok,
+    Err,
+}
+
+impl BuiltinVariant {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinVariant::Some => "Some",
+            BuiltinVariant::None => "None",
+            BuiltinVariant::Ok => "Ok",
+            BuiltinVariant::Err => "Err",
+        }
+    }
+
+    /// Runtime tag: `None`/`Ok` are 0, `Some`/`Err` are 1.
+    pub fn tag(self) -> u32 {
+        match self {
+            BuiltinVariant::None | BuiltinVariant::Ok => 0,
+            BuiltinVariant::Some | BuiltinVariant::Err => 1,
+        }
+    }
+}
+
+/// Piece of an interpolated string: literal text or an `{expr}`.
+#[derive(Clone, Debug)]
+pub enum InterpolationPart {
+    Text(String),
+    Expr(Expr),
 }
 
-/// Match pattern (literal or _).
+/// Match pattern.
 #[derive(Clone, Debug)]
 pub enum MatchPattern {
     Int(i64),
     Bool(bool),
     String(String),
     Underscore,
+    /// Binds the matched value to a new local.
+    Binding(String),
+    /// `Some(p)`, `None`, `Ok(p)`, `Err(p)`
+    Variant(BuiltinVariant, Option<Box<MatchPattern>>),
+    /// User enum variant or struct: `E::A`, `E::B(p, q)`, `m::E::C { x, y: p, .. }`, `Point { x, .. }`
+    Path {
+        path: Vec<String>,
+        fields: PatternFields,
+    },
+    /// `lo..hi` (end excluded) or `lo..=hi`
+    Range {
+        start: i64,
+        end: i64,
+        inclusive: bool,
+    },
+    /// `p | q`: matches when any alternative matches; every alternative binds the same names.
+    Or(Vec<MatchPattern>),
+    /// `(p, q)`: one sub-pattern per tuple element
+    Tuple(Vec<MatchPattern>),
+}
+
+/// One arm of a `match`: `pattern if guard => body`.
+#[derive(Clone, Debug)]
+pub struct MatchArm {
+    /// Covers the pattern and the guard.
+    pub span: Span,
+    pub pattern: MatchPattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+}
+
+/// Sub-patterns of an enum variant or struct pattern.
+#[derive(Clone, Debug)]
+pub enum PatternFields {
+    Unit,
+    Tuple(Vec<MatchPattern>),
+    /// `rest` is true when the list ends with `..`.
+    Struct {
+        fields: Vec<(String, MatchPattern)>,
+        rest: bool,
+    },
 }
 
 /// Statement.
 #[derive(Clone, Debug)]
 pub enum Stmt {
+    /// `let x = ..` binds a name (`MatchPattern::Binding`); `let (a, b) = ..` destructures a
+    /// tuple with a pattern that cannot fail.
     Let {
         span: Span,
         mut_: bool,
-        name: String,
+        pattern: MatchPattern,
         ty: Option<Type>,
         init: Expr,
     },
@@ -132,21 +448,110 @@ pub enum Stmt {
     },
 }
 
+/// Binary operators, grouped by precedence (see `BinaryOp::precedence`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    /// Binding strength; higher binds tighter. All binary operators are left-associative.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => 3,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 5,
+        }
+    }
+
+    /// True for `==`, `!=`, `<`, `<=`, `>`, `>=` (which cannot be chained).
+    pub fn is_comparison(self) -> bool {
+        self.precedence() == 3
+    }
+
+    /// True for `+`, `-`, `*`, `/`, `%`.
+    pub fn is_arithmetic(self) -> bool {
+        self.precedence() >= 4
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinaryOp::Or => "||",
+            BinaryOp::And => "&&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+        }
+    }
+}
+
+/// Prefix operators (besides `*` and `&`, which have their own expressions).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `-x`
+    Neg,
+    /// `!x`
+    Not,
+}
+
 /// Expression.
 #[derive(Clone, Debug)]
 pub enum Expr {
+    /// `42`, or `42u8` with a type suffix
     IntLiteral {
         span: Span,
-        value: i64,
+        value: u64,
+        suffix: Option<Type>,
+    },
+    /// `1.5`, `2f64`
+    FloatLiteral {
+        span: Span,
+        value: f64,
     },
     StringLiteral {
         span: Span,
         value: String,
     },
+    /// String with interpolated expressions: "Hi {name}, you are {age}"
+    Interpolation {
+        span: Span,
+        parts: Vec<InterpolationPart>,
+    },
     BoolLiteral {
         span: Span,
         value: bool,
     },
+    /// `()`
+    UnitLiteral {
+        span: Span,
+    },
     Ident {
         span: Span,
         name: String,
@@ -156,16 +561,53 @@ pub enum Expr {
         span: Span,
         segments: Vec<String>,
     },
+    /// Array literal: [1, 2, 3] (a `List<T>`)
+    ArrayLiteral {
+        span: Span,
+        elements: Vec<Expr>,
+    },
+    /// Array of `count` copies of `value`: [0; SIZE]. The count is a constant expression.
+    ArrayRepeat {
+        span: Span,
+        value: Box<Expr>,
+        count: Box<Expr>,
+    },
+    /// Tuple literal: (1, "a")
+    Tuple {
+        span: Span,
+        elements: Vec<Expr>,
+    },
+    /// Closure: |x, y: int| x + y, or |x| -> int { .. } with a declared return type
+    Closure {
+        span: Span,
+        params: Vec<(String, Option<Type>)>,
+        ret: Option<Type>,
+        body: Box<Expr>,
+    },
     /// Struct literal: user::User { name: "John", age: 20 }
     StructLiteral {
         span: Span,
         path: Vec<String>,
         fields: Vec<(String, Expr)>,
     },
-    /// Method or function call: user.name() or user.set_age(30) or print(x)
+    /// Field access: user.name, or a tuple element: pair.0
+    Field {
+        span: Span,
+        receiver: Box<Expr>,
+        name: String,
+    },
+    /// Indexing: xs[i]
+    Index {
+        span: Span,
+        base: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// Method or function call: user.name() or user.set_age(30) or print(x) or greet::greet() or User::new()
     Call {
         span: Span,
         receiver: Option<Box<Expr>>,
+        /// Leading path segments of a qualified call (`["greet"]` for `greet::greet()`); empty otherwise.
+        qualifier: Vec<String>,
         name: String,
         args: Vec<Expr>,
     },
@@ -175,11 +617,11 @@ pub enum Expr {
         target: Box<Expr>,
         value: Box<Expr>,
     },
-    /// match expr { pat => expr, ... }
+    /// match expr { pat => expr, pat if guard => expr, ... }
     Match {
         span: Span,
         value: Box<Expr>,
-        arms: Vec<(MatchPattern, Expr)>,
+        arms: Vec<MatchArm>,
     },
     /// Dereference: *expr
     Deref {
@@ -192,12 +634,87 @@ pub enum Expr {
         mut_: bool,
         expr: Box<Expr>,
     },
-    /// Binary add: lhs + rhs
-    Add {
+    /// if cond { ... } else { ... }; `else if` is an else block whose value is the nested `If`.
+    If {
+        span: Span,
+        cond: Box<Expr>,
+        then_block: Block,
+        else_block: Option<Block>,
+    },
+    /// while cond { ... }
+    While {
+        span: Span,
+        label: Option<String>,
+        cond: Box<Expr>,
+        body: Block,
+    },
+    /// loop { ... }; its value comes from `break value`.
+    Loop {
+        span: Span,
+        label: Option<String>,
+        body: Block,
+    },
+    /// for var in iter { ... }
+    For {
+        span: Span,
+        label: Option<String>,
+        var: String,
+        var_span: Span,
+        iter: Box<Expr>,
+        body: Block,
+    },
+    /// Half-open range `start..end` (the iterator of a `for` loop).
+    Range {
+        span: Span,
+        start: Box<Expr>,
+        end: Box<Expr>,
+    },
+    /// break, break 'label, break value
+    Break {
+        span: Span,
+        label: Option<String>,
+        value: Option<Box<Expr>>,
+    },
+    /// continue or continue 'label
+    Continue {
+        span: Span,
+        label: Option<String>,
+    },
+    /// Block expression: `{ stmts value? }`
+    Block {
+        span: Span,
+        block: Block,
+    },
+    /// `Some(value)`, `None`, `Ok(value)` or `Err(value)`
+    Variant {
+        span: Span,
+        variant: BuiltinVariant,
+        value: Option<Box<Expr>>,
+    },
+    /// Error propagation: `expr?`
+    Try {
+        span: Span,
+        expr: Box<Expr>,
+    },
+    /// Binary operator: lhs op rhs
+    Binary {
         span: Span,
+        op: BinaryOp,
         lhs: Box<Expr>,
         rhs: Box<Expr>,
     },
+    /// Prefix operator: -expr or !expr
+    Unary {
+        span: Span,
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    /// Numeric conversion: expr as ty
+    Cast {
+        span: Span,
+        expr: Box<Expr>,
+        ty: Type,
+    },
 }
 
 impl Expr {
@@ -205,17 +722,51 @@ impl Expr {
     pub fn span(&self) -> Span {
         match self {
             Expr::IntLiteral { span, .. }
+            | Expr::FloatLiteral { span, .. }
             | Expr::StringLiteral { span, .. }
+            | Expr::Interpolation { span, .. }
             | Expr::BoolLiteral { span, .. }
+            | Expr::UnitLiteral { span }
             | Expr::Ident { span, .. }
             | Expr::Path { span, .. }
+            | Expr::ArrayLiteral { span, .. }
+            | Expr::ArrayRepeat { span, .. }
+            | Expr::Tuple { span, .. }
+            | Expr::Closure { span, .. }
             | Expr::StructLiteral { span, .. }
+            | Expr::Field { span, .. }
+            | Expr::Index { span, .. }
             | Expr::Call { span, .. }
             | Expr::Assign { span, .. }
             | Expr::Match { span, .. }
             | Expr::Deref { span, .. }
             | Expr::Ref { span, .. }
-            | Expr::Add { span, .. } => *span,
+            | Expr::If { span, .. }
+            | Expr::While { span, .. }
+            | Expr::Loop { span, .. }
+            | Expr::For { span, .. }
+            | Expr::Range { span, .. }
+            | Expr::Break { span, .. }
+            | Expr::Continue { span, .. }
+            | Expr::Block { span, .. }
+            | Expr::Variant { span, .. }
+            | Expr::Try { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Cast { span, .. } => *span,
         }
     }
+
+    /// Block-like expressions end in `}` and need no semicolon when used as a statement.
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            self,
+            Expr::If { .. }
+                | Expr::Match { .. }
+                | Expr::While { .. }
+                | Expr::Loop { .. }
+                | Expr::For { .. }
+                | Expr::Block { .. }
+        )
+    }
 }
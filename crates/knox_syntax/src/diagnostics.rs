@@ -8,6 +8,8 @@ pub struct Diagnostic {
     pub level: Level,
     pub message: String,
     pub location: Option<Location>,
+    /// Other places that explain the diagnostic, such as where a conflicting borrow starts.
+    pub notes: Vec<(String, Location)>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -22,6 +24,7 @@ impl Diagnostic {
             level: Level::Error,
             message: message.into(),
             location,
+            notes: Vec::new(),
         }
     }
 
@@ -30,8 +33,15 @@ impl Diagnostic {
             level: Level::Warning,
             message: message.into(),
             location,
+            notes: Vec::new(),
         }
     }
+
+    /// Add a note pointing at `location`.
+    pub fn with_note(mut self, message: impl Into<String>, location: Location) -> Self {
+        self.notes.push((message.into(), location));
+        self
+    }
 }
 
 impl fmt::Display for Diagnostic {
@@ -41,10 +51,14 @@ impl fmt::Display for Diagnostic {
             Level::Warning => "warning",
         };
         if let Some(loc) = &self.location {
-            write!(f, "{} at {:?}: {}", level, loc.span, self.message)
+            write!(f, "{} at {:?}: {}", level, loc.span, self.message)?;
         } else {
-            write!(f, "{}: {}", level, self.message)
+            write!(f, "{}: {}", level, self.message)?;
+        }
+        for (message, loc) in &self.notes {
+            write!(f, "\n  note at {:?}: {}", loc.span, message)?;
         }
+        Ok(())
     }
 }
 
@@ -72,22 +86,41 @@ pub fn offset_to_line_col(source: &str, offset: u32) -> (u32, u32) {
     (line, col)
 }
 
-/// Format a diagnostic with source line (for printing).
-pub fn format_diagnostic(source: &str, file_id: crate::span::FileId, diag: &Diagnostic) -> String {
-    let (level_str, level) = match diag.level {
-        Level::Error => ("error", "error"),
-        Level::Warning => ("warning", "warning"),
+/// A source file that diagnostics point into. A compilation's files are indexed by `FileId`.
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    /// Path shown in messages (relative to the package root when there is one).
+    pub name: String,
+    pub text: String,
+}
+
+/// Format a diagnostic with source line (for printing), followed by its notes. `files` is
+/// indexed by `FileId`; a location in a file not in `files` is printed without a source excerpt.
+pub fn format_diagnostic(files: &[SourceFile], diag: &Diagnostic) -> String {
+    let level = match diag.level {
+        Level::Error => "error",
+        Level::Warning => "warning",
     };
-    let loc = match &diag.location {
-        Some(l) => l,
-        None => return format!("{}: {}", level_str, diag.message),
+    let mut out = excerpt(files, diag.location, level, &diag.message);
+    for (message, loc) in &diag.notes {
+        out.push('\n');
+        out.push_str(&excerpt(files, Some(*loc), "note", message));
+    }
+    out
+}
+
+/// `file:line:col: level: message`, then the source line with the span underlined.
+fn excerpt(files: &[SourceFile], loc: Option<Location>, level: &str, message: &str) -> String {
+    let Some((file, span)) = loc.and_then(|l| Some((files.get(l.file.0 as usize)?, l.span))) else {
+        return format!("{}: {}", level, message);
     };
-    let (line, col) = offset_to_line_col(source, loc.span.start);
+    let source = &file.text;
+    let (line, col) = offset_to_line_col(source, span.start);
     let line_content = source
         .lines()
         .nth((line as usize).saturating_sub(1))
         .unwrap_or("");
-    let (_, col_end) = offset_to_line_col(source, loc.span.end);
+    let (_, col_end) = offset_to_line_col(source, span.end);
     let underline = if col_end > col && (col_end as usize) <= line_content.len() + 1 {
         " ".repeat((col as usize).saturating_sub(1)) + &"^".repeat((col_end - col) as usize)
     } else {
@@ -95,6 +128,6 @@ pub fn format_diagnostic(source: &str, file_id: crate::span::FileId, diag: &Diag
     };
     format!(
         "{}:{}:{}: {}: {}\n  {} | {}\n  {} | {}",
-        file_id.0, line, col, level, diag.message, line, line_content, line, underline
+        file.name, line, col, level, message, line, line_content, line, underline
     )
 }
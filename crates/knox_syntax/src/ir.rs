@@ -23,11 +23,13 @@ pub struct StructLayoutIr {
     pub total_size: u32,
 }
 
-/// Single function in IR.
+/// Single function in IR. Params, results and locals are Wasm-level slots
+/// (one entry per Wasm value: a string is two `Int` slots for ptr and len).
 #[derive(Clone, Debug)]
 pub struct IrFunction {
     pub name: String,
     pub params: Vec<Type>,
+    pub results: Vec<Type>,
     pub locals: Vec<Type>,
     pub body: Vec<IrInstr>,
 }
@@ -36,6 +38,8 @@ pub struct IrFunction {
 #[derive(Clone, Debug)]
 pub enum IrInstr {
     ConstInt(i64),
+    ConstI64(i64),
+    ConstF64(f64),
     /// String literal: load ptr and len into the two locals; data_id indexes into Program.string_data.
     ConstString {
         ptr_local: u32,
@@ -44,16 +48,136 @@ pub enum IrInstr {
     },
     LocalGet(u32),
     LocalSet(u32),
-    StructAlloc(u32),                 // layout_id
+    StructAlloc(u32), // layout_id
+    /// Pops a byte size, pushes a pointer to that many fresh zeroed bytes on the heap.
+    Alloc,
+    /// Grows the list in `list` (a local holding a list pointer) by one element of `stride` bytes
+    /// and pushes the address of the new element.
+    ListPush {
+        list: u32,
+        stride: u32,
+    },
+    /// Removes element `index` (a local) from the list in `list`, moving the later elements down.
+    ListRemove {
+        list: u32,
+        index: u32,
+        stride: u32,
+    },
+    /// Pops two strings (ptr, len, ptr, len); pushes 1 if their bytes are equal, else 0.
+    StrEq,
+    /// Pops a string (ptr, len); pushes a hash of its bytes.
+    StrHash,
+    /// Pops two strings; pushes their concatenation (ptr, len) in fresh heap memory.
+    StrConcat,
+    /// Pops two strings; pushes -1, 0 or 1 as the first sorts before, equal to or after the
+    /// second (by bytes).
+    StrCmp,
+    /// Pops a string, a substring and a start offset; pushes the offset of the first occurrence
+    /// of the substring at or after the start, or -1.
+    StrFind,
+    /// Pops a string; pushes the same bytes without leading and trailing ASCII whitespace.
+    StrTrim,
+    /// Pops an i32; pushes its decimal digits as a string in fresh heap memory.
+    IntToStr,
+    /// Like `IntToStr` for an i64, read as unsigned if `unsigned`.
+    I64ToStr {
+        unsigned: bool,
+    },
+    /// Pops an f64; pushes it as a string: `1.5`, `2.0`, `1.25e20`, `NaN`, `inf`. At most six
+    /// decimals are shown.
+    F64ToStr,
+    /// Pops a string; pushes 1 and its value if it is a decimal `int` (with an optional sign),
+    /// otherwise 0 and 0.
+    StrParseInt,
     StructSet(u32, u32, u32),         // ptr_local, field_offset, value_local
     StructSetStr(u32, u32, u32, u32), // ptr_local, field_offset, ptr_val_local, len_val_local
     StructGet(u32, u32, u32),         // ptr_local, field_offset, dest_local (int/bool)
     StructGetStr(u32, u32, u32, u32), // ptr_local, field_offset, ptr_dest, len_dest
-    Call(u32),                        // function index (result on stack; use LocalSet after)
-    CallStr(u32, u32, u32),           // function index, ptr_dest, len_dest (string return)
+    Call(u32), // function index (args and result on stack; use LocalSet after)
+    /// Calls `func`, which returns `count` values (a string or a tuple), and stores them in the
+    /// consecutive locals starting at `dest`.
+    CallMulti {
+        func: u32,
+        dest: u32,
+        count: u32,
+    },
+    /// Pops an index into the function table, then calls that function with the arguments
+    /// below it on the stack. `params` and `results` are the callee's slots; the results are
+    /// stored in the consecutive locals starting at `dest`.
+    CallIndirect {
+        params: Vec<Type>,
+        results: Vec<Type>,
+        dest: u32,
+    },
     PrintInt(u32),
     PrintStr(u32, u32), // ptr_local, len_local
     Return,
     ReturnInt(u32),
-    ReturnStr(u32, u32),
+    /// Returns the `count` consecutive locals starting at `base` (a string or a tuple).
+    ReturnMulti {
+        base: u32,
+        count: u32,
+    },
+    /// Pops two i32 values, pushes the result.
+    Bin(IrBinOp),
+    /// Pops two i64 values, pushes the result (an i32 for comparisons).
+    BinI64(IrBinOp),
+    /// Pops two f64 values, pushes the result (an i32 for comparisons). Only the arithmetic
+    /// operators and the signed comparisons apply; `DivS` is division.
+    BinF64(IrBinOp),
+    /// Pops a value of numeric type `from`, pushes it converted to `to`. Integers are truncated,
+    /// or extended by the signedness of `from`; floats are truncated toward zero, saturating at
+    /// the bounds of `to` (NaN becomes 0).
+    Cast {
+        from: Type,
+        to: Type,
+    },
+    /// Pops an i32, pushes 1 if it was zero, else 0 (logical not).
+    Eqz,
+    /// Structured control flow: `If` pops an i32 condition; `Else` is optional; `End` closes an `If`, `Block` or `Loop`.
+    /// Blocks produce no stack values: results are written to locals.
+    If,
+    Else,
+    Block,
+    Loop,
+    End,
+    /// Branch to the n-th enclosing `If`/`Block`/`Loop` (0 = innermost): exits a block, restarts a loop.
+    Br(u32),
+    /// Pops an i32; branches like `Br` if it is non-zero.
+    BrIf(u32),
+    /// Pops an i32 index; branches like `Br` to `targets[index]`, or to `default` when out of range.
+    BrTable {
+        targets: Vec<u32>,
+        default: u32,
+    },
+    /// Traps; marks code the checker proved cannot be reached (e.g. after exhaustive match arms).
+    Unreachable,
+    /// Writes `string_data[data_id]` and a newline to stderr, then traps.
+    Panic(u32),
+}
+
+/// Integer operators. Division and comparisons are signed unless suffixed `U`; comparisons
+/// push 0 or 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrBinOp {
+    Add,
+    Sub,
+    Mul,
+    DivS,
+    DivU,
+    RemS,
+    RemU,
+    Eq,
+    Ne,
+    LtS,
+    LtU,
+    LeS,
+    LeU,
+    GtS,
+    GtU,
+    GeS,
+    GeU,
+    And,
+    Xor,
+    ShrU,
 }
@@ -1,4 +1,4 @@
-//! Knox syntax: tokens, AST nodes, spans, diagnostics, accessor specs, IR.
+//! Knox syntax: tokens, AST nodes, spans, diagnostics, struct layouts, IR.
 
 pub mod accessors;
 pub mod ast;
@@ -5,8 +5,13 @@ use crate::span::Span;
 #[derive(Clone, Debug, PartialEq)]
 pub enum TokenKind {
     // Literals
-    IntLiteral(i64),
+    /// Integer literal and its suffix as written (`255u8`). Values past `u64::MAX` saturate.
+    IntLiteral(u128, Option<String>),
+    /// `1.5`, with its suffix as written (`1.5f64`).
+    FloatLiteral(f64, Option<String>),
     StringLiteral(String),
+    /// String literal with `{expr}` parts: "Hi {name}"
+    InterpolatedString(Vec<StringSegment>),
     True,
     False,
 
@@ -20,7 +25,17 @@ pub enum TokenKind {
     Match,
     Return,
     Struct,
+    /// `resource` in `resource struct`
+    Resource,
+    Enum,
+    Const,
+    /// `type` in `type Name = T;`
+    Type,
+    Trait,
     Import,
+    Impl,
+    /// `self` (method receiver)
+    SelfValue,
     Pub,
     Export,
     As,
@@ -31,6 +46,14 @@ pub enum TokenKind {
     Dynamic,
     Some,
     None,
+    While,
+    Loop,
+    For,
+    In,
+    Break,
+    Continue,
+    /// Loop label: `'outer` (name without the quote)
+    Label(String),
 
     // Symbols
     LParen,
@@ -44,6 +67,8 @@ pub enum TokenKind {
     Arrow,    // ->
     FatArrow, // =>
     Dot,
+    DotDot,   // ..
+    DotDotEq, // ..=
     Question,
     Pipe, // |
     Underscore,
@@ -72,7 +97,17 @@ pub enum TokenKind {
     Eof,
 }
 
-#[derive(Clone, Debug)]
+/// Piece of an interpolated string literal.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StringSegment {
+    Text(String),
+    /// The tokens of `{expr}`, ending with `Eof`.
+    Code(Vec<Token>),
+    /// A `{` with no matching `}` before the end of the string (its span).
+    Unclosed(Span),
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,